@@ -28,6 +28,7 @@ use crate::{
     protocol::{
         import_refs::{CommandType, RefCommand, Refs},
         repo::Repo,
+        PackOptions,
     },
 };
 
@@ -77,7 +78,14 @@ impl PackHandler for ImportRepo {
         Ok(None)
     }
 
-    async fn full_pack(&self, _: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+    async fn full_pack(
+        &self,
+        want: Vec<String>,
+        options: &PackOptions,
+    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if !options.is_full() {
+            return self.partial_pack(want, vec![], options).await;
+        }
         let pack_config = &self.context.config.pack;
         let (entry_tx, entry_rx) = mpsc::channel(pack_config.channel_message_size);
         let (stream_tx, stream_rx) = mpsc::channel(pack_config.channel_message_size);
@@ -168,7 +176,11 @@ impl PackHandler for ImportRepo {
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        options: &PackOptions,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if !options.is_full() {
+            return self.partial_pack(want, have, options).await;
+        }
         let mut want_clone = want.clone();
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.git_db_storage.clone();
@@ -261,6 +273,19 @@ impl PackHandler for ImportRepo {
         Ok(ReceiverStream::new(stream_rx))
     }
 
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
+        Ok(self
+            .context
+            .services
+            .git_db_storage
+            .get_commits_by_hashes(self.repo.repo_id, &hashes)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.into())
+            .collect())
+    }
+
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
         Ok(self
             .context
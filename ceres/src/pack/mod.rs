@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{HashSet, VecDeque},
     pin::Pin,
     sync::atomic::{AtomicUsize, Ordering},
 };
@@ -8,17 +8,21 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use futures::Stream;
 use sysinfo::System;
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tokio_stream::wrappers::ReceiverStream;
 
 use crate::protocol::import_refs::{RefCommand, Refs};
+use crate::protocol::{ObjectFilter, PackOptions};
 use callisto::raw_blob;
 use common::{
     config::PackConfig,
     errors::{MegaError, ProtocolError},
     utils::ZERO_ID,
 };
-use mercury::internal::{object::commit::Commit, pack::Pack};
+use mercury::internal::{
+    object::commit::Commit,
+    pack::{encode::PackEncoder, Pack},
+};
 use mercury::{
     errors::GitError,
     internal::{
@@ -33,6 +37,20 @@ use mercury::{
 pub mod import_repo;
 pub mod monorepo;
 
+// the entries of a partial pack are collected before encoding, so the channel is only a buffer
+const PARTIAL_PACK_CHANNEL_SIZE: usize = 1024;
+
+/// The result of walking the history for a shallow or partial pack
+#[derive(Debug, Default)]
+pub struct CommitWalk {
+    /// commits to send, the ones the client has are excluded
+    pub commits: Vec<Commit>,
+    /// commits whose parents are cut off by `deepen` or `deepen-since`
+    pub shallow: Vec<String>,
+    /// shallow commits of the client whose parents are sent now
+    pub unshallow: Vec<String>,
+}
+
 #[async_trait]
 pub trait PackHandler: Send + Sync {
     async fn head_hash(&self) -> (String, Vec<Refs>);
@@ -47,17 +65,26 @@ pub trait PackHandler: Send + Sync {
     /// a single binary vector. There is no need to build the entire tree; the function
     /// only sends all the data related to this repository.
     ///
+    /// A shallow or partial request (see [PackOptions]) is delegated to [PackHandler::partial_pack].
+    ///
     /// # Returns
     /// * `Result<Vec<u8>, GitError>` - The packed binary data as a vector of bytes.
     ///
-    async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError>;
+    async fn full_pack(
+        &self,
+        want: Vec<String>,
+        options: &PackOptions,
+    ) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
     async fn incremental_pack(
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        options: &PackOptions,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError>;
 
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError>;
+
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError>;
 
     async fn get_blobs_by_hashes(
@@ -198,4 +225,217 @@ pub trait PackHandler: Send + Sync {
             sender.send(tree.into()).await.unwrap();
         }
     }
+
+    /// Walk the history from `want`, stopping at the commits in `have` and at the limits of `deepen`/`deepen-since`.
+    /// The shallow commits of the client are walked through, so their history can be deepened.
+    async fn walk_commits(
+        &self,
+        want: &[String],
+        have: &[String],
+        options: &PackOptions,
+    ) -> Result<CommitWalk, GitError> {
+        let to_git_error = |e: MegaError| GitError::CustomError(e.to_string());
+        let have: HashSet<&String> = have.iter().collect();
+        let client_shallow: HashSet<&String> = options.shallow.iter().collect();
+
+        let mut walk = CommitWalk::default();
+        let mut visited: HashSet<String> = want.iter().cloned().collect();
+        let mut queue: VecDeque<(Commit, usize)> = self
+            .get_commits_by_hashes(want.to_vec())
+            .await
+            .map_err(to_git_error)?
+            .into_iter()
+            .map(|c| (c, 1))
+            .collect();
+
+        while let Some((commit, depth)) = queue.pop_front() {
+            let id = commit.id.to_string();
+            let parents: Vec<String> = commit
+                .parent_commit_ids
+                .iter()
+                .map(|p| p.to_string())
+                .filter(|p| !have.contains(p) || client_shallow.contains(p))
+                .collect();
+
+            let mut is_shallow = false;
+            if !parents.is_empty() {
+                if options.depth.is_some_and(|limit| depth >= limit) {
+                    is_shallow = true;
+                } else {
+                    let parents = parents
+                        .into_iter()
+                        .filter(|p| !visited.contains(p))
+                        .collect();
+                    for parent in self
+                        .get_commits_by_hashes(parents)
+                        .await
+                        .map_err(to_git_error)?
+                    {
+                        if options
+                            .deepen_since
+                            .is_some_and(|since| parent.committer.timestamp < since)
+                        {
+                            is_shallow = true;
+                        } else if visited.insert(parent.id.to_string()) {
+                            queue.push_back((parent, depth + 1));
+                        }
+                    }
+                }
+            }
+
+            if is_shallow {
+                walk.shallow.push(id.clone());
+            } else if client_shallow.contains(&id) && !commit.parent_commit_ids.is_empty() {
+                walk.unshallow.push(id.clone());
+            }
+            if !have.contains(&id) {
+                walk.commits.push(commit);
+            }
+        }
+        Ok(walk)
+    }
+
+    /// The shallow-update of a `deepen` request: the new shallow commits & the client's commits to unshallow
+    async fn shallow_update(
+        &self,
+        want: &[String],
+        options: &PackOptions,
+    ) -> Result<(Vec<String>, Vec<String>), GitError> {
+        let walk = self.walk_commits(want, &[], options).await?;
+        Ok((walk.shallow, walk.unshallow))
+    }
+
+    /// Build the pack of a shallow or partial clone/fetch.
+    /// Objects under the trees of `have` are excluded, blobs & trees are omitted according to the filter.
+    /// Wanted objects which are not commits (e.g. the blobs fetched on demand by a partial clone) are always sent.
+    async fn partial_pack(
+        &self,
+        want: Vec<String>,
+        have: Vec<String>,
+        options: &PackOptions,
+    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        let to_git_error = |e: MegaError| GitError::CustomError(e.to_string());
+        let walk = self.walk_commits(&want, &have, options).await?;
+
+        let mut exist_objs = HashSet::new();
+        let have_commits = self
+            .get_commits_by_hashes(have)
+            .await
+            .map_err(to_git_error)?;
+        let have_trees = self
+            .get_trees_by_hashes(have_commits.iter().map(|c| c.tree_id.to_string()).collect())
+            .await
+            .map_err(to_git_error)?;
+        for have_tree in have_trees {
+            exist_objs.insert(have_tree.id.to_string());
+            self.traverse(have_tree, &mut exist_objs, None).await;
+        }
+
+        let mut entries: Vec<Entry> = Vec::new();
+        let other_wants: Vec<String> = want
+            .into_iter()
+            .filter(|w| !walk.commits.iter().any(|c| c.id.to_string() == *w))
+            .collect();
+        for blob in self
+            .get_blobs_by_hashes(other_wants.clone())
+            .await
+            .map_err(to_git_error)?
+        {
+            let blob: Blob = blob.into();
+            entries.push(blob.into());
+        }
+        for tree in self
+            .get_trees_by_hashes(other_wants)
+            .await
+            .map_err(to_git_error)?
+        {
+            entries.push(tree.into());
+        }
+
+        for commit in &walk.commits {
+            let trees = self
+                .get_trees_by_hashes(vec![commit.tree_id.to_string()])
+                .await
+                .map_err(to_git_error)?;
+            for tree in trees {
+                self.collect_objects(tree, 0, options.filter, &mut exist_objs, &mut entries)
+                    .await?;
+            }
+        }
+        entries.extend(walk.commits.into_iter().map(Entry::from));
+
+        let (entry_tx, entry_rx) = mpsc::channel(PARTIAL_PACK_CHANNEL_SIZE);
+        let (stream_tx, stream_rx) = mpsc::channel(PARTIAL_PACK_CHANNEL_SIZE);
+        let encoder = PackEncoder::new(entries.len(), 0, stream_tx);
+        encoder.encode_async(entry_rx).await?;
+        tokio::spawn(async move {
+            for entry in entries {
+                if entry_tx.send(entry).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(ReceiverStream::new(stream_rx))
+    }
+
+    /// Collect `tree` & the objects under it which are not in `exist_objs`.
+    /// `depth` is the depth of `tree` from the root tree, used by the `tree:<depth>` filter.
+    async fn collect_objects(
+        &self,
+        tree: Tree,
+        depth: usize,
+        filter: Option<ObjectFilter>,
+        exist_objs: &mut HashSet<String>,
+        entries: &mut Vec<Entry>,
+    ) -> Result<(), GitError> {
+        let to_git_error = |e: MegaError| GitError::CustomError(e.to_string());
+        if filter.is_some_and(|f| !f.keeps_tree(depth)) {
+            return Ok(());
+        }
+        if !exist_objs.insert(tree.id.to_string()) {
+            return Ok(());
+        }
+
+        let mut search_tree_ids = vec![];
+        let mut search_blob_ids = vec![];
+        for item in &tree.tree_items {
+            let hash = item.id.to_string();
+            if !exist_objs.contains(&hash) {
+                if item.mode == TreeItemMode::Tree {
+                    search_tree_ids.push(hash);
+                } else {
+                    search_blob_ids.push(hash);
+                }
+            }
+        }
+
+        // `blob:none` omits all blobs, `tree:<depth>` omits the ones at least `depth` deep
+        if filter.is_none_or(|f| f.keeps_blobs(depth)) {
+            let blobs = self
+                .get_blobs_by_hashes(search_blob_ids)
+                .await
+                .map_err(to_git_error)?;
+            for b in blobs {
+                let blob: Blob = b.into();
+                if let Some(ObjectFilter::BlobLimit(limit)) = filter {
+                    if blob.data.len() >= limit {
+                        continue;
+                    }
+                }
+                exist_objs.insert(blob.id.to_string());
+                entries.push(blob.into());
+            }
+        }
+
+        let trees = self
+            .get_trees_by_hashes(search_tree_ids)
+            .await
+            .map_err(to_git_error)?;
+        for t in trees {
+            self.collect_objects(t, depth + 1, filter, exist_objs, entries)
+                .await?;
+        }
+        entries.push(tree.into());
+        Ok(())
+    }
 }
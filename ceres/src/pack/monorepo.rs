@@ -32,6 +32,7 @@ use crate::{
     protocol::{
         import_refs::{RefCommand, Refs},
         mr::MergeRequest,
+        PackOptions,
     },
 };
 
@@ -162,7 +163,14 @@ impl PackHandler for MonoRepo {
     }
 
     // monorepo full pack should follow the shallow clone command 'git clone --depth=1'
-    async fn full_pack(&self, want: Vec<String>) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+    async fn full_pack(
+        &self,
+        want: Vec<String>,
+        options: &PackOptions,
+    ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if !options.is_full() {
+            return self.partial_pack(want, vec![], options).await;
+        }
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.mono_storage.clone();
         let obj_num = AtomicUsize::new(0);
@@ -238,7 +246,11 @@ impl PackHandler for MonoRepo {
         &self,
         want: Vec<String>,
         have: Vec<String>,
+        options: &PackOptions,
     ) -> Result<ReceiverStream<Vec<u8>>, GitError> {
+        if !options.is_full() {
+            return self.partial_pack(want, have, options).await;
+        }
         let mut want_clone = want.clone();
         let pack_config = &self.context.config.pack;
         let storage = self.context.services.mono_storage.clone();
@@ -324,6 +336,19 @@ impl PackHandler for MonoRepo {
         Ok(ReceiverStream::new(stream_rx))
     }
 
+    async fn get_commits_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Commit>, MegaError> {
+        Ok(self
+            .context
+            .services
+            .mono_storage
+            .get_commits_by_hashes(&hashes)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.into())
+            .collect())
+    }
+
     async fn get_trees_by_hashes(&self, hashes: Vec<String>) -> Result<Vec<Tree>, MegaError> {
         Ok(self
             .context
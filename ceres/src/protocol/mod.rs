@@ -24,6 +24,7 @@ pub struct SmartProtocol {
     pub path: PathBuf,
     pub command_list: Vec<RefCommand>,
     pub service_type: Option<ServiceType>,
+    pub version: ProtocolVersion,
     pub context: Context,
 }
 
@@ -37,6 +38,28 @@ pub enum TransportProtocol {
     P2p,
 }
 
+/// The version of git wire protocol requested by the client, through the `Git-Protocol` header of http
+/// or the `GIT_PROTOCOL` environment variable of ssh.
+/// See [protocol-v2](https://git-scm.com/docs/protocol-v2) for details.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    V0,
+    V2,
+}
+
+impl ProtocolVersion {
+    /// Parse the colon-separated `key[=value]` parameters, e.g. `version=2:object-format=sha1`.
+    /// Only `version=2` is recognized, other versions fall back to v0.
+    pub fn from_git_protocol(value: &str) -> Self {
+        if value.split(':').any(|param| param.trim() == "version=2") {
+            ProtocolVersion::V2
+        } else {
+            ProtocolVersion::V0
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ServiceType {
     UploadPack,
@@ -80,6 +103,8 @@ pub enum Capability {
     OfsDelta,
     DeepenSince,
     DeepenNot,
+    Shallow,
+    Filter,
 }
 
 impl FromStr for Capability {
@@ -97,11 +122,96 @@ impl FromStr for Capability {
             "no-done" => Ok(Capability::NoDone),
             "deepen-since" => Ok(Capability::DeepenSince),
             "deepen-not" => Ok(Capability::DeepenNot),
+            "shallow" => Ok(Capability::Shallow),
+            "filter" => Ok(Capability::Filter),
             _ => Err(()),
         }
     }
 }
 
+/// Object filter of partial clone, sent by `filter <filter-spec>`.
+/// See [rev-list --filter](https://git-scm.com/docs/git-rev-list#Documentation/git-rev-list.txt---filterltfilter-specgt).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectFilter {
+    /// `blob:none`, omit all blobs
+    BlobNone,
+    /// `blob:limit=<n>[kmg]`, omit blobs of size at least n bytes
+    BlobLimit(usize),
+    /// `tree:<depth>`, omit blobs & trees whose depth from the root tree is at least `depth`
+    TreeDepth(usize),
+}
+
+impl FromStr for ObjectFilter {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError::InvalidInput(format!("invalid filter-spec '{}'", s));
+        if s == "blob:none" {
+            Ok(ObjectFilter::BlobNone)
+        } else if let Some(limit) = s.strip_prefix("blob:limit=") {
+            let (number, unit) = match limit.char_indices().last() {
+                Some((i, 'k' | 'K')) => (&limit[..i], 1 << 10),
+                Some((i, 'm' | 'M')) => (&limit[..i], 1 << 20),
+                Some((i, 'g' | 'G')) => (&limit[..i], 1 << 30),
+                _ => (limit, 1),
+            };
+            let number: usize = number.parse().map_err(|_| invalid())?;
+            Ok(ObjectFilter::BlobLimit(number * unit))
+        } else if let Some(depth) = s.strip_prefix("tree:") {
+            Ok(ObjectFilter::TreeDepth(
+                depth.parse().map_err(|_| invalid())?,
+            ))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl ObjectFilter {
+    /// Whether a tree at `depth` from the root tree (the root tree is at 0) is sent
+    pub fn keeps_tree(&self, depth: usize) -> bool {
+        match self {
+            ObjectFilter::TreeDepth(limit) => depth < *limit,
+            _ => true,
+        }
+    }
+
+    /// Whether the blobs of a tree at `depth` may be sent, they are one level deeper than the tree.
+    /// `blob:limit` is checked by the size of each blob then.
+    pub fn keeps_blobs(&self, depth: usize) -> bool {
+        match self {
+            ObjectFilter::BlobNone => false,
+            ObjectFilter::BlobLimit(_) => true,
+            ObjectFilter::TreeDepth(limit) => depth + 1 < *limit,
+        }
+    }
+}
+
+/// Restrictions of the pack requested by a shallow or partial clone/fetch
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PackOptions {
+    /// `deepen <depth>`, the number of commits from the tips of `want`
+    pub depth: Option<usize>,
+    /// `deepen-since <timestamp>`, commits older than this are not sent
+    pub deepen_since: Option<usize>,
+    /// `shallow <oid>`, the shallow commits of the client
+    pub shallow: Vec<String>,
+    /// `filter <filter-spec>`
+    pub filter: Option<ObjectFilter>,
+}
+
+impl PackOptions {
+    /// Whether the history is cut by `deepen` or `deepen-since`
+    pub fn is_deepen(&self) -> bool {
+        self.depth.is_some() || self.deepen_since.is_some()
+    }
+
+    /// Whether the whole history & all objects are requested, as a normal clone/fetch
+    pub fn is_full(&self) -> bool {
+        !self.is_deepen() && self.shallow.is_empty() && self.filter.is_none()
+    }
+}
+
 pub enum SideBind {
     // sideband 1 will contain packfile data,
     PackfileData,
@@ -132,6 +242,7 @@ impl SmartProtocol {
             path,
             command_list: Vec::new(),
             service_type: None,
+            version: ProtocolVersion::default(),
             context,
         }
     }
@@ -144,6 +255,7 @@ impl SmartProtocol {
             path: PathBuf::new(),
             command_list: Vec::new(),
             service_type: None,
+            version: ProtocolVersion::default(),
             context,
         }
     }
@@ -194,4 +306,66 @@ impl SmartProtocol {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::{ObjectFilter, ProtocolVersion};
+
+    #[test]
+    fn test_protocol_version() {
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("version=2"),
+            ProtocolVersion::V2
+        );
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("object-format=sha1:version=2"),
+            ProtocolVersion::V2
+        );
+        assert_eq!(
+            ProtocolVersion::from_git_protocol("version=1"),
+            ProtocolVersion::V0
+        );
+        assert_eq!(ProtocolVersion::from_git_protocol(""), ProtocolVersion::V0);
+    }
+
+    #[test]
+    fn test_object_filter() {
+        assert_eq!(
+            "blob:none".parse::<ObjectFilter>().ok(),
+            Some(ObjectFilter::BlobNone)
+        );
+        assert_eq!(
+            "blob:limit=100".parse::<ObjectFilter>().ok(),
+            Some(ObjectFilter::BlobLimit(100))
+        );
+        assert_eq!(
+            "blob:limit=1k".parse::<ObjectFilter>().ok(),
+            Some(ObjectFilter::BlobLimit(1024))
+        );
+        assert_eq!(
+            "blob:limit=2m".parse::<ObjectFilter>().ok(),
+            Some(ObjectFilter::BlobLimit(2 * 1024 * 1024))
+        );
+        assert_eq!(
+            "tree:0".parse::<ObjectFilter>().ok(),
+            Some(ObjectFilter::TreeDepth(0))
+        );
+        assert!("blob:limit=k".parse::<ObjectFilter>().is_err());
+        assert!("sparse:oid=abc".parse::<ObjectFilter>().is_err());
+    }
+
+    #[test]
+    fn test_object_filter_depth() {
+        // `tree:2`: the root tree, its blobs & subtrees, but not the blobs of the subtrees
+        let filter = ObjectFilter::TreeDepth(2);
+        assert!(filter.keeps_tree(0) && filter.keeps_blobs(0));
+        assert!(filter.keeps_tree(1) && !filter.keeps_blobs(1));
+        assert!(!filter.keeps_tree(2));
+        // `tree:0` sends no tree at all, `tree:1` only the root tree
+        assert!(!ObjectFilter::TreeDepth(0).keeps_tree(0));
+        let filter = ObjectFilter::TreeDepth(1);
+        assert!(filter.keeps_tree(0) && !filter.keeps_blobs(0));
+
+        assert!(!ObjectFilter::BlobNone.keeps_blobs(0));
+        assert!(ObjectFilter::BlobNone.keeps_tree(10));
+        assert!(ObjectFilter::BlobLimit(0).keeps_blobs(10));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::pin::Pin;
 
 use anyhow::Result;
@@ -7,10 +8,14 @@ use tokio_stream::wrappers::ReceiverStream;
 
 use callisto::sea_orm_active_enums::RefTypeEnum;
 use common::errors::ProtocolError;
+use mercury::internal::object::ObjectTrait;
 
-use crate::protocol::import_refs::RefCommand;
+use crate::protocol::import_refs::{RefCommand, Refs};
 use crate::protocol::ZERO_ID;
-use crate::protocol::{Capability, ServiceType, SideBind, SmartProtocol, TransportProtocol};
+use crate::protocol::{
+    Capability, ObjectFilter, PackOptions, ProtocolVersion, ServiceType, SideBind, SmartProtocol,
+    TransportProtocol,
+};
 
 const LF: char = '\n';
 
@@ -20,6 +25,9 @@ const NUL: char = '\0';
 
 pub const PKT_LINE_END_MARKER: &[u8; 4] = b"0000";
 
+// Protocol v2 separates the sections of a request or response with a delim-pkt
+pub const PKT_LINE_DELIM_MARKER: &[u8; 4] = b"0001";
+
 // see https://git-scm.com/docs/protocol-capabilities
 // The atomic, report-status, report-status-v2, delete-refs, quiet,
 // and push-cert capabilities are sent and recognized by the receive-pack (push to server) process.
@@ -30,7 +38,25 @@ const RECEIVE_CAP_LIST: &str = "report-status report-status-v2 delete-refs quiet
 const COMMON_CAP_LIST: &str = "side-band-64k ofs-delta agent=mega/0.1.0";
 
 // All other capabilities are only recognized by the upload-pack (fetch from server) process.
-const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag ";
+const UPLOAD_CAP_LIST: &str = "multi_ack_detailed no-done include-tag shallow deepen-since filter ";
+
+// Protocol v2 advertises one capability per line, including the commands the server supports.
+// see https://git-scm.com/docs/protocol-v2#_capabilities
+const V2_CAP_LIST: [&str; 5] = [
+    "agent=mega/0.1.0",
+    "ls-refs",
+    "fetch=shallow filter",
+    "object-info",
+    "object-format=sha1",
+];
+
+/// A command request of protocol v2, e.g. `command=ls-refs`
+#[derive(Debug, PartialEq, Clone)]
+pub struct CommandRequest {
+    pub command: String,
+    pub capabilities: Vec<String>,
+    pub args: Vec<String>,
+}
 
 impl SmartProtocol {
     /// # Retrieves the information about Git references (refs) for the specified service type.
@@ -61,6 +87,10 @@ impl SmartProtocol {
         let pack_handler = self.pack_handler().await?;
 
         let service_type = self.service_type.unwrap();
+        if self.version == ProtocolVersion::V2 && service_type == ServiceType::UploadPack {
+            // receive-pack has no v2 definition, it keeps the v0 ref advertisement
+            return Ok(self.git_capabilities_v2());
+        }
 
         // The stream MUST include capability declarations behind a NUL on the first ref.
         let (head_hash, git_refs) = pack_handler.head_hash().await;
@@ -94,6 +124,7 @@ impl SmartProtocol {
         let mut want: Vec<String> = Vec::new();
         let mut have: Vec<String> = Vec::new();
         let mut last_common_commit = String::new();
+        let mut options = PackOptions::default();
 
         let mut read_first_line = false;
         loop {
@@ -117,6 +148,11 @@ impl SmartProtocol {
                     have.push(String::from_utf8(dst[5..45].to_vec()).unwrap());
                 }
                 b"done" => break,
+                // shallow-lines, depth-request & filter-request
+                b"shal" | b"deep" | b"filt" => {
+                    let line = String::from_utf8_lossy(&dst);
+                    parse_pack_option(&mut options, line.trim_end())?;
+                }
                 other => {
                     tracing::error!(
                         "unsupported command: {:?}",
@@ -132,17 +168,36 @@ impl SmartProtocol {
         }
 
         tracing::info!(
-            "want commands: {:?}\n have commands: {:?}\n caps:{:?}",
+            "want commands: {:?}\n have commands: {:?}\n caps:{:?}\n options:{:?}",
             want,
             have,
-            self.capabilities
+            self.capabilities,
+            options
         );
 
         let pack_data;
         let mut protocol_buf = BytesMut::new();
 
+        if options.is_deepen() {
+            // shallow-update, sent before the negotiation
+            let (shallow, unshallow) = pack_handler
+                .shallow_update(&want, &options)
+                .await
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+            for hash in shallow {
+                add_pkt_line_string(&mut protocol_buf, format!("shallow {}\n", hash));
+            }
+            for hash in unshallow {
+                add_pkt_line_string(&mut protocol_buf, format!("unshallow {}\n", hash));
+            }
+            protocol_buf.put(&PKT_LINE_END_MARKER[..]);
+        }
+
         if have.is_empty() {
-            pack_data = pack_handler.full_pack(want.clone()).await.unwrap();
+            pack_data = pack_handler
+                .full_pack(want.clone(), &options)
+                .await
+                .unwrap();
             add_pkt_line_string(&mut protocol_buf, String::from("NAK\n"));
         } else {
             if self.capabilities.contains(&Capability::MultiAckDetailed) {
@@ -159,7 +214,7 @@ impl SmartProtocol {
                     }
                 }
                 pack_data = pack_handler
-                    .incremental_pack(want.clone(), have)
+                    .incremental_pack(want.clone(), have, &options)
                     .await
                     .unwrap();
 
@@ -188,6 +243,168 @@ impl SmartProtocol {
         Ok((pack_data, protocol_buf))
     }
 
+    /// # Builds the capability advertisement of protocol v2.
+    ///
+    /// Unlike v0, the refs are not advertised here, the client lists them with the `ls-refs` command.
+    /// The `# service` line is omitted for http as well, which is how the client recognizes a v2 server.
+    pub fn git_capabilities_v2(&self) -> BytesMut {
+        let mut pkt_line_stream = BytesMut::new();
+        add_pkt_line_string(&mut pkt_line_stream, format!("version 2{}", LF));
+        for cap in V2_CAP_LIST {
+            add_pkt_line_string(&mut pkt_line_stream, format!("{}{}", cap, LF));
+        }
+        pkt_line_stream.put(&PKT_LINE_END_MARKER[..]);
+        pkt_line_stream
+    }
+
+    /// # Handles a protocol v2 command request of `git-upload-pack`.
+    ///
+    /// Supported commands are `ls-refs`, `fetch` and `object-info`.
+    /// The returned `BytesMut` holds the response lines, for `fetch` it ends with the `packfile` section header
+    /// and the pack data is returned as a stream, which should be sent in sideband format and followed by a flush-pkt.
+    /// The responses of other commands are complete, including the ending flush-pkt.
+    pub async fn git_upload_pack_v2(
+        &mut self,
+        upload_request: &mut Bytes,
+    ) -> Result<(Option<ReceiverStream<Vec<u8>>>, BytesMut), ProtocolError> {
+        let request = parse_command_request(upload_request)?;
+        tracing::info!("protocol v2 request: {:?}", request);
+        match request.command.as_str() {
+            "ls-refs" => {
+                let pack_handler = self.pack_handler().await?;
+                let (head_hash, git_refs) = pack_handler.head_hash().await;
+                Ok((None, build_ls_refs(&head_hash, &git_refs, &request.args)))
+            }
+            "fetch" => {
+                let (pack_data, protocol_buf) = self.git_fetch_v2(&request.args).await?;
+                Ok((Some(pack_data), protocol_buf))
+            }
+            "object-info" => Ok((None, self.git_object_info(&request.args).await?)),
+            command => Err(ProtocolError::InvalidInput(format!(
+                "unsupported command: {}",
+                command
+            ))),
+        }
+    }
+
+    /// The `fetch` command of protocol v2.
+    ///
+    /// Without `done`, the `acknowledgments` section is sent first, the server ends the negotiation
+    /// immediately with `ready` because it can always build a pack from the `have` list.
+    async fn git_fetch_v2(
+        &mut self,
+        args: &[String],
+    ) -> Result<(ReceiverStream<Vec<u8>>, BytesMut), ProtocolError> {
+        let pack_handler = self.pack_handler().await?;
+
+        let mut want: Vec<String> = Vec::new();
+        let mut have: Vec<String> = Vec::new();
+        let mut done = false;
+        let mut options = PackOptions::default();
+        for arg in args {
+            if let Some(hash) = arg.strip_prefix("want ") {
+                want.push(hash.to_owned());
+            } else if let Some(hash) = arg.strip_prefix("have ") {
+                have.push(hash.to_owned());
+            } else if arg == "done" {
+                done = true;
+            } else if ["shallow ", "deepen ", "deepen-since ", "filter "]
+                .iter()
+                .any(|prefix| arg.starts_with(prefix))
+            {
+                parse_pack_option(&mut options, arg)?;
+            } else if let Ok(cap) = arg.parse::<Capability>() {
+                // e.g. ofs-delta, the capabilities of v0 are arguments in v2
+                self.capabilities.push(cap);
+            }
+        }
+        // the packfile section is always multiplexed in protocol v2
+        self.capabilities.push(Capability::SideBand64k);
+        tracing::info!(
+            "want commands: {:?}\n have commands: {:?}\n done: {}\n options: {:?}",
+            want,
+            have,
+            done,
+            options
+        );
+
+        let mut protocol_buf = BytesMut::new();
+        if !done {
+            add_pkt_line_string(&mut protocol_buf, format!("acknowledgments{}", LF));
+            let mut found_common = false;
+            for hash in &have {
+                if pack_handler.check_commit_exist(hash).await {
+                    add_pkt_line_string(&mut protocol_buf, format!("ACK {}{}", hash, LF));
+                    found_common = true;
+                }
+            }
+            if !found_common {
+                add_pkt_line_string(&mut protocol_buf, format!("NAK{}", LF));
+            }
+            add_pkt_line_string(&mut protocol_buf, format!("ready{}", LF));
+            protocol_buf.put(&PKT_LINE_DELIM_MARKER[..]);
+        }
+        if options.is_deepen() {
+            let (shallow, unshallow) = pack_handler
+                .shallow_update(&want, &options)
+                .await
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+            add_pkt_line_string(&mut protocol_buf, format!("shallow-info{}", LF));
+            for hash in shallow {
+                add_pkt_line_string(&mut protocol_buf, format!("shallow {}{}", hash, LF));
+            }
+            for hash in unshallow {
+                add_pkt_line_string(&mut protocol_buf, format!("unshallow {}{}", hash, LF));
+            }
+            protocol_buf.put(&PKT_LINE_DELIM_MARKER[..]);
+        }
+        add_pkt_line_string(&mut protocol_buf, format!("packfile{}", LF));
+
+        let pack_data = if have.is_empty() {
+            pack_handler.full_pack(want, &options).await
+        } else {
+            pack_handler.incremental_pack(want, have, &options).await
+        }
+        .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+        Ok((pack_data, protocol_buf))
+    }
+
+    /// The `object-info` command of protocol v2, only the `size` attribute is supported.
+    /// Sizes are looked up for blobs and trees, an unknown object is answered with an empty size.
+    async fn git_object_info(&self, args: &[String]) -> Result<BytesMut, ProtocolError> {
+        let pack_handler = self.pack_handler().await?;
+
+        let with_size = args.iter().any(|arg| arg == "size");
+        let oids: Vec<String> = args
+            .iter()
+            .filter_map(|arg| arg.strip_prefix("oid "))
+            .map(|oid| oid.to_owned())
+            .collect();
+
+        let mut sizes = HashMap::new();
+        if with_size {
+            let blobs = pack_handler
+                .get_blobs_by_hashes(oids.clone())
+                .await
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+            for blob in blobs {
+                if let Some(data) = blob.data {
+                    sizes.insert(blob.sha1, data.len());
+                }
+            }
+            let trees = pack_handler
+                .get_trees_by_hashes(oids.clone())
+                .await
+                .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+            for tree in trees {
+                if let Ok(data) = tree.to_data() {
+                    sizes.insert(tree.id.to_string(), data.len());
+                }
+            }
+        }
+        Ok(build_object_info(&oids, with_size.then_some(&sizes)))
+    }
+
     pub fn git_receive_pack_protocol(&mut self, mut protocol_bytes: Bytes) {
         while !protocol_bytes.is_empty() {
             let (bytes_take, mut pkt_line) = read_pkt_line(&mut protocol_bytes);
@@ -352,6 +569,115 @@ fn read_until_white_space(bytes: &mut Bytes) -> String {
     String::from_utf8(buf).unwrap()
 }
 
+/// Parse a command request of protocol v2:
+/// `command=<name>`, the capability lines, a delim-pkt, then the arguments until a flush-pkt.
+/// The trailing LF of each line is removed.
+pub fn parse_command_request(request: &mut Bytes) -> Result<CommandRequest, ProtocolError> {
+    let mut command = None;
+    let mut capabilities = Vec::new();
+    let mut args = Vec::new();
+    let mut read_args = false;
+    while request.len() >= 4 {
+        if request.starts_with(PKT_LINE_DELIM_MARKER) {
+            request.advance(4);
+            read_args = true;
+            continue;
+        }
+        let (bytes_take, pkt_line) = read_pkt_line(request);
+        if bytes_take == 0 {
+            break; // flush-pkt, end of the request
+        }
+        let line = String::from_utf8(pkt_line.to_vec())
+            .map_err(|e| ProtocolError::InvalidInput(e.to_string()))?;
+        let line = line.trim_end_matches(LF).to_owned();
+        if command.is_none() {
+            match line.strip_prefix("command=") {
+                Some(name) => command = Some(name.to_owned()),
+                None => {
+                    return Err(ProtocolError::InvalidInput(format!(
+                        "expected a command, got: {}",
+                        line
+                    )))
+                }
+            }
+        } else if read_args {
+            args.push(line);
+        } else {
+            capabilities.push(line);
+        }
+    }
+    match command {
+        Some(command) => Ok(CommandRequest {
+            command,
+            capabilities,
+            args,
+        }),
+        None => Err(ProtocolError::InvalidInput("empty request".to_owned())),
+    }
+}
+
+/// Parse a shallow-line (`shallow <oid>`), depth-request (`deepen <depth>`, `deepen-since <timestamp>`)
+/// or filter-request (`filter <filter-spec>`) of a fetch request, the trailing LF is removed already.
+fn parse_pack_option(options: &mut PackOptions, line: &str) -> Result<(), ProtocolError> {
+    let invalid = || ProtocolError::InvalidInput(format!("invalid line: {}", line));
+    if let Some(hash) = line.strip_prefix("shallow ") {
+        options.shallow.push(hash.to_owned());
+    } else if let Some(timestamp) = line.strip_prefix("deepen-since ") {
+        options.deepen_since = Some(timestamp.parse().map_err(|_| invalid())?);
+    } else if let Some(depth) = line.strip_prefix("deepen ") {
+        options.depth = Some(depth.parse().map_err(|_| invalid())?);
+    } else if let Some(spec) = line.strip_prefix("filter ") {
+        options.filter = Some(spec.parse::<ObjectFilter>()?);
+    } else {
+        tracing::error!("unsupported command: {}", line);
+    }
+    Ok(())
+}
+
+/// Build the response of `ls-refs`, the refs are filtered by the `ref-prefix` arguments if there are any.
+/// With `symrefs`, HEAD is annotated with the default branch as `symref-target`.
+fn build_ls_refs(head_hash: &str, git_refs: &[Refs], args: &[String]) -> BytesMut {
+    let symrefs = args.iter().any(|arg| arg == "symrefs");
+    let prefixes: Vec<&str> = args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("ref-prefix "))
+        .collect();
+    let wanted =
+        |name: &str| prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix));
+
+    let mut pkt_line_stream = BytesMut::new();
+    if head_hash != ZERO_ID && wanted("HEAD") {
+        let mut pkt_line = format!("{}{}HEAD", head_hash, SP);
+        if symrefs {
+            if let Some(default) = git_refs.iter().find(|git_ref| git_ref.default_branch) {
+                pkt_line.push_str(&format!("{}symref-target:{}", SP, default.ref_name));
+            }
+        }
+        pkt_line.push(LF);
+        add_pkt_line_string(&mut pkt_line_stream, pkt_line);
+    }
+    for git_ref in git_refs.iter().filter(|git_ref| wanted(&git_ref.ref_name)) {
+        let pkt_line = format!("{}{}{}{}", git_ref.ref_hash, SP, git_ref.ref_name, LF);
+        add_pkt_line_string(&mut pkt_line_stream, pkt_line);
+    }
+    pkt_line_stream.put(&PKT_LINE_END_MARKER[..]);
+    pkt_line_stream
+}
+
+/// Build the response of `object-info`, `sizes` is `None` if the `size` attribute is not requested.
+fn build_object_info(oids: &[String], sizes: Option<&HashMap<String, usize>>) -> BytesMut {
+    let mut pkt_line_stream = BytesMut::new();
+    if let Some(sizes) = sizes {
+        add_pkt_line_string(&mut pkt_line_stream, format!("size{}", LF));
+        for oid in oids {
+            let size = sizes.get(oid).map(|s| s.to_string()).unwrap_or_default();
+            add_pkt_line_string(&mut pkt_line_stream, format!("{}{}{}{}", oid, SP, size, LF));
+        }
+    }
+    pkt_line_stream.put(&PKT_LINE_END_MARKER[..]);
+    pkt_line_stream
+}
+
 pub fn add_pkt_line_string(pkt_line_stream: &mut BytesMut, buf_str: String) {
     let buf_str_length = buf_str.len() + 4;
     pkt_line_stream.put(Bytes::from(format!("{buf_str_length:04x}")));
@@ -365,7 +691,8 @@ pub fn add_pkt_line_string(pkt_line_stream: &mut BytesMut, buf_str: String) {
 ///
 /// The function first reads the 4-byte length field from the `bytes` buffer. The length value is then parsed as a hexadecimal string and converted into a `usize` value.
 ///
-/// If the resulting line length is 0, indicating an empty line, the function returns a line length of 0 and an empty `Bytes` object.
+/// If the resulting line length is 0 (or another special packet shorter than the length field, e.g. the delim-pkt `0001`),
+/// the function returns a line length of 0 and an empty `Bytes` object.
 ///
 /// If the line length is non-zero, the function extracts the line content from the `bytes` buffer. The extracted line content is returned as a `Bytes` object.
 /// Note that this operation modifies the `bytes` buffer, consuming the bytes up to the end of the line.
@@ -396,7 +723,8 @@ pub fn read_pkt_line(bytes: &mut Bytes) -> (usize, Bytes) {
     let pkt_length = bytes.copy_to_bytes(4);
     let pkt_length = usize::from_str_radix(core::str::from_utf8(&pkt_length).unwrap(), 16)
         .unwrap_or_else(|_| panic!("{:?} is not a valid digit?", pkt_length));
-    if pkt_length == 0 {
+    if pkt_length < 4 {
+        // flush-pkt `0000`, and the delim-pkt `0001` & response-end-pkt `0002` of protocol v2
         return (0, Bytes::new());
     }
     // this operation will change the original bytes
@@ -411,9 +739,14 @@ pub mod test {
     use bytes::{Bytes, BytesMut};
     use callisto::sea_orm_active_enums::RefTypeEnum;
 
-    use crate::protocol::import_refs::{CommandType, RefCommand};
-    use crate::protocol::smart::{add_pkt_line_string, read_pkt_line, read_until_white_space};
-    use crate::protocol::{Capability, SmartProtocol};
+    use std::collections::HashMap;
+
+    use crate::protocol::import_refs::{CommandType, RefCommand, Refs};
+    use crate::protocol::smart::{
+        add_pkt_line_string, build_ls_refs, build_object_info, parse_command_request,
+        parse_pack_option, read_pkt_line, read_until_white_space, CommandRequest,
+    };
+    use crate::protocol::{Capability, ObjectFilter, PackOptions, SmartProtocol};
 
     #[test]
     pub fn test_read_pkt_line() {
@@ -491,4 +824,133 @@ pub mod test {
             vec![Capability::ReportStatusv2, Capability::SideBand64k]
         );
     }
+
+    #[test]
+    pub fn test_read_delim_pkt() {
+        let mut bytes = Bytes::from_static(b"00010009done\n0000");
+        assert_eq!(read_pkt_line(&mut bytes).0, 0);
+        assert_eq!(read_pkt_line(&mut bytes).1, Bytes::from_static(b"done\n"));
+        assert_eq!(read_pkt_line(&mut bytes).0, 0);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    pub fn test_capabilities_v2() {
+        let mock = SmartProtocol::mock();
+        let buf = mock.git_capabilities_v2();
+        assert!(buf.starts_with(b"000eversion 2\n"));
+        assert!(buf.ends_with(
+            b"000cls-refs\n0019fetch=shallow filter\n0010object-info\n0017object-format=sha1\n0000"
+        ));
+    }
+
+    #[test]
+    pub fn test_parse_command_request() {
+        let mut buf = BytesMut::new();
+        add_pkt_line_string(&mut buf, "command=ls-refs\n".to_owned());
+        add_pkt_line_string(&mut buf, "agent=libra/0.1.0\n".to_owned());
+        buf.extend_from_slice(b"0001");
+        add_pkt_line_string(&mut buf, "symrefs\n".to_owned());
+        add_pkt_line_string(&mut buf, "ref-prefix refs/heads/\n".to_owned());
+        buf.extend_from_slice(b"0000");
+        let request = parse_command_request(&mut buf.freeze()).unwrap();
+        assert_eq!(
+            request,
+            CommandRequest {
+                command: String::from("ls-refs"),
+                capabilities: vec![String::from("agent=libra/0.1.0")],
+                args: vec![
+                    String::from("symrefs"),
+                    String::from("ref-prefix refs/heads/")
+                ],
+            }
+        );
+
+        let mut buf = BytesMut::new();
+        add_pkt_line_string(
+            &mut buf,
+            "want 7bdc783132575d5b3e78400ace9971970ff43a18\n".to_owned(),
+        );
+        assert!(parse_command_request(&mut buf.freeze()).is_err());
+    }
+
+    #[test]
+    pub fn test_build_ls_refs() {
+        let head = "7bdc783132575d5b3e78400ace9971970ff43a18";
+        let tag = "27dd8d4cf39f3868c6eee38b601bc9e9939304f5";
+        let git_refs = vec![
+            Refs {
+                id: 1,
+                ref_name: String::from("refs/heads/main"),
+                ref_hash: head.to_owned(),
+                default_branch: true,
+            },
+            Refs {
+                id: 2,
+                ref_name: String::from("refs/tags/v0.1.0"),
+                ref_hash: tag.to_owned(),
+                default_branch: false,
+            },
+        ];
+
+        let args = vec![String::from("symrefs")];
+        let mut buf = build_ls_refs(head, &git_refs, &args).freeze();
+        let (_, line) = read_pkt_line(&mut buf);
+        assert_eq!(
+            line,
+            format!("{} HEAD symref-target:refs/heads/main\n", head).as_bytes()
+        );
+        let (_, line) = read_pkt_line(&mut buf);
+        assert_eq!(line, format!("{} refs/heads/main\n", head).as_bytes());
+        let (_, line) = read_pkt_line(&mut buf);
+        assert_eq!(line, format!("{} refs/tags/v0.1.0\n", tag).as_bytes());
+        assert_eq!(&buf[..], b"0000");
+
+        let args = vec![String::from("ref-prefix refs/tags/")];
+        let mut buf = build_ls_refs(head, &git_refs, &args).freeze();
+        let (_, line) = read_pkt_line(&mut buf);
+        assert_eq!(line, format!("{} refs/tags/v0.1.0\n", tag).as_bytes());
+        assert_eq!(&buf[..], b"0000");
+    }
+
+    #[test]
+    pub fn test_build_object_info() {
+        let oids = vec![
+            String::from("7bdc783132575d5b3e78400ace9971970ff43a18"),
+            String::from("27dd8d4cf39f3868c6eee38b601bc9e9939304f5"),
+        ];
+        let sizes = HashMap::from([(oids[0].clone(), 42)]);
+        let buf = build_object_info(&oids, Some(&sizes));
+        assert_eq!(
+            &buf[..],
+            b"0009size\n00307bdc783132575d5b3e78400ace9971970ff43a18 42\n002e27dd8d4cf39f3868c6eee38b601bc9e9939304f5 \n0000"
+        );
+        assert_eq!(&build_object_info(&oids, None)[..], b"0000");
+    }
+
+    #[test]
+    pub fn test_parse_pack_option() {
+        let mut options = PackOptions::default();
+        assert!(options.is_full());
+        parse_pack_option(&mut options, "deepen 1").unwrap();
+        parse_pack_option(&mut options, "deepen-since 1700000000").unwrap();
+        parse_pack_option(
+            &mut options,
+            "shallow 7bdc783132575d5b3e78400ace9971970ff43a18",
+        )
+        .unwrap();
+        parse_pack_option(&mut options, "filter blob:none").unwrap();
+        assert_eq!(
+            options,
+            PackOptions {
+                depth: Some(1),
+                deepen_since: Some(1700000000),
+                shallow: vec![String::from("7bdc783132575d5b3e78400ace9971970ff43a18")],
+                filter: Some(ObjectFilter::BlobNone),
+            }
+        );
+        assert!(options.is_deepen());
+        assert!(parse_pack_option(&mut options, "deepen many").is_err());
+        assert!(parse_pack_option(&mut options, "filter sparse:oid=abc").is_err());
+    }
 }
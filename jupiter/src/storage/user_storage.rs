@@ -135,6 +135,14 @@ impl UserStorage {
         Ok(res)
     }
 
+    pub async fn find_token(&self, token: &str) -> Result<Option<access_token::Model>, MegaError> {
+        let res = access_token::Entity::find()
+            .filter(access_token::Column::Token.eq(token))
+            .one(self.get_connection())
+            .await?;
+        Ok(res)
+    }
+
     pub async fn check_token(&self, user_id: i64, token: &str) -> Result<bool, MegaError> {
         let res = access_token::Entity::find()
             .filter(access_token::Column::UserId.eq(user_id))
@@ -23,4 +23,4 @@ WHERE `remote` IS NOT NULL;
 
 -- (name, kind) as unique key when remote is null
 CREATE UNIQUE INDEX idx_name_kind ON `reference`(`name`, `kind`)
-WHERE `remote` IS NULL;
\ No newline at end of file
+WHERE `remote` IS NULL;
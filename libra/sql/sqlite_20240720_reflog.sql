@@ -0,0 +1,15 @@
+-- added after the first release, so it's applied to the existing databases on connect too
+-- history of reference updates, e.g. `HEAD`, `refs/heads/master`, `refs/stash`
+CREATE TABLE IF NOT EXISTS `reflog` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- full name of the reference
+    `ref_name` TEXT NOT NULL CHECK (ref_name <> ''),
+    `old_oid` TEXT NOT NULL,
+    `new_oid` TEXT NOT NULL,
+    -- `name <email>`
+    `committer` TEXT NOT NULL,
+    -- seconds since the epoch
+    `timestamp` INTEGER NOT NULL,
+    `message` TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_reflog_ref_name ON `reflog`(`ref_name`);
@@ -26,7 +26,7 @@ enum Commands {
     // The about attribute provides a brief description of the subcommand.
     // The arguments of the subcommand are defined in the command module.
 
-    // Init and Clone are the only commands that can be executed without a repository
+    // Init, Clone and Credential are the only commands that can be executed without a repository
     #[command(about = "Initialize a new repository")]
     Init(command::init::InitArgs),
     #[command(about = "Clone a repository into a new directory")]
@@ -37,6 +37,8 @@ enum Commands {
     Add(command::add::AddArgs),
     #[command(about = "Remove files from the working tree and from the index")]
     Rm(command::remove::RemoveArgs),
+    #[command(about = "Remove untracked files from the working tree")]
+    Clean(command::clean::CleanArgs),
     #[command(about = "Restore working tree files")]
     Restore(command::restore::RestoreArgs),
     #[command(about = "Show the working tree status")]
@@ -45,14 +47,30 @@ enum Commands {
     Lfs(command::lfs::LfsCmds),
     #[command(about = "Show commit logs")]
     Log(command::log::LogArgs),
+    #[command(about = "Show what revision and author last modified each line of a file")]
+    Blame(command::blame::BlameArgs),
     #[command(about = "List, create, or delete branches")]
     Branch(command::branch::BranchArgs),
+    #[command(about = "Create, list or delete tags")]
+    Tag(command::tag::TagArgs),
     #[command(about = "Record changes to the repository")]
     Commit(command::commit::CommitArgs),
     #[command(about = "Switch branches")]
     Switch(command::switch::SwitchArgs),
     #[command(about = "Merge changes")]
     Merge(command::merge::MergeArgs),
+    #[command(about = "Reapply commits on top of another base tip")]
+    Rebase(command::rebase::RebaseArgs),
+    #[command(about = "Apply the changes introduced by some existing commits")]
+    CherryPick(command::cherry_pick::CherryPickArgs),
+    #[command(about = "Revert some existing commits")]
+    Revert(command::revert::RevertArgs),
+    #[command(about = "Stash the changes in a dirty working directory away")]
+    Stash(command::stash::StashArgs),
+    #[command(about = "Reset current HEAD to the specified state")]
+    Reset(command::reset::ResetArgs),
+    #[command(about = "Manage reflog information")]
+    Reflog(command::reflog::ReflogArgs),
     #[command(about = "Update remote refs along with associated objects")]
     Push(command::push::PushArgs),
     #[command(about = "Download objects and refs from another repository")]
@@ -61,11 +79,40 @@ enum Commands {
     Pull(command::pull::PullArgs),
     #[command(about = "Show different between files")]
     Diff(command::diff::DiffArgs),
+    #[command(about = "Pick out and massage parameters, resolve revisions to hashes")]
+    RevParse(command::rev_parse::RevParseArgs),
+    #[command(about = "Show various types of objects")]
+    Show(command::show::ShowArgs),
+    #[command(about = "Provide content, type or size information for repository objects")]
+    CatFile(command::cat_file::CatFileArgs),
+    #[command(about = "List the contents of a tree object")]
+    LsTree(command::ls_tree::LsTreeArgs),
+    #[command(
+        subcommand,
+        about = "Reduce your working tree to a subset of tracked files"
+    )]
+    SparseCheckout(command::sparse_checkout::SparseCheckoutCmds),
+    #[command(subcommand, about = "Initialize, update or inspect submodules")]
+    Submodule(command::submodule::SubmoduleCmds),
+    #[command(subcommand, about = "Manage multiple worktrees of the repository")]
+    Worktree(command::worktree::WorktreeCmds),
+    #[command(about = "Debug gitignore / exclude files")]
+    CheckIgnore(command::check_ignore::CheckIgnoreArgs),
+    #[command(about = "Check the PGP signature of commits")]
+    VerifyCommit(command::verify_commit::VerifyCommitArgs),
+    #[command(about = "Cleanup unnecessary files and optimize the local repository")]
+    Gc(command::gc::GcArgs),
+    #[command(about = "Pack unpacked objects in a repository")]
+    Repack(command::repack::RepackArgs),
+    #[command(about = "Prune all unreachable objects from the object database")]
+    Prune(command::prune::PruneArgs),
 
     #[command(subcommand, about = "Manage set of tracked repositories")]
     Remote(command::remote::RemoteCmds),
     #[command(about = "Manage repository configurations")]
     Config(command::config::ConfigArgs),
+    #[command(subcommand, about = "Retrieve and store user credentials")]
+    Credential(command::credential::CredentialCmds),
 
     // other hidden commands
     #[command(
@@ -101,6 +148,7 @@ pub async fn parse_async(args: Option<&[&str]>) -> Result<(), GitError> {
     // TODO: try check repo before parsing
     if let Commands::Init(_) = args.command {
     } else if let Commands::Clone(_) = args.command {
+    } else if let Commands::Credential(_) = args.command {
     } else if !utils::util::check_repo_exist() {
         return Err(GitError::RepoNotFound);
     }
@@ -110,21 +158,43 @@ pub async fn parse_async(args: Option<&[&str]>) -> Result<(), GitError> {
         Commands::Clone(args) => command::clone::execute(args).await,
         Commands::Add(args) => command::add::execute(args).await,
         Commands::Rm(args) => command::remove::execute(args).unwrap(),
+        Commands::Clean(args) => command::clean::execute(args).await,
         Commands::Restore(args) => command::restore::execute(args).await,
         Commands::Status => command::status::execute().await,
         Commands::Lfs(cmd) => command::lfs::execute(cmd).await,
         Commands::Log(args) => command::log::execute(args).await,
+        Commands::Blame(args) => command::blame::execute(args).await,
         Commands::Branch(args) => command::branch::execute(args).await,
+        Commands::Tag(args) => command::tag::execute(args).await,
         Commands::Commit(args) => command::commit::execute(args).await,
         Commands::Switch(args) => command::switch::execute(args).await,
         Commands::Merge(args) => command::merge::execute(args).await,
+        Commands::Rebase(args) => command::rebase::execute(args).await,
+        Commands::CherryPick(args) => command::cherry_pick::execute(args).await,
+        Commands::Revert(args) => command::revert::execute(args).await,
+        Commands::Stash(args) => command::stash::execute(args).await,
+        Commands::Reset(args) => command::reset::execute(args).await,
+        Commands::Reflog(args) => command::reflog::execute(args).await,
         Commands::Push(args) => command::push::execute(args).await,
         Commands::IndexPack(args) => command::index_pack::execute(args),
         Commands::Fetch(args) => command::fetch::execute(args).await,
         Commands::Diff(args) => command::diff::execute(args).await,
+        Commands::RevParse(args) => command::rev_parse::execute(args).await,
+        Commands::Show(args) => command::show::execute(args).await,
+        Commands::CatFile(args) => command::cat_file::execute(args).await,
+        Commands::LsTree(args) => command::ls_tree::execute(args).await,
+        Commands::SparseCheckout(cmd) => command::sparse_checkout::execute(cmd).await,
+        Commands::Submodule(cmd) => command::submodule::execute(cmd).await,
+        Commands::Worktree(cmd) => command::worktree::execute(cmd).await,
+        Commands::CheckIgnore(args) => command::check_ignore::execute(args).await,
+        Commands::VerifyCommit(args) => command::verify_commit::execute(args).await,
+        Commands::Gc(args) => command::gc::execute(args).await,
+        Commands::Repack(args) => command::repack::execute(args).await,
+        Commands::Prune(args) => command::prune::execute(args).await,
         Commands::Remote(cmd) => command::remote::execute(cmd).await,
         Commands::Pull(args) => command::pull::execute(args).await,
         Commands::Config(args) => command::config::execute(args).await,
+        Commands::Credential(cmd) => command::credential::execute(cmd).await,
         Commands::Checkout(args) => command::checkout::execute(args).await,
     }
     Ok(())
@@ -3,8 +3,12 @@ use crate::utils::object_ext::BlobExt;
 use clap::Parser;
 use mercury::internal::index::{Index, IndexEntry};
 use mercury::internal::object::blob::Blob;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
+use crate::utils::ignore::IgnoreRules;
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::submodule;
 use crate::utils::{lfs, path, util};
 
 #[derive(Parser, Debug)]
@@ -27,10 +31,13 @@ pub struct AddArgs {
     /// more detailed output
     #[clap(short, long)]
     pub verbose: bool,
+
+    /// Allow adding otherwise ignored files
+    #[clap(short, long)]
+    pub force: bool,
 }
 
 pub async fn execute(args: AddArgs) {
-    // TODO .gitignore
     if !util::check_repo_exist() {
         return;
     }
@@ -47,9 +54,74 @@ pub async fn execute(args: AddArgs) {
         } // '-A' and '-u' cannot be used together
     }
 
-    // index vs worktree
-    let mut changes = status::changes_to_be_staged(); // to workdir
-                                                      // filter paths to fit `pathspec` that user inputs
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    // index vs worktree, without the ignored files
+    let mut changes = status::changes_to_be_staged().await; // to workdir
+    if args.force {
+        // add the ignored files as well
+        let new: HashSet<PathBuf> = changes.new.iter().cloned().collect();
+        let unmerged = index.unmerged_files();
+        for file in util::integrate_pathspec(&paths) {
+            if !index.tracked(file.to_str().unwrap(), 0)
+                && !unmerged.contains(&file)
+                && !new.contains(&file)
+            {
+                changes.new.push(file);
+            }
+        }
+    } else {
+        // like git, the ignored paths given explicitly are reported rather than skipped silently
+        let mut ignore = IgnoreRules::load().await;
+        let ignored: Vec<&String> = args
+            .pathspec
+            .iter()
+            .filter(|pathspec| {
+                let file_abs = util::cur_dir().join(pathspec);
+                if !file_abs.exists() || !util::is_sub_path(&file_abs, util::working_dir()) {
+                    return false;
+                }
+                let file = util::to_workdir_path(&file_abs);
+                !index.tracked(file.to_str().unwrap(), 0)
+                    && ignore.is_ignored(&file, file_abs.is_dir())
+            })
+            .collect();
+        if !ignored.is_empty() {
+            eprintln!("The following paths are ignored by one of your ignore files:");
+            for pathspec in ignored {
+                eprintln!("{}", pathspec);
+            }
+            eprintln!("hint: Use -f if you really want to add them.");
+        }
+    }
+    // the files out of the sparse checkout are not updated, the ones given explicitly are reported
+    if let Some(sparse) = SparseCheckout::load().await {
+        changes.new.retain(|file| sparse.contains(file));
+        let outside: Vec<&String> = args
+            .pathspec
+            .iter()
+            .filter(|pathspec| {
+                let file_abs = util::cur_dir().join(pathspec);
+                if !util::is_sub_path(&file_abs, util::working_dir()) || file_abs.is_dir() {
+                    return false;
+                }
+                let file = util::to_workdir_path(&file_abs);
+                let name = file.to_str().unwrap();
+                // a tracked file left in the worktree is updated as usual
+                (!index.tracked(name, 0) || index.is_skip_worktree(name)) && !sparse.contains(&file)
+            })
+            .collect();
+        if !outside.is_empty() {
+            eprintln!("The following paths and/or pathspecs matched paths that exist outside of your sparse-checkout definition, so will not be updated in the index:");
+            for pathspec in outside {
+                eprintln!("{}", pathspec);
+            }
+            eprintln!(
+                "hint: Disable or modify the sparsity rules if you intend to update such entries."
+            );
+        }
+    }
+    // filter paths to fit `pathspec` that user inputs
     changes.new = util::filter_to_fit_paths(&changes.new, &paths);
     // if `--all` & <pathspec> is given, it will update `index` as well, so no need to filter `deleted` & `modified`
     if args.pathspec.is_empty() || !args.all {
@@ -64,8 +136,12 @@ pub async fn execute(args: AddArgs) {
         files.extend(changes.new);
     }
 
-    let index_file = path::index();
-    let mut index = Index::load(&index_file).unwrap();
+    // conflicted files are not tracked in stage 0, add them to mark resolution
+    for file in util::filter_to_fit_paths(&index.unmerged_files(), &paths) {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
     for file in &files {
         add_a_file(file, &mut index, args.verbose).await;
     }
@@ -85,7 +161,7 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
         );
         return;
     }
-    if util::is_sub_path(file, util::storage_path()) {
+    if util::is_sub_path(file, util::working_dir().join(util::ROOT_DIR)) {
         // file is in `.libra`
         // Git won't print this
         println!(
@@ -98,8 +174,17 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
 
     let file_abs = util::workdir_to_absolute(file);
     let file_str = file.to_str().unwrap();
+    let unmerged = index.unmerged_files().contains(&file.to_path_buf());
+    if unmerged {
+        // resolve the conflict, stage 0 is added below if the file exists
+        index.remove_unmerged(file_str);
+    }
     if !file_abs.exists() {
-        if index.tracked(file_str, 0) {
+        if unmerged {
+            if verbose {
+                println!("removed: {}", file_str);
+            }
+        } else if index.tracked(file_str, 0) {
             // file is removed
             index.remove(file_str, 0);
             if verbose {
@@ -115,6 +200,27 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
                 file.display()
             );
         }
+    } else if file_abs.is_dir() {
+        // a submodule or another repository, its checked out commit is added as a gitlink
+        let Some(commit) = submodule::head_commit(&file_abs).await else {
+            eprintln!("error: '{}' does not have a commit checked out", file_str);
+            return;
+        };
+        if !index.tracked(file_str, 0) {
+            if !submodule::load().iter().any(|s| s.path == file) {
+                eprintln!("warning: adding embedded repository: {}", file_str);
+                eprintln!(
+                    "hint: register it in '{}' to clone it with the super project",
+                    submodule::GITMODULES
+                );
+            }
+        } else if index.verify_hash(file_str, 0, &commit) {
+            return;
+        }
+        index.update(IndexEntry::new_from_gitlink(file_str.to_string(), commit));
+        if verbose {
+            println!("add(submodule): {}", file.display());
+        }
     } else {
         // file exists
         if !index.tracked(file_str, 0) {
@@ -145,7 +251,7 @@ async fn add_a_file(file: &Path, index: &mut Index, verbose: bool) {
 
 /// Generate a `Blob` from a file
 /// - if the file is tracked by LFS, generate a `Blob` with pointer file
-fn gen_blob_from_file(path: impl AsRef<Path>) -> Blob {
+pub fn gen_blob_from_file(path: impl AsRef<Path>) -> Blob {
     if lfs::is_lfs_tracked(&path) {
         Blob::from_lfs_file(&path)
     } else {
@@ -0,0 +1,376 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use crate::internal::revision;
+use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::utils::util;
+
+use super::rebase::commit_summary;
+
+#[derive(Parser, Debug)]
+pub struct BlameArgs {
+    /// `[<rev>] <file>`, blame the file as of the revision, default is HEAD
+    #[clap(required = true, num_args = 1..=2, value_name = "[REV] FILE")]
+    pub targets: Vec<String>,
+
+    /// Only blame the lines in range: `<start>,<end>`, `<start>,+<count>`, `<start>` or `,<end>`, 1-based
+    #[clap(short = 'L', value_name = "RANGE")]
+    pub line_range: Option<String>,
+
+    /// Show in a format designed for machine consumption, like `git blame --porcelain`
+    #[clap(long)]
+    pub porcelain: bool,
+}
+
+/// Where a line of the blamed file comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlameLine {
+    /// the commit that introduced the line
+    pub commit: SHA1,
+    /// line number in the file of `commit`, 1-based
+    pub orig_line: usize,
+    /// line number in the blamed file, 1-based
+    pub final_line: usize,
+    pub content: String,
+}
+
+pub async fn execute(args: BlameArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let (rev, file) = match args.targets.as_slice() {
+        [file] => ("HEAD", file),
+        [rev, file] => (rev.as_str(), file),
+        _ => unreachable!("clap ensures 1 or 2 targets"),
+    };
+    let commit = match revision::resolve_commit(rev).await {
+        Ok(commit) => commit,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let path = util::to_workdir_path(file);
+    let lines = match find_blob(&commit, &path) {
+        Some(blob) => split_lines(&blob),
+        None => {
+            eprintln!("fatal: no such path '{}' in {}", file, rev);
+            return;
+        }
+    };
+    let range = match &args.line_range {
+        Some(spec) => match parse_line_range(spec, lines.len()) {
+            Ok(range) => range,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        },
+        None => 0..lines.len(),
+    };
+
+    let blame = blame_file(commit, &path, range);
+    if args.porcelain {
+        print_porcelain(&blame, &path);
+    } else {
+        print_blame(&blame);
+    }
+}
+
+/// Parse `-L` range to 0-based line indexes, `total` is the number of lines in the file
+fn parse_line_range(spec: &str, total: usize) -> Result<Range<usize>, String> {
+    let invalid = || format!("invalid line range '{}'", spec);
+    let parse = |n: &str| n.parse::<usize>().map_err(|_| invalid());
+    let (start, end) = match spec.split_once(',') {
+        Some((start, end)) => {
+            let start = match start {
+                "" => 1,
+                start => parse(start)?,
+            };
+            let end = match end.strip_prefix('+') {
+                Some(count) => start + parse(count)?.max(1) - 1,
+                None if end.is_empty() => total,
+                None => parse(end)?,
+            };
+            (start, end)
+        }
+        None => (parse(spec)?, total),
+    };
+    if start == 0 || end < start {
+        return Err(invalid());
+    }
+    if start > total {
+        return Err(format!("file has only {} lines", total));
+    }
+    Ok(start - 1..end.min(total))
+}
+
+/// Blame the lines in `range` (0-based) of file `path` as of `commit`.
+/// Lines are passed from a commit to its parents as long as the parent has them unchanged,
+/// the commit that has no parent to pass to introduced the line.
+pub fn blame_file(commit: SHA1, path: &Path, range: Range<usize>) -> Vec<BlameLine> {
+    let final_lines = find_blob(&commit, path).map(|blob| split_lines(&blob));
+    let final_lines = final_lines.unwrap_or_default();
+    let mut origins: HashMap<usize, (SHA1, usize)> = HashMap::new();
+
+    // suspect commit -> lines to blame: (index in the final file, index in the suspect's file)
+    let mut pending: HashMap<SHA1, Vec<(usize, usize)>> = HashMap::new();
+    pending.insert(commit, range.clone().map(|i| (i, i)).collect());
+    // newer commits first, so a commit is processed after all of its children
+    // a commit is queued again if more lines are passed to it after it's processed (history with merges)
+    let mut queue = BinaryHeap::from([(Commit::load(&commit).committer.timestamp, commit)]);
+
+    while let Some((_, suspect)) = queue.pop() {
+        let mut lines = pending.remove(&suspect).unwrap_or_default();
+        let suspect_commit = Commit::load(&suspect);
+        let suspect_blob = find_blob(&suspect, path).unwrap();
+        let suspect_lines = split_lines(&suspect_blob);
+
+        for parent in &suspect_commit.parent_commit_ids {
+            if lines.is_empty() {
+                break;
+            }
+            let parent_blob = match find_blob(parent, path) {
+                Some(blob) => blob,
+                None => continue, // the file is added after `parent`
+            };
+            // suspect line index -> parent line index, for unchanged lines
+            let mapping: HashMap<usize, usize> = if parent_blob == suspect_blob {
+                (0..suspect_lines.len()).map(|i| (i, i)).collect()
+            } else {
+                let parent_lines = split_lines(&parent_blob);
+                unchanged_lines(&parent_lines, &suspect_lines)
+            };
+            let (passed, kept): (Vec<_>, Vec<_>) = lines
+                .into_iter()
+                .partition(|(_, i)| mapping.contains_key(i));
+            lines = kept;
+            if passed.is_empty() {
+                continue;
+            }
+            if !pending.contains_key(parent) {
+                let timestamp = Commit::load(parent).committer.timestamp;
+                queue.push((timestamp, *parent));
+            }
+            pending
+                .entry(*parent)
+                .or_default()
+                .extend(passed.into_iter().map(|(f, i)| (f, mapping[&i])));
+        }
+        for (final_index, index) in lines {
+            origins.insert(final_index, (suspect, index));
+        }
+    }
+
+    range
+        .map(|i| {
+            let (commit, orig) = origins[&i];
+            BlameLine {
+                commit,
+                orig_line: orig + 1,
+                final_line: i + 1,
+                content: final_lines[i].clone(),
+            }
+        })
+        .collect()
+}
+
+/// Map unchanged lines of `new` to their indexes in `old`
+fn unchanged_lines(old: &[String], new: &[String]) -> HashMap<usize, usize> {
+    let mut mapping = HashMap::new();
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            mapping.extend((0..len).map(|k| (new_index + k, old_index + k)));
+        }
+    }
+    mapping
+}
+
+/// Find the blob of `path` (to workdir) in `commit`
+fn find_blob(commit: &SHA1, path: &Path) -> Option<SHA1> {
+    let mut tree = Tree::load(&Commit::load(commit).tree_id);
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        let name = component.as_os_str().to_str()?;
+        let item = tree.tree_items.iter().find(|item| item.name == name)?;
+        if components.peek().is_none() {
+            return matches!(item.mode, TreeItemMode::Blob | TreeItemMode::BlobExecutable)
+                .then_some(item.id);
+        }
+        if item.mode != TreeItemMode::Tree {
+            return None;
+        }
+        tree = Tree::load(&item.id);
+    }
+    None
+}
+
+/// Lines of the blob without line endings
+fn split_lines(blob: &SHA1) -> Vec<String> {
+    let data = Blob::load(blob).data;
+    String::from_utf8_lossy(&data)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn print_blame(blame: &[BlameLine]) {
+    let mut commits: HashMap<SHA1, Commit> = HashMap::new();
+    for line in blame {
+        commits
+            .entry(line.commit)
+            .or_insert_with(|| Commit::load(&line.commit));
+    }
+    let name_width = commits
+        .values()
+        .map(|c| c.author.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let line_width = blame
+        .last()
+        .map_or(1, |line| line.final_line.to_string().len());
+    for line in blame {
+        let commit = &commits[&line.commit];
+        println!(
+            "{} ({:<name_width$} {} {:>line_width$}) {}",
+            &line.commit.to_string()[..8],
+            commit.author.name,
+            util::format_signature_date(&commit.author),
+            line.final_line,
+            line.content,
+        );
+    }
+}
+
+/// Print like `git blame --porcelain`: the header of a group of lines from the same commit shows the line count,
+/// the commit information is only shown the first time the commit appears.
+fn print_porcelain(blame: &[BlameLine], path: &Path) {
+    let mut shown: HashSet<SHA1> = HashSet::new();
+    let mut i = 0;
+    while i < blame.len() {
+        // consecutive lines from the same commit, also consecutive in that commit
+        let first = &blame[i];
+        let group = blame[i..]
+            .iter()
+            .enumerate()
+            .take_while(|(k, line)| {
+                line.commit == first.commit && line.orig_line == first.orig_line + k
+            })
+            .count();
+        for (k, line) in blame[i..i + group].iter().enumerate() {
+            match k {
+                0 => println!(
+                    "{} {} {} {}",
+                    line.commit, line.orig_line, line.final_line, group
+                ),
+                _ => println!("{} {} {}", line.commit, line.orig_line, line.final_line),
+            }
+            if shown.insert(line.commit) {
+                let commit = Commit::load(&line.commit);
+                let (_, summary) = commit_summary(&commit);
+                for (role, signature) in
+                    [("author", &commit.author), ("committer", &commit.committer)]
+                {
+                    println!("{} {}", role, signature.name);
+                    println!("{}-mail <{}>", role, signature.email);
+                    println!("{}-time {}", role, signature.timestamp);
+                    println!("{}-tz {}", role, signature.timezone);
+                }
+                println!("summary {}", summary);
+                if let Some(parent) = commit.parent_commit_ids.first() {
+                    println!("previous {} {}", parent, path.display());
+                } else {
+                    println!("boundary");
+                }
+                println!("filename {}", path.display());
+            }
+            println!("\t{}", line.content);
+        }
+        i += group;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test;
+    use serial_test::serial;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_args() {
+        let args = BlameArgs::try_parse_from(["blame", "a.txt"]).unwrap();
+        assert_eq!(args.targets, vec!["a.txt"]);
+        let args =
+            BlameArgs::try_parse_from(["blame", "-L", "2,+3", "--porcelain", "HEAD~1", "a.txt"])
+                .unwrap();
+        assert_eq!(args.targets, vec!["HEAD~1", "a.txt"]);
+        assert_eq!(args.line_range, Some("2,+3".to_string()));
+        assert!(args.porcelain);
+        assert!(BlameArgs::try_parse_from(["blame"]).is_err());
+        assert!(BlameArgs::try_parse_from(["blame", "a", "b", "c"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_line_range() {
+        assert_eq!(parse_line_range("2,4", 10).unwrap(), 1..4);
+        assert_eq!(parse_line_range("2,+3", 10).unwrap(), 1..4);
+        assert_eq!(parse_line_range("8", 10).unwrap(), 7..10);
+        assert_eq!(parse_line_range(",3", 10).unwrap(), 0..3);
+        assert_eq!(parse_line_range("5,20", 10).unwrap(), 4..10);
+        assert!(parse_line_range("0,2", 10).is_err());
+        assert!(parse_line_range("4,2", 10).is_err());
+        assert!(parse_line_range("11", 10).is_err());
+        assert!(parse_line_range("a,b", 10).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_blame_file() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "a\nb\nc\n", "first").await;
+        let second = test::commit_file("a.txt", "a\nB\nc\n", "second").await;
+        let _ = test::commit_file("b.txt", "unrelated", "unrelated").await;
+        let fourth = test::commit_file("a.txt", "new\na\nB\nc\nd\n", "fourth").await;
+
+        let path = PathBuf::from("a.txt");
+        let blame = blame_file(fourth, &path, 0..5);
+        let origins: Vec<(SHA1, usize)> = blame.iter().map(|l| (l.commit, l.orig_line)).collect();
+        assert_eq!(
+            origins,
+            vec![
+                (fourth, 1),
+                (first, 1),
+                (second, 2),
+                (first, 3),
+                (fourth, 5)
+            ]
+        );
+        assert_eq!(blame[2].content, "B");
+        assert_eq!(blame[2].final_line, 3);
+
+        // only the lines in range
+        let blame = blame_file(fourth, &path, 1..3);
+        assert_eq!(blame.len(), 2);
+        assert_eq!(blame[0].commit, first);
+        assert_eq!(blame[1].commit, second);
+
+        // blame an older revision
+        let blame = blame_file(second, &path, 0..3);
+        assert_eq!(blame[1].commit, second);
+        assert_eq!(blame[2].commit, first);
+    }
+}
@@ -1,10 +1,17 @@
 use crate::{
     command::get_target_commit,
-    internal::{branch::Branch, config::Config, head::Head},
+    internal::{
+        branch::Branch,
+        config::Config,
+        head::Head,
+        reflog::{self, Reflog},
+    },
+    utils::worktree,
 };
 use clap::Parser;
 use colored::Colorize;
 use mercury::internal::object::commit::Commit;
+use std::collections::HashSet;
 
 use crate::command::load_object;
 
@@ -101,6 +108,9 @@ pub async fn create_branch(new_branch: String, branch_or_commit: Option<String>)
         panic!("fatal: A branch named '{}' already exists.", new_branch);
     }
 
+    let start_point = branch_or_commit
+        .clone()
+        .unwrap_or_else(|| "HEAD".to_string());
     let commit_id = match branch_or_commit {
         Some(branch_or_commit) => {
             let commit = get_target_commit(&branch_or_commit).await;
@@ -122,6 +132,14 @@ pub async fn create_branch(new_branch: String, branch_or_commit: Option<String>)
 
     // create branch
     Branch::update_branch(&new_branch, &commit_id.to_string(), None).await;
+    let message = format!("branch: Created from {}", start_point);
+    Reflog::record(
+        &reflog::branch_ref(&new_branch),
+        Default::default(),
+        commit_id,
+        &message,
+    )
+    .await;
 }
 
 async fn delete_branch(branch_name: String) {
@@ -138,8 +156,16 @@ async fn delete_branch(branch_name: String) {
             );
         }
     }
+    if let Some(worktree) = worktree::find_by_branch(&branch_name).await {
+        panic!(
+            "fatal: Cannot delete branch '{}' used by worktree at '{}'",
+            branch_name,
+            worktree.path.display()
+        );
+    }
 
     Branch::delete_branch(&branch_name, None).await;
+    Reflog::clear(&reflog::branch_ref(&branch_name)).await;
 }
 
 async fn show_current_branch() {
@@ -180,6 +206,15 @@ pub async fn list_branches(remotes: bool) {
         Head::Branch(name) => name,
         Head::Detached(_) => "".to_string(),
     };
+    // the branches checked out in the other worktrees
+    let mut used_names = HashSet::new();
+    if !remotes {
+        for worktree in worktree::list().await {
+            if let (false, Some(Head::Branch(name))) = (worktree.is_current(), worktree.head) {
+                used_names.insert(name);
+            }
+        }
+    }
     for branch in branches {
         let name = branch
             .remote
@@ -188,6 +223,8 @@ pub async fn list_branches(remotes: bool) {
 
         if head_name == name {
             println!("* {}", name.green());
+        } else if used_names.contains(&name) {
+            println!("+ {}", name.cyan());
         } else {
             println!("  {}", name);
         };
@@ -246,6 +283,8 @@ mod tests {
             message: "first".to_string(),
             allow_empty: true,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         commit::execute(commit_args).await;
         let first_commit_id = Branch::find_branch("master", None).await.unwrap().commit;
@@ -254,6 +293,8 @@ mod tests {
             message: "second".to_string(),
             allow_empty: true,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         commit::execute(commit_args).await;
         let second_commit_id = Branch::find_branch("master", None).await.unwrap().commit;
@@ -333,6 +374,8 @@ mod tests {
             message: "first".to_string(),
             allow_empty: true,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         commit::execute(args).await;
         let hash = Head::current_commit().await.unwrap();
@@ -366,6 +409,8 @@ mod tests {
             message: "first".to_string(),
             allow_empty: true,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         commit::execute(args).await;
 
@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::Tree;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::internal::revision;
+use crate::utils::{object_ext, util};
+
+use super::ls_tree;
+
+#[derive(Parser, Debug)]
+#[command(group(clap::ArgGroup::new("mode").required(true)))]
+pub struct CatFileArgs {
+    /// Show the object type
+    #[clap(short = 't', group = "mode")]
+    pub show_type: bool,
+
+    /// Show the object size
+    #[clap(short = 's', group = "mode")]
+    pub size: bool,
+
+    /// Pretty-print the object content based on its type
+    #[clap(short = 'p', group = "mode")]
+    pub pretty: bool,
+
+    /// The object hash, or a revision like `HEAD~1^{tree}`
+    pub object: String,
+}
+
+pub async fn execute(args: CatFileArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let object = match revision::resolve(&args.object).await {
+        Ok(object) => object,
+        Err(e) => {
+            eprintln!("fatal: Not a valid object name {}: {}", args.object, e);
+            return;
+        }
+    };
+    // an object omitted by a partial clone is fetched from the promisor remote
+    if let Err(e) = object_ext::fetch_missing_objects([object]).await {
+        tracing::debug!("failed to fetch missing object {}: {}", object, e);
+    }
+    let storage = util::objects_storage();
+    let (object_type, data) = match (storage.get_object_type(&object), storage.get(&object)) {
+        (Ok(object_type), Ok(data)) => (object_type, data),
+        _ => {
+            eprintln!("fatal: object {} not found", object);
+            return;
+        }
+    };
+    if args.show_type {
+        println!("{}", object_type);
+    } else if args.size {
+        println!("{}", data.len());
+    } else {
+        io::stdout()
+            .write_all(&pretty_print(&object, object_type, data))
+            .unwrap();
+    }
+}
+
+/// Trees are listed like `ls-tree`, other objects are printed as they are stored
+fn pretty_print(object: &SHA1, object_type: ObjectType, data: Vec<u8>) -> Vec<u8> {
+    match object_type {
+        ObjectType::Tree => {
+            let tree = Tree::from_bytes(&data, *object).unwrap();
+            tree.tree_items
+                .iter()
+                .map(|item| ls_tree::format_item(item, Path::new(&item.name)) + "\n")
+                .collect::<String>()
+                .into_bytes()
+        }
+        _ => data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::add::AddArgs;
+    use crate::utils::{path, test};
+    use mercury::internal::index::Index;
+    use serial_test::serial;
+
+    #[test]
+    fn test_parse_args() {
+        let args = CatFileArgs::try_parse_from(["cat-file", "-p", "HEAD"]).unwrap();
+        assert!(args.pretty);
+        assert!(CatFileArgs::try_parse_from(["cat-file", "HEAD"]).is_err()); // mode is required
+        assert!(CatFileArgs::try_parse_from(["cat-file", "-t", "-s", "HEAD"]).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_pretty_print() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("content"));
+        crate::command::add::execute(AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+            force: false,
+        })
+        .await;
+        let storage = util::objects_storage();
+        let blob = Index::load(path::index()).unwrap().tracked_entries(0)[0].hash;
+        assert_eq!(storage.get_object_type(&blob).unwrap(), ObjectType::Blob);
+        let data = storage.get(&blob).unwrap();
+        assert_eq!(data.len(), 7);
+        assert_eq!(pretty_print(&blob, ObjectType::Blob, data), b"content");
+    }
+}
@@ -0,0 +1,147 @@
+use clap::Parser;
+use mercury::internal::index::Index;
+
+use crate::utils::ignore::{IgnoreRules, Rule};
+use crate::utils::{path, util};
+
+#[derive(Parser, Debug)]
+pub struct CheckIgnoreArgs {
+    /// The paths to check
+    #[clap(required = true)]
+    pub pathnames: Vec<String>,
+    /// Show the matching pattern & where it's from, including the negated patterns re-including a path
+    #[clap(short, long)]
+    pub verbose: bool,
+    /// Show the paths not matching any pattern as well, only with `--verbose`
+    #[clap(short, long, requires = "verbose")]
+    pub non_matching: bool,
+    /// Don't look in the index, so the tracked files are checked as well
+    #[clap(long)]
+    pub no_index: bool,
+}
+
+/// Print the ignored paths, or why they are ignored with `--verbose`, like `git check-ignore`
+pub async fn execute(args: CheckIgnoreArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let index = Index::load(path::index()).unwrap();
+    let mut rules = IgnoreRules::load().await;
+    for pathname in &args.pathnames {
+        match check(&mut rules, &index, pathname, &args) {
+            Ok(Some(line)) => println!("{}", line),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// The output line of `pathname`, `None` if nothing to show
+fn check(
+    rules: &mut IgnoreRules,
+    index: &Index,
+    pathname: &str,
+    args: &CheckIgnoreArgs,
+) -> Result<Option<String>, String> {
+    let path_abs = util::cur_dir().join(pathname);
+    let workdir = util::working_dir();
+    if !util::is_sub_path(&path_abs, &workdir) {
+        return Err(format!(
+            "{}: '{}' is outside repository at '{}'",
+            pathname,
+            pathname,
+            workdir.display()
+        ));
+    }
+    let path = util::to_workdir_path(&path_abs);
+    // the tracked files are not subject to the ignore rules
+    let tracked = !args.no_index && index.tracked(path.to_str().unwrap(), 0);
+    let is_dir = path_abs.is_dir() || pathname.ends_with('/');
+    let rule = if tracked {
+        None
+    } else {
+        rules.matched(&path, is_dir)
+    };
+    Ok(match rule {
+        Some(rule) if args.verbose => Some(format!(
+            "{}:{}:{}\t{}",
+            display_source(&rule),
+            rule.line,
+            rule.text,
+            pathname
+        )),
+        Some(rule) if !rule.pattern.negated => Some(pathname.to_string()),
+        None if args.non_matching => Some(format!("::\t{}", pathname)),
+        _ => None,
+    })
+}
+
+/// The ignore file relative to the current dir, or the absolute path if it's not in the worktree
+fn display_source(rule: &Rule) -> String {
+    let source = &rule.source;
+    if util::is_sub_path(source, util::working_dir()) {
+        util::to_relative(source, util::cur_dir())
+            .display()
+            .to_string()
+    } else {
+        source.display().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::utils::test;
+
+    #[test]
+    fn test_parse_args() {
+        assert!(CheckIgnoreArgs::try_parse_from(["check-ignore", "-v", "a"]).is_ok());
+        assert!(CheckIgnoreArgs::try_parse_from(["check-ignore", "-n", "a"]).is_err());
+        assert!(CheckIgnoreArgs::try_parse_from(["check-ignore"]).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_check_ignore() {
+        test::setup_with_new_libra().await;
+        test::ensure_file(".gitignore", Some("*.log\n!keep.log\n"));
+        test::ensure_file("tracked.log", None);
+        add::execute(AddArgs {
+            pathspec: vec!["tracked.log".to_string()],
+            all: false,
+            update: false,
+            verbose: false,
+            force: true,
+        })
+        .await;
+
+        let index = Index::load(path::index()).unwrap();
+        let mut rules = IgnoreRules::load().await;
+        let args = CheckIgnoreArgs::try_parse_from(["check-ignore", "x"]).unwrap();
+        let mut run = |pathname: &str, args: &CheckIgnoreArgs| {
+            check(&mut rules, &index, pathname, args).unwrap()
+        };
+        assert_eq!(run("a.log", &args).as_deref(), Some("a.log"));
+        assert_eq!(run("keep.log", &args), None);
+        assert_eq!(run("a.txt", &args), None);
+        assert_eq!(run("tracked.log", &args), None);
+
+        let args = CheckIgnoreArgs::try_parse_from(["check-ignore", "-v", "-n", "--no-index", "x"])
+            .unwrap();
+        assert_eq!(
+            run("keep.log", &args).as_deref(),
+            Some(".gitignore:2:!keep.log\tkeep.log")
+        );
+        assert_eq!(
+            run("tracked.log", &args).as_deref(),
+            Some(".gitignore:1:*.log\ttracked.log")
+        );
+        assert_eq!(run("a.txt", &args).as_deref(), Some("::\ta.txt"));
+    }
+}
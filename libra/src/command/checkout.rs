@@ -4,8 +4,8 @@ use mercury::hash::SHA1;
 use crate::{
     command::restore::{self, RestoreArgs},
     command::{branch, pull, switch},
-    internal::{branch::Branch, head::Head},
-    utils::util,
+    internal::{branch::Branch, head::Head, reflog::Reflog},
+    utils::{hooks, util, worktree},
 };
 
 #[derive(Parser, Debug)]
@@ -50,10 +50,13 @@ async fn show_current_branch() {
 async fn switch_branch(branch_name: &str) {
     let target_branch: Option<Branch> = Branch::find_branch(branch_name, None).await;
     let commit_id = target_branch.unwrap().commit;
+    let (from, old_head) = (Head::current().await, Head::current_commit().await);
     restore_to_commit(commit_id).await;
 
     let head = Head::Branch(branch_name.to_string());
-    Head::update(head, None).await;
+    Head::update(head.clone(), None).await;
+    Reflog::log_checkout(&from, old_head, &head, commit_id).await;
+    hooks::post_checkout(old_head, commit_id).await;
 }
 
 async fn create_and_switch_new_branch(new_branch: &str) {
@@ -93,6 +96,8 @@ async fn check_branch(branch_name: &str) -> Option<bool> {
             );
             None
         }
+    } else if !worktree::check_branch_unused(branch_name).await {
+        None
     } else {
         println!("Switched to branch '{branch_name}'");
         Some(false)
@@ -197,6 +202,8 @@ mod tests {
             message: "An empty initial commit".to_string(),
             allow_empty: true,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         commit::execute(commit_args).await;
 
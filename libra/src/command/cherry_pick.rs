@@ -0,0 +1,662 @@
+use std::fs;
+use std::str::FromStr;
+
+use clap::Parser;
+use common::utils::parse_commit_msg;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::Signature;
+
+use crate::internal::branch::Branch;
+use crate::internal::commit_graph::CommitGraph;
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::internal::revision::{self, RevRange};
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::rebase::commit_summary;
+use super::{
+    commit, log, merge,
+    restore::{self, RestoreArgs},
+    status,
+};
+
+#[derive(Parser, Debug)]
+pub struct CherryPickArgs {
+    /// Commits to cherry-pick, `A..B` means commits reachable from B but not from A
+    #[clap(required_unless_present_any(["continue_pick", "abort", "skip"]))]
+    pub commits: Vec<String>,
+
+    /// Append a line "(cherry picked from commit ...)" to the original commit message
+    #[clap(short = 'x')]
+    pub record_origin: bool,
+
+    /// Apply the changes to the index & worktree without making commits
+    #[clap(long, short = 'n')]
+    pub no_commit: bool,
+
+    /// Continue the operation in progress after having resolved conflicts
+    #[clap(long = "continue", group = "action", conflicts_with("commits"))]
+    pub continue_pick: bool,
+
+    /// Cancel the operation and return to the pre-sequence state
+    #[clap(long, group = "action", conflicts_with("commits"))]
+    pub abort: bool,
+
+    /// Skip the current commit and continue with the rest of the sequence
+    #[clap(long, group = "action", conflicts_with("commits"))]
+    pub skip: bool,
+}
+
+pub async fn execute(args: CherryPickArgs) {
+    let options = SequenceOptions {
+        action: Action::Pick,
+        record_origin: args.record_origin,
+        no_commit: args.no_commit,
+    };
+    if args.continue_pick {
+        sequence_continue(Action::Pick).await;
+    } else if args.abort {
+        sequence_abort(Action::Pick).await;
+    } else if args.skip {
+        sequence_skip(Action::Pick).await;
+    } else {
+        sequence_start(&args.commits, options).await;
+    }
+}
+
+/// `cherry-pick` applies the changes of commits, `revert` applies the inverse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Pick,
+    Revert,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Pick => "cherry-pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "cherry-pick" => Some(Action::Pick),
+            "revert" => Some(Action::Revert),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SequenceOptions {
+    pub action: Action,
+    /// append "(cherry picked from commit ...)", only for `cherry-pick`
+    pub record_origin: bool,
+    pub no_commit: bool,
+}
+
+/// The state of an in-progress cherry-pick or revert, persisted in `.libra/sequencer`
+#[derive(Debug)]
+struct Sequencer {
+    action: Action,
+    record_origin: bool,
+    /// HEAD before the sequence started, used by `--abort`
+    orig_head: SHA1,
+    /// commits waiting to be applied, in order
+    todo: Vec<SHA1>,
+    /// the commit which stopped the sequence because of conflicts
+    stopped: Option<SHA1>,
+}
+
+impl Sequencer {
+    fn load() -> Option<Self> {
+        let dir = path::sequencer();
+        if !dir.exists() {
+            return None;
+        }
+        let read = |name: &str| {
+            fs::read_to_string(dir.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let parse = |hash: &str| SHA1::from_str(hash).expect("fatal: sequencer state is broken");
+        let opts = read("opts").unwrap_or_default();
+        Some(Sequencer {
+            action: Action::from_name(&read("action")?)?,
+            record_origin: opts.lines().any(|opt| opt == "record-origin"),
+            orig_head: parse(&read("head")?),
+            todo: read("todo")
+                .unwrap_or_default()
+                .lines()
+                .map(parse)
+                .collect(),
+            stopped: read("stopped-sha").map(|hash| parse(&hash)),
+        })
+    }
+
+    fn save(&self) {
+        let dir = path::sequencer();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("action"), self.action.name()).unwrap();
+        let opts = if self.record_origin {
+            "record-origin"
+        } else {
+            ""
+        };
+        fs::write(dir.join("opts"), opts).unwrap();
+        fs::write(dir.join("head"), self.orig_head.to_string()).unwrap();
+        let todo: Vec<String> = self.todo.iter().map(|id| id.to_string()).collect();
+        fs::write(dir.join("todo"), todo.join("\n")).unwrap();
+        match self.stopped {
+            Some(id) => fs::write(dir.join("stopped-sha"), id.to_string()).unwrap(),
+            None => {
+                let _ = fs::remove_file(dir.join("stopped-sha"));
+            }
+        }
+    }
+
+    fn remove() {
+        fs::remove_dir_all(path::sequencer()).unwrap();
+    }
+}
+
+/// The action of the cherry-pick or revert in progress, `None` if there is none
+pub fn sequence_in_progress() -> Option<Action> {
+    Sequencer::load().map(|sequencer| sequencer.action)
+}
+
+/// Start a new cherry-pick or revert of `revs`, shared by `cherry-pick` & `revert` commands
+pub async fn sequence_start(revs: &[String], options: SequenceOptions) {
+    let action = options.action;
+    if let Some(sequencer) = Sequencer::load() {
+        eprintln!(
+            "error: a {} is already in progress",
+            sequencer.action.name()
+        );
+        eprintln!(
+            "hint: try \"libra {} (--continue | --abort | --skip)\"",
+            sequencer.action.name()
+        );
+        return;
+    }
+    if path::merge_head().exists() || path::rebase_merge().exists() {
+        eprintln!("fatal: a merge or rebase is in progress, conclude it first");
+        return;
+    }
+    if !status::is_clean_tracked().await {
+        eprintln!(
+            "error: your local changes would be overwritten by {}.",
+            action.name()
+        );
+        eprintln!("hint: commit your changes or stash them to proceed.");
+        return;
+    }
+    let orig_head = match Head::current_commit().await {
+        Some(commit) => commit,
+        None => {
+            eprintln!(
+                "fatal: no commits yet, can't {} on an unborn branch",
+                action.name()
+            );
+            return;
+        }
+    };
+
+    let mut todo = Vec::new();
+    for rev in revs {
+        match resolve_commits(rev, action).await {
+            Ok(commits) => todo.extend(commits),
+            Err(e) => {
+                eprintln!("fatal: bad revision '{}': {}", rev, e);
+                return;
+            }
+        }
+    }
+    if todo.is_empty() {
+        eprintln!("error: empty commit set passed");
+        return;
+    }
+    for commit_id in &todo {
+        if let Err(e) = check_applicable(action, &Commit::load(commit_id)) {
+            eprintln!("error: {}", e);
+            eprintln!("fatal: {} failed", action.name());
+            return;
+        }
+    }
+
+    if options.no_commit {
+        // only update index & worktree, no state to continue
+        if todo.len() > 1 {
+            // the merge base is HEAD, changes of previous commits in index would be lost
+            eprintln!("error: --no-commit can only apply one commit at a time");
+            return;
+        }
+        let _ = apply_commit(action, &todo[0]).await;
+        return;
+    }
+
+    let sequencer = Sequencer {
+        action,
+        record_origin: options.record_origin,
+        orig_head,
+        todo,
+        stopped: None,
+    };
+    sequencer.save();
+    run(sequencer).await;
+}
+
+pub async fn sequence_continue(action: Action) {
+    let mut sequencer = match load_sequencer(action) {
+        Some(sequencer) => sequencer,
+        None => return,
+    };
+    if Index::load(path::index()).unwrap().has_conflicts() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        return;
+    }
+    let unstaged = status::changes_to_be_staged().await;
+    if !unstaged.modified.is_empty() || !unstaged.deleted.is_empty() {
+        eprintln!("error: cannot continue: You have unstaged changes.");
+        eprintln!("hint: Use \"libra add <file>\" to stage the resolved files.");
+        return;
+    }
+    if let Some(stopped) = sequencer.stopped.take() {
+        commit_applied(&sequencer, &Commit::load(&stopped)).await;
+    }
+    run(sequencer).await;
+}
+
+pub async fn sequence_skip(action: Action) {
+    let mut sequencer = match load_sequencer(action) {
+        Some(sequencer) => sequencer,
+        None => return,
+    };
+    merge::reset_conflicts().await;
+    sequencer.stopped = None;
+    run(sequencer).await;
+}
+
+pub async fn sequence_abort(action: Action) {
+    let sequencer = match load_sequencer(action) {
+        Some(sequencer) => sequencer,
+        None => return,
+    };
+    merge::reset_conflicts().await;
+    let old_head = Head::current_commit().await;
+    // commits are made on the current branch directly, move it back
+    match Head::current().await {
+        Head::Branch(name) => {
+            Branch::update_branch(&name, &sequencer.orig_head.to_string(), None).await
+        }
+        Head::Detached(_) => Head::update(Head::Detached(sequencer.orig_head), None).await,
+    }
+    let message = format!("{}: abort", action.name());
+    Reflog::log_head_update(old_head, sequencer.orig_head, &message).await;
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: Some(sequencer.orig_head.to_string()),
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+    Sequencer::remove();
+}
+
+/// Load the state in progress, which must be started by the same `action`
+fn load_sequencer(action: Action) -> Option<Sequencer> {
+    match Sequencer::load() {
+        Some(sequencer) if sequencer.action == action => Some(sequencer),
+        Some(sequencer) => {
+            eprintln!(
+                "error: a {} is in progress, not a {}",
+                sequencer.action.name(),
+                action.name()
+            );
+            None
+        }
+        None => {
+            eprintln!("error: no {} in progress", action.name());
+            None
+        }
+    }
+}
+
+/// Apply & commit the commits in `todo` one by one, stop and save the state if conflicts occur
+async fn run(mut sequencer: Sequencer) {
+    while !sequencer.todo.is_empty() {
+        let commit_id = sequencer.todo.remove(0);
+        match apply_commit(sequencer.action, &commit_id).await {
+            Ok(()) => {}
+            Err(ApplyError::Conflict) => {
+                sequencer.stopped = Some(commit_id);
+                sequencer.save();
+                return;
+            }
+            Err(ApplyError::Aborted) => {
+                sequencer.todo.insert(0, commit_id);
+                sequencer.save();
+                return;
+            }
+            Err(ApplyError::Fatal) => {
+                // nothing to resolve or continue
+                Sequencer::remove();
+                return;
+            }
+        }
+        commit_applied(&sequencer, &Commit::load(&commit_id)).await;
+        sequencer.save();
+    }
+    Sequencer::remove();
+}
+
+/// Resolve `rev` to commits in the order to be applied.
+/// `A..B` means commits reachable from `B` but not from `A`, merge commits are excluded.
+async fn resolve_commits(
+    rev: &str,
+    action: Action,
+) -> Result<Vec<SHA1>, Box<dyn std::error::Error>> {
+    let range = match revision::resolve_range(rev).await? {
+        RevRange::Single(commit) => return Ok(vec![commit]),
+        range => range,
+    };
+    let mut graph = CommitGraph::open();
+    let commits: Vec<SHA1> = log::get_range_ids(&mut graph, range)
+        .into_iter()
+        .filter(|id| graph.get(id).parents.len() <= 1)
+        .collect();
+    let mut commits = graph.topo_order(&commits);
+    if action == Action::Revert {
+        // revert the newest commit first
+        commits.reverse();
+    }
+    Ok(commits)
+}
+
+/// Why a commit is not applied
+#[derive(Debug, PartialEq)]
+enum ApplyError {
+    /// stopped with conflicts in the index & worktree, to be resolved & continued
+    Conflict,
+    /// stopped before any change, e.g. untracked files would be overwritten, to be applied again by `--continue`
+    Aborted,
+    /// the commit can't be applied at all, nothing is changed
+    Fatal,
+}
+
+/// Check if the (inverse) changes of `commit` can be applied:
+/// a merge commit has no single parent to diff against, and a root commit has no parent to revert to
+fn check_applicable(action: Action, commit: &Commit) -> Result<(), String> {
+    if commit.parent_commit_ids.len() > 1 {
+        // TODO support `--mainline` to choose the parent
+        return Err(format!(
+            "commit {} is a merge but no -m option was given.",
+            commit.id
+        ));
+    }
+    if action == Action::Revert && commit.parent_commit_ids.is_empty() {
+        return Err(format!("can't revert root commit {}", commit.id));
+    }
+    Ok(())
+}
+
+/// Three-way merge the changes (or inverse changes) of `commit_id` into HEAD, index & worktree.
+/// Return `Err` if it can't be applied cleanly, hints are printed.
+async fn apply_commit(action: Action, commit_id: &SHA1) -> Result<(), ApplyError> {
+    let commit = Commit::load(commit_id);
+    let (short_id, subject) = commit_summary(&commit);
+    if let Err(e) = check_applicable(action, &commit) {
+        eprintln!("fatal: {}", e);
+        return Err(ApplyError::Fatal);
+    }
+    let parent_tree = commit
+        .parent_commit_ids
+        .first()
+        .map(|parent| Commit::load(parent).tree_id);
+    let head_tree = Commit::load(&Head::current_commit().await.unwrap()).tree_id;
+
+    let merged = match action {
+        Action::Pick => {
+            merge::merge_into_worktree(
+                parent_tree,
+                head_tree,
+                commit.tree_id,
+                "HEAD",
+                &format!("{}... {}", short_id, subject),
+            )
+            .await
+        }
+        Action::Revert => {
+            merge::merge_into_worktree(
+                Some(commit.tree_id),
+                head_tree,
+                parent_tree.unwrap(), // checked above
+                "HEAD",
+                &format!("parent of {}... {}", short_id, subject),
+            )
+            .await
+        }
+    };
+    let name = action.name();
+    let Some(conflicts) = merged else {
+        eprintln!("error: could not {} {}... {}", name, short_id, subject);
+        eprintln!(
+            "hint: Move or remove the files, then run \"libra {} --continue\".",
+            name
+        );
+        return Err(ApplyError::Aborted);
+    };
+    if !conflicts.is_empty() {
+        eprintln!("error: could not {} {}... {}", name, short_id, subject);
+        eprintln!("hint: After resolving the conflicts, mark them with");
+        eprintln!(
+            "hint: \"libra add <paths>\", then run \"libra {} --continue\".",
+            name
+        );
+        eprintln!(
+            "hint: You can instead skip this commit with \"libra {} --skip\".",
+            name
+        );
+        eprintln!(
+            "hint: To abort and get back to the state before \"libra {}\",",
+            name
+        );
+        eprintln!("hint: run \"libra {} --abort\".", name);
+        return Err(ApplyError::Conflict);
+    }
+    Ok(())
+}
+
+/// Commit the index for the applied `commit`, skip if nothing changed
+async fn commit_applied(sequencer: &Sequencer, commit: &Commit) {
+    let (short_id, subject) = commit_summary(commit);
+    if status::changes_to_be_committed().await.is_empty() {
+        println!(
+            "skipping {}... {} -- the result is empty",
+            short_id, subject
+        );
+        return;
+    }
+    let (message, author) = applied_message(sequencer, commit);
+    let index = Index::load(path::index()).unwrap();
+    let storage = util::objects_storage();
+    let parents = vec![Head::current_commit().await.unwrap()];
+    commit::commit_index(&index, &storage, &message, parents, author, false).await;
+}
+
+/// The message & author of the new commit, the author is kept for `cherry-pick`
+fn applied_message(sequencer: &Sequencer, commit: &Commit) -> (String, Option<Signature>) {
+    match sequencer.action {
+        Action::Pick => {
+            let mut message = parse_commit_msg(&commit.message).0.trim_end().to_string();
+            if sequencer.record_origin {
+                message.push_str(&format!("\n\n(cherry picked from commit {})", commit.id));
+            }
+            (message, Some(commit.author.clone()))
+        }
+        Action::Revert => {
+            let (_, subject) = commit_summary(commit);
+            let message = format!(
+                "Revert \"{}\"\n\nThis reverts commit {}.",
+                subject, commit.id
+            );
+            (message, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::branch;
+    use crate::utils::test;
+    use serial_test::serial;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_args() {
+        let args = CherryPickArgs::try_parse_from(["cherry-pick", "-x", "a1b2c3", "main"]).unwrap();
+        assert!(args.record_origin);
+        assert_eq!(args.commits, vec!["a1b2c3", "main"]);
+        assert!(CherryPickArgs::try_parse_from(["cherry-pick", "master..feature"]).is_ok());
+        assert!(CherryPickArgs::try_parse_from(["cherry-pick", "--continue"]).is_ok());
+        assert!(CherryPickArgs::try_parse_from(["cherry-pick"]).is_err());
+        assert!(CherryPickArgs::try_parse_from(["cherry-pick", "--abort", "--skip"]).is_err());
+    }
+
+    /// `master`: base -> master, `feature`: base -> fix a -> add b, HEAD at `master`
+    async fn setup_branches(master_a: &str) {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\nb\nc\n"));
+        test::commit_all("base").await;
+        branch::create_branch("feature".to_string(), None).await;
+        test::ensure_file("a.txt", Some(master_a));
+        test::commit_all("master").await;
+
+        test::switch_to("feature").await;
+        test::ensure_file("a.txt", Some("a\nb\nfix\n"));
+        test::commit_all("fix a").await;
+        test::ensure_file("b.txt", Some("b\n"));
+        test::commit_all("add b").await;
+        test::switch_to("master").await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_cherry_pick_range() {
+        setup_branches("master\nb\nc\n").await;
+        let master = Head::current_commit().await.unwrap();
+        let feature = Branch::find_branch("feature", None).await.unwrap().commit;
+        let fix = Commit::load(&feature).parent_commit_ids[0];
+
+        execute(CherryPickArgs::parse_from([
+            "cherry-pick",
+            "-x",
+            "master..feature",
+        ]))
+        .await;
+
+        assert!(!path::sequencer().exists());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "master\nb\nfix\n");
+        assert!(PathBuf::from("b.txt").exists());
+        let add_b = Commit::load(&Head::current_commit().await.unwrap());
+        let fix_a = Commit::load(&add_b.parent_commit_ids[0]);
+        assert_eq!(fix_a.parent_commit_ids, vec![master]);
+        assert_eq!(
+            parse_commit_msg(&fix_a.message).0,
+            format!("fix a\n\n(cherry picked from commit {})", fix)
+        );
+        assert_eq!(fix_a.author, Commit::load(&fix).author);
+        assert!(status::is_clean().await);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_cherry_pick_conflict() {
+        setup_branches("a\nb\nmaster\n").await;
+        let master = Head::current_commit().await.unwrap();
+
+        execute(CherryPickArgs::parse_from([
+            "cherry-pick",
+            "master..feature",
+        ]))
+        .await;
+        let sequencer = Sequencer::load().unwrap();
+        assert!(sequencer.stopped.is_some());
+        assert_eq!(sequencer.todo.len(), 1);
+
+        execute(CherryPickArgs::parse_from(["cherry-pick", "--abort"])).await;
+        assert!(!path::sequencer().exists());
+        assert_eq!(Head::current_commit().await.unwrap(), master);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\nb\nmaster\n");
+
+        execute(CherryPickArgs::parse_from([
+            "cherry-pick",
+            "master..feature",
+        ]))
+        .await;
+        test::ensure_file("a.txt", Some("a\nb\nresolved\n"));
+        test::add_all().await;
+        execute(CherryPickArgs::parse_from(["cherry-pick", "--continue"])).await;
+        assert!(!path::sequencer().exists());
+        assert!(PathBuf::from("b.txt").exists());
+        let head = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(parse_commit_msg(&head.message).0, "add b");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_revert() {
+        setup_branches("master\nb\nc\n").await;
+        test::ensure_file("c.txt", Some("c\n"));
+        test::commit_all("add c").await;
+        let add_c = Head::current_commit().await.unwrap();
+
+        sequence_start(
+            &["HEAD".to_string()],
+            SequenceOptions {
+                action: Action::Revert,
+                record_origin: false,
+                no_commit: false,
+            },
+        )
+        .await;
+
+        assert!(!PathBuf::from("c.txt").exists());
+        let head = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(head.parent_commit_ids, vec![add_c]);
+        assert_eq!(
+            parse_commit_msg(&head.message).0,
+            format!("Revert \"add c\"\n\nThis reverts commit {}.", add_c)
+        );
+        assert!(status::is_clean().await);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_unapplicable_commits() {
+        setup_branches("master\nb\nc\n").await;
+        merge::execute(merge::MergeArgs::parse_from(["merge", "feature"])).await;
+        let merged = Head::current_commit().await.unwrap();
+        assert_eq!(Commit::load(&merged).parent_commit_ids.len(), 2);
+
+        // rejected up front, no sequence to continue
+        execute(CherryPickArgs::parse_from(["cherry-pick", "HEAD"])).await;
+        assert!(!path::sequencer().exists());
+        let root = revision::resolve_commit("HEAD~2").await.unwrap();
+        assert!(Commit::load(&root).parent_commit_ids.is_empty());
+        let options = SequenceOptions {
+            action: Action::Revert,
+            record_origin: false,
+            no_commit: false,
+        };
+        sequence_start(&[root.to_string()], options).await;
+        assert!(!path::sequencer().exists());
+        assert_eq!(Head::current_commit().await.unwrap(), merged);
+    }
+}
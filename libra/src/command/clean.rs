@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use mercury::internal::index::Index;
+
+use crate::internal::config::Config;
+use crate::utils::ignore::IgnoreRules;
+use crate::utils::{path, util};
+
+#[derive(Parser, Debug)]
+pub struct CleanArgs {
+    /// Do not remove anything, just show what would be removed
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+    /// Required to remove files, unless `clean.requireForce` is `false`
+    #[clap(short, long)]
+    pub force: bool,
+    /// Remove the untracked directories too, the nested repositories are kept
+    #[clap(short = 'd')]
+    pub dirs: bool,
+    /// Remove the ignored files too
+    #[clap(short = 'x', conflicts_with = "only_ignored")]
+    pub ignored: bool,
+    /// Remove only the ignored files, e.g. to rebuild from scratch but keep the new files
+    #[clap(short = 'X')]
+    pub only_ignored: bool,
+}
+
+/// Remove the untracked files in the current directory, by the ignore rules like `git clean`:
+/// - the ignored files are kept, unless `-x` or `-X`
+/// - the untracked directories are kept, unless `-d`
+pub async fn execute(args: CleanArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let require_force =
+        Config::get("clean", None, "requireForce").await.as_deref() != Some("false");
+    if require_force && !args.force && !args.dry_run {
+        eprintln!("fatal: clean.requireForce defaults to true and neither -n nor -f given; refusing to clean");
+        return;
+    }
+
+    let index = Index::load(path::index()).unwrap();
+    let mut tracked_dirs = HashSet::new();
+    let mut tracked_files = HashSet::new();
+    for file in index
+        .tracked_files()
+        .into_iter()
+        .chain(index.unmerged_files())
+    {
+        tracked_dirs.extend(file.ancestors().skip(1).map(Path::to_path_buf));
+        tracked_files.insert(file);
+    }
+    let mut cleaner = Cleaner {
+        args: &args,
+        rules: IgnoreRules::load().await,
+        tracked_dirs,
+        tracked_files,
+    };
+    let (paths, _) = match cleaner.walk(&util::cur_dir()) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+
+    for path in paths {
+        let abs = util::workdir_to_absolute(&path);
+        let mut display = util::workdir_to_current(&path).display().to_string();
+        if abs.is_dir() {
+            display.push('/');
+        }
+        if args.dry_run {
+            println!("Would remove {}", display);
+            continue;
+        }
+        let removed = if abs.is_dir() {
+            fs::remove_dir_all(&abs)
+        } else {
+            fs::remove_file(&abs)
+        };
+        match removed {
+            Ok(()) => println!("Removing {}", display),
+            Err(e) => eprintln!("warning: failed to remove {}: {}", display, e),
+        }
+    }
+}
+
+struct Cleaner<'a> {
+    args: &'a CleanArgs,
+    rules: IgnoreRules,
+    /// The directories containing tracked files, in workdir paths
+    tracked_dirs: HashSet<PathBuf>,
+    tracked_files: HashSet<PathBuf>,
+}
+
+impl Cleaner<'_> {
+    /// If an untracked path is to be removed, by whether it's ignored
+    fn selected(&self, ignored: bool) -> bool {
+        if self.args.only_ignored {
+            ignored
+        } else {
+            self.args.ignored || !ignored
+        }
+    }
+
+    /// The workdir paths to remove in `dir`, and whether all of its entries are removed
+    fn walk(&mut self, dir: &Path) -> std::io::Result<(Vec<PathBuf>, bool)> {
+        let mut paths = Vec::new();
+        let mut all = true;
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for abs in entries {
+            if abs.file_name().unwrap_or_default() == util::ROOT_DIR {
+                all = false;
+                continue;
+            }
+            let path = util::to_workdir_path(&abs);
+            let is_dir = abs.is_dir();
+            if self.tracked_files.contains(&path) {
+                all = false;
+                continue;
+            }
+            if is_dir && self.tracked_dirs.contains(&path) {
+                let (sub_paths, _) = self.walk(&abs)?;
+                paths.extend(sub_paths);
+                all = false;
+                continue;
+            }
+
+            let ignored = self.rules.is_ignored(&path, is_dir);
+            if !is_dir {
+                if self.selected(ignored) {
+                    paths.push(path);
+                } else {
+                    all = false;
+                }
+                continue;
+            }
+            // an untracked directory
+            if !self.args.dirs || util::is_nested_repo(&abs) {
+                all = false;
+            } else if ignored || self.args.ignored {
+                // all files in it are ignored, or all are selected
+                if self.selected(ignored) {
+                    paths.push(path);
+                } else {
+                    all = false;
+                }
+            } else {
+                // it may have ignored files to keep, or to remove with `-X`
+                let (sub_paths, sub_all) = self.walk(&abs)?;
+                if sub_all && !self.args.only_ignored {
+                    paths.push(path);
+                } else {
+                    paths.extend(sub_paths);
+                    all = false;
+                }
+            }
+        }
+        Ok((paths, all))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    fn args(force: bool, dirs: bool, ignored: bool, only_ignored: bool) -> CleanArgs {
+        CleanArgs {
+            dry_run: false,
+            force,
+            dirs,
+            ignored,
+            only_ignored,
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_clean() {
+        test::setup_with_new_libra().await;
+        test::ensure_file(".gitignore", Some("*.log\nbuild/\n"));
+        test::ensure_file("a.txt", Some("a"));
+        test::commit_all("init").await;
+        for file in ["b.txt", "c.log", "new/d.txt", "new/e.log", "build/f.o"] {
+            test::ensure_file(file, Some(file));
+        }
+        let exists = |file: &str| util::working_dir().join(file).exists();
+
+        // refused without -f
+        execute(args(false, false, false, false)).await;
+        assert!(exists("b.txt"));
+        // the untracked files, except the ignored ones & the directories
+        execute(args(true, false, false, false)).await;
+        assert!(!exists("b.txt"));
+        assert!(exists("a.txt") && exists("c.log") && exists("new/d.txt"));
+        // the ignored files are kept in the untracked directories
+        execute(args(true, true, false, false)).await;
+        assert!(!exists("new/d.txt"));
+        assert!(exists("new/e.log") && exists("build/f.o"));
+        // only the ignored files
+        execute(args(true, true, false, true)).await;
+        assert!(!exists("c.log") && !exists("new/e.log") && !exists("build"));
+        assert!(exists("new") && exists(".gitignore") && exists("a.txt"));
+        // everything untracked
+        execute(args(true, true, true, false)).await;
+        assert!(!exists("new"));
+        assert!(exists(".gitignore") && exists("a.txt"));
+    }
+}
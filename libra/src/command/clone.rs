@@ -1,10 +1,13 @@
 use crate::command::restore::RestoreArgs;
+use crate::command::submodule::SubmoduleCmds;
 use crate::command::{self, branch};
 use crate::internal::branch::Branch;
 use crate::internal::config::{Config, RemoteConfig};
 use crate::internal::head::Head;
+use crate::internal::protocol;
 use crate::utils::path_ext::PathExt;
-use crate::utils::util;
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::{hooks, util};
 use clap::Parser;
 use colored::Colorize;
 use scopeguard::defer;
@@ -12,13 +15,13 @@ use std::cell::Cell;
 use std::path::PathBuf;
 use std::{env, fs};
 
-use super::fetch::{self};
+use super::fetch::{self, FetchOptions};
 
 const ORIGIN: &str = "origin"; // default remote name, prevent spelling mistakes
 
 #[derive(Parser, Debug)]
 pub struct CloneArgs {
-    /// The remote repository location to clone from, usually a URL with HTTPS or SSH
+    /// The remote repository location to clone from, usually a URL with HTTPS or SSH, or a local path
     pub remote_repo: String,
 
     /// The local path to clone the repository to
@@ -27,6 +30,26 @@ pub struct CloneArgs {
     /// The branch to clone
     #[clap(short = 'b', long, required = false)]
     pub branch: Option<String>,
+
+    /// When cloning a local repository, hard link its object files instead of copying the objects
+    #[clap(long)]
+    pub hardlinks: bool,
+
+    /// Create a shallow clone with a history truncated to the specified number of commits
+    #[clap(long)]
+    pub depth: Option<usize>,
+
+    /// Partial clone, omit objects by the filter-spec, e.g. `blob:none`, they are fetched on demand
+    #[clap(long)]
+    pub filter: Option<String>,
+
+    /// Initialize the sparse checkout, only the files at the root are checked out
+    #[clap(long)]
+    pub sparse: bool,
+
+    /// Initialize & clone the submodules after the clone, recursively
+    #[clap(long)]
+    pub recurse_submodules: bool,
 }
 
 pub async fn execute(args: CloneArgs) {
@@ -35,6 +58,15 @@ pub async fn execute(args: CloneArgs) {
     if !remote_repo.ends_with('/') {
         remote_repo.push('/');
     }
+    // a local path is relative to the current directory, which will be changed to the new repo
+    match protocol::parse_remote_url(&remote_repo) {
+        Ok(url) if url.scheme() == "file" => remote_repo = url.to_string(),
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    }
     let local_path = args.local_path.unwrap_or_else(|| {
         let repo_name = util::get_repo_name_from_url(&remote_repo).unwrap();
         util::cur_dir().join(repo_name).to_string_or_panic()
@@ -90,21 +122,41 @@ pub async fn execute(args: CloneArgs) {
         quiet: false,
     };
     command::init::execute(init_args).await;
+    if args.sparse {
+        // more directories can be checked out by `sparse-checkout set/add` later
+        SparseCheckout::new(true, &[]).save().await;
+    }
 
     /* fetch remote */
     let remote_config = RemoteConfig {
         name: "origin".to_string(),
         url: remote_repo.clone(),
     };
-    fetch::fetch_repository(&remote_config, args.branch.clone()).await;
+    let options = FetchOptions {
+        hardlinks: args.hardlinks,
+        depth: args.depth,
+        filter: args.filter,
+    };
+    fetch::fetch_repository(&remote_config, args.branch.clone(), &options).await;
 
     /* setup */
     setup(remote_repo.clone(), args.branch.clone()).await;
+    if args.recurse_submodules {
+        command::submodule::execute(SubmoduleCmds::Update {
+            init: true,
+            recursive: true,
+            paths: vec![],
+        })
+        .await;
+    }
 
     is_success.set(true);
 }
 
 async fn setup(remote_repo: String, specified_branch: Option<String>) {
+    // set config: remote.origin.url, also used to fetch the missing objects of a partial clone
+    Config::insert("remote", Some(ORIGIN), "url", &remote_repo).await;
+
     // look for remote head and set local HEAD&branch
     let remote_head = Head::remote_current(ORIGIN).await;
 
@@ -117,8 +169,6 @@ async fn setup(remote_repo: String, specified_branch: Option<String>) {
     } else {
         println!("warning: You appear to have cloned an empty repository.");
 
-        // set config: remote.origin.url
-        Config::insert("remote", Some(ORIGIN), "url", &remote_repo).await;
         // set config: remote.origin.fetch
         // todo: temporary ignore fetch option
 
@@ -149,6 +199,7 @@ async fn setup_branch(branch_name: String) {
         pathspec: vec![util::working_dir_string()],
     })
     .await;
+    hooks::post_checkout(None, origin_head_branch.commit).await;
 }
 
 /// Unit tests for the clone module
@@ -171,6 +222,11 @@ mod tests {
             remote_repo: remote_url,
             local_path: Some(local_repo.clone()),
             branch: Some("dev".to_string()),
+            hardlinks: false,
+            depth: None,
+            filter: None,
+            sparse: false,
+            recurse_submodules: false,
         })
         .await;
 
@@ -199,6 +255,11 @@ mod tests {
             remote_repo: remote_url,
             local_path: Some(local_repo.clone()),
             branch: None,
+            hardlinks: false,
+            depth: None,
+            filter: None,
+            sparse: false,
+            recurse_submodules: false,
         })
         .await;
 
@@ -227,6 +288,11 @@ mod tests {
             remote_repo: remote_url,
             local_path: Some(local_repo.clone()),
             branch: None,
+            hardlinks: false,
+            depth: None,
+            filter: None,
+            sparse: false,
+            recurse_submodules: false,
         })
         .await;
 
@@ -1,9 +1,15 @@
+use std::fs;
 use std::str::FromStr;
 use std::{collections::HashSet, path::PathBuf};
 
 use crate::internal::branch::Branch;
+use crate::internal::commit_graph;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::internal::signing;
 use crate::utils::client_storage::ClientStorage;
+use crate::utils::hooks;
 use crate::utils::path;
 use crate::utils::util;
 use clap::Parser;
@@ -11,10 +17,11 @@ use common::utils::{check_conventional_commits_message, format_commit_msg};
 use mercury::hash::SHA1;
 use mercury::internal::index::Index;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::signature::{Signature, SignatureType};
 use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
 use mercury::internal::object::ObjectTrait;
 
-use super::save_object;
+use super::{merge, save_object};
 
 #[derive(Parser, Debug)]
 pub struct CommitArgs {
@@ -28,44 +35,177 @@ pub struct CommitArgs {
     /// check if commit message follows conventional commits
     #[arg(long, requires("message"))]
     pub conventional: bool,
+
+    /// PGP-sign the commit, with the key of `user.signingkey` or in vault
+    #[arg(short = 'S', long)]
+    pub gpg_sign: bool,
+
+    /// bypass the pre-commit and commit-msg hooks
+    #[arg(short = 'n', long)]
+    pub no_verify: bool,
 }
 
 pub async fn execute(args: CommitArgs) {
     /* check args */
     let index = Index::load(path::index()).unwrap();
     let storage = ClientStorage::init(path::objects());
+    if index.has_conflicts() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        return;
+    }
+    if !args.no_verify {
+        if let Err(e) = hooks::run(hooks::PRE_COMMIT, &[], None).await {
+            eprintln!("fatal: {}, the commit is aborted", e);
+            return;
+        }
+    }
+    // `pre-commit` may update the index, e.g. by a formatter
+    let index = Index::load(path::index()).unwrap();
     let tracked_entries = index.tracked_entries(0);
     if tracked_entries.is_empty() && !args.allow_empty {
         panic!("fatal: no changes added to commit, use --allow-empty to override");
     }
-    if args.conventional && !check_conventional_commits_message(&args.message) {
+    let message = match run_message_hooks(&args.message, args.no_verify).await {
+        Ok(message) => message,
+        Err(e) => {
+            eprintln!("fatal: {}, the commit is aborted", e);
+            return;
+        }
+    };
+    if args.conventional && !check_conventional_commits_message(&message) {
         panic!("fatal: commit message does not follow conventional commits");
     }
 
-    /* Create tree */
-    let tree = create_tree(&index, &storage, "".into()).await;
-
     /* Create & save commit objects */
     let parents_commit_ids = get_parents_ids().await;
+    // `commit.gpgsign` signs all commits, like git
+    let sign =
+        args.gpg_sign || Config::get("commit", None, "gpgsign").await.as_deref() == Some("true");
+    if commit_index(&index, &storage, &message, parents_commit_ids, None, sign)
+        .await
+        .is_none()
+    {
+        return;
+    }
+
+    // the merge is concluded
+    merge::clear_merge_state();
+    let _ = hooks::run(hooks::POST_COMMIT, &[], None).await;
+}
+
+/// Let `prepare-commit-msg` & `commit-msg` edit the message in `.libra/COMMIT_EDITMSG`, or reject it
+/// - the message is kept as is if no hook edits it, otherwise the message read back is cleaned up:
+///   trailing whitespaces & redundant empty lines are removed
+async fn run_message_hooks(message: &str, no_verify: bool) -> Result<String, String> {
+    let file = path::commit_editmsg();
+    let content = format!("{}\n", message);
+    fs::write(&file, &content).map_err(|e| e.to_string())?;
+    let file_str = file.to_str().unwrap();
+    // the source of the message, `merge` when concluding a merge like git
+    let source = if path::merge_head().exists() {
+        "merge"
+    } else {
+        "message"
+    };
+    hooks::run(hooks::PREPARE_COMMIT_MSG, &[file_str, source], None).await?;
+    if !no_verify {
+        hooks::run(hooks::COMMIT_MSG, &[file_str], None).await?;
+    }
+    let edited = fs::read_to_string(&file).map_err(|e| e.to_string())?;
+    if edited == content {
+        return Ok(message.to_string());
+    }
+    let message = cleanup_message(&edited);
+    if message.is_empty() {
+        return Err("empty commit message".to_string());
+    }
+    Ok(message)
+}
+
+/// Remove the trailing whitespaces, the leading & trailing empty lines and the consecutive ones, like git
+fn cleanup_message(message: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines().map(str::trim_end) {
+        if line.is_empty() && matches!(lines.last(), None | Some(&"")) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// Create a commit of `index` with `parents`, save it and update HEAD to it.
+/// - `message`: plain message without signature
+/// - `author`: keep the original author when replaying commits (e.g. `rebase`), default is the committer
+/// - `sign`: add a PGP signature in the `gpgsig` header, see [signing]
+///
+/// Return `None` if the commit can't be signed, nothing is changed then.
+pub async fn commit_index(
+    index: &Index,
+    storage: &ClientStorage,
+    message: &str,
+    parents: Vec<SHA1>,
+    author: Option<Signature>,
+    sign: bool,
+) -> Option<Commit> {
+    /* Create tree */
+    let tree = create_tree(index, storage, "".into()).await;
+
     // There must be a `blank line`(\n) before `message`, or remote unpack failed
-    let commit = Commit::from_tree_id(
+    let (name, email) = Config::user_identity().await;
+    let committer = Signature::new(SignatureType::Committer, name.clone(), email.clone());
+    let author = author.unwrap_or_else(|| Signature::new(SignatureType::Author, name, email));
+    let mut commit = Commit::new(
+        author,
+        committer,
         tree.id,
-        parents_commit_ids,
-        &format_commit_msg(&args.message, None),
+        parents,
+        &format_commit_msg(message, None),
     );
 
-    // TODO  default signature created in `from_tree_id`, wait `git config` to set correct user info
+    if sign {
+        // the signed data is the commit without the signature, then it's embedded as a header
+        let signature = match signing::sign(&commit.to_data().unwrap()).await {
+            Ok(signature) => signature,
+            Err(e) => {
+                eprintln!("fatal: failed to sign the commit: {}", e);
+                return None;
+            }
+        };
+        let header = signing::to_gpgsig_header(&signature);
+        commit = Commit::new(
+            commit.author,
+            commit.committer,
+            commit.tree_id,
+            commit.parent_commit_ids,
+            &format_commit_msg(message, Some(&header)),
+        );
+    }
 
     storage
         .put(&commit.id, &commit.to_data().unwrap(), commit.get_type())
         .unwrap();
+    commit_graph::update(&[commit.id]);
 
     /* update HEAD */
+    let old_head = Head::current_commit().await;
     update_head(&commit.id.to_string()).await;
+    let kind = match commit.parent_commit_ids.len() {
+        0 => " (initial)",
+        1 => "",
+        _ => " (merge)",
+    };
+    let subject = message.lines().next().unwrap_or_default();
+    Reflog::log_head_update(old_head, commit.id, &format!("commit{}: {}", kind, subject)).await;
+    Some(commit)
 }
 
 /// recursively create tree from index's tracked entries
-async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathBuf) -> Tree {
+pub async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathBuf) -> Tree {
     // blob created when add file to index
     let get_blob_entry = |path: &PathBuf| {
         let name = util::path_to_string(path);
@@ -139,13 +279,18 @@ async fn create_tree(index: &Index, storage: &ClientStorage, current_root: PathB
 }
 
 /// get current head commit id as parent, if in branch, get branch's commit id, if detached head, get head's commit id
+/// - if merging, `MERGE_HEAD` is the second parent
 async fn get_parents_ids() -> Vec<SHA1> {
     // let current_commit_id = reference::Model::current_commit_hash(db).await.unwrap();
     let current_commit_id = Head::current_commit().await;
-    match current_commit_id {
+    let mut parents = match current_commit_id {
         Some(id) => vec![id],
         None => vec![], // first commit
+    };
+    if let Some(merge_head) = merge::read_merge_head() {
+        parents.push(merge_head);
     }
+    parents
 }
 
 /// update HEAD to new commit, if in branch, update branch's commit id, if detached head, update head's commit id
@@ -225,6 +370,8 @@ mod test {
             message: "init".to_string(),
             allow_empty: false,
             conventional: false,
+            gpg_sign: false,
+            no_verify: false,
         };
         execute(args).await;
     }
@@ -239,6 +386,8 @@ mod test {
                 message: "init".to_string(),
                 allow_empty: true,
                 conventional: false,
+                gpg_sign: false,
+                no_verify: false,
             };
             execute(args).await;
 
@@ -266,6 +415,7 @@ mod test {
                 all: true,
                 update: false,
                 verbose: false,
+                force: false,
                 pathspec: vec![],
             };
             crate::command::add::execute(args).await;
@@ -276,6 +426,8 @@ mod test {
                 message: "add some files".to_string(),
                 allow_empty: false,
                 conventional: false,
+                gpg_sign: false,
+                no_verify: false,
             };
             execute(args).await;
 
@@ -297,4 +449,72 @@ mod test {
             assert_eq!(tree.tree_items.len(), 2); // 2 subtree according to the test data
         }
     }
+
+    #[tokio::test]
+    #[serial]
+    #[cfg(unix)]
+    async fn test_commit_hooks() {
+        test::setup_with_new_libra().await;
+        let args = |no_verify| CommitArgs {
+            message: "init  \n\n\n".to_string(),
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: false,
+            no_verify,
+        };
+
+        // a failing `pre-commit` aborts the commit, unless `--no-verify`
+        test::install_hook(hooks::PRE_COMMIT, "exit 1");
+        execute(args(false)).await;
+        assert_eq!(Head::current_commit().await, None);
+        execute(args(true)).await;
+        let first = Head::current_commit().await.unwrap();
+        fs::remove_file(util::storage_path().join("hooks").join(hooks::PRE_COMMIT)).unwrap();
+        // not cleaned up if no hook edits it
+        let commit: Commit = load_object(&first).unwrap();
+        assert!(commit.message.contains("init  \n"));
+
+        // `commit-msg` edits the message
+        test::install_hook(hooks::COMMIT_MSG, "echo 'Signed-off-by: a' >> \"$1\"");
+        execute(args(false)).await;
+        let commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
+        assert_eq!(commit.parent_commit_ids, vec![first]);
+        assert_eq!(commit.message.trim(), "init\n\nSigned-off-by: a");
+        fs::remove_file(util::storage_path().join("hooks").join(hooks::COMMIT_MSG)).unwrap();
+
+        // the source of the message is `merge` when concluding a merge
+        test::install_hook(hooks::PREPARE_COMMIT_MSG, "echo \"$2\" >> \"$1\"");
+        fs::write(path::merge_head(), first.to_string()).unwrap();
+        execute(args(false)).await;
+        let commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
+        assert_eq!(commit.parent_commit_ids.len(), 2);
+        assert_eq!(commit.message.trim(), "init\n\nmerge");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_identity() {
+        test::setup_with_new_libra().await;
+        Config::insert("user", None, "name", "alice").await;
+        Config::insert("user", None, "email", "alice@example.com").await;
+        test::ensure_file("a.txt", Some("a"));
+        test::commit_all("init").await;
+
+        let commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
+        assert_eq!(commit.author.name, "alice");
+        assert_eq!(commit.committer.email, "alice@example.com");
+        let entry = Reflog::find(crate::internal::reflog::HEAD, 0)
+            .await
+            .unwrap();
+        assert_eq!(entry.committer, "alice <alice@example.com>");
+    }
+
+    #[test]
+    fn test_cleanup_message() {
+        assert_eq!(
+            cleanup_message("\n\ntitle  \n\n\n\nbody\n\n"),
+            "title\n\nbody"
+        );
+        assert_eq!(cleanup_message(" \n\n"), "");
+    }
 }
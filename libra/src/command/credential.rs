@@ -0,0 +1,67 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use crate::internal::credential::{self, Credential};
+
+/// The plumbing of the credential helpers, see [git-credential](https://git-scm.com/docs/git-credential).
+/// The credential is read from stdin as `key=value` lines, until an empty line.
+#[derive(Subcommand, Debug)]
+pub enum CredentialCmds {
+    /// Fill the credential by the helpers or the user, and print it
+    Fill,
+    /// Tell the helpers to store the credential
+    Approve,
+    /// Tell the helpers to erase the credential
+    Reject,
+    /// Serve the credentials of the `cache` helper
+    #[command(hide = true)]
+    CacheDaemon { socket: PathBuf },
+}
+
+pub async fn execute(cmd: CredentialCmds) {
+    if let CredentialCmds::CacheDaemon { socket } = &cmd {
+        if let Err(e) = credential::run_cache_daemon(socket) {
+            eprintln!("fatal: unable to run the credential cache daemon: {}", e);
+        }
+        return;
+    }
+    let cred = match read_credential() {
+        Ok(cred) => cred,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    match cmd {
+        CredentialCmds::Fill => {
+            let cred = credential::fill(cred, true).await;
+            if !cred.is_complete() {
+                eprintln!("fatal: could not read the credential");
+                return;
+            }
+            print!("{}", cred);
+        }
+        CredentialCmds::Approve => credential::approve(&cred).await,
+        CredentialCmds::Reject => credential::reject(&cred).await,
+        CredentialCmds::CacheDaemon { .. } => {}
+    }
+}
+
+/// Read the credential from stdin until an empty line or EOF
+fn read_credential() -> Result<Credential, String> {
+    let mut input = String::new();
+    loop {
+        let mut line = String::new();
+        let n = io::stdin()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        if n == 0 || line.trim_end_matches(['\r', '\n']).is_empty() {
+            break;
+        }
+        input.push_str(line.trim_end_matches(['\r', '\n']));
+        input.push('\n');
+    }
+    Credential::parse(&input)
+}
@@ -11,7 +11,12 @@ use mercury::{
     hash::SHA1,
     internal::{
         index::Index,
-        object::{blob::Blob, commit::Commit, tree::Tree, types::ObjectType},
+        object::{
+            blob::Blob,
+            commit::Commit,
+            tree::{Tree, TreeItemMode},
+            types::ObjectType,
+        },
         pack::utils::calculate_object_hash,
     },
 };
@@ -19,11 +24,19 @@ use similar;
 
 use crate::{
     command::{
-        get_target_commit, load_object,
+        calc_file_blob_hash, get_target_commit, load_object, merge,
         status::{self, changes_to_be_committed},
     },
-    internal::head::Head,
-    utils::{object_ext::TreeExt, path, util},
+    internal::{
+        head::Head,
+        revision::{self, RevRange},
+    },
+    utils::{
+        object_ext::{self, CommitExt, TreeExt},
+        path,
+        sparse::{self, SparseCheckout},
+        submodule, util,
+    },
 };
 
 #[cfg(unix)]
@@ -33,7 +46,7 @@ use crate::utils::path_ext::PathExt;
 
 #[derive(Parser, Debug)]
 pub struct DiffArgs {
-    /// Old commit, default is HEAD
+    /// Old commit, default is HEAD. `A..B` compares `A` with `B`, `A...B` compares the merge base of `A` & `B` with `B`
     #[clap(long, value_name = "COMMIT")]
     pub old: Option<String>,
 
@@ -55,11 +68,40 @@ pub struct DiffArgs {
     pub output: Option<String>,
 }
 
-pub async fn execute(args: DiffArgs) {
+pub async fn execute(mut args: DiffArgs) {
     if !util::check_repo_exist() {
         return;
     }
     tracing::debug!("diff args: {:?}", args);
+    if let Some(old) = args.old.as_ref().filter(|old| old.contains("..")) {
+        if args.new.is_some() || args.staged {
+            eprintln!(
+                "fatal: --new or --staged can't be used with a range '{}'",
+                old
+            );
+            return;
+        }
+        let (old, new) = match revision::resolve_range(old).await {
+            Ok(RevRange::Range { exclude, include }) => (exclude, include),
+            Ok(RevRange::Symmetric(left, right)) => {
+                let (left_commit, right_commit) = (Commit::load(&left), Commit::load(&right));
+                match merge::lca_commit(&left_commit, &right_commit).await {
+                    Some(base) => (base.id, right),
+                    None => {
+                        eprintln!("fatal: {}: no merge base", old);
+                        return;
+                    }
+                }
+            }
+            Ok(RevRange::Single(_)) => unreachable!("`..` is always a range"),
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        };
+        args.old = Some(old.to_string());
+        args.new = Some(new.to_string());
+    }
     let index = Index::load(path::index()).unwrap();
 
     let mut w = match args.output {
@@ -77,9 +119,11 @@ pub async fn execute(args: DiffArgs) {
         None => None,
     };
 
-    let old_blobs = match args.old {
+    // the commits of the submodules
+    let mut gitlinks = HashSet::new();
+    let mut old_blobs = match args.old {
         Some(ref source) => match get_target_commit(source).await {
-            Ok(commit_hash) => get_commit_blobs(&commit_hash).await,
+            Ok(commit_hash) => commit_blobs(&commit_hash, &mut gitlinks),
             Err(e) => {
                 eprintln!("fatal: {}, can't use as diff old source", e);
                 return;
@@ -89,18 +133,18 @@ pub async fn execute(args: DiffArgs) {
             // if the staged is not empty, use it as old commit. Otherwise, use HEAD
             if status::changes_to_be_committed().await.is_empty() {
                 let commit_hash = Head::current_commit().await.unwrap();
-                get_commit_blobs(&commit_hash).await
+                commit_blobs(&commit_hash, &mut gitlinks)
             } else {
                 let changes = changes_to_be_committed().await;
                 // diff didn't show untracked or deleted files
-                get_files_blobs(&changes.modified)
+                get_files_blobs(&changes.modified, &index, &mut gitlinks).await
             }
         }
     };
 
-    let new_blobs = match args.new {
+    let mut new_blobs = match args.new {
         Some(ref source) => match get_target_commit(source).await {
-            Ok(commit_hash) => get_commit_blobs(&commit_hash).await,
+            Ok(commit_hash) => commit_blobs(&commit_hash, &mut gitlinks),
             Err(e) => {
                 eprintln!("fatal: {}, can't use as diff new source", e);
                 return;
@@ -115,16 +159,40 @@ pub async fn execute(args: DiffArgs) {
                 // NOTE: git didn't show diff for untracked files, but we do
                 util::list_workdir_files().unwrap()
             };
-            get_files_blobs(&files)
+            get_files_blobs(&files, &index, &mut gitlinks).await
         }
     };
 
+    if args.new.is_none() && !args.staged {
+        // the files out of the sparse checkout are not compared with the worktree
+        let sparse = SparseCheckout::load().await;
+        old_blobs.retain(|(file, _)| !index.is_skip_worktree(file.to_str().unwrap()));
+        new_blobs.retain(|(file, _)| {
+            index.tracked(file.to_str().unwrap(), 0) || sparse::is_included(sparse.as_ref(), file)
+        });
+        // the submodules not checked out (empty directories) are unchanged
+        for entry in index.tracked_entries(0) {
+            let path_abs = util::workdir_to_absolute(&entry.name);
+            if entry.is_gitlink() && path_abs.is_dir() && !util::is_nested_repo(&path_abs) {
+                new_blobs.push((PathBuf::from(&entry.name), entry.hash));
+                gitlinks.insert(entry.hash);
+            }
+        }
+    }
+
     // use pathspec to filter files
     let paths: Vec<PathBuf> = args.pathspec.iter().map(util::to_workdir_path).collect();
 
     let mut buf: Vec<u8> = Vec::new();
     // filter files, cross old and new files, and pathspec
-    diff(old_blobs, new_blobs, paths.into_iter().collect(), &mut buf).await;
+    diff(
+        old_blobs,
+        new_blobs,
+        gitlinks,
+        paths.into_iter().collect(),
+        &mut buf,
+    )
+    .await;
 
     match w {
         Some(ref mut file) => {
@@ -152,9 +220,11 @@ pub async fn execute(args: DiffArgs) {
     }
 }
 
+/// - `gitlinks`: the commits of the submodules in the blobs, see [commit_blobs]
 pub async fn diff(
     old_blobs: Vec<(PathBuf, SHA1)>,
     new_blobs: Vec<(PathBuf, SHA1)>,
+    gitlinks: HashSet<SHA1>,
     filter: Vec<PathBuf>,
     w: &mut dyn io::Write,
 ) {
@@ -169,10 +239,37 @@ pub async fn diff(
         union_files.len()
     );
 
+    // the blobs omitted by a partial clone are fetched in one request
+    let storage = util::objects_storage();
+    let mut missing_blobs = Vec::new();
+    for file in &union_files {
+        let (old_hash, new_hash) = (old_blobs.get(file), new_blobs.get(file));
+        if old_hash == new_hash || !filter.is_empty() && !filter.iter().any(|p| file.sub_of(p)) {
+            continue;
+        }
+        let file = util::workdir_to_absolute(file);
+        for hash in [old_hash, new_hash].into_iter().flatten() {
+            if gitlinks.contains(hash) || storage.exist(hash) {
+                continue;
+            }
+            // the blob of a file in the worktree is not in any repository
+            if file.is_file() && calc_file_blob_hash(&file).ok().as_ref() == Some(hash) {
+                continue;
+            }
+            missing_blobs.push(*hash);
+        }
+    }
+    if let Err(e) = object_ext::fetch_missing_objects(missing_blobs).await {
+        eprintln!("fatal: failed to fetch missing objects: {}", e);
+    }
+
+    // a submodule is a commit, neither a blob in this repository nor a file in the worktree
+    let is_gitlink = |hash: &SHA1| gitlinks.contains(hash);
     let read_content = |file: &PathBuf, hash: &SHA1| {
         // read content from blob or file
         match load_object::<Blob>(hash) {
             Ok(blob) => blob.data,
+            Err(_) if is_gitlink(hash) => format!("Subproject commit {}\n", hash).into_bytes(),
             Err(_) => {
                 let file = util::workdir_to_absolute(file);
                 std::fs::read(&file)
@@ -214,10 +311,14 @@ pub async fn diff(
         )
         .unwrap();
 
+        let mode = match new_hash.or(old_hash) {
+            Some(hash) if is_gitlink(hash) => "160000",
+            _ => "100644",
+        };
         if old_hash.is_none() {
-            writeln!(w, "new file mode 100644").unwrap();
+            writeln!(w, "new file mode {}", mode).unwrap();
         } else if new_hash.is_none() {
-            writeln!(w, "deleted file mode 100644").unwrap();
+            writeln!(w, "deleted file mode {}", mode).unwrap();
         }
 
         let old_index = old_hash.map_or("0000000".to_string(), |h| h.to_string()[0..8].to_string());
@@ -247,24 +348,53 @@ pub async fn diff(
     }
 }
 
-async fn get_commit_blobs(commit_hash: &SHA1) -> Vec<(PathBuf, SHA1)> {
+/// Get all blobs of a commit recursively, like [merge::commit_blobs], with the submodules added to `gitlinks`
+pub fn commit_blobs(commit_hash: &SHA1, gitlinks: &mut HashSet<SHA1>) -> Vec<(PathBuf, SHA1)> {
     let commit = load_object::<Commit>(commit_hash).unwrap();
-    let tree = load_object::<Tree>(&commit.tree_id).unwrap();
-    tree.get_plain_items()
+    tree_blobs(&commit.tree_id, gitlinks)
 }
 
-// diff need to print hash even if the file is not added
-fn get_files_blobs(files: &[PathBuf]) -> Vec<(PathBuf, SHA1)> {
-    files
-        .iter()
-        .map(|p| {
-            let path = util::workdir_to_absolute(p);
-            let data = std::fs::read(&path).unwrap();
-            (p.to_owned(), calculate_object_hash(ObjectType::Blob, &data))
-        })
+/// Get all blobs of a tree recursively, like [merge::tree_blobs], with the submodules added to `gitlinks`
+pub fn tree_blobs(tree_id: &SHA1, gitlinks: &mut HashSet<SHA1>) -> Vec<(PathBuf, SHA1)> {
+    let tree = load_object::<Tree>(tree_id).unwrap();
+    let items = tree.get_plain_items_with_mode();
+    gitlinks.extend(
+        items
+            .iter()
+            .filter(|(_, _, mode)| *mode == TreeItemMode::Commit)
+            .map(|(_, hash, _)| *hash),
+    );
+    items
+        .into_iter()
+        .map(|(path, hash, _)| (path, hash))
         .collect()
 }
 
+// diff need to print hash even if the file is not added
+// - a submodule is its checked out commit, or the commit in the index if it's not checked out
+async fn get_files_blobs(
+    files: &[PathBuf],
+    index: &Index,
+    gitlinks: &mut HashSet<SHA1>,
+) -> Vec<(PathBuf, SHA1)> {
+    let mut blobs = Vec::new();
+    for p in files {
+        let path = util::workdir_to_absolute(p);
+        if path.is_dir() {
+            let commit = match submodule::head_commit(&path).await {
+                Some(commit) => Some(commit),
+                None => index.get_hash(p.to_str().unwrap(), 0),
+            };
+            gitlinks.extend(commit);
+            blobs.extend(commit.map(|commit| (p.to_owned(), commit)));
+            continue;
+        }
+        let data = std::fs::read(&path).unwrap();
+        blobs.push((p.to_owned(), calculate_object_hash(ObjectType::Blob, &data)));
+    }
+    blobs
+}
+
 // display file with type
 fn file_display(file: &Path, hash: Option<&SHA1>, file_type: Option<infer::Type>) -> String {
     let file_name = match hash {
@@ -1,25 +1,30 @@
+use ceres::protocol::ObjectFilter;
 use ceres::protocol::ServiceType::UploadPack;
 use clap::Parser;
 use indicatif::ProgressBar;
 use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
 use std::io;
+use std::str::FromStr;
 use std::time::Instant;
 use std::vec;
 use std::{collections::HashSet, fs, io::Write};
 use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::runtime::{Handle, RuntimeFlavor};
 use tokio_util::io::StreamReader;
-use url::Url;
 
-use crate::command::load_object;
+use crate::utils::object_ext::CommitExt;
 use crate::utils::util;
 use crate::{
     command::index_pack::{self, IndexPackArgs},
     internal::{
         branch::Branch,
+        commit_graph,
         config::{Config, RemoteConfig},
         head::Head,
-        protocol::{https_client::HttpsClient, ProtocolClient},
+        protocol::{self, FetchStream, RemoteClient, UploadPackOptions},
+        shallow,
+        tag::Tag,
     },
     utils::{self, path_ext::PathExt},
 };
@@ -38,15 +43,29 @@ pub struct FetchArgs {
     /// Fetch all remotes.
     #[clap(long, short, conflicts_with("repository"))]
     pub all: bool,
+
+    /// Limit fetching to the specified number of commits from the tip of each remote branch
+    #[clap(long)]
+    pub depth: Option<usize>,
+
+    /// Omit objects from the pack (partial fetch): `blob:none`, `blob:limit=<n>[kmg]` or `tree:<depth>`
+    #[clap(long)]
+    pub filter: Option<String>,
 }
 
 pub async fn execute(args: FetchArgs) {
     tracing::debug!("`fetch` args: {:?}", args);
     tracing::warn!("didn't test yet");
+    let options = FetchOptions {
+        depth: args.depth,
+        filter: args.filter,
+        ..Default::default()
+    };
     if args.all {
         let remotes = Config::all_remote_configs().await;
+        let options = &options;
         let tasks = remotes.into_iter().map(|remote| async move {
-            fetch_repository(&remote, None).await;
+            fetch_repository(&remote, None, options).await;
         });
         futures::future::join_all(tasks).await;
     } else {
@@ -65,7 +84,7 @@ pub async fn execute(args: FetchArgs) {
         };
         let remote_config = Config::remote_config(&remote).await;
         match remote_config {
-            Some(remote_config) => fetch_repository(&remote_config, args.refspec).await,
+            Some(remote_config) => fetch_repository(&remote_config, args.refspec, &options).await,
             None => {
                 tracing::error!("remote config '{}' not found", remote);
                 eprintln!(
@@ -77,9 +96,34 @@ pub async fn execute(args: FetchArgs) {
     }
 }
 
+/// Options of fetching that are not from the command line of `fetch`, e.g. set by `clone`
+#[derive(Debug, Default, Clone)]
+pub struct FetchOptions {
+    /// hard link the objects of a local repository instead of copying them
+    pub hardlinks: bool,
+    /// create or deepen a shallow history with the number of commits from the tips
+    pub depth: Option<usize>,
+    /// the filter-spec of a partial clone, e.g. `blob:none`, the omitted objects are fetched on demand
+    pub filter: Option<String>,
+}
+
+/// The response of `git-upload-pack`
+#[derive(Default)]
+struct UploadPackResponse {
+    pack_data: Vec<u8>,
+    /// the shallow-update: new shallow commits of the client
+    shallow: Vec<SHA1>,
+    /// the shallow-update: commits that are not shallow anymore, their parents are sent
+    unshallow: Vec<SHA1>,
+}
+
 /// Fetch from remote repository
 /// - `branch` is optional, if `None`, fetch all branches
-pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<String>) {
+pub async fn fetch_repository(
+    remote_config: &RemoteConfig,
+    branch: Option<String>,
+    options: &FetchOptions,
+) {
     println!(
         "fetching from {}{}",
         remote_config.name,
@@ -91,16 +135,43 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
     );
 
     // fetch remote
-    let url = match Url::parse(&remote_config.url) {
-        Ok(url) => url,
+    let mut client = match protocol::parse_remote_url(&remote_config.url)
+        .and_then(|url| RemoteClient::from_url(&url))
+    {
+        Ok(client) => client,
         Err(e) => {
-            eprintln!("fatal: invalid URL '{}': {}", remote_config.url, e);
+            eprintln!("fatal: {}", e);
             return;
         }
     };
-    let http_client = HttpsClient::from_url(&url);
 
-    let refs = match http_client.discovery_reference(UploadPack).await {
+    // a partial clone keeps the filter of the first fetch, saved as `remote.<name>.partialclonefilter`
+    let mut filter = match &options.filter {
+        Some(filter) => Some(filter.clone()),
+        None => Config::get("remote", Some(&remote_config.name), "partialclonefilter").await,
+    };
+    if let Some(Err(e)) = filter.as_ref().map(|f| f.parse::<ObjectFilter>()) {
+        eprintln!("fatal: {}", e);
+        return;
+    }
+    let mut depth = options.depth;
+    if let RemoteClient::Local(local) = &mut client {
+        local.hardlinks = options.hardlinks;
+        if depth.is_some() || filter.is_some() {
+            eprintln!(
+                "warning: --depth and --filter are ignored when fetching from a local repository"
+            );
+            depth = None;
+            filter = None;
+        }
+    }
+
+    // only the wanted refs are listed if the server supports protocol v2
+    let ref_prefixes = match &branch {
+        Some(branch) => vec![format!("refs/heads/{}", branch)],
+        None => vec!["refs/heads/".to_owned(), "refs/tags/".to_owned()],
+    };
+    let refs = match client.discovery_reference(UploadPack, &ref_prefixes).await {
         Ok(refs) => refs,
         Err(e) => {
             eprintln!("fatal: {}", e);
@@ -114,6 +185,14 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
     }
 
     let remote_head = refs.iter().find(|r| r._ref == "HEAD").cloned();
+    // remote tags are fetched along with all branches, `^{}` is the peeled object of an annotated tag
+    let ref_tags = refs
+        .iter()
+        .filter(|r| {
+            branch.is_none() && r._ref.starts_with("refs/tags/") && !r._ref.ends_with("^{}")
+        })
+        .cloned()
+        .collect::<Vec<_>>();
     // remote branches
     let mut ref_heads = refs // DO NOT use `refs` later
         .into_iter()
@@ -131,95 +210,46 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
         }
     }
 
-    let want = ref_heads
+    let storage = util::objects_storage();
+    let mut want = ref_heads
         .iter()
         .map(|r| r._hash.clone())
         .collect::<Vec<_>>();
-    let have = current_have().await; // TODO: return `DiscRef` rather than only hash, to compare `have` & `want` more accurately
-
-    let mut result_stream = http_client.fetch_objects(&have, &want).await.unwrap();
-
-    let mut reader = StreamReader::new(&mut result_stream);
-    let mut pack_data = Vec::new();
-    let mut reach_pack = false;
-    let bar = ProgressBar::new_spinner();
-    let time = Instant::now();
-    loop {
-        let (len, data) = read_pkt_line(&mut reader).await.unwrap();
-        if len == 0 {
-            break;
-        }
-        if data.len() >= 5 && &data[1..5] == b"PACK" {
-            reach_pack = true;
-            tracing::debug!("Receiving PACK data...");
-        }
-        if reach_pack {
-            // 2.PACK data
-            let bytes_per_sec = pack_data.len() as f64 / time.elapsed().as_secs_f64();
-            let total = util::auto_unit_bytes(pack_data.len() as u64);
-            let bps = util::auto_unit_bytes(bytes_per_sec as u64);
-            bar.set_message(format!("Receiving objects: {total:.2} | {bps:.2}/s"));
-            bar.tick();
-            // Side-Band Capability, should be enabled if Server Support
-            let code = data[0];
-            let data = &data[1..];
-            match code {
-                1 => {
-                    // Data
-                    pack_data.extend(data); // TODO: decode meanwhile & calc progress
-                }
-                2 => {
-                    // Progress
-                    print!("{}", String::from_utf8_lossy(data));
-                    std::io::stdout().flush().unwrap();
-                }
-                3 => {
-                    // Error
-                    eprintln!("{}", String::from_utf8_lossy(data));
-                }
-                _ => {
-                    eprintln!("unknown side-band-64k code: {}", code);
-                }
-            }
-        } else if &data != b"NAK\n" {
-            // 1.front info (server progress), ignore NAK (first line)
-            print!("{}", String::from_utf8_lossy(&data)); // data contains '\r' & '\n' at end
-            std::io::stdout().flush().unwrap();
+    for r in &ref_tags {
+        if !want.contains(&r._hash) && !storage.exist(&SHA1::from_str(&r._hash).unwrap()) {
+            want.push(r._hash.clone());
         }
     }
-    bar.finish();
-
-    /* save pack file */
-    let pack_file = {
-        let hash = SHA1::new(&pack_data[..pack_data.len() - 20]);
-
-        let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]);
-        assert_eq!(hash, checksum);
-        let checksum = checksum.to_string();
-        println!("checksum: {}", checksum);
+    let have = current_have().await; // TODO: return `DiscRef` rather than only hash, to compare `have` & `want` more accurately
 
-        if pack_data.len() > 32 {
-            // 12 header + 20 hash
-            let pack_file = utils::path::objects()
-                .join("pack")
-                .join(format!("pack-{}.pack", checksum));
-            let mut file = fs::File::create(pack_file.clone()).unwrap();
-            file.write_all(&pack_data).expect("write failed");
+    let upload_options = UploadPackOptions {
+        depth,
+        filter: filter.clone(),
+        shallow: shallow::read().iter().map(SHA1::to_string).collect(),
+    };
 
-            Some(pack_file.to_string_or_panic())
-        } else {
-            tracing::debug!("Empty pack file");
-            None
+    let result_stream = client
+        .fetch_objects(&have, &want, &upload_options)
+        .await
+        .unwrap();
+    let response = match receive_pack(result_stream, depth.is_some()).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
         }
     };
-
-    if let Some(pack_file) = pack_file {
-        /* build .idx file from PACK */
-        index_pack::execute(IndexPackArgs {
-            pack_file,
-            index_file: None,
-            index_version: None,
-        });
+    if let Err(e) = save_pack(&response.pack_data) {
+        eprintln!("fatal: {}", e);
+        return;
+    }
+    if !response.shallow.is_empty() || !response.unshallow.is_empty() {
+        shallow::update(&response.shallow, &response.unshallow);
+    }
+    if let Some(filter) = &filter {
+        // the remote is a promisor, the omitted objects can be fetched from it later
+        set_remote_config(&remote_config.name, "promisor", "true").await;
+        set_remote_config(&remote_config.name, "partialclonefilter", filter).await;
     }
 
     /* update reference  */
@@ -228,6 +258,18 @@ pub async fn fetch_repository(remote_config: &RemoteConfig, branch: Option<Strin
         let remote = Some(remote_config.name.as_str());
         Branch::update_branch(branch_name, &r._hash, remote).await;
     }
+    for r in &ref_tags {
+        let tag_name = r._ref.strip_prefix("refs/tags/").unwrap();
+        // existing local tags are kept, like git without `--force`
+        if Tag::find_tag(tag_name).await.is_none() {
+            Tag::update_tag(tag_name, &r._hash).await;
+        }
+    }
+    let fetched: Vec<SHA1> = ref_heads
+        .iter()
+        .map(|r| SHA1::from_str(&r._hash).unwrap())
+        .collect();
+    commit_graph::update(&fetched);
     match remote_head {
         Some(remote_head) => {
             let remote_head_ref = ref_heads.iter().find(|r| r._hash == remote_head._hash);
@@ -289,7 +331,7 @@ async fn current_have() -> Vec<String> {
     for remote in remotes {
         let branchs = Branch::list_branches(remote.as_deref()).await;
         for branch in branchs {
-            let commit: Commit = load_object(&branch.commit).unwrap();
+            let commit = Commit::load(&branch.commit);
             check_and_insert(&commit, &mut inserted, &mut c_pending);
         }
     }
@@ -298,9 +340,10 @@ async fn current_have() -> Vec<String> {
         let item = c_pending.pop().unwrap();
         have.push(item.commit.to_string());
 
-        let commit: Commit = load_object(&item.commit).unwrap();
+        // the parents of shallow commits are missing, `Commit::load` cuts them off
+        let commit = Commit::load(&item.commit);
         for parent in commit.parent_commit_ids {
-            let parent: Commit = load_object(&parent).unwrap();
+            let parent = Commit::load(&parent);
             check_and_insert(&parent, &mut inserted, &mut c_pending);
         }
     }
@@ -308,6 +351,180 @@ async fn current_have() -> Vec<String> {
     have
 }
 
+/// Receive the response of `git-upload-pack` until the end of the pack.
+/// - `deepen`: a shallow-update section (v0) is expected before the pack, ended by a flush-pkt
+async fn receive_pack(mut stream: FetchStream<'_>, deepen: bool) -> io::Result<UploadPackResponse> {
+    let mut reader = StreamReader::new(&mut stream);
+    let mut response = UploadPackResponse::default();
+    let mut reach_pack = false;
+    let mut in_shallow_info = deepen;
+    let bar = ProgressBar::new_spinner();
+    let time = Instant::now();
+    loop {
+        let (len, data) = read_pkt_line(&mut reader).await?;
+        if len < 4 {
+            // flush-pkt (v0) or delim-pkt (v2) after the shallow-update, otherwise the end
+            if in_shallow_info && !reach_pack {
+                in_shallow_info = false;
+                continue;
+            }
+            break;
+        }
+        if data.len() >= 5 && &data[1..5] == b"PACK" {
+            reach_pack = true;
+            tracing::debug!("Receiving PACK data...");
+        }
+        if reach_pack {
+            // 2.PACK data
+            let pack_data = &mut response.pack_data;
+            let bytes_per_sec = pack_data.len() as f64 / time.elapsed().as_secs_f64();
+            let total = util::auto_unit_bytes(pack_data.len() as u64);
+            let bps = util::auto_unit_bytes(bytes_per_sec as u64);
+            bar.set_message(format!("Receiving objects: {total:.2} | {bps:.2}/s"));
+            bar.tick();
+            // Side-Band Capability, should be enabled if Server Support
+            let code = data[0];
+            let data = &data[1..];
+            match code {
+                1 => {
+                    // Data
+                    pack_data.extend(data); // TODO: decode meanwhile & calc progress
+                }
+                2 => {
+                    // Progress, to stderr like git, so that the stdout of e.g. `cat-file` is kept clean
+                    eprint!("{}", String::from_utf8_lossy(data));
+                }
+                3 => {
+                    // Error
+                    eprintln!("{}", String::from_utf8_lossy(data));
+                }
+                _ => {
+                    eprintln!("unknown side-band-64k code: {}", code);
+                }
+            }
+        } else if let Some(hash) = data.strip_prefix(b"shallow ") {
+            response.shallow.push(parse_hash(hash)?);
+        } else if let Some(hash) = data.strip_prefix(b"unshallow ") {
+            response.unshallow.push(parse_hash(hash)?);
+        } else if &data == b"shallow-info\n" {
+            // the section header of protocol v2, ended by a delim-pkt
+            in_shallow_info = true;
+        } else if &data == b"packfile\n" {
+            in_shallow_info = false;
+        } else if &data != b"NAK\n" {
+            // 1.front info (server progress), ignore NAK (first line)
+            eprint!("{}", String::from_utf8_lossy(&data)); // data contains '\r' & '\n' at end
+        }
+    }
+    bar.finish();
+    Ok(response)
+}
+
+fn parse_hash(hash: &[u8]) -> io::Result<SHA1> {
+    let hash = String::from_utf8_lossy(hash);
+    SHA1::from_str(hash.trim()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Save the pack file to `objects/pack` and build its `.idx` file, an empty pack is skipped
+fn save_pack(pack_data: &[u8]) -> io::Result<()> {
+    if pack_data.len() < 20 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the pack file is truncated",
+        ));
+    }
+    let hash = SHA1::new(&pack_data[..pack_data.len() - 20]);
+
+    let checksum = SHA1::from_bytes(&pack_data[pack_data.len() - 20..]);
+    if hash != checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("pack checksum mismatch: {} != {}", hash, checksum),
+        ));
+    }
+    let checksum = checksum.to_string();
+    tracing::debug!("checksum: {}", checksum);
+
+    if pack_data.len() > 32 {
+        // 12 header + 20 hash
+        let pack_file = utils::path::objects()
+            .join("pack")
+            .join(format!("pack-{}.pack", checksum));
+        fs::File::create(&pack_file)?.write_all(pack_data)?;
+
+        /* build .idx file from PACK */
+        index_pack::execute(IndexPackArgs {
+            pack_file: pack_file.to_string_or_panic(),
+            index_file: None,
+            index_version: None,
+        });
+        // cover the new pack, so that no .idx file is searched one by one
+        if let Err(e) = util::objects_storage().write_midx() {
+            eprintln!("warning: failed to write multi-pack-index: {}", e);
+        }
+    } else {
+        tracing::debug!("Empty pack file");
+    }
+    Ok(())
+}
+
+/// Insert or update a config entry of `remote.<name>`
+async fn set_remote_config(name: &str, key: &str, value: &str) {
+    if Config::get("remote", Some(name), key).await.is_some() {
+        Config::update("remote", Some(name), key, value).await;
+    } else {
+        Config::insert("remote", Some(name), key, value).await;
+    }
+}
+
+/// Fetch the objects omitted by a partial clone from the promisor remote, e.g. the blobs of `--filter=blob:none`
+pub async fn fetch_promised_objects(objects: &[SHA1]) -> Result<(), String> {
+    let mut promisor = None;
+    for remote in Config::all_remote_configs().await {
+        if Config::get("remote", Some(&remote.name), "promisor").await == Some("true".into()) {
+            promisor = Some(remote);
+            break;
+        }
+    }
+    let remote = promisor.ok_or("no promisor remote configured".to_string())?;
+    let client = protocol::parse_remote_url(&remote.url)
+        .and_then(|url| RemoteClient::from_url(&url))
+        .map_err(|e| e.to_string())?;
+    let want = objects.iter().map(SHA1::to_string).collect();
+    // only the wanted objects, not the whole trees of them
+    let options = UploadPackOptions {
+        filter: Some("blob:none".to_owned()),
+        ..Default::default()
+    };
+    let stream = client
+        .fetch_objects(&vec![], &want, &options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = receive_pack(stream, false)
+        .await
+        .map_err(|e| e.to_string())?;
+    save_pack(&response.pack_data).map_err(|e| e.to_string())
+}
+
+/// Blocking version of [fetch_promised_objects] for the synchronous object loading, e.g. `Blob::load`.
+/// A current-thread runtime (used by the tests) can't be blocked in place, the objects are fetched
+/// by a new runtime on another thread then.
+pub fn fetch_promised_objects_blocking(objects: &[SHA1]) -> Result<(), String> {
+    let block_on = || {
+        tokio::runtime::Runtime::new()
+            .map_err(|e| e.to_string())?
+            .block_on(fetch_promised_objects(objects))
+    };
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(fetch_promised_objects(objects)))
+        }
+        Ok(_) => std::thread::scope(|s| s.spawn(block_on).join())
+            .unwrap_or_else(|_| Err("failed to fetch missing objects".to_string())),
+        Err(_) => block_on(),
+    }
+}
+
 /// Read 4 bytes hex number
 async fn read_hex_4(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<u32> {
     let mut buf = [0u8; 4];
@@ -319,10 +536,37 @@ async fn read_hex_4(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<u32> {
 /// - return (raw length, data)
 async fn read_pkt_line(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<(usize, Vec<u8>)> {
     let len = read_hex_4(reader).await?;
-    if len == 0 {
-        return Ok((0, Vec::new()));
+    if len < 4 {
+        // flush-pkt `0000`, delim-pkt `0001` or response-end-pkt `0002`
+        return Ok((len as usize, Vec::new()));
     }
     let mut data = vec![0u8; (len - 4) as usize];
     reader.read_exact(&mut data).await?;
     Ok((len as usize, data))
 }
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    #[tokio::test]
+    #[serial]
+    /// The fetch runs on another thread in a current-thread runtime, rather than failing
+    async fn test_fetch_promised_objects_blocking() {
+        test::setup_with_new_libra().await;
+        let result = fetch_promised_objects_blocking(&[SHA1::default()]);
+        assert_eq!(result, Err("no promisor remote configured".to_string()));
+    }
+
+    #[tokio::test]
+    #[serial]
+    /// A truncated or corrupted pack is an error rather than a panic
+    async fn test_save_pack_invalid() {
+        test::setup_with_new_libra().await;
+        assert!(save_pack(b"PACK").is_err());
+        assert!(save_pack(&[0u8; 32]).is_err());
+    }
+}
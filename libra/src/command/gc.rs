@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+
+use crate::command::{prune, repack};
+use crate::internal::commit_graph;
+use crate::internal::config::Config;
+use crate::utils::util;
+
+/// The delta window of `--aggressive`, same as `gc.aggressiveWindow` of git
+const AGGRESSIVE_WINDOW: usize = 250;
+
+#[derive(Parser, Debug)]
+pub struct GcArgs {
+    /// Prune the unreachable objects older than this date, see `prune --expire`
+    #[clap(long, default_value = prune::DEFAULT_EXPIRE)]
+    pub prune: String,
+    /// Try harder to find deltas, with a larger delta window, slower but smaller
+    #[clap(long)]
+    pub aggressive: bool,
+}
+
+/// Pack all reachable objects into one pack, and prune the unreachable ones after the grace period:
+/// 1. the unreachable objects in old packs are written as loose objects, unless the packs are expired
+/// 2. the reachable objects are repacked, then the old packs and redundant loose objects are removed
+/// 3. the commit-graph is rewritten for the reachable commits
+/// 4. the unreachable loose objects are pruned if they are expired
+pub async fn execute(args: GcArgs) {
+    let expire = match prune::parse_expire(&args.prune) {
+        Ok(expire) => expire,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let window = if args.aggressive {
+        AGGRESSIVE_WINDOW
+    } else {
+        repack::DEFAULT_WINDOW
+    };
+
+    let reachable = prune::reachable_objects().await;
+    let storage = util::objects_storage();
+    let loose: HashSet<SHA1> = storage.list_objects_loose().into_iter().collect();
+    let old_packs = storage.list_all_packs();
+    for pack in &old_packs {
+        let modified = fs::metadata(pack).and_then(|meta| meta.modified()).ok();
+        let mtime = modified
+            .map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64)
+            .unwrap_or(i64::MAX);
+        if mtime < expire {
+            continue; // all its unreachable objects are expired as well
+        }
+        let objects = match storage.list_pack_objects(pack) {
+            Ok(objects) => objects,
+            Err(e) => {
+                eprintln!("fatal: failed to read {}: {}", pack.display(), e);
+                return;
+            }
+        };
+        for obj_id in objects {
+            if reachable.contains(&obj_id) || loose.contains(&obj_id) {
+                continue;
+            }
+            let obj_type = storage.get_object_type(&obj_id).unwrap();
+            let data = storage.get(&obj_id).unwrap();
+            storage.put(&obj_id, &data, obj_type).unwrap();
+            // keep the age of the pack, or the object would never expire if `gc` runs often
+            if let Some(modified) = modified {
+                let set_modified = fs::File::options()
+                    .write(true)
+                    .open(storage.get_obj_path(&obj_id))
+                    .and_then(|file| file.set_modified(modified));
+                if let Err(e) = set_modified {
+                    eprintln!("warning: failed to set the time of {}: {}", obj_id, e);
+                }
+            }
+        }
+    }
+
+    if !reachable.is_empty() {
+        let objects: Vec<SHA1> = reachable.iter().copied().collect();
+        let pack_file = match repack::write_pack(&objects, window).await {
+            Ok(pack_file) => pack_file,
+            Err(e) => {
+                eprintln!("fatal: failed to write pack: {}", e);
+                return;
+            }
+        };
+        println!(
+            "Packed {} objects into {}",
+            objects.len(),
+            pack_file.file_name().unwrap().to_str().unwrap()
+        );
+        let packed: Vec<SHA1> = loose.intersection(&reachable).copied().collect();
+        repack::remove_redundant(&old_packs, &pack_file, &packed);
+    } else {
+        repack::remove_redundant(&old_packs, Path::new(""), &[]);
+    }
+    let write_bitmaps =
+        Config::get("repack", None, "writeBitmaps").await.as_deref() == Some("true");
+    repack::write_midx(write_bitmaps).await;
+    // the commits to be pruned are dropped from the commit-graph
+    if let Err(e) = commit_graph::write_for_refs().await {
+        eprintln!("warning: failed to write commit-graph: {}", e);
+    }
+
+    let pruned = prune::prune_loose(&reachable, expire, false);
+    if !pruned.is_empty() {
+        println!("Pruned {} unreachable objects", pruned.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mercury::internal::object::blob::Blob;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::commit::{self, CommitArgs};
+    use crate::command::save_object;
+    use crate::internal::head::Head;
+    use crate::utils::test;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_gc() {
+        test::setup_with_new_libra().await;
+        commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let head = Head::current_commit().await.unwrap();
+        let dangling = Blob::from_content("dangling");
+        save_object(&dangling, &dangling.id).unwrap();
+
+        execute(GcArgs {
+            prune: prune::DEFAULT_EXPIRE.to_string(),
+            aggressive: false,
+        })
+        .await;
+        let storage = util::objects_storage();
+        assert_eq!(storage.list_all_packs().len(), 1);
+        // the dangling object is kept loose in the grace period
+        assert_eq!(storage.list_objects_loose(), vec![dangling.id]);
+        assert!(storage.exist(&head));
+
+        execute(GcArgs {
+            prune: "now".to_string(),
+            aggressive: false,
+        })
+        .await;
+        assert!(storage.list_objects_loose().is_empty());
+        assert!(!storage.exist(&dangling.id));
+        assert!(storage.exist(&head));
+        assert_eq!(storage.list_all_packs().len(), 1);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_gc_explode_keeps_pack_time() {
+        test::setup_with_new_libra().await;
+        let dangling = Blob::from_content("dangling");
+        save_object(&dangling, &dangling.id).unwrap();
+        let pack = repack::write_pack(&[dangling.id], 0).await.unwrap();
+        let storage = util::objects_storage();
+        storage.remove_loose(&dangling.id).unwrap();
+        let day_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(24 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(&pack)
+            .unwrap()
+            .set_modified(day_ago)
+            .unwrap();
+
+        execute(GcArgs {
+            prune: prune::DEFAULT_EXPIRE.to_string(),
+            aggressive: false,
+        })
+        .await;
+        // exploded in the grace period, as old as the pack
+        assert_eq!(storage.list_objects_loose(), vec![dangling.id]);
+        let modified = fs::metadata(storage.get_obj_path(&dangling.id))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified, day_ago);
+    }
+}
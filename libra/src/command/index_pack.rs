@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::io::Write;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
@@ -8,8 +8,12 @@ use clap::Parser;
 use sha1::{Digest, Sha1};
 
 use mercury::errors::GitError;
+use mercury::hash::SHA1;
 use mercury::internal::pack::Pack;
 
+/// The magic number of .idx file since version 2, `\377tOc`
+pub const IDX_V2_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
 #[derive(Parser, Debug)]
 pub struct IndexPackArgs {
     /// Pack file path
@@ -21,7 +25,7 @@ pub struct IndexPackArgs {
     pub index_file: Option<String>, // Option is must, or clap will require it
 
     /// This is intended to be used by the test suite only.
-    /// It allows to force the version for the generated pack index
+    /// It allows to force the version for the generated pack index, 2 by default
     #[clap(long, required = false)]
     pub index_version: Option<u8>,
 }
@@ -43,21 +47,21 @@ pub fn execute(args: IndexPackArgs) {
         return;
     }
 
-    if let Some(version) = args.index_version {
-        match version {
-            1 => build_index_v1(&pack_file, &index_file).unwrap(),
-            2 => println!("support later"),
-            _ => eprintln!("fatal: unsupported index version"),
+    let result = match args.index_version.unwrap_or(2) {
+        1 => build_index_v1(&pack_file, &index_file),
+        2 => build_index_v2(&pack_file, &index_file),
+        _ => {
+            eprintln!("fatal: unsupported index version");
+            return;
         }
-    } else {
-        // default version = 1
-        build_index_v1(&pack_file, &index_file).unwrap();
+    };
+    if let Err(e) = result {
+        eprintln!("fatal: {}", e);
     }
 }
 
-/// Build index file for pack file, version 1
-/// [pack-format](https://git-scm.com/docs/pack-format)
-pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+/// Decode the pack file, return the offset of each object sorted by hash, and the pack checksum
+fn decode_offsets(pack_file: &str) -> Result<(BTreeMap<SHA1, usize>, SHA1), GitError> {
     let pack_path = PathBuf::from(pack_file);
     let tmp_path = pack_path.parent().unwrap();
     let pack_file = std::fs::File::open(pack_file)?;
@@ -73,19 +77,20 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
     pack.decode(&mut pack_reader, move |entry, offset| {
         obj_map_c.lock().unwrap().insert(entry.hash, offset);
     })?;
+    let obj_map = Arc::try_unwrap(obj_map).unwrap().into_inner().unwrap();
+    Ok((obj_map, pack.signature))
+}
 
-    let mut index_hash = Sha1::new();
-    let mut index_file = std::fs::File::create(index_file)?;
-    // fan-out table
-    // The header consists of 256 4-byte network byte order integers.
-    // N-th entry of this table records the number of objects in the corresponding pack,
-    // the first byte of whose object name is less than or equal to N.
-    // This is called the first-level fan-out table.
+/// The fan-out table of the sorted hashes
+/// - The header consists of 256 4-byte network byte order integers.
+///   N-th entry of this table records the number of objects in the corresponding pack,
+///   the first byte of whose object name is less than or equal to N.
+///   This is called the first-level fan-out table.
+fn build_fan_out<'a>(hashes: impl Iterator<Item = &'a SHA1>) -> Result<Vec<u8>, GitError> {
     let mut i: u8 = 0;
     let mut cnt: u32 = 0;
     let mut fan_out = Vec::with_capacity(256 * 4);
-    let obj_map = Arc::try_unwrap(obj_map).unwrap().into_inner().unwrap();
-    for (hash, _) in obj_map.iter() {
+    for hash in hashes {
         // sorted
         let first_byte = hash.0[0];
         while first_byte > i {
@@ -103,6 +108,18 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
         }
         i += 1;
     }
+    Ok(fan_out)
+}
+
+/// Build index file for pack file, version 1
+/// [pack-format](https://git-scm.com/docs/pack-format)
+pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_offsets(pack_file)?;
+
+    let mut index_hash = Sha1::new();
+    let mut index_file = std::fs::File::create(index_file)?;
+    // fan-out table
+    let fan_out = build_fan_out(obj_map.keys())?;
     index_hash.update(&fan_out);
     index_file.write_all(&fan_out)?;
 
@@ -110,17 +127,22 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
     // object is stored in the pack-file as the offset from the beginning.
     // one object name of the appropriate size (20 bytes).
     for (hash, offset) in obj_map {
+        let offset = u32::try_from(offset).map_err(|_| {
+            GitError::InvalidPackFile(
+                "pack is too large for index version 1, use version 2".to_string(),
+            )
+        })?;
         let mut buf = Vec::with_capacity(24);
-        buf.write_u32::<BigEndian>(offset as u32)?;
+        buf.write_u32::<BigEndian>(offset)?;
         buf.write_all(&hash.0)?;
 
         index_hash.update(&buf);
         index_file.write_all(&buf)?;
     }
 
-    index_hash.update(pack.signature.0);
+    index_hash.update(signature.0);
     // A copy of the pack checksum at the end of the corresponding pack-file.
-    index_file.write_all(&pack.signature.0)?;
+    index_file.write_all(&signature.0)?;
     let index_hash: [u8; 20] = index_hash.finalize().into();
     // Index checksum of all of the above.
     index_file.write_all(&index_hash)?;
@@ -128,3 +150,119 @@ pub fn build_index_v1(pack_file: &str, index_file: &str) -> Result<(), GitError>
     tracing::debug!("Index file is written to {:?}", index_file);
     Ok(())
 }
+
+/// Build index file for pack file, version 2, which has the CRC32 of each object
+/// and supports packs larger than 4 GiB with 8-byte offsets
+/// [pack-format](https://git-scm.com/docs/pack-format)
+pub fn build_index_v2(pack_file: &str, index_file: &str) -> Result<(), GitError> {
+    let (obj_map, signature) = decode_offsets(pack_file)?;
+    let crc32 = compute_crc32(pack_file, obj_map.values().copied().collect())?;
+    let entries: Vec<_> = obj_map
+        .into_iter()
+        .map(|(hash, offset)| (hash, offset as u64, crc32[&offset]))
+        .collect();
+
+    std::fs::write(index_file, encode_index_v2(&entries, &signature)?)?;
+    tracing::debug!("Index file is written to {:?}", index_file);
+    Ok(())
+}
+
+/// Encode the index file of version 2 from `(hash, offset, crc32)` of each object sorted by hash
+pub fn encode_index_v2(
+    entries: &[(SHA1, u64, u32)],
+    signature: &SHA1,
+) -> Result<Vec<u8>, GitError> {
+    let mut buf = Vec::new();
+    // header: magic number & version
+    buf.write_all(&IDX_V2_MAGIC)?;
+    buf.write_u32::<BigEndian>(2)?;
+    buf.extend(build_fan_out(entries.iter().map(|(hash, _, _)| hash))?);
+    // sorted object names
+    for (hash, _, _) in entries {
+        buf.write_all(&hash.0)?;
+    }
+    // 4-byte CRC32 values of the packed object data
+    for (_, _, crc32) in entries {
+        buf.write_u32::<BigEndian>(*crc32)?;
+    }
+    // 4-byte offsets, if the MSB is set, the rest 31 bits is the index into the 8-byte offsets table
+    let mut large_offsets = Vec::new();
+    for &(_, offset, _) in entries {
+        if offset < 0x8000_0000 {
+            buf.write_u32::<BigEndian>(offset as u32)?;
+        } else {
+            buf.write_u32::<BigEndian>(0x8000_0000 | large_offsets.len() as u32)?;
+            large_offsets.push(offset);
+        }
+    }
+    // 8-byte offsets, only for the objects beyond 2 GiB
+    for offset in large_offsets {
+        buf.write_u64::<BigEndian>(offset)?;
+    }
+    // A copy of the pack checksum at the end of the corresponding pack-file.
+    buf.write_all(&signature.0)?;
+    // Index checksum of all of the above.
+    let index_hash: [u8; 20] = Sha1::digest(&buf).into();
+    buf.write_all(&index_hash)?;
+    Ok(buf)
+}
+
+/// The CRC32 of each object in the pack file, which is the data from its offset to the next one
+fn compute_crc32(
+    pack_file: &str,
+    mut offsets: Vec<usize>,
+) -> Result<BTreeMap<usize, u32>, GitError> {
+    let mut file = std::fs::File::open(pack_file)?;
+    // the last object ends before the 20-byte checksum
+    let end = file.metadata()?.len() as usize - 20;
+    offsets.sort();
+    file.seek(SeekFrom::Start(
+        offsets.first().copied().unwrap_or(end) as u64
+    ))?;
+    let mut reader = BufReader::new(file);
+
+    let mut crc32 = BTreeMap::new();
+    let mut buf = vec![0; 64 * 1024];
+    for (i, &offset) in offsets.iter().enumerate() {
+        let next = offsets.get(i + 1).copied().unwrap_or(end);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = next - offset;
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            reader.read_exact(&mut buf[..len])?;
+            hasher.update(&buf[..len]);
+            remaining -= len;
+        }
+        crc32.insert(offset, hasher.finalize());
+    }
+    Ok(crc32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test;
+
+    #[test]
+    fn test_build_index_v2_same_as_git() {
+        let packs = test::find_cargo_dir()
+            .parent()
+            .unwrap()
+            .join("tests/data/packs");
+        let tmp = tempfile::tempdir().unwrap();
+        for name in [
+            "pack-1d0e6c14760c956c173ede71cb28f33d921e232f",
+            "ref-delta-65d47638aa7cb7c39f1bd1d5011a415439b887a8",
+        ] {
+            // the .idx files are created by `git index-pack --index-version=2`
+            let pack_file = tmp.path().join(format!("{}.pack", name));
+            std::fs::copy(packs.join(format!("{}.pack", name)), &pack_file).unwrap();
+            let index_file = tmp.path().join(format!("{}.idx", name));
+            build_index_v2(pack_file.to_str().unwrap(), index_file.to_str().unwrap()).unwrap();
+            assert_eq!(
+                std::fs::read(index_file).unwrap(),
+                std::fs::read(packs.join(format!("{}.idx", name))).unwrap()
+            );
+        }
+    }
+}
@@ -15,6 +15,7 @@ use clap::Parser;
 use crate::command::branch;
 use crate::internal::db;
 use crate::internal::model::{config, reference};
+use crate::internal::shallow;
 use crate::utils::util::{DATABASE, ROOT_DIR};
 
 #[derive(Parser, Debug)]
@@ -128,10 +129,11 @@ pub async fn init(args: InitArgs) -> io::Result<()> {
     }
 
     // Create .libra & sub-dirs
-    let dirs = ["objects/pack", "objects/info", "info"];
+    let dirs = ["objects/pack", "objects/info", "info", "hooks"];
     for dir in dirs {
         fs::create_dir_all(root_dir.join(dir))?;
     }
+    shallow::forget();
     // Create info/exclude
     // `include_str!` includes the file content while compiling
     fs::write(
@@ -257,7 +259,7 @@ mod tests {
 
     pub fn verify_init(base_dir: &Path) {
         // List of subdirectories to verify
-        let dirs = ["objects/pack", "objects/info", "info"];
+        let dirs = ["objects/pack", "objects/info", "info", "hooks"];
 
         // Loop through the directories and verify they exist
         for dir in dirs {
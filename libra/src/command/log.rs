@@ -1,71 +1,487 @@
-use std::cmp::min;
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::process::{Command, Stdio};
 
-use crate::command::load_object;
 use crate::internal::branch::Branch;
+use crate::internal::commit_graph::CommitGraph;
+use crate::internal::config::Config;
 use crate::internal::head::Head;
+use crate::internal::revision::{self, RevRange};
+use crate::internal::signing;
+use crate::internal::tag::Tag;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::util;
+use chrono::TimeZone;
 use clap::Parser;
 use colored::Colorize;
-#[cfg(unix)]
-use std::io::Write;
-#[cfg(unix)]
-use std::process::{Command, Stdio};
+use regex::Regex;
 
 use mercury::hash::SHA1;
 use mercury::internal::object::commit::Commit;
-use std::collections::VecDeque;
 use std::str::FromStr;
 
 use common::utils::parse_commit_msg;
-#[derive(Parser, Debug)]
+
+use super::{diff, merge, rebase};
+
+#[derive(Parser, Debug, Default)]
 pub struct LogArgs {
     /// Limit the number of output
     #[clap(short, long)]
     pub number: Option<usize>,
+
+    /// Show commits of the revision or range (`A..B`, `A...B`), default is HEAD
+    pub revision: Option<String>,
+
+    /// Show each commit in one line, same as `--format oneline`
+    #[clap(long)]
+    pub oneline: bool,
+
+    /// Draw a text-based graph of the commit history on the left
+    #[clap(long)]
+    pub graph: bool,
+
+    /// Show all ref names (branches, remote branches & tags) of the commits, not only HEAD
+    #[clap(long)]
+    pub decorate: bool,
+
+    /// Only show commits whose author (`name <email>`) matches the regex
+    #[clap(long)]
+    pub author: Option<String>,
+
+    /// Only show commits whose message matches the regex
+    #[clap(long)]
+    pub grep: Option<String>,
+
+    /// Only show commits more recent than the date, e.g. `2024-01-31`, `2024-01-31 08:00:00`, `2 weeks ago`
+    #[clap(long, alias = "after")]
+    pub since: Option<String>,
+
+    /// Only show commits older than the date, same formats as `--since`
+    #[clap(long, alias = "before")]
+    pub until: Option<String>,
+
+    /// Show the patch of each commit, merge commits are shown without patch
+    #[clap(short = 'p', long = "patch")]
+    pub patch: bool,
+
+    /// Check the PGP signature of each signed commit
+    #[clap(long)]
+    pub show_signature: bool,
+
+    /// `oneline`, `medium` (default), or a template with placeholders:
+    /// `%H` `%h` commit hash, `%T` `%t` tree hash, `%P` `%p` parent hashes,
+    /// `%an` `%ae` `%ad` `%at` author name, email, date & timestamp, `%cn` `%ce` `%cd` `%ct` for committer,
+    /// `%s` subject, `%b` body, `%B` raw message, `%d` `%D` ref names, `%n` newline, `%%` a raw `%`
+    #[clap(long, alias = "pretty", conflicts_with = "oneline")]
+    pub format: Option<String>,
+
+    /// Only show commits that modify the paths, must be given after `--`
+    #[clap(last = true)]
+    pub paths: Vec<String>,
 }
 
 ///  Get all reachable commits from the given commit hash
 ///  **didn't consider the order of the commits**
+#[allow(dead_code)]
 pub async fn get_reachable_commits(commit_hash: String) -> Vec<Commit> {
-    let mut queue = VecDeque::new();
-    let mut commit_set: HashSet<String> = HashSet::new(); // to avoid duplicate commits because of circular reference
-    let mut reachable_commits: Vec<Commit> = Vec::new();
-    queue.push_back(commit_hash);
-
-    while !queue.is_empty() {
-        let commit_id = queue.pop_front().unwrap();
-        let commit_id_hash = SHA1::from_str(&commit_id).unwrap();
-        let commit = load_object::<Commit>(&commit_id_hash)
-            .expect("fatal: storage broken, object not found");
-        if commit_set.contains(&commit_id) {
+    let commit_id = SHA1::from_str(&commit_hash).unwrap();
+    CommitGraph::open()
+        .ancestors(&[commit_id])
+        .iter()
+        .map(Commit::load)
+        .collect()
+}
+
+/// Get the ids of the commits in `range` from the commit-graph, **didn't consider the order of the commits**
+/// - `A..B`: reachable from `B` but not from `A`
+/// - `A...B`: reachable from either `A` or `B` but not from both
+pub fn get_range_ids(graph: &mut CommitGraph, range: RevRange) -> Vec<SHA1> {
+    match range {
+        RevRange::Single(commit) => graph.ancestors(&[commit]),
+        RevRange::Range { exclude, include } => {
+            let excluded: HashSet<SHA1> = graph.ancestors(&[exclude]).into_iter().collect();
+            graph
+                .ancestors(&[include])
+                .into_iter()
+                .filter(|id| !excluded.contains(id))
+                .collect()
+        }
+        RevRange::Symmetric(left, right) => {
+            let left = graph.ancestors(&[left]);
+            let right = graph.ancestors(&[right]);
+            let left_ids: HashSet<SHA1> = left.iter().copied().collect();
+            let right_ids: HashSet<SHA1> = right.iter().copied().collect();
+            let left = left.into_iter().filter(|id| !right_ids.contains(id));
+            let right = right.into_iter().filter(|id| !left_ids.contains(id));
+            left.chain(right).collect()
+        }
+    }
+}
+
+/// How to show a commit
+enum LogFormat {
+    /// `commit <hash>`, author, date & the full message
+    Medium,
+    /// `<short hash> <subject>`
+    Oneline,
+    /// a template with placeholders, see [LogArgs::format]
+    Template(String),
+}
+
+impl LogFormat {
+    fn new(format: Option<&str>, oneline: bool) -> Self {
+        match format {
+            _ if oneline => LogFormat::Oneline,
+            None | Some("medium") => LogFormat::Medium,
+            Some("oneline") => LogFormat::Oneline,
+            Some(template) => {
+                let template = template
+                    .strip_prefix("format:")
+                    .or_else(|| template.strip_prefix("tformat:"))
+                    .unwrap_or(template);
+                LogFormat::Template(template.to_string())
+            }
+        }
+    }
+}
+
+/// Conditions that the shown commits must meet
+#[derive(Default)]
+struct CommitFilter {
+    author: Option<Regex>,
+    grep: Option<Regex>,
+    since: Option<i64>,
+    until: Option<i64>,
+    /// to workdir
+    paths: Vec<PathBuf>,
+}
+
+impl CommitFilter {
+    fn new(args: &LogArgs) -> Result<Self, String> {
+        let regex = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| e.to_string())
+        };
+        let date = |date: &Option<String>| date.as_deref().map(parse_date).transpose();
+        Ok(CommitFilter {
+            author: regex(&args.author)?,
+            grep: regex(&args.grep)?,
+            since: date(&args.since)?,
+            until: date(&args.until)?,
+            paths: args.paths.iter().map(util::to_workdir_path).collect(),
+        })
+    }
+
+    fn matches(&self, commit: &Commit) -> bool {
+        let timestamp = commit.committer.timestamp as i64;
+        if self.since.is_some_and(|since| timestamp < since)
+            || self.until.is_some_and(|until| timestamp > until)
+        {
+            return false;
+        }
+        if let Some(author) = &self.author {
+            let signature = format!("{} <{}>", commit.author.name, commit.author.email);
+            if !author.is_match(&signature) {
+                return false;
+            }
+        }
+        if let Some(grep) = &self.grep {
+            let (message, _) = parse_commit_msg(&commit.message);
+            if !grep.is_match(message) {
+                return false;
+            }
+        }
+        self.paths.is_empty() || touches_paths(commit, &self.paths)
+    }
+}
+
+/// Check if `commit` changes files in `paths` compared with its parents.
+/// A merge commit is skipped if any parent has the same files in `paths`, like git's history simplification.
+fn touches_paths(commit: &Commit, paths: &[PathBuf]) -> bool {
+    let filter = |blobs: HashMap<PathBuf, SHA1>| -> HashMap<PathBuf, SHA1> {
+        blobs
+            .into_iter()
+            .filter(|(path, _)| util::is_sub_of_paths(path, paths))
+            .collect()
+    };
+    let blobs = filter(merge::tree_blobs(&commit.tree_id));
+    if commit.parent_commit_ids.is_empty() {
+        return !blobs.is_empty();
+    }
+    commit
+        .parent_commit_ids
+        .iter()
+        .all(|parent| filter(merge::commit_blobs(parent)) != blobs)
+}
+
+/// Parse the date of `--since` & `--until` to a unix timestamp:
+/// a timestamp, `<n> <unit>[s] ago`, `YYYY-MM-DD` or `YYYY-MM-DD HH:MM:SS` (local time)
+pub(crate) fn parse_date(date: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid date '{}'", date);
+    let date = date.trim();
+    if let Ok(timestamp) = date.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    if let Some(relative) = date.strip_suffix(" ago") {
+        let (n, unit) = relative.split_once(' ').ok_or_else(invalid)?;
+        let n: i64 = n.parse().map_err(|_| invalid())?;
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 3600,
+            "day" => 86400,
+            "week" => 7 * 86400,
+            "month" => 30 * 86400,
+            "year" => 365 * 86400,
+            _ => return Err(invalid()),
+        };
+        return Ok(chrono::Utc::now().timestamp() - n * seconds);
+    }
+    let datetime = chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .map_err(|_| invalid())?;
+    chrono::Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map(|datetime| datetime.timestamp())
+        .ok_or_else(invalid)
+}
+
+/// Ref names pointing to each commit, `all` for branches, remote branches & tags, otherwise only HEAD
+async fn collect_decorations(head: &Head, all: bool) -> HashMap<SHA1, Vec<String>> {
+    let mut decorations: HashMap<SHA1, Vec<String>> = HashMap::new();
+    if let Some(commit) = Head::current_commit().await {
+        let name = match head {
+            Head::Branch(name) => format!("{}{}", "HEAD -> ".blue(), name.green()),
+            Head::Detached(_) => "HEAD".blue().to_string(),
+        };
+        decorations.entry(commit).or_default().push(name);
+    }
+    if !all {
+        return decorations;
+    }
+    for tag in Tag::list_tags().await {
+        let name = format!("tag: {}", tag.name).yellow().to_string();
+        decorations.entry(tag.commit()).or_default().push(name);
+    }
+    for branch in Branch::list_branches(None).await {
+        if matches!(head, Head::Branch(name) if *name == branch.name) {
+            continue; // shown with HEAD
+        }
+        let name = branch.name.green().to_string();
+        decorations.entry(branch.commit).or_default().push(name);
+    }
+    for remote in Config::all_remote_configs().await {
+        for branch in Branch::list_branches(Some(&remote.name)).await {
+            let name = format!("{}/{}", remote.name, branch.name).red().to_string();
+            decorations.entry(branch.commit).or_default().push(name);
+        }
+    }
+    decorations
+}
+
+/// Format `commit` to lines, `refs` are the decorations of the commit
+fn format_commit(commit: &Commit, format: &LogFormat, refs: &[String]) -> Vec<String> {
+    let (message, _) = parse_commit_msg(&commit.message);
+    let decoration = match refs.is_empty() {
+        true => String::new(),
+        false => format!(
+            " {}{}{}",
+            "(".yellow(),
+            refs.join(&", ".yellow().to_string()),
+            ")".yellow()
+        ),
+    };
+    let text = match format {
+        LogFormat::Medium => format!(
+            "{} {}{}\nAuthor: {}\n{}\n",
+            "commit".yellow(),
+            commit.id.to_string().yellow(),
+            decoration,
+            commit.author,
+            message
+        ),
+        LogFormat::Oneline => {
+            let (short_id, subject) = rebase::commit_summary(commit);
+            format!("{}{} {}", short_id.yellow(), decoration, subject)
+        }
+        LogFormat::Template(template) => expand_template(template, commit, message, refs),
+    };
+    text.lines().map(String::from).collect()
+}
+
+/// Replace the placeholders in `template`, unknown placeholders are kept as they are
+fn expand_template(template: &str, commit: &Commit, message: &str, refs: &[String]) -> String {
+    let short = |hash: &SHA1| hash.to_string()[..7].to_string();
+    let join = |hashes: Vec<String>| hashes.join(" ");
+    let (subject, body) = match message.split_once('\n') {
+        Some((subject, body)) => (subject, body.trim()),
+        None => (message, ""),
+    };
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
             continue;
         }
-        commit_set.insert(commit_id);
+        let expanded = match chars.peek() {
+            Some('H') => commit.id.to_string(),
+            Some('h') => short(&commit.id),
+            Some('T') => commit.tree_id.to_string(),
+            Some('t') => short(&commit.tree_id),
+            Some('P') => join(
+                commit
+                    .parent_commit_ids
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect(),
+            ),
+            Some('p') => join(commit.parent_commit_ids.iter().map(short).collect()),
+            Some('s') => subject.to_string(),
+            Some('b') => body.to_string(),
+            Some('B') => message.to_string(),
+            Some('d') if !refs.is_empty() => format!(" ({})", refs.join(", ")),
+            Some('d') => String::new(),
+            Some('D') => refs.join(", "),
+            Some('n') => "\n".to_string(),
+            Some('%') => "%".to_string(),
+            Some(&role @ ('a' | 'c')) => {
+                let signature = match role {
+                    'a' => &commit.author,
+                    _ => &commit.committer,
+                };
+                let mut lookahead = chars.clone();
+                lookahead.next();
+                let field = match lookahead.peek() {
+                    Some('n') => Some(signature.name.clone()),
+                    Some('e') => Some(signature.email.clone()),
+                    Some('d') => Some(util::format_signature_date(signature)),
+                    Some('t') => Some(signature.timestamp.to_string()),
+                    _ => None,
+                };
+                match field {
+                    Some(field) => {
+                        chars.next(); // the role, the field is consumed below
+                        field
+                    }
+                    None => {
+                        result.push('%');
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                result.push('%');
+                continue;
+            }
+        };
+        chars.next();
+        result.push_str(&expanded);
+    }
+    result
+}
+
+/// Lanes of `--graph`, each lane is waiting for a commit to be shown
+#[derive(Default)]
+struct Graph {
+    lanes: Vec<SHA1>,
+}
+
+impl Graph {
+    /// `|` for each lane, 2 columns per lane
+    fn lane_row(lanes: usize) -> Vec<char> {
+        (0..lanes * 2)
+            .map(|i| if i % 2 == 0 { '|' } else { ' ' })
+            .collect()
+    }
+
+    fn render(row: Vec<char>) -> String {
+        row.into_iter().collect::<String>().trim_end().to_string()
+    }
 
-        let parent_commit_ids = commit.parent_commit_ids.clone();
-        for parent_commit_id in parent_commit_ids {
-            queue.push_back(parent_commit_id.to_string());
+    /// Draw `commit` whose formatted text is `lines`, `parents` are those will be shown
+    fn draw(&mut self, commit: &SHA1, parents: &[SHA1], lines: &[String]) -> Vec<String> {
+        let mut rows = vec![];
+        let idx = match self.lanes.iter().position(|lane| lane == commit) {
+            Some(idx) => idx,
+            None => {
+                self.lanes.push(*commit);
+                self.lanes.len() - 1
+            }
+        };
+
+        // other lanes waiting for the commit join it: `|/`
+        if let Some(first) = (idx + 1..self.lanes.len()).find(|&i| self.lanes[i] == *commit) {
+            let mut row = Self::lane_row(self.lanes.len());
+            for i in first..self.lanes.len() {
+                row[2 * i] = ' ';
+                row[2 * i - 1] = '/';
+            }
+            rows.push(Self::render(row));
+            let mut i = 0;
+            self.lanes.retain(|lane| {
+                i += 1;
+                i - 1 <= idx || lane != commit
+            });
+        }
+
+        let mut row = Self::lane_row(self.lanes.len());
+        row[2 * idx] = '*';
+        let first_line = lines.first().map(String::as_str).unwrap_or_default();
+        rows.push(format!("{} {}", Self::render(row), first_line));
+
+        // the first parent takes over the lane, other parents get new lanes: `|\`
+        let old_lanes = self.lanes.len();
+        let new_parents: Vec<SHA1> = parents
+            .iter()
+            .skip(1)
+            .filter(|p| !self.lanes.contains(p))
+            .cloned()
+            .collect();
+        match parents.first() {
+            Some(parent) => self.lanes[idx] = *parent,
+            None => {
+                self.lanes.remove(idx);
+            }
+        }
+        if !new_parents.is_empty() {
+            let mut row = Self::lane_row(old_lanes);
+            row[2 * idx + 1] = '\\';
+            for i in idx + 1..old_lanes {
+                row[2 * i] = ' ';
+                row[2 * i + 1] = '\\';
+            }
+            rows.push(Self::render(row));
+            let at = (idx + 1).min(self.lanes.len());
+            self.lanes.splice(at..at, new_parents);
         }
-        reachable_commits.push(commit);
+
+        let prefix = Self::render(Self::lane_row(self.lanes.len()));
+        for line in lines.iter().skip(1) {
+            rows.push(format!("{} {}", prefix, line).trim_end().to_string());
+        }
+        rows
     }
-    reachable_commits
 }
 
 pub async fn execute(args: LogArgs) {
-    #[cfg(unix)]
-    let mut process = Command::new("less") // create a pipe to less
-        .arg("-R") // raw control characters
-        .arg("-F")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::inherit())
-        .spawn()
-        .expect("failed to execute process");
-
     let head = Head::current().await;
     // check if the current branch has any commits
     if let Head::Branch(branch_name) = head.to_owned() {
         let branch = Branch::find_branch(&branch_name, None).await;
-        if branch.is_none() {
+        if branch.is_none() && args.revision.is_none() {
             panic!(
                 "fatal: your current branch '{}' does not have any commits yet ",
                 branch_name
@@ -73,51 +489,109 @@ pub async fn execute(args: LogArgs) {
         }
     }
 
-    let commit_hash = Head::current_commit().await.unwrap().to_string();
+    let range = match args.revision {
+        Some(ref revision) => match revision::resolve_range(revision).await {
+            Ok(range) => range,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        },
+        None => RevRange::Single(Head::current_commit().await.unwrap()),
+    };
+    let filter = match CommitFilter::new(&args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let format = LogFormat::new(args.format.as_deref(), args.oneline);
+    let decorations = collect_decorations(&head, args.decorate).await;
 
-    let mut reachable_commits = get_reachable_commits(commit_hash.clone()).await;
-    // default sort with signature time
-    reachable_commits.sort_by(|a, b| b.committer.timestamp.cmp(&a.committer.timestamp));
+    let mut graph = CommitGraph::open();
+    let mut ids = get_range_ids(&mut graph, range);
+    if args.graph {
+        // children before parents, so that the graph can be drawn from top to bottom
+        ids = graph.topo_order(&ids).into_iter().rev().collect();
+    } else {
+        // default sort with signature time
+        ids.sort_by_key(|id| Reverse(graph.get(id).timestamp));
+    }
+    // only the commits to show are loaded, rather than the whole history
+    let commits: Vec<Commit> = ids
+        .iter()
+        .map(Commit::load)
+        .filter(|commit| filter.matches(commit))
+        .take(args.number.unwrap_or(usize::MAX))
+        .collect();
+    let shown: HashSet<SHA1> = commits.iter().map(|c| c.id).collect();
 
-    let max_output_number = min(args.number.unwrap_or(usize::MAX), reachable_commits.len());
-    let mut output_number = 0;
-    for commit in reachable_commits {
-        if output_number >= max_output_number {
-            break;
+    #[cfg(unix)]
+    let mut process = Command::new("less") // create a pipe to less
+        .arg("-R") // raw control characters
+        .arg("-F")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .spawn()
+        .expect("failed to execute process");
+    #[cfg(unix)]
+    let mut output: Box<dyn Write> = match process.stdin.take() {
+        Some(stdin) => Box::new(stdin),
+        None => {
+            eprintln!("Failed to capture stdin");
+            Box::new(io::stdout())
         }
-        output_number += 1;
-        let mut message = {
-            let mut message = format!("{} {}", "commit".yellow(), &commit.id.to_string().yellow());
+    };
+    #[cfg(not(unix))]
+    let mut output: Box<dyn Write> = Box::new(io::stdout());
 
-            // TODO other branch's head should shown branch name
-            if output_number == 1 {
-                message = format!("{} {}{}", message, "(".yellow(), "HEAD".blue());
-                if let Head::Branch(name) = head.to_owned() {
-                    // message += &"-> ".blue();
-                    // message += &head.name.as_ref().unwrap().green();
-                    message = format!("{}{}{}", message, " -> ".blue(), name.green());
-                }
-                message = format!("{}{}", message, ")".yellow());
+    let mut graph = Graph::default();
+    for commit in &commits {
+        let refs = decorations.get(&commit.id).cloned().unwrap_or_default();
+        let mut lines = format_commit(commit, &format, &refs);
+        if args.show_signature {
+            if let Some(result) = signing::verify_commit(commit).await {
+                // under the `commit <hash>` line, or above the formatted commit
+                let at = match format {
+                    LogFormat::Medium => 1,
+                    _ => 0,
+                };
+                lines.insert(at, signing::describe(&result));
             }
-            message
-        };
-        message.push_str(&format!("\nAuthor: {}", commit.author));
-        let (msg, _) = parse_commit_msg(&commit.message);
-        message.push_str(&format!("\n{}\n", msg));
-
-        #[cfg(unix)]
-        {
-            if let Some(ref mut stdin) = process.stdin {
-                writeln!(stdin, "{}", message).unwrap();
-            } else {
-                eprintln!("Failed to capture stdin");
+        }
+        if args.patch && commit.parent_commit_ids.len() <= 1 {
+            let mut gitlinks = HashSet::new();
+            let old_blobs = match commit.parent_commit_ids.first() {
+                Some(parent) => diff::commit_blobs(parent, &mut gitlinks),
+                None => vec![],
+            };
+            let new_blobs = diff::tree_blobs(&commit.tree_id, &mut gitlinks);
+            let mut patch: Vec<u8> = Vec::new();
+            let paths = filter.paths.clone();
+            diff::diff(old_blobs, new_blobs, gitlinks, paths, &mut patch).await;
+            lines.extend(String::from_utf8_lossy(&patch).lines().map(String::from));
+            lines.push(String::new());
+        }
+        if args.graph {
+            let parents: Vec<SHA1> = commit
+                .parent_commit_ids
+                .iter()
+                .filter(|p| shown.contains(p))
+                .cloned()
+                .collect();
+            lines = graph.draw(&commit.id, &parents, &lines);
+        }
+        for line in lines {
+            if writeln!(output, "{}", line).is_err() {
+                break; // the pager is closed
             }
         }
-        #[cfg(not(unix))]
-        {
-            println!("{}", message);
+        if matches!(format, LogFormat::Medium) && !args.graph {
+            let _ = writeln!(output);
         }
     }
+    drop(output); // close the pipe, so that the pager can exit
     #[cfg(unix)]
     {
         let _ = process.wait().expect("failed to wait on child");
@@ -142,16 +616,140 @@ mod tests {
         assert_eq!(reachable_commits.len(), 6);
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_get_range_ids() {
+        test::setup_with_new_libra().await;
+        let _ = create_test_commit_tree().await;
+
+        let range = revision::resolve_range("HEAD~1..HEAD").await.unwrap();
+        assert_eq!(get_range_ids(&mut CommitGraph::open(), range).len(), 3); // 4, 5, 6
+        let range = revision::resolve_range("HEAD^2...HEAD~1").await.unwrap();
+        assert_eq!(get_range_ids(&mut CommitGraph::open(), range).len(), 3); // 3, 4, 5
+        let range = revision::resolve_range("HEAD^2").await.unwrap();
+        assert_eq!(get_range_ids(&mut CommitGraph::open(), range).len(), 4); // 1, 2, 4, 5
+    }
+
     #[tokio::test]
     #[ignore] // ignore this test because it will open less and block the test
     async fn test_execute_log() {
         test::setup_with_new_libra().await;
         let _ = create_test_commit_tree().await;
 
-        let args = LogArgs { number: Some(6) };
+        let args = LogArgs {
+            number: Some(6),
+            ..Default::default()
+        };
         execute(args).await;
     }
 
+    #[test]
+    fn test_parse_args() {
+        let args = LogArgs::try_parse_from(["log", "--oneline", "HEAD~1", "--", "src"]).unwrap();
+        assert!(args.oneline);
+        assert_eq!(args.revision.as_deref(), Some("HEAD~1"));
+        assert_eq!(args.paths, vec!["src"]);
+        assert!(LogArgs::try_parse_from(["log", "--oneline", "--format", "%h"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1700000000"), Ok(1700000000));
+        let now = chrono::Utc::now().timestamp();
+        let two_days_ago = parse_date("2 days ago").unwrap();
+        assert!((now - 2 * 86400 - two_days_ago).abs() <= 1);
+        let day = parse_date("2024-01-31").unwrap();
+        assert_eq!(parse_date("2024-01-31 01:00:00").unwrap(), day + 3600);
+        assert_eq!(parse_date("2024-01-31T01:00:00").unwrap(), day + 3600);
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2 fortnights ago").is_err());
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let parent = Commit::from_tree_id(SHA1::new(&[1; 20]), vec![], "parent");
+        let commit = Commit::from_tree_id(
+            SHA1::new(&[2; 20]),
+            vec![parent.id],
+            &format_commit_msg("subject\n\nbody", None),
+        );
+        let (message, _) = parse_commit_msg(&commit.message);
+        let refs = vec!["tag: v1".to_string()];
+        let expand = |template| expand_template(template, &commit, message, &refs);
+        assert_eq!(expand("%H"), commit.id.to_string());
+        assert_eq!(
+            expand("%h %p"),
+            format!(
+                "{} {}",
+                &commit.id.to_string()[..7],
+                &parent.id.to_string()[..7]
+            )
+        );
+        assert_eq!(expand("%s%n%b"), "subject\nbody");
+        assert_eq!(expand("%an <%ae>%d"), "mega <admin@mega.org> (tag: v1)");
+        assert_eq!(expand("%at"), commit.author.timestamp.to_string());
+        assert_eq!(expand("100%% %x %a"), "100% %x %a");
+    }
+
+    #[test]
+    fn test_graph() {
+        // M merges A & B, both are children of base
+        let (base, a, b, m) = (
+            SHA1::new(&[1; 20]),
+            SHA1::new(&[2; 20]),
+            SHA1::new(&[3; 20]),
+            SHA1::new(&[4; 20]),
+        );
+        let mut graph = Graph::default();
+        let mut rows = vec![];
+        rows.extend(graph.draw(&m, &[a, b], &["M".to_string(), "".to_string()]));
+        rows.extend(graph.draw(&a, &[base], &["A".to_string()]));
+        rows.extend(graph.draw(&b, &[base], &["B".to_string()]));
+        rows.extend(graph.draw(&base, &[], &["base".to_string()]));
+        assert_eq!(
+            rows,
+            vec!["* M", "|\\", "| |", "* | A", "| * B", "|/", "* base"]
+        );
+        assert!(graph.lanes.is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_commit_filter() {
+        test::setup_with_new_libra().await;
+        let mut commit = Commit::from_tree_id(
+            SHA1::new(&[1; 20]),
+            vec![SHA1::new(&[2; 20])],
+            &format_commit_msg("fix: typo", None),
+        );
+        commit.committer.timestamp = 1000;
+
+        let args = LogArgs {
+            author: Some("admin@mega".to_string()),
+            grep: Some("^fix".to_string()),
+            since: Some("999".to_string()),
+            until: Some("1000".to_string()),
+            ..Default::default()
+        };
+        assert!(CommitFilter::new(&args).unwrap().matches(&commit));
+
+        let args = LogArgs {
+            author: Some("nobody".to_string()),
+            ..Default::default()
+        };
+        assert!(!CommitFilter::new(&args).unwrap().matches(&commit));
+        let args = LogArgs {
+            since: Some("1001".to_string()),
+            ..Default::default()
+        };
+        assert!(!CommitFilter::new(&args).unwrap().matches(&commit));
+        let args = LogArgs {
+            grep: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(CommitFilter::new(&args).is_err());
+    }
+
     /// create a test commit tree structure as graph and create branch (master) head to commit 6
     /// return a commit hash of commit 6
     ///            3   6
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::tree::{Tree, TreeItem, TreeItemMode};
+
+use crate::internal::revision;
+use crate::utils::object_ext::TreeExt;
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct LsTreeArgs {
+    /// The tree to list, or a commit / tag that refers to a tree
+    pub tree_ish: String,
+
+    /// Recurse into sub-trees, only blobs (and submodules) are listed
+    #[clap(short)]
+    pub recursive: bool,
+
+    /// List only paths, without mode, type & hash
+    #[clap(long)]
+    pub name_only: bool,
+}
+
+pub async fn execute(args: LsTreeArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let tree = match revision::resolve_tree(&args.tree_ish).await {
+        Ok(tree) => tree,
+        Err(e) => {
+            eprintln!("fatal: not a tree object: {}", e);
+            return;
+        }
+    };
+    for line in tree_lines(&tree, args.recursive, args.name_only) {
+        println!("{}", line);
+    }
+}
+
+/// The object type of a tree item, like `git ls-tree`
+fn item_type(mode: TreeItemMode) -> &'static str {
+    match mode {
+        TreeItemMode::Tree => "tree",
+        TreeItemMode::Commit => "commit",
+        _ => "blob",
+    }
+}
+
+/// Format a tree item as `<mode> SP <type> SP <object> TAB <path>`, the mode is padded to 6 digits
+pub fn format_item(item: &TreeItem, path: &Path) -> String {
+    format!(
+        "{:0>6} {} {}\t{}",
+        String::from_utf8_lossy(item.mode.to_bytes()),
+        item_type(item.mode),
+        item.id,
+        path.display()
+    )
+}
+
+/// List the items of `tree`, paths are relative to the tree
+fn tree_lines(tree: &SHA1, recursive: bool, name_only: bool) -> Vec<String> {
+    let mut lines = vec![];
+    list_tree(
+        &Tree::load(tree),
+        &PathBuf::new(),
+        recursive,
+        &mut |item, path| {
+            lines.push(match name_only {
+                true => path.display().to_string(),
+                false => format_item(item, path),
+            })
+        },
+    );
+    lines
+}
+
+fn list_tree(tree: &Tree, prefix: &Path, recursive: bool, f: &mut dyn FnMut(&TreeItem, &Path)) {
+    for item in &tree.tree_items {
+        let path = prefix.join(&item.name);
+        if recursive && item.mode == TreeItemMode::Tree {
+            list_tree(&Tree::load(&item.id), &path, recursive, f);
+        } else {
+            f(item, &path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{add::AddArgs, commit::CommitArgs};
+    use crate::internal::head::Head;
+    use crate::utils::object_ext::CommitExt;
+    use crate::utils::test;
+    use mercury::internal::object::commit::Commit;
+    use serial_test::serial;
+
+    #[test]
+    fn test_parse_args() {
+        let args = LsTreeArgs::try_parse_from(["ls-tree", "-r", "--name-only", "HEAD"]).unwrap();
+        assert!(args.recursive && args.name_only);
+        assert_eq!(args.tree_ish, "HEAD");
+        assert!(LsTreeArgs::try_parse_from(["ls-tree"]).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_tree_lines() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", None);
+        test::ensure_file("dir/b.txt", None);
+        crate::command::add::execute(AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+            force: false,
+        })
+        .await;
+        crate::command::commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: false,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let tree = revision::resolve_tree("HEAD").await.unwrap();
+        assert_eq!(
+            tree,
+            Commit::load(&Head::current_commit().await.unwrap()).tree_id
+        );
+
+        let lines = tree_lines(&tree, false, false);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("100644 blob ") && lines[0].ends_with("\ta.txt"));
+        assert!(lines[1].starts_with("040000 tree ") && lines[1].ends_with("\tdir"));
+
+        let names = tree_lines(&tree, true, true);
+        let expected = [PathBuf::from("a.txt"), PathBuf::from("dir").join("b.txt")];
+        assert_eq!(names, expected.map(|p| p.display().to_string()));
+    }
+}
@@ -1,24 +1,63 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+
 use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::{Index, IndexEntry};
+use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::Tree;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
 
 use crate::{
-    internal::{branch::Branch, head::Head},
-    utils::util,
+    internal::{branch::Branch, commit_graph::CommitGraph, head::Head, reflog::Reflog},
+    utils::{
+        hooks,
+        ignore::IgnoreRules,
+        object_ext::{BlobExt, CommitExt, TreeExt},
+        path,
+        path_ext::PathExt,
+        util,
+    },
 };
 
 use super::{
-    get_target_commit, load_object, log,
+    commit::{self, CommitArgs},
+    get_target_commit, load_object,
     restore::{self, RestoreArgs},
+    status,
 };
 
 #[derive(Parser, Debug)]
 pub struct MergeArgs {
     /// The branch to merge into the current branch, could be remote branch
-    pub branch: String,
+    #[clap(required_unless_present("abort"))]
+    pub branch: Option<String>,
+
+    /// Abort the current conflict resolution process, and reconstruct the pre-merge state
+    #[clap(long, conflicts_with("branch"))]
+    pub abort: bool,
+
+    /// bypass the pre-commit and commit-msg hooks of the merge commit
+    #[clap(long)]
+    pub no_verify: bool,
 }
 
 pub async fn execute(args: MergeArgs) {
-    let target_commit_hash = get_target_commit(&args.branch).await;
+    if args.abort {
+        merge_abort().await;
+        return;
+    }
+    if path::merge_head().exists() {
+        eprintln!("fatal: You have not concluded your merge (MERGE_HEAD exists).");
+        eprintln!("Please, commit your changes before you merge.");
+        return;
+    }
+    let branch = args.branch.unwrap();
+    let target_commit_hash = get_target_commit(&branch).await;
     if target_commit_hash.is_err() {
         eprintln!("{}", target_commit_hash.err().unwrap());
         return;
@@ -45,43 +84,36 @@ pub async fn execute(args: MergeArgs) {
             &target_commit.id.to_string()[..6]
         );
         // fast-forward merge
-        merge_ff(target_commit).await;
+        merge_ff(target_commit, &branch).await;
     } else {
-        // didn't support yet
-        eprintln!("fatal: Not possible to fast-forward merge, try merge manually");
+        merge_three_way(
+            &lca,
+            &current_commit,
+            &target_commit,
+            &branch,
+            args.no_verify,
+        )
+        .await;
     }
 }
 
-async fn lca_commit(lhs: &Commit, rhs: &Commit) -> Option<Commit> {
-    let lhs_reachable = log::get_reachable_commits(lhs.id.to_string()).await;
-    let rhs_reachable = log::get_reachable_commits(rhs.id.to_string()).await;
-
-    // Commit `eq` is based on tree_id, so we shouldn't use it here
-
-    for commit in lhs_reachable.iter() {
-        if commit.id == rhs.id {
-            return Some(commit.to_owned());
-        }
-    }
-
-    for commit in rhs_reachable.iter() {
-        if commit.id == lhs.id {
-            return Some(commit.to_owned());
-        }
-    }
-
-    for lhs_parent in lhs_reachable.iter() {
-        for rhs_parent in rhs_reachable.iter() {
-            if lhs_parent.id == rhs_parent.id {
-                return Some(lhs_parent.to_owned());
-            }
-        }
-    }
-    None
+/// The best common ancestor of the commits, walked with the generation numbers of the commit-graph
+pub async fn lca_commit(lhs: &Commit, rhs: &Commit) -> Option<Commit> {
+    let base = CommitGraph::open().merge_base(&lhs.id, &rhs.id)?;
+    Some(Commit::load(&base))
 }
 
 /// try merge in fast-forward mode, if it's not possible, do nothing
-async fn merge_ff(commit: Commit) {
+async fn merge_ff(commit: Commit, branch: &str) {
+    let old_head = Head::current_commit().await;
+    let ours_blobs = match old_head {
+        Some(old_head) => commit_blobs(&old_head),
+        None => HashMap::new(),
+    };
+    let incoming = tree_blobs(&commit.tree_id).into_keys();
+    if !check_untracked_overwritten(incoming.filter(|file| !ours_blobs.contains_key(file))).await {
+        return;
+    }
     println!("Fast-forward");
     // fast-forward merge
     let head = Head::current().await;
@@ -93,6 +125,8 @@ async fn merge_ff(commit: Commit) {
             Head::update(Head::Detached(commit.id), None).await;
         }
     }
+    let message = format!("merge {}: Fast-forward", branch);
+    Reflog::log_head_update(old_head, commit.id, &message).await;
     // change the working directory to the commit
     // restore all files to worktree from HEAD
     restore::execute(RestoreArgs {
@@ -102,4 +136,656 @@ async fn merge_ff(commit: Commit) {
         pathspec: vec![util::working_dir_string()],
     })
     .await;
+    // `0`: not a squash merge
+    let _ = hooks::run(hooks::POST_MERGE, &["0"], None).await;
+}
+
+/// Check that no untracked file is at the `files` coming from the other side, which would be overwritten.
+/// The ignored files are expendable, like git. Return `false` with the error printed if there is any.
+pub async fn check_untracked_overwritten(files: impl Iterator<Item = PathBuf>) -> bool {
+    let index = Index::load(path::index()).unwrap();
+    let mut ignore = IgnoreRules::load().await;
+    let mut untracked: Vec<PathBuf> = files
+        .filter(|file| {
+            let file_abs = util::workdir_to_absolute(file);
+            file_abs.exists()
+                && !index.tracked(file.to_str().unwrap(), 0)
+                && !ignore.is_ignored(file, file_abs.is_dir())
+        })
+        .collect();
+    if untracked.is_empty() {
+        return true;
+    }
+    untracked.sort();
+    eprintln!("error: The following untracked working tree files would be overwritten by merge:");
+    for file in untracked {
+        eprintln!("\t{}", util::workdir_to_current(&file).display());
+    }
+    eprintln!("Please move or remove them before you merge.");
+    eprintln!("Aborting");
+    false
+}
+
+/// Merge `theirs` into `ours` with `base` as the merge base, then create a merge commit.
+/// If there are conflicts, stop and leave `MERGE_HEAD` for the user to resolve & commit.
+async fn merge_three_way(
+    base: &Commit,
+    ours: &Commit,
+    theirs: &Commit,
+    branch: &str,
+    no_verify: bool,
+) {
+    if !status::is_clean_tracked().await {
+        status::execute().await;
+        eprintln!("error: Your local changes would be overwritten by merge.");
+        eprintln!("Please commit your changes or stash them before you merge.");
+        return;
+    }
+
+    let Some(conflicts) = merge_into_worktree(
+        Some(base.tree_id),
+        ours.tree_id,
+        theirs.tree_id,
+        "HEAD",
+        branch,
+    )
+    .await
+    else {
+        return;
+    };
+
+    let message = format!("Merge branch '{}'", branch);
+    fs::write(path::merge_head(), theirs.id.to_string()).unwrap();
+    fs::write(path::merge_msg(), &message).unwrap();
+
+    if conflicts.is_empty() {
+        // `commit` takes `MERGE_HEAD` as the second parent & clears the merge state
+        commit::execute(CommitArgs {
+            message,
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: false,
+            no_verify,
+        })
+        .await;
+        // the commit may be aborted by the hooks, then the merge is left to be committed
+        if !path::merge_head().exists() {
+            let _ = hooks::run(hooks::POST_MERGE, &["0"], None).await;
+        }
+    } else {
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+    }
+}
+
+/// Abort the conflicted merge, restore index & worktree to `HEAD`
+async fn merge_abort() {
+    if !path::merge_head().exists() {
+        eprintln!("fatal: There is no merge to abort (MERGE_HEAD missing).");
+        return;
+    }
+    reset_conflicts().await;
+    clear_merge_state();
+}
+
+/// Drop all unmerged entries and restore index & worktree to `HEAD`,
+/// conflicted files which don't exist in `HEAD` are removed from worktree.
+pub async fn reset_conflicts() {
+    let head_items: HashSet<PathBuf> = match Head::current_commit().await {
+        Some(commit_id) => commit_blobs(&commit_id).into_keys().collect(),
+        None => HashSet::new(),
+    };
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    for file in index.unmerged_files() {
+        index.remove_unmerged(file.to_str().unwrap());
+        let file_abs = util::workdir_to_absolute(&file);
+        if !head_items.contains(&file) && file_abs.exists() {
+            fs::remove_file(&file_abs).unwrap();
+            util::clear_empty_dir(&file_abs);
+        }
+    }
+    index.save(&index_file).unwrap();
+
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: Some("HEAD".to_string()),
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+}
+
+/// Read the commit being merged from `MERGE_HEAD`, `None` if not merging
+pub fn read_merge_head() -> Option<SHA1> {
+    let content = fs::read_to_string(path::merge_head()).ok()?;
+    SHA1::from_str(content.trim()).ok()
+}
+
+/// Remove `MERGE_HEAD` & `MERGE_MSG`
+pub fn clear_merge_state() {
+    let _ = fs::remove_file(path::merge_head());
+    let _ = fs::remove_file(path::merge_msg());
+}
+
+/// Get all blobs of a commit, to workdir path
+pub fn commit_blobs(commit_id: &SHA1) -> HashMap<PathBuf, SHA1> {
+    let commit = Commit::load(commit_id);
+    tree_blobs(&commit.tree_id)
+}
+
+/// Get all blobs of a tree recursively, to workdir path
+pub fn tree_blobs(tree_id: &SHA1) -> HashMap<PathBuf, SHA1> {
+    Tree::load(tree_id).get_plain_items().into_iter().collect()
+}
+
+/// The merge result of one path
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergedEntry {
+    /// Merged without conflict, `None` means the file is deleted
+    Clean(Option<SHA1>),
+    /// Conflicted, keep all versions to record as stage 1 (base), 2 (ours) and 3 (theirs).
+    /// - `content`: file content with conflict markers, `None` if it can't be merged by content
+    Conflict {
+        base: Option<SHA1>,
+        ours: Option<SHA1>,
+        theirs: Option<SHA1>,
+        content: Option<String>,
+    },
+}
+
+/// Three-way merge of blobs path by path, the merged blobs are saved to storage.
+/// - `ours_label` & `theirs_label` are used in conflict markers
+pub fn merge_blobs(
+    base: &HashMap<PathBuf, SHA1>,
+    ours: &HashMap<PathBuf, SHA1>,
+    theirs: &HashMap<PathBuf, SHA1>,
+    ours_label: &str,
+    theirs_label: &str,
+) -> BTreeMap<PathBuf, MergedEntry> {
+    let all_paths: HashSet<&PathBuf> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut result = BTreeMap::new();
+    for path in all_paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let entry = if o == t {
+            // both sides are same (or both deleted)
+            MergedEntry::Clean(o.copied())
+        } else if b == o {
+            // only `theirs` changed
+            MergedEntry::Clean(t.copied())
+        } else if b == t {
+            // only `ours` changed
+            MergedEntry::Clean(o.copied())
+        } else {
+            match (o, t) {
+                (Some(o), Some(t)) => {
+                    // both modified or both added, try to merge by content
+                    let load_text = |hash: &SHA1| String::from_utf8(Blob::load(hash).data).ok();
+                    let base_text = match b {
+                        Some(b) => load_text(b),
+                        None => Some(String::new()), // add/add, merge with empty base
+                    };
+                    match (base_text, load_text(o), load_text(t)) {
+                        (Some(base_text), Some(ours_text), Some(theirs_text)) => {
+                            let merged = merge_content(
+                                &base_text,
+                                &ours_text,
+                                &theirs_text,
+                                ours_label,
+                                theirs_label,
+                            );
+                            if merged.conflicted {
+                                MergedEntry::Conflict {
+                                    base: b.copied(),
+                                    ours: Some(*o),
+                                    theirs: Some(*t),
+                                    content: Some(merged.text),
+                                }
+                            } else {
+                                let blob = Blob::from_content(&merged.text);
+                                MergedEntry::Clean(Some(blob.save()))
+                            }
+                        }
+                        _ => MergedEntry::Conflict {
+                            // binary files
+                            base: b.copied(),
+                            ours: Some(*o),
+                            theirs: Some(*t),
+                            content: None,
+                        },
+                    }
+                }
+                _ => MergedEntry::Conflict {
+                    // modify/delete
+                    base: b.copied(),
+                    ours: o.copied(),
+                    theirs: t.copied(),
+                    content: None,
+                },
+            }
+        };
+        result.insert(path.clone(), entry);
+    }
+    result
+}
+
+/// Three-way merge trees into `index` & worktree, which should be at `ours` before calling.
+/// - clean merged files are updated in worktree and recorded as stage 0
+/// - conflicted files are recorded as stage 1, 2 & 3, and written to worktree with conflict markers
+/// - `base`: `None` means no common ancestor, merged as two root trees
+///
+/// return the conflicted paths (to workdir),
+/// `None` if untracked files would be overwritten, then nothing is changed and the error is printed
+pub async fn merge_into_worktree(
+    base: Option<SHA1>,
+    ours: SHA1,
+    theirs: SHA1,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Option<Vec<PathBuf>> {
+    let base_blobs = base.map(|id| tree_blobs(&id)).unwrap_or_default();
+    let ours_blobs = tree_blobs(&ours);
+    let theirs_blobs = tree_blobs(&theirs);
+    let merged = merge_blobs(
+        &base_blobs,
+        &ours_blobs,
+        &theirs_blobs,
+        ours_label,
+        theirs_label,
+    );
+    // the files written to the worktree but not in `ours`
+    let incoming = merged
+        .iter()
+        .filter(|(file, entry)| {
+            !ours_blobs.contains_key(*file) && **entry != MergedEntry::Clean(None)
+        })
+        .map(|(file, _)| file.clone());
+    if !check_untracked_overwritten(incoming).await {
+        return None;
+    }
+
+    let workdir = util::working_dir();
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    let mut conflicts = Vec::new();
+    for (file, entry) in merged {
+        let file_str = file.to_string_or_panic();
+        let file_abs = util::workdir_to_absolute(&file);
+        match entry {
+            MergedEntry::Clean(Some(hash)) => {
+                if ours_blobs.get(&file) != Some(&hash) {
+                    restore::restore_to_file(&hash, &file).await.unwrap();
+                    index.update(IndexEntry::new_from_file(&file, hash, &workdir).unwrap());
+                }
+            }
+            MergedEntry::Clean(None) => {
+                if ours_blobs.contains_key(&file) {
+                    if file_abs.exists() {
+                        fs::remove_file(&file_abs).unwrap();
+                        util::clear_empty_dir(&file_abs);
+                    }
+                    index.remove(&file_str, 0);
+                }
+            }
+            MergedEntry::Conflict {
+                base,
+                ours,
+                theirs,
+                content,
+            } => {
+                index.remove(&file_str, 0);
+                for (stage, hash) in [(1, base), (2, ours), (3, theirs)] {
+                    if let Some(hash) = hash {
+                        let blob = Blob::load(&hash);
+                        let mut entry = IndexEntry::new_from_blob(
+                            file_str.clone(),
+                            hash,
+                            blob.data.len() as u32,
+                        );
+                        entry.flags.stage = stage;
+                        index.add(entry);
+                    }
+                }
+                match (content, ours, theirs) {
+                    (Some(content), _, _) => {
+                        util::write_file(content.as_bytes(), &file_abs).unwrap();
+                        println!("CONFLICT (content): Merge conflict in {}", file.display());
+                    }
+                    (None, Some(_), Some(_)) => {
+                        // keep `ours` in worktree
+                        println!("warning: Cannot merge binary files: {}", file.display());
+                        println!("CONFLICT (content): Merge conflict in {}", file.display());
+                    }
+                    (None, Some(_), None) => {
+                        // keep `ours` in worktree
+                        println!(
+                            "CONFLICT (modify/delete): {} deleted in {} and modified in {}.",
+                            file.display(),
+                            theirs_label,
+                            ours_label
+                        );
+                    }
+                    (None, None, Some(hash)) => {
+                        restore::restore_to_file(&hash, &file).await.unwrap();
+                        println!(
+                            "CONFLICT (modify/delete): {} deleted in {} and modified in {}.",
+                            file.display(),
+                            ours_label,
+                            theirs_label
+                        );
+                    }
+                    (None, None, None) => unreachable!("both deleted should be clean"),
+                }
+                conflicts.push(file);
+            }
+        }
+    }
+    index.save(&index_file).unwrap();
+    Some(conflicts)
+}
+
+/// The result of [merge_content]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentMerge {
+    pub text: String,
+    pub conflicted: bool,
+}
+
+/// A changed region, `base` lines are replaced by `new` lines of the other side
+#[derive(Debug)]
+struct Hunk {
+    base: Range<usize>,
+    new: Range<usize>,
+}
+
+/// Diff `base` with `other` by lines, adjacent changes are combined into one hunk
+fn diff_hunks(base: &[&str], other: &[&str]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, base, other) {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let (old_range, new_range) = (op.old_range(), op.new_range());
+        match hunks.last_mut() {
+            Some(last) if last.base.end == old_range.start => {
+                last.base.end = old_range.end;
+                last.new.end = new_range.end;
+            }
+            _ => hunks.push(Hunk {
+                base: old_range,
+                new: new_range,
+            }),
+        }
+    }
+    hunks
+}
+
+/// Apply `hunks` of one side to `base[range]`
+fn apply_hunks<'a>(
+    base: &[&'a str],
+    other: &[&'a str],
+    hunks: &[Hunk],
+    range: Range<usize>,
+) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut pos = range.start;
+    for hunk in hunks {
+        lines.extend_from_slice(&base[pos..hunk.base.start]);
+        lines.extend_from_slice(&other[hunk.new.clone()]);
+        pos = hunk.base.end;
+    }
+    lines.extend_from_slice(&base[pos..range.end]);
+    lines
+}
+
+/// Line-based three-way merge (diff3), overlapping or adjacent changes of both sides are conflicts.
+/// Conflicts are wrapped by markers like git:
+/// ```text
+/// <<<<<<< ours_label
+/// ours
+/// =======
+/// theirs
+/// >>>>>>> theirs_label
+/// ```
+pub fn merge_content(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    ours_label: &str,
+    theirs_label: &str,
+) -> ContentMerge {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let ours_hunks = diff_hunks(&base_lines, &ours_lines);
+    let theirs_hunks = diff_hunks(&base_lines, &theirs_lines);
+
+    let mut text = String::new();
+    let mut conflicted = false;
+    let (mut i, mut j) = (0, 0); // next hunk of ours & theirs
+    let mut pos = 0; // lines before `pos` of base have been processed
+    loop {
+        // start a region with the first unprocessed hunk
+        let start = match (ours_hunks.get(i), theirs_hunks.get(j)) {
+            (None, None) => break,
+            (Some(o), None) => o.base.start,
+            (None, Some(t)) => t.base.start,
+            (Some(o), Some(t)) => o.base.start.min(t.base.start),
+        };
+        let (ours_begin, theirs_begin) = (i, j);
+        let mut end = start;
+        // extend the region with all overlapping or adjacent hunks of both sides
+        loop {
+            if let Some(o) = ours_hunks.get(i).filter(|o| o.base.start <= end) {
+                end = end.max(o.base.end);
+                i += 1;
+            } else if let Some(t) = theirs_hunks.get(j).filter(|t| t.base.start <= end) {
+                end = end.max(t.base.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        text.extend(base_lines[pos..start].iter().copied());
+        let ours_region = apply_hunks(
+            &base_lines,
+            &ours_lines,
+            &ours_hunks[ours_begin..i],
+            start..end,
+        );
+        let theirs_region = apply_hunks(
+            &base_lines,
+            &theirs_lines,
+            &theirs_hunks[theirs_begin..j],
+            start..end,
+        );
+        if ours_begin == i {
+            text.extend(theirs_region); // only theirs changed
+        } else if theirs_begin == j || ours_region == theirs_region {
+            text.extend(ours_region); // only ours changed, or both changed in the same way
+        } else {
+            conflicted = true;
+            let push_region = |text: &mut String, region: Vec<&str>| {
+                text.extend(region);
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+            };
+            text.push_str(&format!("<<<<<<< {}\n", ours_label));
+            push_region(&mut text, ours_region);
+            text.push_str("=======\n");
+            push_region(&mut text, theirs_region);
+            text.push_str(&format!(">>>>>>> {}\n", theirs_label));
+        }
+        pos = end;
+    }
+    text.extend(base_lines[pos..].iter().copied());
+
+    ContentMerge { text, conflicted }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::branch;
+    use crate::utils::test;
+    use serial_test::serial;
+
+    /// create `master` & `feature` which both modified `a.txt` based on the same commit
+    async fn setup_diverged_branches(master_a: &str, feature_a: &str) {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\nb\nc\n"));
+        test::ensure_file("b.txt", Some("b\n"));
+        test::commit_all("base").await;
+        branch::create_branch("feature".to_string(), None).await;
+
+        test::ensure_file("a.txt", Some(master_a));
+        test::commit_all("master").await;
+
+        test::switch_to("feature").await;
+        test::ensure_file("a.txt", Some(feature_a));
+        fs::remove_file("b.txt").unwrap();
+        test::ensure_file("c.txt", Some("c\n"));
+        test::commit_all("feature").await;
+        test::switch_to("master").await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_merge_three_way_clean() {
+        setup_diverged_branches("A\nb\nc\n", "a\nb\nC\n").await;
+        let master = Head::current_commit().await.unwrap();
+        let feature = Branch::find_branch("feature", None).await.unwrap().commit;
+
+        execute(MergeArgs::parse_from(["merge", "feature"])).await;
+
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "A\nb\nC\n");
+        assert!(!PathBuf::from("b.txt").exists());
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "c\n");
+        assert!(!path::merge_head().exists());
+
+        let merge_commit = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(merge_commit.parent_commit_ids, vec![master, feature]);
+        assert!(status::is_clean().await);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_merge_three_way_conflict() {
+        setup_diverged_branches("a\nmaster\nc\n", "a\nfeature\nc\n").await;
+        let master = Head::current_commit().await.unwrap();
+
+        execute(MergeArgs::parse_from(["merge", "feature"])).await;
+
+        assert_eq!(
+            fs::read_to_string("a.txt").unwrap(),
+            "a\n<<<<<<< HEAD\nmaster\n=======\nfeature\n>>>>>>> feature\nc\n"
+        );
+        let index = Index::load(path::index()).unwrap();
+        assert_eq!(index.unmerged_files(), vec![PathBuf::from("a.txt")]);
+        assert!(
+            index.tracked("a.txt", 1) && index.tracked("a.txt", 2) && index.tracked("a.txt", 3)
+        );
+        assert!(!PathBuf::from("b.txt").exists()); // merged cleanly
+        assert_eq!(
+            read_merge_head(),
+            Some(Branch::find_branch("feature", None).await.unwrap().commit)
+        );
+        assert_eq!(Head::current_commit().await.unwrap(), master); // no commit created
+
+        execute(MergeArgs::parse_from(["merge", "--abort"])).await;
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\nmaster\nc\n");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "b\n");
+        assert!(!PathBuf::from("c.txt").exists());
+        assert!(!Index::load(path::index()).unwrap().has_conflicts());
+        assert!(read_merge_head().is_none());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_merge_untracked_overwritten() {
+        setup_diverged_branches("A\nb\nc\n", "a\nb\nC\n").await;
+        let master = Head::current_commit().await.unwrap();
+        // `c.txt` is added on `feature`
+        test::ensure_file("c.txt", Some("untracked\n"));
+
+        execute(MergeArgs::parse_from(["merge", "feature"])).await;
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "untracked\n");
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "A\nb\nc\n");
+        assert!(!path::merge_head().exists());
+        assert_eq!(Head::current_commit().await.unwrap(), master);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_merge_ff_untracked_overwritten() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\n"));
+        test::commit_all("base").await;
+        let base = Head::current_commit().await.unwrap();
+        branch::create_branch("feature".to_string(), None).await;
+        test::switch_to("feature").await;
+        test::ensure_file("d.txt", Some("d\n"));
+        test::commit_all("add d").await;
+        test::switch_to("master").await;
+        test::ensure_file("d.txt", Some("untracked\n"));
+        execute(MergeArgs::parse_from(["merge", "feature"])).await;
+        assert_eq!(fs::read_to_string("d.txt").unwrap(), "untracked\n");
+        assert_eq!(Head::current_commit().await.unwrap(), base);
+    }
+
+    #[test]
+    fn test_merge_content_clean() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "a\nB\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\n";
+        let merged = merge_content(base, ours, theirs, "HEAD", "feature");
+        assert!(!merged.conflicted);
+        assert_eq!(merged.text, "a\nB\nc\nd\nE\n");
+
+        // both sides make the same change
+        let merged = merge_content(base, ours, ours, "HEAD", "feature");
+        assert!(!merged.conflicted);
+        assert_eq!(merged.text, ours);
+    }
+
+    #[test]
+    fn test_merge_content_conflict() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nours\nc\n";
+        let theirs = "a\ntheirs\nc\n";
+        let merged = merge_content(base, ours, theirs, "HEAD", "feature");
+        assert!(merged.conflicted);
+        assert_eq!(
+            merged.text,
+            "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_content_add_add() {
+        // no base, e.g. both sides add the same file
+        let merged = merge_content("", "same\n", "same\n", "HEAD", "feature");
+        assert!(!merged.conflicted);
+        assert_eq!(merged.text, "same\n");
+
+        let merged = merge_content("", "ours", "theirs", "HEAD", "feature");
+        assert!(merged.conflicted);
+        assert_eq!(
+            merged.text,
+            "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert!(MergeArgs::try_parse_from(["merge", "feature"]).is_ok());
+        assert!(MergeArgs::try_parse_from(["merge", "--abort"]).is_ok());
+        assert!(MergeArgs::try_parse_from(["merge"]).is_err());
+        assert!(MergeArgs::try_parse_from(["merge", "--abort", "feature"]).is_err());
+    }
 }
@@ -1,27 +1,48 @@
 pub mod add;
+pub mod blame;
 pub mod branch;
+pub mod cat_file;
+pub mod check_ignore;
 pub mod checkout;
+pub mod cherry_pick;
+pub mod clean;
 pub mod clone;
 pub mod commit;
 pub mod config;
+pub mod credential;
 pub mod diff;
 pub mod fetch;
+pub mod gc;
 pub mod index_pack;
 pub mod init;
 pub mod lfs;
 pub mod log;
+pub mod ls_tree;
 pub mod merge;
+pub mod prune;
 pub mod pull;
 pub mod push;
+pub mod rebase;
+pub mod reflog;
 pub mod remote;
 pub mod remove;
+pub mod repack;
+pub mod reset;
 pub mod restore;
+pub mod rev_parse;
+pub mod revert;
+pub mod show;
+pub mod sparse_checkout;
+pub mod stash;
 pub mod status;
+pub mod submodule;
 pub mod switch;
+pub mod tag;
+pub mod verify_commit;
+pub mod worktree;
 
-use crate::internal::branch::Branch;
-use crate::internal::head::Head;
-use crate::internal::protocol::https_client::BasicAuth;
+use crate::internal::credential::Credential;
+use crate::internal::revision;
 use crate::utils;
 use crate::utils::object_ext::BlobExt;
 use crate::utils::util;
@@ -32,8 +53,6 @@ use std::io;
 use std::io::Write;
 use std::path::Path;
 
-const HEAD: &str = "HEAD";
-
 // impl load for all objects
 fn load_object<T>(hash: &SHA1) -> Result<T, GitError>
 where
@@ -55,19 +74,25 @@ where
     Ok(())
 }
 
-/// Ask for username and password (CLI interaction)
-fn ask_username_password() -> (String, String) {
-    print!("username: ");
-    // Normally your OS will buffer output by line when it's connected to a terminal,
-    // which is why it usually flushes when a newline is written to stdout.
-    io::stdout().flush().unwrap(); // ensure the prompt is shown
-    let mut username = String::new();
-    io::stdin().read_line(&mut username).unwrap();
-    username = username.trim().to_string();
-    tracing::debug!("username: {}", username);
+/// Ask for the missing username and the password of `cred` (CLI interaction)
+/// - the prompts go to `stderr`, `stdout` may be the output of `credential fill`
+pub fn ask_credential(cred: &mut Credential) {
+    let target = match (&cred.protocol, &cred.host) {
+        (Some(protocol), Some(host)) => format!(" for '{}://{}'", protocol, host),
+        _ => String::new(),
+    };
+    if cred.username.is_none() {
+        eprint!("Username{}: ", target);
+        io::stderr().flush().unwrap(); // ensure the prompt is shown
+        let mut username = String::new();
+        io::stdin().read_line(&mut username).unwrap();
+        username = username.trim().to_string();
+        tracing::debug!("username: {}", username);
+        cred.username = Some(username);
+    }
 
-    print!("password: ");
-    io::stdout().flush().unwrap();
+    eprint!("Password{}: ", target);
+    io::stderr().flush().unwrap();
     let password = if std::env::var("LIBRA_NO_HIDE_PASSWORD").is_ok() {
         // for test
         let mut password = String::new();
@@ -79,13 +104,7 @@ fn ask_username_password() -> (String, String) {
         // error in test environment: "No such device or address"
         read_password().unwrap() // hide password
     };
-    (username, password)
-}
-
-/// same as ask_username_password, but return BasicAuth
-pub fn ask_basic_auth() -> BasicAuth {
-    let (username, password) = ask_username_password();
-    BasicAuth { username, password }
+    cred.password = Some(password);
 }
 
 /// Calculate the hash of a file blob
@@ -100,31 +119,10 @@ pub fn calc_file_blob_hash(path: impl AsRef<Path>) -> io::Result<SHA1> {
     Ok(blob.id)
 }
 
-/// Get the commit hash from branch name or commit hash, support remote branch
+/// Get the commit hash from a revision, e.g. branch (local or remote), tag, hash, `HEAD~2`, `master@{1}`.
+/// See [revision] for the full syntax.
 pub async fn get_target_commit(branch_or_commit: &str) -> Result<SHA1, Box<dyn std::error::Error>> {
-    if branch_or_commit == HEAD {
-        return Ok(Head::current_commit().await.unwrap());
-    }
-
-    let possible_branches = Branch::search_branch(branch_or_commit).await;
-    if possible_branches.len() > 1 {
-        return Err("Ambiguous branch name".into());
-        // TODO: git have a priority list of branches to use, continue with ambiguity, we didn't implement it yet
-    }
-
-    if possible_branches.is_empty() {
-        let storage = util::objects_storage();
-        let possible_commits = storage.search(branch_or_commit);
-        if possible_commits.len() > 1 {
-            return Err(format!("Ambiguous commit hash '{}'", branch_or_commit).into());
-        }
-        if possible_commits.is_empty() {
-            return Err(format!("No such branch or commit: '{}'", branch_or_commit).into());
-        }
-        Ok(possible_commits[0])
-    } else {
-        Ok(possible_branches[0].commit)
-    }
+    Ok(revision::resolve_commit(branch_or_commit).await?)
 }
 
 #[cfg(test)]
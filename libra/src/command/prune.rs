@@ -0,0 +1,254 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::command::log::parse_date;
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::internal::shallow;
+use crate::internal::tag::Tag;
+use crate::utils::{util, worktree};
+
+/// The default grace period of unreachable objects, same as `gc.pruneExpire` of git
+pub const DEFAULT_EXPIRE: &str = "2 weeks ago";
+
+#[derive(Parser, Debug)]
+pub struct PruneArgs {
+    /// Only prune the unreachable loose objects older than this date,
+    /// e.g. `2 weeks ago`, `2024-01-01` or a timestamp; `now` to prune all of them
+    #[clap(long, default_value = DEFAULT_EXPIRE)]
+    pub expire: String,
+    /// Do not remove anything, just report what would be removed
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+pub async fn execute(args: PruneArgs) {
+    let expire = match parse_expire(&args.expire) {
+        Ok(expire) => expire,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let reachable = reachable_objects().await;
+    for (obj_id, obj_type) in prune_loose(&reachable, expire, args.dry_run) {
+        if args.dry_run {
+            println!("{} {}", obj_id, obj_type);
+        }
+    }
+}
+
+/// Parse the expiry date to a timestamp, `now` means all objects are expired
+pub fn parse_expire(expire: &str) -> Result<i64, String> {
+    match expire {
+        "now" => Ok(i64::MAX),
+        date => parse_date(date),
+    }
+}
+
+/// Remove the loose objects which are not in `keep` and were modified before `expire` (a timestamp),
+/// return the removed (or would be removed in `dry_run`) objects
+pub fn prune_loose(keep: &HashSet<SHA1>, expire: i64, dry_run: bool) -> Vec<(SHA1, ObjectType)> {
+    let storage = util::objects_storage();
+    let mut pruned = Vec::new();
+    for obj_id in storage.list_objects_loose() {
+        if keep.contains(&obj_id) {
+            continue;
+        }
+        // the grace period protects the objects just written by a running command, e.g. `add`
+        let mtime = fs::metadata(storage.get_obj_path(&obj_id))
+            .and_then(|meta| meta.modified())
+            .map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64)
+            .unwrap_or(i64::MAX);
+        if mtime >= expire {
+            continue;
+        }
+        let obj_type = match storage.get_object_type(&obj_id) {
+            Ok(obj_type) => obj_type,
+            Err(_) => continue, // corrupt object, leave it to the user
+        };
+        if !dry_run {
+            if let Err(e) = storage.remove_loose(&obj_id) {
+                eprintln!("warning: failed to remove {}: {}", obj_id, e);
+                continue;
+            }
+        }
+        pruned.push((obj_id, obj_type));
+    }
+    pruned
+}
+
+/// All objects reachable from branches (local & remote), tags, HEAD, reflogs, the index
+/// and the state of an in-progress merge, rebase, cherry-pick or revert, of all the worktrees.
+pub async fn reachable_objects() -> HashSet<SHA1> {
+    let mut roots = ref_tips().await;
+    for entry in Reflog::list_all().await {
+        roots.push(entry.old_oid);
+        roots.push(entry.new_oid);
+    }
+    for storage in worktree_storages().await {
+        if let Ok(index) = Index::load(storage.join("index")) {
+            for stage in 0..=3 {
+                roots.extend(index.tracked_entries(stage).iter().map(|e| e.hash));
+            }
+        }
+        roots.extend(state_commits(&storage));
+    }
+
+    let mut reachable = HashSet::new();
+    walk_objects(roots, |obj_id| reachable.insert(*obj_id));
+    reachable
+}
+
+/// The objects pointed by the HEADs of the worktrees, branches (local & remote) and tags
+pub async fn ref_tips() -> Vec<SHA1> {
+    let mut tips: Vec<SHA1> = Vec::new();
+    for worktree in worktree::list().await {
+        if let Some(Head::Detached(commit)) = worktree.head {
+            tips.push(commit); // the attached ones are branches
+        }
+    }
+    tips.extend(Branch::list_branches(None).await.iter().map(|b| b.commit));
+    for remote in Config::all_remote_configs().await {
+        let branches = Branch::list_branches(Some(&remote.name)).await;
+        tips.extend(branches.iter().map(|b| b.commit));
+    }
+    tips.extend(Tag::list_tags().await.iter().map(|t| t.object));
+    tips
+}
+
+/// Walk the objects reachable from `roots`, `visit` returns if the objects it refers to should be walked,
+/// e.g. `false` for a visited object.
+/// - The objects missing from a shallow or partial clone are skipped, rather than fetched.
+pub fn walk_objects(mut roots: Vec<SHA1>, mut visit: impl FnMut(&SHA1) -> bool) {
+    let storage = util::objects_storage();
+    while let Some(obj_id) = roots.pop() {
+        if !storage.exist(&obj_id) || !visit(&obj_id) {
+            continue;
+        }
+        let obj_type = storage.get_object_type(&obj_id).unwrap();
+        if obj_type == ObjectType::Blob {
+            continue;
+        }
+        let data = storage.get(&obj_id).unwrap();
+        match obj_type {
+            ObjectType::Commit => {
+                let mut commit = Commit::from_bytes(&data, obj_id).unwrap();
+                shallow::graft(&mut commit);
+                roots.push(commit.tree_id);
+                roots.extend(commit.parent_commit_ids);
+            }
+            ObjectType::Tree => {
+                let tree = Tree::from_bytes(&data, obj_id).unwrap();
+                let items = tree.tree_items.into_iter();
+                // a submodule commit is in another repository
+                roots.extend(
+                    items
+                        .filter(|i| i.mode != TreeItemMode::Commit)
+                        .map(|i| i.id),
+                );
+            }
+            ObjectType::Tag => {
+                let tag = TagObject::from_bytes(&data, obj_id).unwrap();
+                roots.push(tag.object_hash);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The storages of all the worktrees, `.libra` & `.libra/worktrees/*`
+async fn worktree_storages() -> Vec<PathBuf> {
+    let mut storages = vec![util::storage_path()];
+    storages.extend(worktree::list().await.into_iter().filter_map(|w| w.storage));
+    storages
+}
+
+/// The commits recorded by an in-progress merge, rebase, cherry-pick or revert,
+/// any hash in their state files is taken, so new state files are covered as well
+fn state_commits(storage: &Path) -> Vec<SHA1> {
+    // the names of `path::merge_head`, `path::rebase_merge` & `path::sequencer` in the storage
+    let mut files = vec![storage.join("MERGE_HEAD")];
+    for dir in [storage.join("rebase-merge"), storage.join("sequencer")] {
+        if let Ok(entries) = fs::read_dir(dir) {
+            files.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+        }
+    }
+    files
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .flat_map(|content| {
+            content
+                .split_whitespace()
+                .filter_map(|word| SHA1::from_str(word).ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::commit::{self, CommitArgs};
+    use crate::command::save_object;
+    use crate::utils::test;
+    use mercury::internal::object::blob::Blob;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_prune_unreachable() {
+        test::setup_with_new_libra().await;
+        commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let head = Head::current_commit().await.unwrap();
+        let dangling = Blob::from_content("dangling");
+        save_object(&dangling, &dangling.id).unwrap();
+
+        let reachable = reachable_objects().await;
+        assert!(reachable.contains(&head));
+        assert!(!reachable.contains(&dangling.id));
+
+        // within the grace period
+        execute(PruneArgs {
+            expire: DEFAULT_EXPIRE.to_string(),
+            dry_run: false,
+        })
+        .await;
+        let storage = util::objects_storage();
+        assert!(storage.exist(&dangling.id));
+
+        let pruned = prune_loose(&reachable, i64::MAX, true);
+        assert_eq!(pruned, vec![(dangling.id, ObjectType::Blob)]);
+        assert!(storage.exist(&dangling.id));
+
+        execute(PruneArgs {
+            expire: "now".to_string(),
+            dry_run: false,
+        })
+        .await;
+        assert!(!storage.exist(&dangling.id));
+        assert!(storage.exist(&head));
+    }
+}
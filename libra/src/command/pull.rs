@@ -26,6 +26,8 @@ pub async fn execute(args: PullArgs) {
         repository: args.repository,
         refspec: args.refspec,
         all: false,
+        depth: None,
+        filter: None,
     })
     .await;
 
@@ -34,7 +36,9 @@ pub async fn execute(args: PullArgs) {
         Head::Branch(name) => match Config::branch_config(&name).await {
             Some(branch_config) => {
                 let merge_args = merge::MergeArgs {
-                    branch: format!("{}/{}", branch_config.remote, branch_config.merge),
+                    branch: Some(format!("{}/{}", branch_config.remote, branch_config.merge)),
+                    abort: false,
+                    no_verify: false,
                 };
                 merge::execute(merge_args).await;
             }
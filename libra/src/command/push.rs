@@ -1,11 +1,13 @@
-use crate::command::branch;
+use crate::command::{branch, load_object};
 use crate::internal::branch::Branch;
+use crate::internal::commit_graph::CommitGraph;
 use crate::internal::config::Config;
 use crate::internal::head::Head;
-use crate::internal::protocol::https_client::HttpsClient;
 use crate::internal::protocol::lfs_client::LFSClient;
-use crate::internal::protocol::ProtocolClient;
+use crate::internal::protocol::{self, ProtocolClient, RemoteClient};
+use crate::internal::tag::Tag;
 use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::utils::{bitmap, hooks, util};
 use bytes::BytesMut;
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
 use ceres::protocol::ServiceType::ReceivePack;
@@ -14,31 +16,42 @@ use colored::Colorize;
 use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag as TagObject;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
 use mercury::internal::pack::encode::PackEncoder;
 use mercury::internal::pack::entry::Entry;
 use std::collections::{HashSet, VecDeque};
 use std::io::Write;
 use std::str::FromStr;
 use tokio::sync::mpsc;
-use url::Url;
 
 #[derive(Parser, Debug)]
 pub struct PushArgs {
     // TODO --force
     /// repository, e.g. origin
-    #[clap(requires("refspec"))]
+    #[clap(requires("target"))]
     repository: Option<String>,
-    /// ref to push, e.g. master
-    #[clap(requires("repository"))]
+    /// ref to push, e.g. master or a tag name
+    #[clap(requires("repository"), group = "target")]
     refspec: Option<String>,
 
     #[clap(long, short = 'u', requires("refspec"), requires("repository"))]
     set_upstream: bool,
+
+    /// push all tags (`refs/tags/*`)
+    #[clap(long, group = "target")]
+    tags: bool,
+
+    /// bypass the pre-push hook
+    #[clap(long)]
+    no_verify: bool,
 }
 
 pub async fn execute(args: PushArgs) {
-    if args.repository.is_some() ^ args.refspec.is_some() {
+    if (args.refspec.is_some() && args.repository.is_none())
+        || (args.repository.is_some() && args.refspec.is_none() && !args.tags)
+    {
         // must provide both or none
         eprintln!("fatal: both repository and refspec should be provided");
         return;
@@ -68,21 +81,49 @@ pub async fn execute(args: PushArgs) {
     };
     let repo_url = Config::get_remote_url(&repository).await;
 
+    // (local object, remote ref) to update
+    let mut updates: Vec<(SHA1, String)> = Vec::new();
     let branch = args.refspec.unwrap_or(branch);
-    let commit_hash = Branch::find_branch(&branch, None)
-        .await
-        .unwrap()
-        .commit
-        .to_string();
-
-    println!(
-        "pushing {}({}) to {}({})",
-        branch, commit_hash, repository, repo_url
-    );
-
-    let url = Url::parse(&repo_url).unwrap();
-    let client = HttpsClient::from_url(&url);
-    let refs = match client.discovery_reference(ReceivePack).await {
+    let mut push_branch = false;
+    if args.tags {
+        for tag in Tag::list_tags().await {
+            updates.push((tag.object, format!("refs/tags/{}", tag.name)));
+        }
+    } else if let Some(local_branch) = Branch::find_branch(&branch, None).await {
+        let tracked_branch = Config::get("branch", Some(&branch), "merge")
+            .await // New branch may not have tracking branch
+            .unwrap_or_else(|| format!("refs/heads/{}", branch));
+        updates.push((local_branch.commit, tracked_branch));
+        push_branch = true;
+    } else if let Some(tag) = Tag::find_tag(&branch).await {
+        updates.push((tag.object, format!("refs/tags/{}", tag.name)));
+    } else {
+        eprintln!("error: src refspec {} does not match any", branch);
+        return;
+    }
+
+    for (local, remote_ref) in &updates {
+        println!(
+            "pushing {}({}) to {}({})",
+            remote_ref, local, repository, repo_url
+        );
+    }
+
+    let url = match protocol::parse_remote_url(&repo_url) {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let client = match RemoteClient::from_url(&url) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    let refs = match client.discovery_reference(ReceivePack, &[]).await {
         Ok(refs) => refs,
         Err(e) => {
             eprintln!("fatal: {}", e);
@@ -90,36 +131,77 @@ pub async fn execute(args: PushArgs) {
         }
     };
 
-    let tracked_branch = Config::get("branch", Some(&branch), "merge")
-        .await // New branch may not have tracking branch
-        .unwrap_or_else(|| format!("refs/heads/{}", branch));
-
-    let tracked_ref = refs.iter().find(|r| r._ref == tracked_branch);
-    // [0; 20] if new branch
-    let remote_hash = tracked_ref
-        .map(|r| r._hash.clone())
-        .unwrap_or(SHA1::default().to_string());
-    if remote_hash == commit_hash {
+    // (local object, remote ref, remote object), [0; 20] if new ref
+    let updates: Vec<(SHA1, String, SHA1)> = updates
+        .into_iter()
+        .map(|(local, remote_ref)| {
+            let remote_hash = refs
+                .iter()
+                .find(|r| r._ref == remote_ref)
+                .map(|r| SHA1::from_str(&r._hash).unwrap())
+                .unwrap_or_default();
+            (local, remote_ref, remote_hash)
+        })
+        .filter(|(local, _, remote_hash)| local != remote_hash)
+        .collect();
+    if updates.is_empty() {
         println!("Everything up-to-date");
         return;
     }
 
+    if !args.no_verify {
+        // `<local ref> <local object> <remote ref> <remote object>` per line
+        let input: String = updates
+            .iter()
+            .map(|(local, remote_ref, remote_hash)| {
+                let local_ref = if remote_ref.starts_with("refs/tags/") {
+                    remote_ref.clone()
+                } else {
+                    format!("refs/heads/{}", branch)
+                };
+                format!("{} {} {} {}\n", local_ref, local, remote_ref, remote_hash)
+            })
+            .collect();
+        if let Err(e) = hooks::run(hooks::PRE_PUSH, &[&repository, &repo_url], Some(&input)).await {
+            eprintln!("error: {}, failed to push to '{}'", e, repo_url);
+            return;
+        }
+    }
+
     let mut data = BytesMut::new();
-    add_pkt_line_string(
-        &mut data,
-        format!(
-            "{} {} {}\0report-status\n",
-            remote_hash, commit_hash, tracked_branch
-        ),
-    );
+    for (i, (local, remote_ref, remote_hash)) in updates.iter().enumerate() {
+        // capabilities are declared behind the first command
+        let capabilities = if i == 0 { "\0report-status" } else { "" };
+        add_pkt_line_string(
+            &mut data,
+            format!("{} {} {}{}\n", remote_hash, local, remote_ref, capabilities),
+        );
+    }
     data.extend_from_slice(b"0000");
     tracing::debug!("{:?}", data);
 
     // TODO 考虑remote有多个refs，可以少发一点commits
-    let objs = incremental_objs(
-        SHA1::from_str(&commit_hash).unwrap(),
-        SHA1::from_str(&remote_hash).unwrap(),
-    );
+    let storage = util::objects_storage();
+    let current_remote_branch = refs
+        .iter()
+        .find(|r| r._ref == format!("refs/heads/{}", branch))
+        .map(|r| SHA1::from_str(&r._hash).unwrap());
+    let mut objs = HashSet::new();
+    for (local, _, remote_hash) in &updates {
+        // objects reachable from a commit which remote has (and also exists locally) are not sent
+        let base = [Some(*remote_hash), current_remote_branch]
+            .into_iter()
+            .flatten()
+            .find(|hash| storage.exist(hash))
+            .map(|hash| Tag::peel(&hash))
+            .unwrap_or_default();
+        objs.extend(incremental_objs(Tag::peel(local), base));
+        if storage.is_object_type(local, ObjectType::Tag) {
+            // annotated tag object itself
+            let tag: TagObject = load_object(local).unwrap();
+            objs.insert(tag.into());
+        }
+    }
 
     {
         // upload lfs files
@@ -152,29 +234,39 @@ pub async fn execute(args: PushArgs) {
     data.extend_from_slice(&pack_data);
     println!("Delta compression done.");
 
-    let res = client.send_pack(data.freeze()).await.unwrap(); // TODO: send stream
-
-    if res.status() != 200 {
-        eprintln!("status code: {}", res.status());
-    }
-    let mut data = res.bytes().await.unwrap();
+    let mut data = match client.send_pack(data.freeze()).await {
+        // TODO: send stream
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
     let (_, pkt_line) = read_pkt_line(&mut data);
     if pkt_line != "unpack ok\n" {
         eprintln!("fatal: unpack failed");
         return;
     }
-    let (_, pkt_line) = read_pkt_line(&mut data);
-    if !pkt_line.starts_with("ok".as_ref()) {
-        eprintln!("fatal: ref update failed [{:?}]", pkt_line);
+    // one status line for each ref, until flush-pkt
+    let mut failed = false;
+    loop {
+        let (len, pkt_line) = read_pkt_line(&mut data);
+        if len == 0 {
+            break;
+        }
+        if !pkt_line.starts_with("ok".as_ref()) {
+            eprintln!("fatal: ref update failed [{:?}]", pkt_line);
+            failed = true;
+        }
+    }
+    if failed {
         return;
     }
-    let (len, _) = read_pkt_line(&mut data);
-    assert_eq!(len, 0);
 
     println!("{}", "Push success".green());
 
     // set after push success
-    if args.set_upstream {
+    if args.set_upstream && push_branch {
         branch::set_upstream(&branch, &format!("{}/{}", repository, branch)).await;
     }
 }
@@ -186,23 +278,31 @@ fn collect_history_commits(commit_id: &SHA1) -> HashSet<SHA1> {
         return HashSet::new();
     }
 
-    let mut commits = HashSet::new();
-    let mut queue = VecDeque::new();
-    queue.push_back(*commit_id);
-    while let Some(commit) = queue.pop_front() {
-        commits.insert(commit);
-
-        let commit = Commit::load(&commit);
-        for parent in commit.parent_commit_ids.iter() {
-            queue.push_back(*parent);
-        }
-    }
-    commits
+    // from the commit-graph, rather than loading every commit
+    CommitGraph::open()
+        .ancestors(&[*commit_id])
+        .into_iter()
+        .collect()
 }
 
 fn incremental_objs(local_ref: SHA1, remote_ref: SHA1) -> HashSet<Entry> {
     tracing::debug!("local_ref: {}, remote_ref: {}", local_ref, remote_ref);
 
+    // reachability bitmaps, no need to walk the trees of the commits with bitmaps
+    // (`remote_ref` is zero if the remote branch is new, which is skipped in the walk)
+    if let Some(objs) = bitmap::objects_between(&[local_ref], &[remote_ref]) {
+        let storage = util::objects_storage();
+        println!("Counting objects: {} done (bitmap).", objs.len());
+        return objs
+            .into_iter()
+            .map(|hash| Entry {
+                obj_type: storage.get_object_type(&hash).unwrap(),
+                data: storage.get(&hash).unwrap(),
+                hash,
+            })
+            .collect();
+    }
+
     // just fast-forward optimization
     if remote_ref != SHA1::default() {
         // remote exists
@@ -311,12 +411,10 @@ fn diff_tree_objs(old_tree: Option<&SHA1>, new_tree: &SHA1) -> HashSet<Entry> {
                 TreeItemMode::Tree => {
                     objs.extend(diff_tree_objs(None, &item.id)); //TODO optimize, find same name tree
                 }
+                TreeItemMode::Commit => {
+                    // gitlink (submodule), its commit is in its own repository, not pushed with this one
+                }
                 _ => {
-                    // TODO: submodule (TreeItemMode: Commit)
-                    if item.mode == TreeItemMode::Commit {
-                        // (160000)| Gitlink (Submodule)
-                        eprintln!("{}", "Warning: Submodule is not supported yet".red());
-                    }
                     let blob = Blob::load(&item.id);
                     objs.insert(blob.into());
                 }
@@ -368,5 +466,21 @@ mod test {
         let args = vec!["push", "origin"];
         let args = PushArgs::try_parse_from(args);
         assert!(args.is_err());
+
+        let args = vec!["push", "origin", "master", "--tags"];
+        let args = PushArgs::try_parse_from(args);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn test_parse_args_tags() {
+        let args = PushArgs::parse_from(vec!["push", "--tags"]);
+        assert!(args.tags);
+        assert_eq!(args.repository, None);
+
+        let args = PushArgs::parse_from(vec!["push", "origin", "--tags"]);
+        assert!(args.tags);
+        assert_eq!(args.repository, Some("origin".to_string()));
+        assert_eq!(args.refspec, None);
     }
 }
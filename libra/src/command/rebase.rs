@@ -0,0 +1,460 @@
+use std::collections::HashSet;
+use std::fs;
+use std::str::FromStr;
+
+use clap::Parser;
+use common::utils::parse_commit_msg;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::branch::Branch;
+use crate::internal::commit_graph::CommitGraph;
+use crate::internal::head::Head;
+use crate::internal::reflog::{self, Reflog};
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::{
+    commit, get_target_commit, merge,
+    restore::{self, RestoreArgs},
+    status,
+};
+
+const DETACHED_HEAD: &str = "detached HEAD";
+
+#[derive(Parser, Debug)]
+pub struct RebaseArgs {
+    /// Upstream branch or commit, commits of HEAD that are not in upstream will be replayed
+    #[clap(required_unless_present_any(["continue_rebase", "abort", "skip"]))]
+    pub upstream: Option<String>,
+
+    /// Starting point at which to create the new commits, default is `upstream`
+    #[clap(long, requires("upstream"))]
+    pub onto: Option<String>,
+
+    /// Restart the rebasing process after having resolved a merge conflict
+    #[clap(long = "continue", group = "action", conflicts_with("upstream"))]
+    pub continue_rebase: bool,
+
+    /// Abort the rebase operation and reset HEAD to the original branch
+    #[clap(long, group = "action", conflicts_with("upstream"))]
+    pub abort: bool,
+
+    /// Restart the rebasing process by skipping the current commit
+    #[clap(long, group = "action", conflicts_with("upstream"))]
+    pub skip: bool,
+}
+
+pub async fn execute(args: RebaseArgs) {
+    if args.continue_rebase {
+        rebase_continue().await;
+    } else if args.abort {
+        rebase_abort().await;
+    } else if args.skip {
+        rebase_skip().await;
+    } else {
+        rebase_start(&args.upstream.unwrap(), args.onto.as_deref()).await;
+    }
+}
+
+/// The state of an in-progress rebase, persisted in `.libra/rebase-merge` to survive conflicts
+#[derive(Debug)]
+struct RebaseState {
+    /// the branch being rebased, `None` if HEAD was detached
+    head_name: Option<String>,
+    orig_head: SHA1,
+    onto: SHA1,
+    /// commits waiting to be replayed, in order
+    todo: Vec<SHA1>,
+    /// the commit which stopped the rebase because of conflicts
+    stopped: Option<SHA1>,
+}
+
+impl RebaseState {
+    fn load() -> Option<Self> {
+        let dir = path::rebase_merge();
+        if !dir.exists() {
+            return None;
+        }
+        let read = |name: &str| {
+            fs::read_to_string(dir.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let parse = |hash: &str| SHA1::from_str(hash).expect("fatal: rebase state is broken");
+        Some(RebaseState {
+            head_name: read("head-name").filter(|name| name != DETACHED_HEAD),
+            orig_head: parse(&read("orig-head")?),
+            onto: parse(&read("onto")?),
+            todo: read("todo")
+                .unwrap_or_default()
+                .lines()
+                .map(parse)
+                .collect(),
+            stopped: read("stopped-sha").map(|hash| parse(&hash)),
+        })
+    }
+
+    fn save(&self) {
+        let dir = path::rebase_merge();
+        fs::create_dir_all(&dir).unwrap();
+        let head_name = self.head_name.as_deref().unwrap_or(DETACHED_HEAD);
+        fs::write(dir.join("head-name"), head_name).unwrap();
+        fs::write(dir.join("orig-head"), self.orig_head.to_string()).unwrap();
+        fs::write(dir.join("onto"), self.onto.to_string()).unwrap();
+        let todo: Vec<String> = self.todo.iter().map(|id| id.to_string()).collect();
+        fs::write(dir.join("todo"), todo.join("\n")).unwrap();
+        match self.stopped {
+            Some(id) => fs::write(dir.join("stopped-sha"), id.to_string()).unwrap(),
+            None => {
+                let _ = fs::remove_file(dir.join("stopped-sha"));
+            }
+        }
+    }
+
+    fn remove() {
+        fs::remove_dir_all(path::rebase_merge()).unwrap();
+    }
+}
+
+async fn rebase_start(upstream: &str, onto: Option<&str>) {
+    if path::rebase_merge().exists() {
+        eprintln!("fatal: It seems that there is already a rebase-merge directory.");
+        eprintln!("Try \"libra rebase (--continue | --abort | --skip)\"");
+        return;
+    }
+    if path::merge_head().exists() {
+        eprintln!("fatal: You have not concluded your merge (MERGE_HEAD exists).");
+        return;
+    }
+    if !status::is_clean_tracked().await {
+        eprintln!("error: cannot rebase: You have unstaged changes.");
+        eprintln!("error: Please commit or stash them.");
+        return;
+    }
+    let orig_head = match Head::current_commit().await {
+        Some(commit) => commit,
+        None => {
+            eprintln!("fatal: no commits yet, nothing to rebase");
+            return;
+        }
+    };
+    let upstream_id = match get_target_commit(upstream).await {
+        Ok(commit) => commit,
+        Err(e) => {
+            eprintln!("fatal: invalid upstream '{}': {}", upstream, e);
+            return;
+        }
+    };
+    let onto_id = match onto {
+        Some(onto) => match get_target_commit(onto).await {
+            Ok(commit) => commit,
+            Err(e) => {
+                eprintln!("fatal: invalid onto '{}': {}", onto, e);
+                return;
+            }
+        },
+        None => upstream_id,
+    };
+    let head_name = match Head::current().await {
+        Head::Branch(name) => Some(name),
+        Head::Detached(_) => None,
+    };
+
+    let mut graph = CommitGraph::open();
+    if onto_id == upstream_id && graph.is_ancestor(&upstream_id, &orig_head) {
+        println!(
+            "Current branch {} is up to date.",
+            head_name.as_deref().unwrap_or(DETACHED_HEAD)
+        );
+        return;
+    }
+    let upstream_commits: HashSet<SHA1> = graph.ancestors(&[upstream_id]).into_iter().collect();
+    // merge commits are not replayed, like git
+    let todo: Vec<SHA1> = graph
+        .ancestors(&[orig_head])
+        .into_iter()
+        .filter(|id| !upstream_commits.contains(id) && graph.get(id).parents.len() <= 1)
+        .collect();
+    let todo = graph.topo_order(&todo);
+
+    let state = RebaseState {
+        head_name,
+        orig_head,
+        onto: onto_id,
+        todo,
+        stopped: None,
+    };
+    state.save();
+    // replay on `onto` with detached HEAD, the branch is updated when finished
+    let message = format!("rebase (start): checkout {}", onto.unwrap_or(upstream));
+    checkout_commit(onto_id, &message).await;
+    replay(state).await;
+}
+
+async fn rebase_continue() {
+    let mut state = match RebaseState::load() {
+        Some(state) => state,
+        None => {
+            eprintln!("fatal: No rebase in progress?");
+            return;
+        }
+    };
+    if Index::load(path::index()).unwrap().has_conflicts() {
+        eprintln!("error: Committing is not possible because you have unmerged files.");
+        eprintln!("hint: Fix them up in the work tree, and then use 'libra add <file>'");
+        return;
+    }
+    let unstaged = status::changes_to_be_staged().await;
+    if !unstaged.modified.is_empty() || !unstaged.deleted.is_empty() {
+        eprintln!("error: cannot continue: You have unstaged changes.");
+        eprintln!("hint: Use \"libra add <file>\" to stage the resolved files.");
+        return;
+    }
+    if let Some(stopped) = state.stopped.take() {
+        commit_replayed(&Commit::load(&stopped)).await;
+    }
+    replay(state).await;
+}
+
+async fn rebase_skip() {
+    let mut state = match RebaseState::load() {
+        Some(state) => state,
+        None => {
+            eprintln!("fatal: No rebase in progress?");
+            return;
+        }
+    };
+    // drop the changes of the stopped commit
+    merge::reset_conflicts().await;
+    state.stopped = None;
+    replay(state).await;
+}
+
+async fn rebase_abort() {
+    let state = match RebaseState::load() {
+        Some(state) => state,
+        None => {
+            eprintln!("fatal: No rebase in progress?");
+            return;
+        }
+    };
+    merge::reset_conflicts().await;
+    let message = format!("rebase (abort): returning to {}", state.orig_head);
+    checkout_commit(state.orig_head, &message).await;
+    if let Some(name) = state.head_name {
+        // the branch is not moved until rebase finished
+        Head::update(Head::Branch(name), None).await;
+    }
+    RebaseState::remove();
+}
+
+/// Replay commits in `todo` one by one on HEAD, stop and save the state if conflicts occur
+async fn replay(mut state: RebaseState) {
+    while !state.todo.is_empty() {
+        let commit_id = state.todo.remove(0);
+        let commit = Commit::load(&commit_id);
+        let head_tree = Commit::load(&Head::current_commit().await.unwrap()).tree_id;
+        let base_tree = commit
+            .parent_commit_ids
+            .first()
+            .map(|parent| Commit::load(parent).tree_id);
+
+        let (short_id, subject) = commit_summary(&commit);
+        let Some(conflicts) = merge::merge_into_worktree(
+            base_tree,
+            head_tree,
+            commit.tree_id,
+            "HEAD",
+            &format!("{} ({})", short_id, subject),
+        )
+        .await
+        else {
+            // nothing is changed, replay it again by `--continue`
+            state.todo.insert(0, commit_id);
+            state.save();
+            eprintln!("error: could not apply {}... {}", short_id, subject);
+            eprintln!("hint: Move or remove the files, then run \"libra rebase --continue\".");
+            return;
+        };
+        if !conflicts.is_empty() {
+            state.stopped = Some(commit_id);
+            state.save();
+            eprintln!("error: could not apply {}... {}", short_id, subject);
+            eprintln!("hint: Resolve all conflicts manually, mark them as resolved with");
+            eprintln!(
+                "hint: \"libra add <conflicted_files>\", then run \"libra rebase --continue\"."
+            );
+            eprintln!("hint: You can instead skip this commit: run \"libra rebase --skip\".");
+            eprintln!("hint: To abort and get back to the state before \"libra rebase\", run \"libra rebase --abort\".");
+            return;
+        }
+        commit_replayed(&commit).await;
+        state.save();
+    }
+    finish(state).await;
+}
+
+/// Commit the index as the replayed `commit` on HEAD, keep its author & message.
+/// The commit is dropped if it makes no change (e.g. already in upstream).
+async fn commit_replayed(commit: &Commit) {
+    if status::changes_to_be_committed().await.is_empty() {
+        let (short_id, subject) = commit_summary(commit);
+        println!(
+            "dropping {}... {} -- patch contents already upstream",
+            short_id, subject
+        );
+        return;
+    }
+    let index = Index::load(path::index()).unwrap();
+    let storage = util::objects_storage();
+    let (message, _) = parse_commit_msg(&commit.message);
+    let parents = vec![Head::current_commit().await.unwrap()];
+    commit::commit_index(
+        &index,
+        &storage,
+        message,
+        parents,
+        Some(commit.author.clone()),
+        false,
+    )
+    .await;
+}
+
+/// All commits are replayed, move the original branch to the new HEAD and clean the state
+async fn finish(state: RebaseState) {
+    let head = Head::current_commit().await.unwrap();
+    match state.head_name {
+        Some(name) => {
+            let branch_ref = reflog::branch_ref(&name);
+            Branch::update_branch(&name, &head.to_string(), None).await;
+            Head::update(Head::Branch(name.clone()), None).await;
+            let message = format!("rebase (finish): {} onto {}", branch_ref, state.onto);
+            Reflog::record(&branch_ref, state.orig_head, head, &message).await;
+            let message = format!("rebase (finish): returning to {}", branch_ref);
+            Reflog::record(&reflog::head_ref(), head, head, &message).await;
+            println!("Successfully rebased and updated refs/heads/{}.", name);
+        }
+        None => println!("Successfully rebased and updated detached HEAD."),
+    }
+    RebaseState::remove();
+}
+
+/// Detach HEAD at `commit_id` and restore index & worktree to it, `message` is recorded in the reflog
+async fn checkout_commit(commit_id: SHA1, message: &str) {
+    let old_head = Head::current_commit().await;
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: Some(commit_id.to_string()),
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+    Head::update(Head::Detached(commit_id), None).await;
+    Reflog::log_head_update(old_head, commit_id, message).await;
+}
+
+/// Abbreviated hash & the first line of message
+pub fn commit_summary(commit: &Commit) -> (String, String) {
+    let (message, _) = parse_commit_msg(&commit.message);
+    let subject = message.lines().next().unwrap_or_default().to_string();
+    (commit.id.to_string()[..7].to_string(), subject)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::branch;
+    use crate::utils::object_ext::TreeExt;
+    use crate::utils::test;
+    use mercury::internal::object::tree::Tree;
+    use serial_test::serial;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_args() {
+        assert!(RebaseArgs::try_parse_from(["rebase", "master"]).is_ok());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--onto", "main", "master"]).is_ok());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--continue"]).is_ok());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--abort"]).is_ok());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--skip"]).is_ok());
+        assert!(RebaseArgs::try_parse_from(["rebase"]).is_err());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--continue", "--abort"]).is_err());
+        assert!(RebaseArgs::try_parse_from(["rebase", "--continue", "master"]).is_err());
+    }
+
+    /// `master`: base -> master, `feature`: base -> feature 1 -> feature 2, HEAD at `feature`
+    async fn setup_diverged_branches(master_a: &str) {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\nb\nc\n"));
+        test::commit_all("base").await;
+        branch::create_branch("feature".to_string(), None).await;
+        test::ensure_file("a.txt", Some(master_a));
+        test::commit_all("master").await;
+
+        test::switch_to("feature").await;
+        test::ensure_file("a.txt", Some("a\nb\nfeature\n"));
+        test::commit_all("feature 1").await;
+        test::ensure_file("b.txt", Some("b\n"));
+        test::commit_all("feature 2").await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_rebase() {
+        setup_diverged_branches("master\nb\nc\n").await;
+        let master = Branch::find_branch("master", None).await.unwrap().commit;
+        let orig_head = Head::current_commit().await.unwrap();
+
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+
+        assert!(!path::rebase_merge().exists());
+        match Head::current().await {
+            Head::Branch(name) => assert_eq!(name, "feature"),
+            _ => panic!("HEAD should be back to branch"),
+        }
+        let feature_2 = Commit::load(&Head::current_commit().await.unwrap());
+        assert_ne!(feature_2.id, orig_head);
+        let feature_1 = Commit::load(&feature_2.parent_commit_ids[0]);
+        assert_eq!(feature_1.parent_commit_ids, vec![master]);
+        assert_eq!(parse_commit_msg(&feature_1.message).0, "feature 1");
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "master\nb\nfeature\n");
+        assert!(PathBuf::from("b.txt").exists());
+        assert!(status::is_clean().await);
+
+        let tree = Tree::load(&feature_2.tree_id);
+        assert_eq!(tree.tree_items.len(), 2);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_rebase_conflict_continue_and_abort() {
+        setup_diverged_branches("a\nb\nmaster\n").await;
+        let orig_head = Head::current_commit().await.unwrap();
+
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+        // `feature 1` conflicts with `master`
+        let state = RebaseState::load().unwrap();
+        assert!(state.stopped.is_some());
+        assert_eq!(state.todo.len(), 1);
+        assert!(Index::load(path::index()).unwrap().has_conflicts());
+
+        execute(RebaseArgs::parse_from(["rebase", "--abort"])).await;
+        assert!(!path::rebase_merge().exists());
+        assert_eq!(Head::current_commit().await.unwrap(), orig_head);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\nb\nfeature\n");
+        assert!(status::is_clean().await);
+
+        execute(RebaseArgs::parse_from(["rebase", "master"])).await;
+        test::ensure_file("a.txt", Some("a\nb\nresolved\n"));
+        test::add_all().await;
+        execute(RebaseArgs::parse_from(["rebase", "--continue"])).await;
+
+        assert!(!path::rebase_merge().exists());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\nb\nresolved\n");
+        assert!(PathBuf::from("b.txt").exists());
+        let head = Commit::load(&Head::current_commit().await.unwrap());
+        assert_eq!(parse_commit_msg(&head.message).0, "feature 2");
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Parser;
+
+use crate::internal::reflog::{self, Reflog};
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct ReflogArgs {
+    /// The reference to show, a branch name or `HEAD`
+    #[clap(default_value = "HEAD")]
+    pub ref_name: String,
+}
+
+pub async fn execute(args: ReflogArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let (ref_name, display) = match args.ref_name.as_str() {
+        reflog::HEAD => (reflog::head_ref(), reflog::HEAD),
+        "stash" => ("refs/stash".to_string(), "stash"),
+        name => match name.strip_prefix("refs/heads/") {
+            Some(branch) => (name.to_string(), branch),
+            None => (reflog::branch_ref(name), name),
+        },
+    };
+    for (n, entry) in Reflog::list(&ref_name).await.iter().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            &entry.new_oid.to_string()[..7],
+            display,
+            n,
+            entry.message
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = ReflogArgs::try_parse_from(["reflog"]).unwrap();
+        assert_eq!(args.ref_name, "HEAD");
+        let args = ReflogArgs::try_parse_from(["reflog", "master"]).unwrap();
+        assert_eq!(args.ref_name, "master");
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::pack::encode::PackEncoder;
+use mercury::internal::pack::entry::Entry;
+use tokio::sync::mpsc;
+
+use crate::command::index_pack;
+use crate::utils::{bitmap, path, util};
+
+/// The number of objects to try as the delta base, same as `pack.window` of git
+pub const DEFAULT_WINDOW: usize = 10;
+/// The capacity of the channels between the loader, the encoder & the writer of [write_pack]
+const PACK_CHANNEL_SIZE: usize = 1024;
+
+#[derive(Parser, Debug)]
+pub struct RepackArgs {
+    /// Pack everything into a single pack, including the objects already in packs.
+    /// Without it, only the loose objects are packed
+    #[clap(short = 'a')]
+    pub all: bool,
+    /// After packing, remove the redundant packs and loose objects
+    #[clap(short = 'd')]
+    pub delete: bool,
+    /// The number of objects to try as the delta base of each object, 0 to disable delta
+    #[clap(long, default_value_t = DEFAULT_WINDOW)]
+    pub window: usize,
+    /// Write reachability bitmaps for the multi-pack-index, to count objects faster in `push`
+    #[clap(short = 'b', long)]
+    pub write_bitmap_index: bool,
+}
+
+pub async fn execute(args: RepackArgs) {
+    let storage = util::objects_storage();
+    let loose = storage.list_objects_loose();
+    let old_packs = if args.all {
+        storage.list_all_packs()
+    } else {
+        Vec::new()
+    };
+    let mut objects: HashSet<SHA1> = loose.iter().copied().collect();
+    if args.all {
+        objects.extend(storage.list_objects_pack());
+    }
+    if objects.is_empty() {
+        println!("Nothing new to pack.");
+        return;
+    }
+
+    let objects: Vec<SHA1> = objects.into_iter().collect();
+    let pack_file = match write_pack(&objects, args.window).await {
+        Ok(pack_file) => pack_file,
+        Err(e) => {
+            eprintln!("fatal: failed to write pack: {}", e);
+            return;
+        }
+    };
+    println!(
+        "Packed {} objects into {}",
+        objects.len(),
+        pack_file.file_name().unwrap().to_str().unwrap()
+    );
+
+    if args.delete {
+        remove_redundant(&old_packs, &pack_file, &loose);
+    }
+    write_midx(args.write_bitmap_index).await;
+}
+
+/// Write the multi-pack-index for the current packs, and the bitmaps if `write_bitmaps`
+pub async fn write_midx(write_bitmaps: bool) {
+    if let Err(e) = util::objects_storage().write_midx() {
+        eprintln!("warning: failed to write multi-pack-index: {}", e);
+        return;
+    }
+    if write_bitmaps {
+        match bitmap::write_bitmaps().await {
+            Ok(count) => println!("Wrote bitmaps for {} commits", count),
+            Err(e) => eprintln!("warning: failed to write bitmaps: {}", e),
+        }
+    }
+}
+
+/// Pack the objects into `objects/pack/pack-<checksum>.pack` with a v2 .idx file,
+/// try to encode each object as a delta of the previous `window` objects.
+/// The objects are loaded one by one & the pack is streamed to the file, not held in memory.
+pub async fn write_pack(objects: &[SHA1], window: usize) -> Result<PathBuf, GitError> {
+    let storage = util::objects_storage();
+    let mut order = Vec::with_capacity(objects.len());
+    for obj_id in objects {
+        let obj_type = storage.get_object_type(obj_id)?;
+        let size = storage.get(obj_id)?.len();
+        order.push((obj_type.to_u8(), size, *obj_id));
+    }
+    // like git, the similar objects are put together to find a good delta base in the window:
+    // same type first, then larger first, so that a delta usually removes data
+    order.sort_by(|a, b| (a.0, b.1).cmp(&(b.0, a.1)));
+
+    let (entry_tx, entry_rx) = mpsc::channel(PACK_CHANNEL_SIZE);
+    let (stream_tx, mut stream_rx) = mpsc::channel(PACK_CHANNEL_SIZE);
+    let encoder = PackEncoder::new(order.len(), window, stream_tx);
+    let encoding = encoder.encode_async(entry_rx).await?;
+    let loader = tokio::spawn(async move {
+        let storage = util::objects_storage();
+        for (_, _, obj_id) in order {
+            let entry = Entry {
+                obj_type: storage.get_object_type(&obj_id)?,
+                data: storage.get(&obj_id)?,
+                hash: obj_id,
+            };
+            if entry_tx.send(entry).await.is_err() {
+                break; // the encoder failed
+            }
+        }
+        Ok::<(), GitError>(())
+    });
+
+    let pack_dir = path::objects().join("pack");
+    fs::create_dir_all(&pack_dir)?;
+    let tmp_file = pack_dir.join(format!("tmp_pack_{}", std::process::id()));
+    let mut writer = BufWriter::new(fs::File::create(&tmp_file)?);
+    let mut tail = Vec::new(); // the last 20 bytes are the checksum
+    let mut written = Ok(());
+    while let Some(chunk) = stream_rx.recv().await {
+        if written.is_ok() {
+            written = writer.write_all(&chunk);
+        }
+        tail.extend(chunk);
+        tail.drain(..tail.len().saturating_sub(20));
+    }
+    let result = match (loader.await, encoding.await) {
+        (Ok(loaded), Ok(())) => {
+            loaded.and(written.and_then(|_| writer.flush()).map_err(Into::into))
+        }
+        (Err(e), _) | (_, Err(e)) => Err(GitError::PackEncodeError(e.to_string())),
+    };
+    drop(writer);
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_file);
+        return Err(e);
+    }
+
+    let checksum = SHA1::from_bytes(&tail);
+    let pack_file = pack_dir.join(format!("pack-{}.pack", checksum));
+    fs::rename(&tmp_file, &pack_file)?;
+    index_pack::build_index_v2(
+        pack_file.to_str().unwrap(),
+        pack_file.with_extension("idx").to_str().unwrap(),
+    )?;
+    Ok(pack_file)
+}
+
+/// Remove the packs & loose objects which are all in the new pack
+pub fn remove_redundant(old_packs: &[PathBuf], new_pack: &Path, loose: &[SHA1]) {
+    let storage = util::objects_storage();
+    for pack in old_packs {
+        // the same objects result in the same pack
+        if pack == new_pack {
+            continue;
+        }
+        if let Err(e) = storage.remove_pack(pack) {
+            eprintln!("warning: failed to remove {}: {}", pack.display(), e);
+        }
+    }
+    for obj_id in loose {
+        if let Err(e) = storage.remove_loose(obj_id) {
+            eprintln!("warning: failed to remove {}: {}", obj_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::load_object;
+    use crate::internal::head::Head;
+    use crate::utils::test;
+    use mercury::internal::object::blob::Blob;
+    use mercury::internal::object::commit::Commit;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_repack_all() {
+        test::setup_with_new_libra().await;
+        let content = "line\n".repeat(100);
+        test::ensure_file("a.txt", Some(&content));
+        test::commit_all("add a.txt").await;
+        test::ensure_file("b.txt", Some(&(content.clone() + "more\n")));
+        test::commit_all("add b.txt").await;
+
+        let storage = util::objects_storage();
+        let objects = storage.list_objects_loose();
+        assert!(!objects.is_empty());
+        execute(RepackArgs {
+            all: true,
+            delete: true,
+            window: DEFAULT_WINDOW,
+            write_bitmap_index: false,
+        })
+        .await;
+        assert!(storage.list_objects_loose().is_empty());
+        let packs = storage.list_all_packs();
+        assert_eq!(packs.len(), 1);
+        let idx = fs::read(packs[0].with_extension("idx")).unwrap();
+        assert_eq!(idx[..4], index_pack::IDX_V2_MAGIC);
+        for obj_id in &objects {
+            assert!(storage.exist(obj_id));
+        }
+
+        // packs are merged
+        test::ensure_file("c.txt", Some("c\n"));
+        test::commit_all("add c.txt").await;
+        execute(RepackArgs {
+            all: false,
+            delete: true,
+            window: DEFAULT_WINDOW,
+            write_bitmap_index: false,
+        })
+        .await;
+        assert_eq!(storage.list_all_packs().len(), 2);
+        execute(RepackArgs {
+            all: true,
+            delete: true,
+            window: DEFAULT_WINDOW,
+            write_bitmap_index: false,
+        })
+        .await;
+        let packs = storage.list_all_packs();
+        assert_eq!(packs.len(), 1);
+        let packed = storage.list_pack_objects(&packs[0]).unwrap();
+        assert_eq!(packed.len(), objects.len() + 3); // blob, tree & commit of c.txt
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_repack_bitmaps() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\n"));
+        test::commit_all("add a.txt").await;
+        test::ensure_file("b.txt", Some("b\n"));
+        test::commit_all("add b.txt").await;
+        let base = Head::current_commit().await.unwrap();
+        execute(RepackArgs {
+            all: true,
+            delete: true,
+            window: DEFAULT_WINDOW,
+            write_bitmap_index: true,
+        })
+        .await;
+        let storage = util::objects_storage();
+        let midx = storage.load_midx().unwrap();
+        assert_eq!(midx.objects.len(), storage.list_objects_pack().len());
+        assert!(bitmap::BitmapIndex::load(&midx).is_some());
+
+        // the new objects are loose
+        test::ensure_file("c.txt", Some("c\n"));
+        test::commit_all("add c.txt").await;
+        let head = Head::current_commit().await.unwrap();
+        let commit: Commit = load_object(&head).unwrap();
+        let mut objects = bitmap::objects_between(&[head], &[base]).unwrap();
+        objects.sort();
+        let mut expected = vec![head, commit.tree_id, Blob::from_content("c\n").id];
+        expected.sort();
+        assert_eq!(objects, expected);
+
+        // the bitmaps are removed with the old multi-pack-index
+        test::ensure_file("d.txt", Some("d\n"));
+        test::commit_all("add d.txt").await;
+        execute(RepackArgs {
+            all: false,
+            delete: true,
+            window: DEFAULT_WINDOW,
+            write_bitmap_index: false,
+        })
+        .await;
+        assert!(bitmap::objects_between(&[head], &[base]).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::branch::Branch;
+use crate::internal::head::Head;
+use crate::internal::reflog::Reflog;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+use super::{
+    get_target_commit, merge,
+    rebase::commit_summary,
+    restore::{self, RestoreArgs},
+    status,
+};
+
+#[derive(Parser, Debug)]
+pub struct ResetArgs {
+    /// The commit to reset the current branch to
+    #[clap(default_value = "HEAD")]
+    pub target: String,
+
+    /// Only move HEAD, the index and working tree are not touched
+    #[clap(long, group = "mode")]
+    pub soft: bool,
+
+    /// Move HEAD and reset the index, but not the working tree (default)
+    #[clap(long, group = "mode")]
+    pub mixed: bool,
+
+    /// Move HEAD and reset the index and working tree, changes to tracked files are discarded
+    #[clap(long, group = "mode")]
+    pub hard: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResetMode {
+    Soft,
+    Mixed,
+    Hard,
+}
+
+pub async fn execute(args: ResetArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let mode = match (args.soft, args.hard) {
+        (true, _) => ResetMode::Soft,
+        (_, true) => ResetMode::Hard,
+        _ => ResetMode::Mixed,
+    };
+    let old_head = match Head::current_commit().await {
+        Some(commit) => commit,
+        None => {
+            eprintln!("fatal: ambiguous argument 'HEAD': unknown revision, no commits yet");
+            return;
+        }
+    };
+    let target = match get_target_commit(&args.target).await {
+        Ok(commit) => commit,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    if mode == ResetMode::Soft && merge::read_merge_head().is_some() {
+        eprintln!("fatal: Cannot do a soft reset in the middle of a merge.");
+        return;
+    }
+
+    move_head(target).await;
+    let message = format!("reset: moving to {}", args.target);
+    Reflog::log_head_update(Some(old_head), target, &message).await;
+
+    match mode {
+        ResetMode::Soft => {}
+        ResetMode::Mixed => {
+            reset_files(target, false).await;
+            let unstaged = status::changes_to_be_staged().await.to_relative();
+            if !unstaged.modified.is_empty() || !unstaged.deleted.is_empty() {
+                println!("Unstaged changes after reset:");
+                unstaged
+                    .modified
+                    .iter()
+                    .for_each(|f| println!("M\t{}", f.display()));
+                unstaged
+                    .deleted
+                    .iter()
+                    .for_each(|f| println!("D\t{}", f.display()));
+            }
+        }
+        ResetMode::Hard => {
+            reset_files(target, true).await;
+            let (short_id, subject) = commit_summary(&Commit::load(&target));
+            println!("HEAD is now at {} {}", short_id, subject);
+        }
+    }
+}
+
+/// Move the current branch to `target`, or HEAD itself if detached
+async fn move_head(target: SHA1) {
+    match Head::current().await {
+        Head::Branch(name) => Branch::update_branch(&name, &target.to_string(), None).await,
+        Head::Detached(_) => Head::update(Head::Detached(target), None).await,
+    }
+}
+
+/// Reset the index (and the working tree if `worktree`) to `target`,
+/// merge state is dropped as the conflicts are discarded.
+async fn reset_files(target: SHA1, worktree: bool) {
+    if worktree {
+        merge::reset_conflicts().await;
+    } else {
+        // the conflicts are replaced by the entries of `target`, the files in the worktree are kept
+        let index_file = path::index();
+        let mut index = Index::load(&index_file).unwrap();
+        for file in index.unmerged_files() {
+            index.remove_unmerged(file.to_str().unwrap());
+        }
+        index.save(&index_file).unwrap();
+    }
+    restore::execute(RestoreArgs {
+        worktree,
+        staged: true,
+        source: Some(target.to_string()),
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+    merge::clear_merge_state();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::reflog;
+    use crate::utils::test;
+    use serial_test::serial;
+    use std::fs;
+
+    #[test]
+    fn test_parse_args() {
+        let args = ResetArgs::try_parse_from(["reset"]).unwrap();
+        assert_eq!(args.target, "HEAD");
+        assert!(!args.soft && !args.mixed && !args.hard);
+        let args = ResetArgs::try_parse_from(["reset", "--hard", "HEAD@{1}"]).unwrap();
+        assert!(args.hard);
+        assert_eq!(args.target, "HEAD@{1}");
+        assert!(ResetArgs::try_parse_from(["reset", "--soft", "--hard"]).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_reset_modes() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "1", "first").await;
+        let second = test::commit_file("a.txt", "2", "second").await;
+
+        // soft: only HEAD moves, the change stays staged
+        execute(ResetArgs::parse_from(["reset", "--soft", "HEAD@{1}"])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), first);
+        assert_eq!(
+            status::changes_to_be_committed().await.modified,
+            vec![std::path::PathBuf::from("a.txt")]
+        );
+
+        // mixed: the change is unstaged but kept in the working tree
+        execute(ResetArgs::parse_from(["reset", &second.to_string()])).await;
+        execute(ResetArgs::parse_from(["reset", &first.to_string()])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), first);
+        assert!(status::changes_to_be_committed().await.is_empty());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "2");
+
+        // hard: the working tree is reset too
+        execute(ResetArgs::parse_from(["reset", "--hard"])).await;
+        assert!(status::is_clean().await);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "1");
+
+        // the branch is moved & logged, the lost commit can be found in the reflog
+        let branch_log = Reflog::list(&reflog::branch_ref("master")).await;
+        assert_eq!(branch_log[0].message, "reset: moving to HEAD");
+        assert_eq!(branch_log[1].message, format!("reset: moving to {}", first));
+        execute(ResetArgs::parse_from(["reset", "--hard", "HEAD@{2}"])).await;
+        assert_eq!(Head::current_commit().await.unwrap(), second);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "2");
+    }
+}
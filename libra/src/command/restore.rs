@@ -1,17 +1,16 @@
 use crate::command::calc_file_blob_hash;
-use crate::internal::branch::Branch;
-use crate::internal::head::Head;
 use crate::internal::protocol::lfs_client::LFSClient;
-use crate::utils::object_ext::{BlobExt, CommitExt, TreeExt};
+use crate::internal::revision;
+use crate::utils::object_ext::{self, BlobExt, CommitExt, TreeExt};
 use crate::utils::path_ext::PathExt;
+use crate::utils::sparse::{self, SparseCheckout};
 use crate::utils::{lfs, path, util};
 use clap::Parser;
 use mercury::hash::SHA1;
 use mercury::internal::index::{Index, IndexEntry};
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
-use mercury::internal::object::tree::Tree;
-use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::{fs, io};
@@ -52,60 +51,53 @@ pub async fn execute(args: RestoreArgs) {
         source = Some(HEAD.to_string());
     }
 
-    let storage = util::objects_storage();
     let target_commit: Option<SHA1> = match source {
         None => {
             assert!(!staged); // pre-processed ↑
             None // Index
         }
-        Some(ref src) => {
-            // ref: prevent moving `source`
-            if src == HEAD {
-                // Default Source
-                Head::current_commit().await
-            } else if Branch::exists(src).await {
-                // Branch Name, e.g. master
-                Some(Branch::find_branch(src, None).await.unwrap().commit)
-            } else {
-                // [Commit Hash, e.g. a1b2c3d4] || [Wrong Branch Name]
-                let objs = storage.search(src);
-                // TODO hash can be `commit` or `tree`
-                if objs.len() != 1 || !storage.is_object_type(&objs[0], ObjectType::Commit) {
-                    None // Wrong Commit Hash
-                } else {
-                    Some(objs[0])
-                }
+        // TODO source can be a `tree` too
+        Some(ref src) => match revision::resolve_commit(src).await {
+            Ok(commit) => Some(commit),
+            Err(e) => {
+                eprintln!("fatal: could not resolve {}: {}", src, e);
+                return;
             }
-        }
+        },
     };
 
-    // to workdir path
-    let target_blobs: Vec<(PathBuf, SHA1)> = {
-        // `source` has been pre-process before ↑
-        if source.is_none() {
+    // to workdir path, the gitlinks (submodules) are the commits to check out in them
+    let (target_blobs, gitlinks): (Vec<(PathBuf, SHA1)>, HashSet<PathBuf>) = match target_commit {
+        Some(commit) => {
+            // restore from commit hash
+            let tree_id = Commit::load(&commit).tree_id;
+            let tree = Tree::load(&tree_id);
+            let items = tree.get_plain_items_with_mode();
+            let gitlinks = items
+                .iter()
+                .filter(|(_, _, mode)| *mode == TreeItemMode::Commit)
+                .map(|(path, _, _)| path.clone())
+                .collect();
+            let blobs = items
+                .into_iter()
+                .map(|(path, hash, _)| (path, hash))
+                .collect();
+            (blobs, gitlinks)
+        }
+        None => {
             // only this situation, restore from [Index]
-            assert!(!staged);
             let index = Index::load(path::index()).unwrap();
-            index
-                .tracked_entries(0)
+            let entries = index.tracked_entries(0);
+            let gitlinks = entries
+                .iter()
+                .filter(|entry| entry.is_gitlink())
+                .map(|entry| PathBuf::from(&entry.name))
+                .collect();
+            let blobs = entries
                 .into_iter()
                 .map(|entry| (PathBuf::from(&entry.name), entry.hash))
-                .collect()
-        } else {
-            // restore from commit hash
-            if let Some(commit) = target_commit {
-                let tree_id = Commit::load(&commit).tree_id;
-                let tree = Tree::load(&tree_id);
-                tree.get_plain_items()
-            } else {
-                let src = source.unwrap();
-                if storage.search(&src).len() != 1 {
-                    eprintln!("fatal: could not resolve {}", src);
-                } else {
-                    eprintln!("fatal: reference is not a commit: {}", src);
-                }
-                return;
-            }
+                .collect();
+            (blobs, gitlinks)
         }
     };
 
@@ -115,14 +107,16 @@ pub async fn execute(args: RestoreArgs) {
         .iter()
         .map(PathBuf::from)
         .collect::<Vec<PathBuf>>();
+    // the files out of the sparse checkout are not materialised, but marked skip-worktree in the index
+    let sparse = SparseCheckout::load().await;
     // restore worktree and staged respectively
     // The order is very important
     // `restore_worktree` will decide whether to delete the file based on whether it is tracked in the index.
     if worktree {
-        restore_worktree(&paths, &target_blobs).await;
+        restore_worktree(&paths, &target_blobs, &gitlinks, sparse.as_ref()).await;
     }
     if staged {
-        restore_index(&paths, &target_blobs);
+        restore_index(&paths, &target_blobs, &gitlinks, sparse.as_ref());
     }
 }
 
@@ -139,7 +133,7 @@ fn preprocess_blobs(blobs: &[(PathBuf, SHA1)]) -> HashMap<PathBuf, SHA1> {
 /// Restore a blob to file.
 /// If blob is an LFS pointer, download the actual file from LFS server.
 /// - `path` : to workdir
-async fn restore_to_file(hash: &SHA1, path: &PathBuf) -> io::Result<()> {
+pub async fn restore_to_file(hash: &SHA1, path: &PathBuf) -> io::Result<()> {
     let blob = Blob::load(hash);
     let path_abs = util::workdir_to_absolute(path);
     if let Some(parent) = path_abs.parent() {
@@ -191,9 +185,17 @@ fn get_worktree_deleted_files_in_filters(
 /// Restore the worktree
 /// - `filter`: abs or relative to current (user input)
 /// - `target_blobs`: to workdir path
-pub async fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
+/// - `gitlinks`: the submodules in `target_blobs`, only their directories are created, see `submodule update`
+/// - `sparse`: the missing files out of the sparse checkout are not restored
+pub async fn restore_worktree(
+    filter: &Vec<PathBuf>,
+    target_blobs: &[(PathBuf, SHA1)],
+    gitlinks: &HashSet<PathBuf>,
+    sparse: Option<&SparseCheckout>,
+) {
     let target_blobs = preprocess_blobs(target_blobs);
-    let deleted_files = get_worktree_deleted_files_in_filters(filter, &target_blobs);
+    let mut deleted_files = get_worktree_deleted_files_in_filters(filter, &target_blobs);
+    deleted_files.retain(|path| sparse::is_included(sparse, path));
 
     {
         // validate input pathspec(filter)
@@ -220,12 +222,25 @@ pub async fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, S
     let mut file_paths = util::integrate_pathspec(filter);
     file_paths.extend(deleted_files);
 
+    // the blobs omitted by a partial clone are fetched in one request
+    let missing_blobs = file_paths
+        .iter()
+        .filter(|path| !gitlinks.contains(*path))
+        .filter_map(|path| target_blobs.get(path))
+        .cloned();
+    if let Err(e) = object_ext::fetch_missing_objects(missing_blobs).await {
+        eprintln!("fatal: failed to fetch missing objects: {}", e);
+    }
+
     let index = Index::load(path::index()).unwrap();
     for path_wd in &file_paths {
         let path_abs = util::workdir_to_absolute(path_wd);
         if !path_abs.exists() {
             // file not exist, deleted or illegal
-            if target_blobs.contains_key(path_wd) {
+            if gitlinks.contains(path_wd) {
+                // submodule, an empty directory until it's checked out
+                fs::create_dir_all(&path_abs).unwrap();
+            } else if target_blobs.contains_key(path_wd) {
                 // file in target_blobs (deleted), need to restore
                 restore_to_file(&target_blobs[path_wd], path_wd)
                     .await
@@ -234,6 +249,9 @@ pub async fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, S
                 // not in target_commit and workdir (illegal path), user input
                 unreachable!("It should be checked before");
             }
+        } else if path_abs.is_dir() {
+            // a checked out submodule, its commit is changed by `submodule update`, it's kept even if not in the target
+            continue;
         } else {
             // file exists
             let path_wd_str = path_wd.to_string_or_panic();
@@ -256,6 +274,18 @@ pub async fn restore_worktree(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, S
             }
         }
     }
+
+    // the submodules removed from the target are removed too if they are not checked out (empty)
+    for entry in index.tracked_entries(0) {
+        let path_abs = util::workdir_to_absolute(&entry.name);
+        if entry.is_gitlink()
+            && !target_blobs.contains_key(&PathBuf::from(&entry.name))
+            && path_abs.sub_of_paths(filter)
+            && util::is_empty_dir(&path_abs)
+        {
+            fs::remove_dir(&path_abs).unwrap();
+        }
+    }
 }
 
 /// Get the deleted files in the `index`(vs target_blobs), filtered by `filters`
@@ -276,7 +306,15 @@ fn get_index_deleted_files_in_filters(
         .collect() // HashSet auto deduplication
 }
 
-pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
+/// Restore the index
+/// - `gitlinks`: the submodules in `target_blobs`, added as gitlinks
+/// - `sparse`: the files out of the sparse checkout & not in the worktree are marked skip-worktree
+pub fn restore_index(
+    filter: &Vec<PathBuf>,
+    target_blobs: &[(PathBuf, SHA1)],
+    gitlinks: &HashSet<PathBuf>,
+    sparse: Option<&SparseCheckout>,
+) {
     let target_blobs = preprocess_blobs(target_blobs);
 
     let idx_file = path::index();
@@ -289,16 +327,16 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
     for path in &file_paths {
         // to workdir
         let path_str = path.to_string_or_panic();
+        index.remove_unmerged(&path_str); // restored path is not conflicted anymore
         if !index.tracked(&path_str, 0) {
             // file not exist in index
             if target_blobs.contains_key(path) {
                 // file in target_blobs (deleted), need to restore
                 let hash = target_blobs[path];
-                let blob = Blob::load(&hash);
-                index.add(IndexEntry::new_from_blob(
-                    path_str,
+                index.add(new_index_entry(
+                    path_str.clone(),
                     hash,
-                    blob.data.len() as u32,
+                    gitlinks.contains(path),
                 ));
             } else {
                 eprintln!(
@@ -313,11 +351,10 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
                 let hash = target_blobs[path];
                 if !index.verify_hash(&path_str, 0, &hash) {
                     // modified
-                    let blob = Blob::load(&hash);
-                    index.update(IndexEntry::new_from_blob(
-                        path_str,
+                    index.update(new_index_entry(
+                        path_str.clone(),
                         hash,
-                        blob.data.len() as u32,
+                        gitlinks.contains(path),
                     ));
                 } // else: same, keep
             } else {
@@ -325,6 +362,18 @@ pub fn restore_index(filter: &Vec<PathBuf>, target_blobs: &[(PathBuf, SHA1)]) {
                 index.remove(&path_str, 0); // TODO all stages
             }
         }
+        let skip = !sparse::is_included(sparse, path) && !util::workdir_to_absolute(path).exists();
+        index.set_skip_worktree(&path_str, skip);
     }
     index.save(&idx_file).unwrap(); // DO NOT forget to save
 }
+
+/// The index entry of a file in the target, or a gitlink of a submodule whose commit isn't in this repository
+fn new_index_entry(name: String, hash: SHA1, gitlink: bool) -> IndexEntry {
+    if gitlink {
+        IndexEntry::new_from_gitlink(name, hash)
+    } else {
+        let blob = Blob::load(&hash);
+        IndexEntry::new_from_blob(name, hash, blob.data.len() as u32)
+    }
+}
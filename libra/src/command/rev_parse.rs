@@ -0,0 +1,151 @@
+use clap::Parser;
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::revision::{self, RevRange};
+use crate::utils::object_ext::CommitExt;
+use crate::utils::util;
+
+use super::merge;
+
+#[derive(Parser, Debug)]
+pub struct RevParseArgs {
+    /// Revisions or ranges to resolve, e.g. `HEAD~2`, `v1.0^{tree}`, `master@{upstream}`, `A..B`
+    #[clap(required = true)]
+    pub revs: Vec<String>,
+
+    /// Output abbreviated hashes
+    #[clap(long)]
+    pub short: bool,
+
+    /// Output the short symbolic name instead of the hash, e.g. the current branch for `HEAD`
+    #[clap(long, conflicts_with = "short")]
+    pub abbrev_ref: bool,
+}
+
+pub async fn execute(args: RevParseArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    for rev in &args.revs {
+        if args.abbrev_ref {
+            match abbrev_ref(rev).await {
+                Ok(name) => println!("{}", name),
+                Err(e) => {
+                    eprintln!("fatal: {}", e);
+                    return;
+                }
+            }
+            continue;
+        }
+
+        // a range is printed as the commits to include, and those to exclude prefixed with `^`, like git
+        let lines = if rev.contains("..") {
+            match revision::resolve_range(rev).await {
+                Ok(RevRange::Range { exclude, include }) => {
+                    vec![(include, false), (exclude, true)]
+                }
+                Ok(RevRange::Symmetric(left, right)) => {
+                    let mut lines = vec![(left, false), (right, false)];
+                    let base = merge::lca_commit(&Commit::load(&left), &Commit::load(&right)).await;
+                    if let Some(base) = base {
+                        lines.push((base.id, true));
+                    }
+                    lines
+                }
+                Ok(RevRange::Single(commit)) => vec![(commit, false)],
+                Err(e) => {
+                    eprintln!("fatal: {}", e);
+                    return;
+                }
+            }
+        } else {
+            match revision::resolve(rev).await {
+                Ok(object) => vec![(object, false)],
+                Err(e) => {
+                    eprintln!("fatal: {}", e);
+                    return;
+                }
+            }
+        };
+        for (object, exclude) in lines {
+            println!(
+                "{}{}",
+                if exclude { "^" } else { "" },
+                format_hash(&object, args.short)
+            );
+        }
+    }
+}
+
+fn format_hash(hash: &SHA1, short: bool) -> String {
+    let hash = hash.to_string();
+    match short {
+        true => hash[..7].to_string(),
+        false => hash,
+    }
+}
+
+/// The symbolic name of `rev`: `HEAD` is the current branch, `@{upstream}` is the remote branch
+async fn abbrev_ref(rev: &str) -> Result<String, String> {
+    let current_branch = || async {
+        match Head::current().await {
+            Head::Branch(name) => Some(name),
+            Head::Detached(_) => None,
+        }
+    };
+    let (branch, selector) = rev.split_once("@{").unwrap_or((rev, ""));
+    let branch = match branch {
+        "" | "@" | "HEAD" => match current_branch().await {
+            Some(name) => name,
+            None if selector.is_empty() => return Ok("HEAD".to_string()),
+            None => return Err("HEAD does not point to a branch".to_string()),
+        },
+        name => {
+            revision::resolve(rev).await?; // must be valid
+            name.strip_prefix("refs/heads/").unwrap_or(name).to_string()
+        }
+    };
+    match selector {
+        "" => Ok(branch),
+        "upstream}" | "u}" => match Config::branch_config(&branch).await {
+            Some(config) => Ok(format!("{}/{}", config.remote, config.merge)),
+            None => Err(format!("no upstream configured for branch '{}'", branch)),
+        },
+        _ => Err(format!("ambiguous argument '{}': not a symbolic ref", rev)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test;
+    use serial_test::serial;
+
+    #[test]
+    fn test_parse_args() {
+        let args = RevParseArgs::try_parse_from(["rev-parse", "HEAD~1", "A..B"]).unwrap();
+        assert_eq!(args.revs, vec!["HEAD~1", "A..B"]);
+        let args = RevParseArgs::try_parse_from(["rev-parse", "--abbrev-ref", "HEAD"]).unwrap();
+        assert!(args.abbrev_ref);
+        assert!(RevParseArgs::try_parse_from(["rev-parse"]).is_err());
+        assert!(
+            RevParseArgs::try_parse_from(["rev-parse", "--short", "--abbrev-ref", "HEAD"]).is_err()
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_abbrev_ref() {
+        test::setup_with_new_libra().await;
+        assert_eq!(abbrev_ref("HEAD").await.unwrap(), "master");
+        assert_eq!(abbrev_ref("@").await.unwrap(), "master");
+        assert!(abbrev_ref("@{u}").await.is_err()); // no upstream
+
+        Config::insert("branch", Some("master"), "remote", "origin").await;
+        Config::insert("branch", Some("master"), "merge", "refs/heads/main").await;
+        assert_eq!(abbrev_ref("HEAD@{upstream}").await.unwrap(), "origin/main");
+    }
+}
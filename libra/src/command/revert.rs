@@ -0,0 +1,57 @@
+use clap::Parser;
+
+use super::cherry_pick::{self, Action, SequenceOptions};
+
+#[derive(Parser, Debug)]
+pub struct RevertArgs {
+    /// Commits to revert, `A..B` means commits reachable from B but not from A
+    #[clap(required_unless_present_any(["continue_revert", "abort", "skip"]))]
+    pub commits: Vec<String>,
+
+    /// Apply the inverse changes to the index & worktree without making commits
+    #[clap(long, short = 'n')]
+    pub no_commit: bool,
+
+    /// Continue the operation in progress after having resolved conflicts
+    #[clap(long = "continue", group = "action", conflicts_with("commits"))]
+    pub continue_revert: bool,
+
+    /// Cancel the operation and return to the pre-sequence state
+    #[clap(long, group = "action", conflicts_with("commits"))]
+    pub abort: bool,
+
+    /// Skip the current commit and continue with the rest of the sequence
+    #[clap(long, group = "action", conflicts_with("commits"))]
+    pub skip: bool,
+}
+
+pub async fn execute(args: RevertArgs) {
+    if args.continue_revert {
+        cherry_pick::sequence_continue(Action::Revert).await;
+    } else if args.abort {
+        cherry_pick::sequence_abort(Action::Revert).await;
+    } else if args.skip {
+        cherry_pick::sequence_skip(Action::Revert).await;
+    } else {
+        let options = SequenceOptions {
+            action: Action::Revert,
+            record_origin: false,
+            no_commit: args.no_commit,
+        };
+        cherry_pick::sequence_start(&args.commits, options).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = RevertArgs::try_parse_from(["revert", "-n", "HEAD"]).unwrap();
+        assert!(args.no_commit);
+        assert!(RevertArgs::try_parse_from(["revert", "--abort"]).is_ok());
+        assert!(RevertArgs::try_parse_from(["revert"]).is_err());
+        assert!(RevertArgs::try_parse_from(["revert", "--continue", "HEAD"]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use clap::Parser;
+use colored::Colorize;
+use common::utils::parse_commit_msg;
+use mercury::hash::SHA1;
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::internal::revision;
+use crate::utils::object_ext::{self, BlobExt, CommitExt, TreeExt};
+use crate::utils::util;
+
+use super::diff;
+
+#[derive(Parser, Debug)]
+pub struct ShowArgs {
+    /// The object to show: commit with its patch, annotated tag with the tagged object, tree or blob
+    #[clap(default_value = "HEAD")]
+    pub object: String,
+}
+
+pub async fn execute(args: ShowArgs) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let object = match revision::resolve(&args.object).await {
+        Ok(object) => object,
+        Err(e) => {
+            eprintln!("fatal: {}", e);
+            return;
+        }
+    };
+    show_object(&object, &args.object).await;
+}
+
+async fn show_object(object: &SHA1, name: &str) {
+    // an object omitted by a partial clone is fetched from the promisor remote
+    if let Err(e) = object_ext::fetch_missing_objects([*object]).await {
+        tracing::debug!("failed to fetch missing object {}: {}", object, e);
+    }
+    let storage = util::objects_storage();
+    match storage.get_object_type(object) {
+        Ok(ObjectType::Commit) => show_commit(&Commit::load(object)).await,
+        Ok(ObjectType::Tag) => {
+            let data = storage.get(object).unwrap();
+            let tag = Tag::from_bytes(&data, *object).unwrap();
+            println!("{} {}", "tag".yellow(), tag.tag_name);
+            print!("Tagger: {}", tag.tagger);
+            println!("\n{}\n", tag.message.trim_end());
+            // the tagged object is not a tag in most cases, no infinite recursion
+            Box::pin(show_object(&tag.object_hash, name)).await;
+        }
+        Ok(ObjectType::Tree) => {
+            println!("{} {}\n", "tree".yellow(), name);
+            for item in Tree::load(object).tree_items {
+                match item.mode {
+                    TreeItemMode::Tree => println!("{}/", item.name),
+                    _ => println!("{}", item.name),
+                }
+            }
+        }
+        Ok(ObjectType::Blob) => {
+            io::stdout().write_all(&Blob::load(object).data).unwrap();
+        }
+        _ => eprintln!("fatal: bad object {}", name),
+    }
+}
+
+/// Show the commit header and its patch against the first parent, merge commits are shown without patch
+async fn show_commit(commit: &Commit) {
+    println!("{} {}", "commit".yellow(), commit.id.to_string().yellow());
+    if commit.parent_commit_ids.len() > 1 {
+        let parents: Vec<String> = commit
+            .parent_commit_ids
+            .iter()
+            .map(|p| p.to_string()[..7].to_string())
+            .collect();
+        println!("Merge: {}", parents.join(" "));
+    }
+    print!("Author: {}", commit.author);
+    let (message, _) = parse_commit_msg(&commit.message);
+    println!("\n{}\n", message.trim_end());
+
+    if commit.parent_commit_ids.len() > 1 {
+        return;
+    }
+    let mut gitlinks = HashSet::new();
+    let old_blobs = match commit.parent_commit_ids.first() {
+        Some(parent) => diff::commit_blobs(parent, &mut gitlinks),
+        None => vec![],
+    };
+    let new_blobs = diff::tree_blobs(&commit.tree_id, &mut gitlinks);
+    diff::diff(old_blobs, new_blobs, gitlinks, vec![], &mut io::stdout()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = ShowArgs::try_parse_from(["show"]).unwrap();
+        assert_eq!(args.object, "HEAD");
+        let args = ShowArgs::try_parse_from(["show", "v1.0^{tree}"]).unwrap();
+        assert_eq!(args.object, "v1.0^{tree}");
+    }
+}
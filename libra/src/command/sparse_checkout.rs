@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+
+use crate::command::{calc_file_blob_hash, restore};
+use crate::utils::sparse::{self, SparseCheckout, DEFAULT_PATTERNS};
+use crate::utils::{object_ext, path, util};
+
+/// [Docs](https://git-scm.com/docs/git-sparse-checkout)
+#[derive(Subcommand, Debug)]
+pub enum SparseCheckoutCmds {
+    /// Enable the sparse checkout, only the files at the root are kept if there is no pattern yet
+    Init(ModeArgs),
+    /// Enable the sparse checkout with the directories (cone mode) or patterns
+    Set {
+        patterns: Vec<String>,
+        #[clap(flatten)]
+        mode: ModeArgs,
+    },
+    /// Add directories (cone mode) or patterns to the sparse set
+    Add {
+        #[clap(required = true)]
+        patterns: Vec<String>,
+    },
+    /// List the directories (cone mode) or patterns of the sparse set
+    List,
+    /// Disable the sparse checkout and restore all the files in the worktree
+    Disable,
+}
+
+#[derive(Args, Debug)]
+pub struct ModeArgs {
+    /// Directories as the patterns, the default
+    #[clap(long)]
+    pub cone: bool,
+    /// Gitignore-style patterns of the files to include
+    #[clap(long, conflicts_with = "cone")]
+    pub no_cone: bool,
+}
+
+pub async fn execute(cmd: SparseCheckoutCmds) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let current = SparseCheckout::load().await;
+    let sparse = match cmd {
+        SparseCheckoutCmds::Init(mode) => {
+            // the set is kept from the last time, unless the mode is changed
+            let cone = is_cone(&mode, current.as_ref());
+            match SparseCheckout::load_saved().await {
+                Some(saved) if saved.is_cone() == cone => saved,
+                _ => empty(cone),
+            }
+        }
+        SparseCheckoutCmds::Set { patterns, mode } => {
+            let cone = is_cone(&mode, current.as_ref());
+            if patterns.is_empty() {
+                empty(cone)
+            } else {
+                SparseCheckout::new(cone, &patterns)
+            }
+        }
+        SparseCheckoutCmds::Add { patterns } => match current {
+            Some(mut sparse) => {
+                sparse.add(&patterns);
+                sparse
+            }
+            None => {
+                eprintln!("fatal: no sparse-checkout to add to");
+                return;
+            }
+        },
+        SparseCheckoutCmds::List => {
+            match current {
+                Some(sparse) => sparse.patterns().iter().for_each(|p| println!("{}", p)),
+                None => eprintln!("fatal: this worktree is not sparse"),
+            }
+            return;
+        }
+        SparseCheckoutCmds::Disable => {
+            SparseCheckout::disable().await;
+            update_worktree(None).await;
+            return;
+        }
+    };
+    sparse.save().await;
+    update_worktree(Some(&sparse)).await;
+}
+
+/// The mode given by the flags, or the current one, cone mode by default
+fn is_cone(mode: &ModeArgs, current: Option<&SparseCheckout>) -> bool {
+    match (mode.cone, mode.no_cone, current) {
+        (true, _, _) => true,
+        (_, true, _) => false,
+        (_, _, Some(current)) => current.is_cone(),
+        _ => true,
+    }
+}
+
+/// The set with only the files at the root
+fn empty(cone: bool) -> SparseCheckout {
+    if cone {
+        SparseCheckout::new(true, &[])
+    } else {
+        let patterns: Vec<String> = DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect();
+        SparseCheckout::new(false, &patterns)
+    }
+}
+
+/// Materialise the tracked files in the sparse set & remove the others from the worktree,
+/// updating the skip-worktree bits in the index; all the files are materialised if `sparse` is `None`.
+/// The files out of the set with local changes are left in the worktree.
+pub async fn update_worktree(sparse: Option<&SparseCheckout>) {
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    let workdir = util::working_dir();
+    let entries: Vec<(PathBuf, SHA1, bool)> = index
+        .tracked_entries(0)
+        .into_iter()
+        .filter(|entry| !entry.is_gitlink()) // the submodules are left to `submodule update`
+        .map(|entry| {
+            let path = PathBuf::from(&entry.name);
+            (path, entry.hash, entry.flags.skip_worktree)
+        })
+        .collect();
+
+    let mut to_restore = Vec::new();
+    let mut left = Vec::new();
+    for (path, hash, skipped) in entries {
+        let name = path.to_str().unwrap().to_string();
+        let path_abs = workdir.join(&path);
+        if sparse::is_included(sparse, &path) {
+            if !path_abs.exists() {
+                to_restore.push((path, hash));
+            }
+            index.set_skip_worktree(&name, false);
+        } else if !skipped {
+            if path_abs.exists() {
+                let modified = index.is_modified(&name, 0, &workdir)
+                    && calc_file_blob_hash(&path_abs).unwrap() != hash;
+                if modified {
+                    left.push(path);
+                    continue;
+                }
+                fs::remove_file(&path_abs).unwrap();
+                util::clear_empty_dir(&path_abs);
+            }
+            index.set_skip_worktree(&name, true);
+        }
+    }
+
+    // the blobs omitted by a partial clone are fetched in one request
+    let missing_blobs = to_restore.iter().map(|(_, hash)| *hash);
+    if let Err(e) = object_ext::fetch_missing_objects(missing_blobs).await {
+        eprintln!("fatal: failed to fetch missing objects: {}", e);
+        return;
+    }
+    for (path, hash) in &to_restore {
+        restore::restore_to_file(hash, path).await.unwrap();
+    }
+    index.save(&index_file).unwrap();
+
+    if !left.is_empty() {
+        eprintln!("warning: The following paths are not up to date and were left despite sparse patterns:");
+        for path in left {
+            eprintln!("\t{}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::add::{self, AddArgs};
+    use crate::command::commit::{self, CommitArgs};
+    use crate::command::status;
+    use crate::utils::test;
+
+    #[test]
+    fn test_parse_args() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Cli {
+            #[command(subcommand)]
+            cmd: SparseCheckoutCmds,
+        }
+        assert!(Cli::try_parse_from(["sparse-checkout", "set", "--no-cone", "/*"]).is_ok());
+        assert!(Cli::try_parse_from(["sparse-checkout", "init", "--cone", "--no-cone"]).is_err());
+        assert!(Cli::try_parse_from(["sparse-checkout", "add"]).is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_sparse_checkout() {
+        test::setup_with_new_libra().await;
+        for file in ["top.txt", "a/a.txt", "a/b/b.txt", "c/c.txt"] {
+            test::ensure_file(file, Some(file));
+        }
+        add::execute(AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+            force: false,
+        })
+        .await;
+        commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: false,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+
+        let set = |patterns: &[&str]| SparseCheckoutCmds::Set {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            mode: ModeArgs {
+                cone: false,
+                no_cone: false,
+            },
+        };
+        execute(set(&["a/b"])).await;
+        let workdir = util::working_dir();
+        assert!(workdir.join("top.txt").exists());
+        assert!(workdir.join("a/a.txt").exists());
+        assert!(workdir.join("a/b/b.txt").exists());
+        assert!(!workdir.join("c").exists());
+        let index = Index::load(path::index()).unwrap();
+        assert!(index.is_skip_worktree("c/c.txt"));
+        assert!(status::is_clean().await);
+
+        // local changes are kept
+        test::ensure_file("a/b/b.txt", Some("changed"));
+        execute(set(&["c"])).await;
+        assert!(workdir.join("c/c.txt").exists());
+        assert!(!workdir.join("a/a.txt").exists());
+        assert!(workdir.join("a/b/b.txt").exists());
+
+        execute(SparseCheckoutCmds::Disable).await;
+        assert!(workdir.join("a/a.txt").exists());
+        let index = Index::load(path::index()).unwrap();
+        assert!(!index.is_skip_worktree("c/c.txt"));
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use common::utils::{format_commit_msg, parse_commit_msg};
+use mercury::hash::SHA1;
+use mercury::internal::index::{Index, IndexEntry};
+use mercury::internal::object::blob::Blob;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tree::Tree;
+
+use crate::internal::head::Head;
+use crate::internal::reflog::{Reflog, ReflogEntry};
+use crate::utils::object_ext::{BlobExt, CommitExt};
+use crate::utils::path_ext::PathExt;
+use crate::utils::{path, util};
+
+use super::{
+    add, commit, diff, merge,
+    restore::{self, RestoreArgs},
+    save_object, status,
+};
+
+/// The stash stack is kept as the reflog of `refs/stash`, `stash@{0}` is the newest
+const STASH_REF: &str = "refs/stash";
+
+#[derive(Parser, Debug)]
+pub struct StashArgs {
+    /// `push` if not specified
+    #[command(subcommand)]
+    pub command: Option<StashCmds>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StashCmds {
+    /// Save local changes to a new stash entry and revert them to HEAD
+    Push {
+        /// The description of the stash entry
+        #[clap(long, short)]
+        message: Option<String>,
+    },
+    /// Apply a stash entry and remove it from the stash list
+    Pop {
+        /// `stash@{n}` or `n`, default is the latest one
+        stash: Option<String>,
+    },
+    /// Apply a stash entry on top of the current worktree
+    Apply {
+        /// `stash@{n}` or `n`, default is the latest one
+        stash: Option<String>,
+    },
+    /// List the stash entries
+    List,
+    /// Remove a stash entry from the stash list
+    Drop {
+        /// `stash@{n}` or `n`, default is the latest one
+        stash: Option<String>,
+    },
+    /// Show the changes recorded in a stash entry
+    Show {
+        /// `stash@{n}` or `n`, default is the latest one
+        stash: Option<String>,
+        /// Show the changes as a patch
+        #[clap(long, short)]
+        patch: bool,
+    },
+}
+
+pub async fn execute(args: StashArgs) {
+    match args.command.unwrap_or(StashCmds::Push { message: None }) {
+        StashCmds::Push { message } => push(message).await,
+        StashCmds::Pop { stash } => {
+            if let Some((n, entry)) = find_stash(stash.as_deref()).await {
+                if apply(&entry).await {
+                    drop_stash(n, &entry).await;
+                }
+            }
+        }
+        StashCmds::Apply { stash } => {
+            if let Some((_, entry)) = find_stash(stash.as_deref()).await {
+                apply(&entry).await;
+            }
+        }
+        StashCmds::List => {
+            for (n, entry) in Reflog::list(STASH_REF).await.iter().enumerate() {
+                println!("stash@{{{}}}: {}", n, entry.message);
+            }
+        }
+        StashCmds::Drop { stash } => {
+            if let Some((n, entry)) = find_stash(stash.as_deref()).await {
+                drop_stash(n, &entry).await;
+            }
+        }
+        StashCmds::Show { stash, patch } => {
+            if let Some((_, entry)) = find_stash(stash.as_deref()).await {
+                show(&entry, patch).await;
+            }
+        }
+    }
+}
+
+/// Save index & worktree as commits, then reset them to HEAD.
+/// Like git, the stash commit `W` records the worktree, its parents are `HEAD` and `I` which records the index.
+async fn push(message: Option<String>) {
+    let head = match Head::current_commit().await {
+        Some(commit) => commit,
+        None => {
+            eprintln!("fatal: You do not have the initial commit yet");
+            return;
+        }
+    };
+    if Index::load(path::index()).unwrap().has_conflicts() {
+        eprintln!("error: could not save index tree, you have unmerged files");
+        return;
+    }
+    if status::is_clean_tracked().await {
+        println!("No local changes to save");
+        return;
+    }
+
+    let branch = match Head::current().await {
+        Head::Branch(name) => name,
+        Head::Detached(_) => "(no branch)".to_string(),
+    };
+    let head_commit = Commit::load(&head);
+    let (head_message, _) = parse_commit_msg(&head_commit.message);
+    let head_summary = format!(
+        "{} {}",
+        &head.to_string()[..7],
+        head_message.lines().next().unwrap_or_default()
+    );
+    let message = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}: {}", branch, head_summary),
+    };
+
+    let storage = util::objects_storage();
+    let index = Index::load(path::index()).unwrap();
+    let index_tree = commit::create_tree(&index, &storage, "".into()).await;
+    let index_commit = Commit::from_tree_id(
+        index_tree.id,
+        vec![head],
+        &format_commit_msg(&format!("index on {}: {}", branch, head_summary), None),
+    );
+    save_object(&index_commit, &index_commit.id).unwrap();
+
+    let worktree_tree = create_worktree_tree().await;
+    let stash_commit = Commit::from_tree_id(
+        worktree_tree.id,
+        vec![head, index_commit.id],
+        &format_commit_msg(&message, None),
+    );
+    save_object(&stash_commit, &stash_commit.id).unwrap();
+
+    let old_stash = Reflog::find(STASH_REF, 0)
+        .await
+        .map(|entry| entry.new_oid)
+        .unwrap_or_default();
+    if let Err(e) = Reflog::append(STASH_REF, old_stash, stash_commit.id, &message).await {
+        eprintln!("fatal: cannot save the current status: {}", e);
+        return;
+    }
+
+    restore::execute(RestoreArgs {
+        worktree: true,
+        staged: true,
+        source: Some("HEAD".to_string()),
+        pathspec: vec![util::working_dir_string()],
+    })
+    .await;
+    println!("Saved working directory and index state {}", message);
+}
+
+/// Create the tree of tracked files with their contents in worktree, untracked files are not included
+async fn create_worktree_tree() -> Tree {
+    let workdir = util::working_dir();
+    // a temporary index, NOT saved
+    let mut index = Index::load(path::index()).unwrap();
+    for file in index.tracked_files() {
+        let file_str = file.to_string_or_panic();
+        let file_abs = util::workdir_to_absolute(&file);
+        if !file_abs.exists() {
+            index.remove(&file_str, 0);
+        } else if index.is_modified(&file_str, 0, &workdir) {
+            let blob = add::gen_blob_from_file(&file_abs);
+            if !index.verify_hash(&file_str, 0, &blob.id) {
+                blob.save();
+                index.update(IndexEntry::new_from_file(&file, blob.id, &workdir).unwrap());
+            }
+        }
+    }
+    commit::create_tree(&index, &util::objects_storage(), "".into()).await
+}
+
+/// Merge the changes of the stash entry into worktree, return `false` if it can't be applied cleanly
+async fn apply(entry: &ReflogEntry) -> bool {
+    let head = match Head::current_commit().await {
+        Some(commit) => commit,
+        None => {
+            eprintln!("fatal: You do not have the initial commit yet");
+            return false;
+        }
+    };
+    if !status::is_clean_tracked().await {
+        eprintln!("error: Your local changes would be overwritten by stash apply.");
+        eprintln!("hint: Please commit your changes or stash them before you apply.");
+        return false;
+    }
+    let stash_commit = Commit::load(&entry.new_oid);
+    let base_tree = Commit::load(&stash_commit.parent_commit_ids[0]).tree_id;
+    let head_tree = Commit::load(&head).tree_id;
+    let Some(conflicts) = merge::merge_into_worktree(
+        Some(base_tree),
+        head_tree,
+        stash_commit.tree_id,
+        "Updated upstream",
+        "Stashed changes",
+    )
+    .await
+    else {
+        return false;
+    };
+    if !conflicts.is_empty() {
+        eprintln!("The stash entry is kept in case you need it again.");
+        return false;
+    }
+    unstage_changes(&merge::tree_blobs(&head_tree));
+    true
+}
+
+/// Reset the index to `head_blobs` but keep new files added, like `git stash apply` without `--index`
+fn unstage_changes(head_blobs: &HashMap<PathBuf, SHA1>) {
+    let index_file = path::index();
+    let mut index = Index::load(&index_file).unwrap();
+    for (file, hash) in head_blobs {
+        let file_str = file.to_string_or_panic();
+        if !index.verify_hash(&file_str, 0, hash) {
+            let blob = Blob::load(hash);
+            index.update(IndexEntry::new_from_blob(
+                file_str,
+                *hash,
+                blob.data.len() as u32,
+            ));
+        }
+    }
+    index.save(&index_file).unwrap();
+}
+
+async fn drop_stash(n: usize, entry: &ReflogEntry) {
+    Reflog::delete(entry.id).await;
+    println!("Dropped stash@{{{}}} ({})", n, entry.new_oid);
+}
+
+async fn show(entry: &ReflogEntry, patch: bool) {
+    let stash_commit = Commit::load(&entry.new_oid);
+    let mut gitlinks = HashSet::new();
+    let old_blobs: HashMap<_, _> =
+        diff::commit_blobs(&stash_commit.parent_commit_ids[0], &mut gitlinks)
+            .into_iter()
+            .collect();
+    let new_blobs: HashMap<_, _> = diff::tree_blobs(&stash_commit.tree_id, &mut gitlinks)
+        .into_iter()
+        .collect();
+    if patch {
+        diff::diff(
+            old_blobs.into_iter().collect(),
+            new_blobs.into_iter().collect(),
+            gitlinks,
+            vec![],
+            &mut io::stdout(),
+        )
+        .await;
+        return;
+    }
+    let mut files: Vec<&PathBuf> = old_blobs.keys().chain(new_blobs.keys()).collect();
+    files.sort();
+    files.dedup();
+    for file in files {
+        let state = match (old_blobs.get(file), new_blobs.get(file)) {
+            (None, Some(_)) => "A",
+            (Some(_), None) => "D",
+            (Some(old), Some(new)) if old != new => "M",
+            _ => continue,
+        };
+        println!("{}\t{}", state, file.display());
+    }
+}
+
+/// Find the stash entry by `stash@{n}` or `n`, the latest one if `None`
+async fn find_stash(stash: Option<&str>) -> Option<(usize, ReflogEntry)> {
+    let n = match stash {
+        None => 0,
+        Some(stash) => match parse_stash_index(stash) {
+            Some(n) => n,
+            None => {
+                eprintln!("error: '{}' is not a stash reference", stash);
+                return None;
+            }
+        },
+    };
+    match Reflog::find(STASH_REF, n).await {
+        Some(entry) => Some((n, entry)),
+        None if n == 0 => {
+            eprintln!("error: No stash entries found.");
+            None
+        }
+        None => {
+            eprintln!("error: stash@{{{}}} does not exist", n);
+            None
+        }
+    }
+}
+
+/// Parse `stash@{n}` or `n` to `n`
+fn parse_stash_index(stash: &str) -> Option<usize> {
+    let n = match stash.strip_prefix("stash@{") {
+        Some(rest) => rest.strip_suffix('}')?,
+        None => stash,
+    };
+    n.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test;
+    use serial_test::serial;
+    use std::fs;
+
+    #[test]
+    fn test_parse_stash_index() {
+        assert_eq!(parse_stash_index("stash@{0}"), Some(0));
+        assert_eq!(parse_stash_index("stash@{12}"), Some(12));
+        assert_eq!(parse_stash_index("3"), Some(3));
+        assert_eq!(parse_stash_index("stash@{x}"), None);
+        assert_eq!(parse_stash_index("stash@{1"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = StashArgs::try_parse_from(["stash"]).unwrap();
+        assert!(args.command.is_none());
+        let args = StashArgs::try_parse_from(["stash", "push", "-m", "wip"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(StashCmds::Push { message: Some(_) })
+        ));
+        let args = StashArgs::try_parse_from(["stash", "pop", "stash@{1}"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(StashCmds::Pop { stash: Some(_) })
+        ));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_stash_push_pop() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\n"));
+        test::ensure_file("b.txt", Some("b\n"));
+        test::commit_all("base").await;
+
+        test::ensure_file("a.txt", Some("a modified\n"));
+        fs::remove_file("b.txt").unwrap();
+        test::ensure_file("c.txt", Some("c\n"));
+        test::add_all().await;
+        test::ensure_file("a.txt", Some("a modified again\n"));
+
+        execute(StashArgs::parse_from(["stash", "push", "-m", "wip"])).await;
+        assert!(status::is_clean_tracked().await);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\n");
+        assert!(PathBuf::from("b.txt").exists());
+        assert!(!PathBuf::from("c.txt").exists());
+
+        let entries = Reflog::list(STASH_REF).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].message, "On master: wip");
+        let stash_commit = Commit::load(&entries[0].new_oid);
+        assert_eq!(stash_commit.parent_commit_ids.len(), 2);
+
+        execute(StashArgs::parse_from(["stash", "pop"])).await;
+        assert!(Reflog::list(STASH_REF).await.is_empty());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a modified again\n");
+        assert!(!PathBuf::from("b.txt").exists());
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "c\n");
+        // modifications are not staged but new files are
+        let staged = status::changes_to_be_committed().await;
+        assert!(staged.modified.is_empty() && staged.deleted.is_empty());
+        assert_eq!(staged.new, vec![PathBuf::from("c.txt")]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_stash_stack() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a\n"));
+        test::commit_all("base").await;
+
+        execute(StashArgs::parse_from(["stash"])).await;
+        assert!(Reflog::list(STASH_REF).await.is_empty()); // nothing to save
+
+        test::ensure_file("a.txt", Some("first\n"));
+        execute(StashArgs::parse_from(["stash"])).await;
+        test::ensure_file("a.txt", Some("second\n"));
+        execute(StashArgs::parse_from(["stash"])).await;
+        let entries = Reflog::list(STASH_REF).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].new_oid, entries[0].old_oid);
+        assert!(entries[0].message.starts_with("WIP on master: "));
+
+        execute(StashArgs::parse_from(["stash", "drop", "stash@{0}"])).await;
+        execute(StashArgs::parse_from(["stash", "apply"])).await;
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "first\n");
+        assert_eq!(Reflog::list(STASH_REF).await.len(), 1);
+    }
+}
@@ -7,8 +7,12 @@ use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::Tree;
 
 use crate::command::calc_file_blob_hash;
+use crate::command::{cherry_pick, merge};
 use crate::internal::head::Head;
+use crate::utils::ignore::IgnoreRules;
 use crate::utils::object_ext::{CommitExt, TreeExt};
+use crate::utils::sparse::{self, SparseCheckout};
+use crate::utils::submodule;
 use crate::utils::{path, util};
 use mercury::internal::index::Index;
 
@@ -45,7 +49,6 @@ pub async fn execute() {
     if !util::check_repo_exist() {
         return;
     }
-    // TODO .gitignore
     match Head::current().await {
         Head::Detached(commit) => {
             println!(
@@ -62,10 +65,65 @@ pub async fn execute() {
         println!("\nNo commits yet\n");
     }
 
+    let index = Index::load(path::index()).unwrap();
+    if SparseCheckout::load().await.is_some() {
+        let tracked = index.tracked_entries(0);
+        let present = tracked.iter().filter(|e| !e.flags.skip_worktree).count();
+        println!(
+            "You are in a sparse checkout with {}% of tracked files present.\n",
+            present * 100 / tracked.len().max(1)
+        );
+    }
+    let unmerged = index.unmerged_files();
+    if merge::read_merge_head().is_some() {
+        if unmerged.is_empty() {
+            println!("All conflicts fixed but you are still merging.");
+            println!("  use \"libra commit\" to conclude merge");
+        } else {
+            println!("You have unmerged paths.");
+            println!("  fix conflicts and run \"libra commit\"");
+            println!("  use \"libra merge --abort\" to abort the merge");
+        }
+    } else if let Some(action) = cherry_pick::sequence_in_progress() {
+        let doing = match action {
+            cherry_pick::Action::Pick => "cherry-picking",
+            cherry_pick::Action::Revert => "reverting",
+        };
+        println!("You are currently {}.", doing);
+        if unmerged.is_empty() {
+            println!(
+                "  (all conflicts fixed: run \"libra {} --continue\")",
+                action.name()
+            );
+        } else {
+            println!(
+                "  (fix conflicts and run \"libra {} --continue\")",
+                action.name()
+            );
+            println!(
+                "  (use \"libra {} --skip\" to skip this commit)",
+                action.name()
+            );
+            println!(
+                "  (use \"libra {} --abort\" to cancel the operation)",
+                action.name()
+            );
+        }
+    } else if path::rebase_merge().exists() {
+        println!("You are currently rebasing.");
+        if unmerged.is_empty() {
+            println!("  (all conflicts fixed: run \"libra rebase --continue\")");
+        } else {
+            println!("  (fix conflicts and then run \"libra rebase --continue\")");
+            println!("  (use \"libra rebase --skip\" to skip this commit)");
+            println!("  (use \"libra rebase --abort\" to check out the original branch)");
+        }
+    }
+
     // to cur_dir relative path
     let staged = changes_to_be_committed().await.to_relative();
-    let unstaged = changes_to_be_staged().to_relative();
-    if staged.is_empty() && unstaged.is_empty() {
+    let unstaged = changes_to_be_staged().await.to_relative();
+    if staged.is_empty() && unstaged.is_empty() && unmerged.is_empty() {
         println!("nothing to commit, working tree clean");
         return;
     }
@@ -87,6 +145,19 @@ pub async fn execute() {
         });
     }
 
+    if !unmerged.is_empty() {
+        println!("Unmerged paths:");
+        println!("  use \"libra add <file>...\" to mark resolution");
+        unmerged.iter().for_each(|f| {
+            let str = format!(
+                "\t{}: {}",
+                unmerged_state(&index, f.to_str().unwrap()),
+                util::workdir_to_current(f).display()
+            );
+            println!("{}", str.bright_red());
+        });
+    }
+
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
         println!("Changes not staged for commit:");
         println!("  use \"libra add <file>...\" to update what will be committed");
@@ -110,13 +181,36 @@ pub async fn execute() {
     }
 }
 
+/// Describe how the file is conflicted according to its stages, like git
+fn unmerged_state(index: &Index, file: &str) -> &'static str {
+    match (
+        index.tracked(file, 1),
+        index.tracked(file, 2),
+        index.tracked(file, 3),
+    ) {
+        (true, true, true) => "both modified",
+        (false, true, true) => "both added",
+        (true, true, false) => "deleted by them",
+        (true, false, true) => "deleted by us",
+        _ => "unmerged",
+    }
+}
+
 /// Check if the working tree is clean
 pub async fn is_clean() -> bool {
     let staged = changes_to_be_committed().await;
-    let unstaged = changes_to_be_staged();
+    let unstaged = changes_to_be_staged().await;
     staged.is_empty() && unstaged.is_empty()
 }
 
+/// Check if the tracked files are clean (no staged or unstaged changes), untracked files are ignored
+pub async fn is_clean_tracked() -> bool {
+    let unstaged = changes_to_be_staged().await;
+    unstaged.modified.is_empty()
+        && unstaged.deleted.is_empty()
+        && changes_to_be_committed().await.is_empty()
+}
+
 /**
  * Compare the difference between `index` and the last `Commit Tree`
  */
@@ -125,6 +219,7 @@ pub async fn changes_to_be_committed() -> Changes {
     let index = Index::load(path::index()).unwrap();
     let head_commit = Head::current_commit().await;
     let tracked_files = index.tracked_files();
+    let unmerged_files: HashSet<PathBuf> = index.unmerged_files().into_iter().collect();
 
     if head_commit.is_none() {
         // no commit yet
@@ -139,6 +234,9 @@ pub async fn changes_to_be_committed() -> Changes {
 
     for (item_path, item_hash) in tree_files.iter() {
         let item_str = item_path.to_str().unwrap();
+        if unmerged_files.contains(item_path) {
+            continue; // conflicted, shown as unmerged
+        }
         if index.tracked(item_str, 0) {
             if !index.verify_hash(item_str, 0, item_hash) {
                 changes.modified.push(item_path.clone());
@@ -159,16 +257,29 @@ pub async fn changes_to_be_committed() -> Changes {
 }
 
 /// Compare the difference between `index` and the `workdir`
-pub fn changes_to_be_staged() -> Changes {
+/// - the untracked files ignored by `.gitignore`, `.libraignore` etc. are not listed as new
+/// - the files out of the sparse checkout are skipped, tracked (skip-worktree) or not
+/// - a submodule (gitlink) or an untracked nested repository is a single path, its files are not listed
+pub async fn changes_to_be_staged() -> Changes {
     let mut changes = Changes::default();
     let workdir = util::working_dir();
     let index = Index::load(path::index()).unwrap();
     let tracked_files = index.tracked_files();
     for file in tracked_files.iter() {
         let file_str = file.to_str().unwrap();
+        if index.is_skip_worktree(file_str) {
+            continue;
+        }
         let file_abs = util::workdir_to_absolute(file);
         if !file_abs.exists() {
             changes.deleted.push(file.clone());
+        } else if index.get(file_str, 0).unwrap().is_gitlink() {
+            // submodule, modified if another commit is checked out, unchanged if not checked out
+            if let Some(commit) = submodule::head_commit(&file_abs).await {
+                if !index.verify_hash(file_str, 0, &commit) {
+                    changes.modified.push(file.clone());
+                }
+            }
         } else if index.is_modified(file_str, 0, &workdir) {
             // only calc the hash if the file is modified (metadata), for optimization
             let file_hash = calc_file_blob_hash(&file_abs).unwrap();
@@ -177,9 +288,14 @@ pub fn changes_to_be_staged() -> Changes {
             }
         }
     }
-    let files = util::list_workdir_files().unwrap(); // to workdir
+    let unmerged_files: HashSet<PathBuf> = index.unmerged_files().into_iter().collect();
+    let files = IgnoreRules::load().await.list_files(&workdir).unwrap(); // to workdir
+    let sparse = SparseCheckout::load().await;
     for file in files.iter() {
-        if !index.tracked(file.to_str().unwrap(), 0) {
+        if !index.tracked(file.to_str().unwrap(), 0)
+            && !unmerged_files.contains(file)
+            && sparse::is_included(sparse.as_ref(), file)
+        {
             // file not tracked in `index`
             changes.new.push(file.clone());
         }
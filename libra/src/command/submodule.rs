@@ -0,0 +1,310 @@
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use mercury::hash::SHA1;
+use mercury::internal::index::Index;
+
+use crate::internal::config::Config;
+use crate::utils::submodule::{self, Submodule};
+use crate::utils::{path, util};
+
+/// [Docs](https://git-scm.com/docs/git-submodule)
+#[derive(Subcommand, Debug)]
+pub enum SubmoduleCmds {
+    /// Initialize the submodules by registering their urls in `.gitmodules` to the config
+    Init { paths: Vec<String> },
+    /// Clone the missing submodules & check out the commits recorded in the super project
+    Update {
+        /// Initialize the submodules not initialized yet
+        #[clap(long)]
+        init: bool,
+        /// Update the submodules in the submodules as well
+        #[clap(long)]
+        recursive: bool,
+        paths: Vec<String>,
+    },
+    /// Show the commits checked out in the submodules
+    Status { paths: Vec<String> },
+    /// Update the urls of the submodules in the config & their remotes to the ones in `.gitmodules`
+    Sync {
+        /// Synchronize the submodules in the submodules as well
+        #[clap(long)]
+        recursive: bool,
+        paths: Vec<String>,
+    },
+}
+
+pub async fn execute(cmd: SubmoduleCmds) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    let index = Index::load(path::index()).unwrap();
+    match cmd {
+        SubmoduleCmds::Init { paths } => init(&submodules(&index, &paths)).await,
+        SubmoduleCmds::Update {
+            init: init_first,
+            recursive,
+            paths,
+        } => {
+            let submodules = submodules(&index, &paths);
+            if init_first {
+                init(&submodules).await;
+            }
+            update(&submodules, recursive).await;
+        }
+        SubmoduleCmds::Status { paths } => {
+            for (path, commit) in gitlinks(&index, &paths) {
+                let (sign, commit) =
+                    match submodule::head_commit(&util::workdir_to_absolute(&path)).await {
+                        None => ('-', commit), // not checked out
+                        Some(head) if head == commit => (' ', commit),
+                        Some(head) => ('+', head),
+                    };
+                println!(
+                    "{}{} {}",
+                    sign,
+                    commit,
+                    util::workdir_to_current(&path).display()
+                );
+            }
+        }
+        SubmoduleCmds::Sync { recursive, paths } => {
+            sync(&submodules(&index, &paths), recursive).await
+        }
+    }
+}
+
+/// The gitlinks in the index (to workdir) matching the pathspecs (all if empty)
+fn gitlinks(index: &Index, paths: &[String]) -> Vec<(PathBuf, SHA1)> {
+    index
+        .tracked_entries(0)
+        .into_iter()
+        .filter(|entry| entry.is_gitlink())
+        .map(|entry| (PathBuf::from(&entry.name), entry.hash))
+        .filter(|(path, _)| {
+            paths.is_empty() || util::is_sub_of_paths(util::workdir_to_absolute(path), paths)
+        })
+        .collect()
+}
+
+/// The submodules in `.gitmodules` with the commits recorded in the index, matching the pathspecs
+fn submodules(index: &Index, paths: &[String]) -> Vec<(Submodule, SHA1)> {
+    let modules = submodule::load();
+    gitlinks(index, paths)
+        .into_iter()
+        .filter_map(
+            |(path, commit)| match modules.iter().find(|m| m.path == path) {
+                Some(module) => Some((module.clone(), commit)),
+                None => {
+                    eprintln!(
+                        "fatal: no submodule mapping found in {} for path '{}'",
+                        submodule::GITMODULES,
+                        path.display()
+                    );
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Register the urls of the submodules not initialized yet
+async fn init(submodules: &[(Submodule, SHA1)]) {
+    let base = submodule::base_url().await;
+    for (module, _) in submodules {
+        if Config::get("submodule", Some(&module.name), "url")
+            .await
+            .is_some()
+        {
+            continue;
+        }
+        let url = match submodule::resolve_url(&module.url, &base) {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                continue;
+            }
+        };
+        Config::insert("submodule", Some(&module.name), "url", &url).await;
+        println!(
+            "Submodule '{}' ({}) registered for path '{}'",
+            module.name,
+            url,
+            module.path.display()
+        );
+    }
+}
+
+/// Clone the initialized submodules if missing, then check out the recorded commits (detached)
+async fn update(submodules: &[(Submodule, SHA1)], recursive: bool) {
+    for (module, commit) in submodules {
+        let Some(url) = Config::get("submodule", Some(&module.name), "url").await else {
+            continue; // not initialized
+        };
+        let dir = util::workdir_to_absolute(&module.path);
+        let path = module.path.display();
+        if !util::is_nested_repo(&dir) {
+            let target = dir.to_str().unwrap();
+            if let Err(e) =
+                submodule::run_libra(&util::working_dir(), &["clone", url.as_str(), target])
+            {
+                eprintln!(
+                    "fatal: clone of '{}' into submodule path '{}' failed: {}",
+                    url, path, e
+                );
+                continue;
+            }
+        } else if !dir.join(util::ROOT_DIR).is_dir() {
+            // libra would find the super project from there
+            eprintln!(
+                "warning: submodule path '{}' is not a libra repository, skipped",
+                path
+            );
+            continue;
+        }
+
+        if submodule::head_commit(&dir).await != Some(*commit) {
+            let commit_str = commit.to_string();
+            let checkout = ["switch", "--detach", commit_str.as_str()];
+            // the commit may be fetched after the clone, the errors are reported by the commands
+            let _ = submodule::run_libra(&dir, &checkout);
+            if submodule::head_commit(&dir).await != Some(*commit) {
+                let _ = submodule::run_libra(&dir, &["fetch"])
+                    .and_then(|_| submodule::run_libra(&dir, &checkout));
+            }
+            if submodule::head_commit(&dir).await != Some(*commit) {
+                eprintln!(
+                    "fatal: Unable to checkout '{}' in submodule path '{}'",
+                    commit, path
+                );
+                continue;
+            }
+            println!("Submodule path '{}': checked out '{}'", path, commit);
+        }
+
+        if recursive {
+            if let Err(e) =
+                submodule::run_libra(&dir, &["submodule", "update", "--init", "--recursive"])
+            {
+                eprintln!("fatal: failed to recurse into submodule '{}': {}", path, e);
+            }
+        }
+    }
+}
+
+/// Update the urls of the initialized submodules from `.gitmodules`, in the config & their `origin`
+async fn sync(submodules: &[(Submodule, SHA1)], recursive: bool) {
+    let base = submodule::base_url().await;
+    for (module, _) in submodules {
+        if Config::get("submodule", Some(&module.name), "url")
+            .await
+            .is_none()
+        {
+            continue; // not initialized
+        }
+        let url = match submodule::resolve_url(&module.url, &base) {
+            Ok(url) => url,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                continue;
+            }
+        };
+        println!(
+            "Synchronizing submodule url for '{}'",
+            module.path.display()
+        );
+        Config::update("submodule", Some(&module.name), "url", &url).await;
+
+        let dir = util::workdir_to_absolute(&module.path);
+        if !dir.join(util::ROOT_DIR).is_dir() {
+            continue; // not cloned yet
+        }
+        if let Err(e) = submodule::run_libra(&dir, &["config", "remote.origin.url", url.as_str()]) {
+            eprintln!("fatal: {}", e);
+            continue;
+        }
+        if recursive {
+            if let Err(e) = submodule::run_libra(&dir, &["submodule", "sync", "--recursive"]) {
+                eprintln!(
+                    "fatal: failed to recurse into submodule '{}': {}",
+                    module.path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use mercury::internal::index::IndexEntry;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::command::status;
+    use crate::utils::test;
+
+    /// `lib/a` & `b` are submodules, `lib/a` is checked out (a git repository) at another commit
+    async fn setup_submodules() -> (SHA1, SHA1) {
+        test::setup_with_new_libra().await;
+        test::ensure_file(
+            submodule::GITMODULES,
+            Some(
+                "[submodule \"a\"]\n\tpath = lib/a\n\turl = https://example.com/a.git\n\
+                 [submodule \"b\"]\n\tpath = b\n\turl = ../b.git\n",
+            ),
+        );
+        let (recorded, checked_out) = (SHA1::from_bytes(&[1; 20]), SHA1::from_bytes(&[2; 20]));
+        let mut index = Index::load(path::index()).unwrap();
+        index.add(IndexEntry::new_from_gitlink("lib/a".to_string(), recorded));
+        index.add(IndexEntry::new_from_gitlink("b".to_string(), recorded));
+        index.save(path::index()).unwrap();
+
+        let git_dir = util::working_dir().join("lib/a/.git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("HEAD"), format!("{}\n", checked_out)).unwrap();
+        test::ensure_file("lib/a/file.txt", Some("in the submodule"));
+        fs::create_dir_all(util::working_dir().join("b")).unwrap();
+        (recorded, checked_out)
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_init() {
+        setup_submodules().await;
+        execute(SubmoduleCmds::Init {
+            paths: vec!["b".to_string()],
+        })
+        .await;
+        assert_eq!(Config::get("submodule", Some("a"), "url").await, None);
+        let expected = submodule::resolve_url("../b.git", &util::working_dir_string()).unwrap();
+        assert_eq!(
+            Config::get("submodule", Some("b"), "url").await,
+            Some(expected)
+        );
+
+        execute(SubmoduleCmds::Init { paths: vec![] }).await;
+        assert_eq!(
+            Config::get("submodule", Some("a"), "url").await.as_deref(),
+            Some("https://example.com/a.git")
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_gitlinks_in_status() {
+        let (_, checked_out) = setup_submodules().await;
+        assert_eq!(
+            submodule::head_commit(&util::working_dir().join("lib/a")).await,
+            Some(checked_out)
+        );
+        let changes = status::changes_to_be_staged().await;
+        // another commit is checked out in `lib/a`, `b` is not checked out
+        assert_eq!(changes.modified, vec![PathBuf::from("lib/a")]);
+        assert!(changes.deleted.is_empty());
+        // the files of the submodule are not listed
+        assert!(!changes.new.iter().any(|file| file.starts_with("lib")));
+    }
+}
@@ -3,8 +3,8 @@ use mercury::hash::SHA1;
 
 use crate::{
     command::branch,
-    internal::{branch::Branch, head::Head},
-    utils::util::{self, get_commit_base},
+    internal::{branch::Branch, head::Head, reflog::Reflog, revision},
+    utils::{hooks, util, worktree},
 };
 
 use super::{
@@ -29,7 +29,7 @@ pub struct SwitchArgs {
 
 pub async fn execute(args: SwitchArgs) {
     // check status
-    let unstaged = status::changes_to_be_staged();
+    let unstaged = status::changes_to_be_staged().await;
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
         status::execute().await;
         eprintln!("fatal: uncommitted changes, can't switch branch");
@@ -47,12 +47,14 @@ pub async fn execute(args: SwitchArgs) {
         }
         None => match args.detach {
             true => {
-                let commit_base = get_commit_base(&args.branch.unwrap());
-                if commit_base.is_err() {
-                    eprintln!("{}", commit_base.unwrap());
-                    return;
-                }
-                switch_to_commit(commit_base.unwrap()).await;
+                let commit = match revision::resolve_commit(&args.branch.unwrap()).await {
+                    Ok(commit) => commit,
+                    Err(e) => {
+                        eprintln!("fatal: {}", e);
+                        return;
+                    }
+                };
+                switch_to_commit(commit).await;
             }
             false => {
                 switch_to_branch(args.branch.unwrap()).await;
@@ -63,7 +65,7 @@ pub async fn execute(args: SwitchArgs) {
 
 // Check status before change the branch
 pub async fn check_status() -> bool {
-    let unstaged: status::Changes = status::changes_to_be_staged();
+    let unstaged: status::Changes = status::changes_to_be_staged().await;
     if !unstaged.deleted.is_empty() || !unstaged.modified.is_empty() {
         status::execute().await;
         eprintln!("fatal: uncommitted changes, can't switch branch");
@@ -79,10 +81,13 @@ pub async fn check_status() -> bool {
 
 /// change the working directory to the version of commit_hash
 async fn switch_to_commit(commit_hash: SHA1) {
+    let (from, old_head) = (Head::current().await, Head::current_commit().await);
     restore_to_commit(commit_hash).await;
     // update HEAD
     let head = Head::Detached(commit_hash);
-    Head::update(head, None).await;
+    Head::update(head.clone(), None).await;
+    Reflog::log_checkout(&from, old_head, &head, commit_hash).await;
+    hooks::post_checkout(old_head, commit_hash).await;
 }
 
 async fn switch_to_branch(branch_name: String) {
@@ -98,12 +103,18 @@ async fn switch_to_branch(branch_name: String) {
         }
         return;
     }
+    if !worktree::check_branch_unused(&branch_name).await {
+        return;
+    }
     let commit_id = target_branch.unwrap().commit;
+    let (from, old_head) = (Head::current().await, Head::current_commit().await);
     restore_to_commit(commit_id).await;
     // update HEAD
     // let mut head: ActiveModel = reference::Model::current_head(db).await.unwrap().into();
     let head = Head::Branch(branch_name);
-    Head::update(head, None).await;
+    Head::update(head.clone(), None).await;
+    Reflog::log_checkout(&from, old_head, &head, commit_id).await;
+    hooks::post_checkout(old_head, commit_id).await;
 }
 
 async fn restore_to_commit(commit_id: SHA1) {
@@ -154,6 +165,7 @@ mod tests {
             all: false,
             update: false,
             verbose: true,
+            force: false,
         };
         add::execute(add_args).await;
         assert!(check_status().await);
@@ -0,0 +1,279 @@
+use clap::Parser;
+use common::utils::format_commit_msg;
+use mercury::internal::object::signature::{Signature, SignatureType};
+use mercury::internal::object::tag::Tag as TagObject;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+use wax::Pattern;
+
+use crate::command::branch::is_valid_git_branch_name;
+use crate::command::{get_target_commit, load_object, save_object};
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::signing;
+use crate::internal::tag::Tag;
+use crate::utils::util;
+
+#[derive(Parser, Debug)]
+pub struct TagArgs {
+    /// new tag name
+    #[clap(group = "sub")]
+    name: Option<String>,
+
+    /// the commit the new tag refers to, default is HEAD
+    #[clap(requires = "name")]
+    commit: Option<String>,
+
+    /// make an annotated tag object, requires a message
+    #[clap(short, long, requires = "name", requires = "message")]
+    annotate: bool,
+
+    /// tag message, makes an annotated tag
+    #[clap(short, long, requires = "name")]
+    message: Option<String>,
+
+    /// make a PGP-signed annotated tag, requires a message
+    #[clap(short, long, requires = "name", requires = "message")]
+    sign: bool,
+
+    /// replace the tag if it already exists
+    #[clap(short, long, requires = "name")]
+    force: bool,
+
+    /// delete the tag
+    #[clap(short, long, group = "sub")]
+    delete: Option<String>,
+
+    /// list tags, only those matching the glob pattern if given
+    #[clap(short, long, group = "sub", num_args = 0..=1)]
+    list: Option<Option<String>>,
+
+    /// verify the PGP signature of the tag
+    #[clap(short, long, group = "sub")]
+    verify: Option<String>,
+}
+
+pub async fn execute(args: TagArgs) {
+    if let Some(name) = args.name {
+        // `-a` requires `-m`, no editor to input the message
+        let message = match args.annotate || args.sign || args.message.is_some() {
+            true => Some(args.message.unwrap_or_default()),
+            false => None,
+        };
+        create_tag(&name, args.commit, message, args.sign, args.force).await;
+    } else if let Some(name) = args.delete {
+        delete_tag(&name).await;
+    } else if let Some(name) = args.verify {
+        verify_tag(&name).await;
+    } else {
+        // default behavior
+        list_tags(args.list.flatten().as_deref()).await;
+    }
+}
+
+/// Create a lightweight tag, or an annotated tag object if `message` is given
+/// - `sign`: append a PGP signature to the message of the tag object
+async fn create_tag(
+    name: &str,
+    commit: Option<String>,
+    message: Option<String>,
+    sign: bool,
+    force: bool,
+) {
+    // tag names follow the same rules as branch names
+    if !is_valid_git_branch_name(name) {
+        eprintln!("fatal: '{}' is not a valid tag name.", name);
+        return;
+    }
+    if !force && Tag::find_tag(name).await.is_some() {
+        eprintln!("fatal: tag '{}' already exists", name);
+        return;
+    }
+
+    let commit_id = match commit {
+        Some(commit) => match get_target_commit(&commit).await {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        },
+        None => match Head::current_commit().await {
+            Some(commit_id) => commit_id,
+            None => {
+                eprintln!("fatal: Failed to resolve 'HEAD' as a valid ref.");
+                return;
+            }
+        },
+    };
+
+    let object = match message {
+        Some(message) => {
+            let (user_name, user_email) = Config::user_identity().await;
+            let tagger = Signature::new(SignatureType::Tagger, user_name, user_email);
+            let object_type = util::objects_storage().get_object_type(&commit_id).unwrap();
+            let mut message = format_commit_msg(&message, None);
+            if sign && !message.ends_with('\n') {
+                // the signature follows the message, on its own lines
+                message.push('\n');
+            }
+            let mut tag =
+                TagObject::new(commit_id, object_type, name.to_string(), tagger, &message);
+            if sign {
+                // the signed data is the tag without the signature
+                match signing::sign(&tag.to_data().unwrap()).await {
+                    Ok(signature) => message.push_str(&signature),
+                    Err(e) => {
+                        eprintln!("fatal: failed to sign the tag: {}", e);
+                        return;
+                    }
+                }
+                tag = TagObject::new(
+                    tag.object_hash,
+                    tag.object_type,
+                    tag.tag_name,
+                    tag.tagger,
+                    &message,
+                );
+            }
+            save_object(&tag, &tag.id).unwrap();
+            tag.id
+        }
+        None => commit_id,
+    };
+    Tag::update_tag(name, &object.to_string()).await;
+}
+
+async fn delete_tag(name: &str) {
+    match Tag::find_tag(name).await {
+        Some(tag) => {
+            Tag::delete_tag(name).await;
+            println!(
+                "Deleted tag '{}' (was {})",
+                name,
+                &tag.object.to_string()[..7]
+            );
+        }
+        None => eprintln!("error: tag '{}' not found.", name),
+    }
+}
+
+async fn verify_tag(name: &str) {
+    let tag = match Tag::find_tag(name).await {
+        Some(tag) => tag,
+        None => {
+            eprintln!("error: tag '{}' not found.", name);
+            return;
+        }
+    };
+    // a lightweight tag has no tag object to carry the signature
+    let object_type = util::objects_storage()
+        .get_object_type(&tag.object)
+        .unwrap();
+    if object_type != ObjectType::Tag {
+        eprintln!(
+            "error: {}: cannot verify a non-tag object of type {}.",
+            name, object_type
+        );
+        return;
+    }
+    let tag_object: TagObject = load_object(&tag.object).unwrap();
+    match signing::verify_tag(&tag_object).await {
+        Some(result) => eprintln!("{}", signing::describe(&result)),
+        None => eprintln!("error: no signature found in tag '{}'", name),
+    }
+}
+
+async fn list_tags(pattern: Option<&str>) {
+    let glob = match pattern.map(wax::Glob::new).transpose() {
+        Ok(glob) => glob,
+        Err(e) => {
+            eprintln!("fatal: invalid pattern '{}': {}", pattern.unwrap(), e);
+            return;
+        }
+    };
+    for tag in Tag::list_tags().await {
+        let matched = match &glob {
+            Some(glob) => glob.is_match(tag.name.as_str()),
+            None => true,
+        };
+        if matched {
+            println!("{}", tag.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{add::AddArgs, commit::CommitArgs};
+    use crate::utils::test;
+    use serial_test::serial;
+
+    #[test]
+    fn test_parse_args() {
+        let args = TagArgs::try_parse_from(["tag", "-a", "-m", "msg", "v1", "HEAD"]).unwrap();
+        assert!(args.annotate);
+        assert_eq!(args.name, Some("v1".to_string()));
+        assert_eq!(args.commit, Some("HEAD".to_string()));
+        let args = TagArgs::try_parse_from(["tag", "-l"]).unwrap();
+        assert_eq!(args.list, Some(None));
+        let args = TagArgs::try_parse_from(["tag", "-l", "v1.*"]).unwrap();
+        assert_eq!(args.list, Some(Some("v1.*".to_string())));
+        assert!(TagArgs::try_parse_from(["tag", "-a", "v1"]).is_err()); // requires message
+        assert!(TagArgs::try_parse_from(["tag", "-d", "v1", "v2"]).is_err());
+        assert!(TagArgs::try_parse_from(["tag", "-s", "v1"]).is_err()); // requires message
+        let args = TagArgs::try_parse_from(["tag", "-v", "v1"]).unwrap();
+        assert_eq!(args.verify, Some("v1".to_string()));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_create_and_delete_tags() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", None);
+        crate::command::add::execute(AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+            force: false,
+        })
+        .await;
+        crate::command::commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: false,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let head = Head::current_commit().await.unwrap();
+
+        execute(TagArgs::parse_from(["tag", "v1"])).await;
+        let lightweight = Tag::find_tag("v1").await.unwrap();
+        assert_eq!(lightweight.object, head);
+
+        execute(TagArgs::parse_from(["tag", "-m", "release", "v2"])).await;
+        let annotated = Tag::find_tag("v2").await.unwrap();
+        assert_ne!(annotated.object, head);
+        let tag_object: TagObject = load_object(&annotated.object).unwrap();
+        assert_eq!(tag_object.object_hash, head);
+        assert_eq!(tag_object.object_type, ObjectType::Commit);
+        assert_eq!(tag_object.tag_name, "v2");
+        assert_eq!(annotated.commit(), head);
+        assert_eq!(get_target_commit("v2").await.unwrap(), head);
+
+        // existing tag is not replaced without `--force`
+        execute(TagArgs::parse_from(["tag", "v2"])).await;
+        assert_eq!(Tag::find_tag("v2").await.unwrap().object, annotated.object);
+        execute(TagArgs::parse_from(["tag", "-f", "v2"])).await;
+        assert_eq!(Tag::find_tag("v2").await.unwrap().object, head);
+
+        let names: Vec<String> = Tag::list_tags().await.into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["v1", "v2"]);
+
+        execute(TagArgs::parse_from(["tag", "-d", "v1"])).await;
+        assert!(Tag::find_tag("v1").await.is_none());
+    }
+}
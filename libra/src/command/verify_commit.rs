@@ -0,0 +1,81 @@
+use clap::Parser;
+use mercury::internal::object::commit::Commit;
+
+use crate::command::{get_target_commit, load_object};
+use crate::internal::signing;
+
+#[derive(Parser, Debug)]
+pub struct VerifyCommitArgs {
+    /// The commits to verify, e.g. a hash, branch or `HEAD~1`
+    #[clap(required = true)]
+    pub commits: Vec<String>,
+}
+
+pub async fn execute(args: VerifyCommitArgs) {
+    for commit in args.commits {
+        let commit_id = match get_target_commit(&commit).await {
+            Ok(commit_id) => commit_id,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        };
+        let commit: Commit = load_object(&commit_id).unwrap();
+        // like git, the result is reported to stderr
+        match signing::verify_commit(&commit).await {
+            Some(result) => eprintln!("{}", signing::describe(&result)),
+            None => eprintln!("error: no signature found in commit {}", commit_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+    use vault::pgp::{self, KeyType};
+
+    use super::*;
+    use crate::command::commit::{self, CommitArgs};
+    use crate::internal::config::Config;
+    use crate::internal::head::Head;
+    use crate::utils::{test, util};
+
+    #[tokio::test]
+    #[serial]
+    async fn test_sign_and_verify_commit() {
+        test::setup_with_new_libra().await;
+        // an external key file, rather than the shared key pair in vault
+        let (_, sec_key) =
+            pgp::gen_pgp_keypair(pgp::params(KeyType::Rsa(2048), None, "test"), None);
+        let key_file = util::storage_path().join("signing.asc");
+        std::fs::write(&key_file, sec_key.to_armored_string(None.into()).unwrap()).unwrap();
+        Config::insert("user", None, "signingkey", key_file.to_str().unwrap()).await;
+
+        commit::execute(CommitArgs {
+            message: "signed".to_string(),
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: true,
+            no_verify: false,
+        })
+        .await;
+        let commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
+        assert!(commit
+            .message
+            .starts_with("gpgsig -----BEGIN PGP SIGNATURE-----"));
+        assert_eq!(commit.format_message(), "signed");
+        let result = signing::verify_commit(&commit).await.unwrap();
+        assert_eq!(result, Ok(pgp::fingerprint(&sec_key)));
+
+        commit::execute(CommitArgs {
+            message: "unsigned".to_string(),
+            allow_empty: true,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let commit: Commit = load_object(&Head::current_commit().await.unwrap()).unwrap();
+        assert!(signing::verify_commit(&commit).await.is_none());
+    }
+}
@@ -0,0 +1,553 @@
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+
+use clap::Subcommand;
+use mercury::hash::SHA1;
+use path_absolutize::Absolutize;
+
+use crate::command::restore::{self, RestoreArgs};
+use crate::command::{branch, get_target_commit, status};
+use crate::internal::branch::Branch;
+use crate::internal::head::Head;
+use crate::internal::reflog::{self, Reflog};
+use crate::utils::sparse::SparseCheckout;
+use crate::utils::worktree::{self, Worktree};
+use crate::utils::{hooks, path, util};
+
+/// [Docs](https://git-scm.com/docs/git-worktree)
+#[derive(Subcommand, Debug)]
+pub enum WorktreeCmds {
+    /// Create a worktree at `path` & check out `commit-ish` in it.
+    /// Without `commit-ish`, the branch named after `path` is checked out, created from HEAD if not exists
+    Add {
+        path: String,
+        /// a branch to check out, or a commit to check out detached
+        commit_ish: Option<String>,
+        /// create a new branch from `commit-ish` (HEAD by default) & check it out
+        #[clap(short = 'b', conflicts_with = "detach")]
+        new_branch: Option<String>,
+        /// check out the commit detached, even if `commit-ish` is a branch
+        #[clap(long)]
+        detach: bool,
+        /// lock the worktree after creation
+        #[clap(long)]
+        lock: bool,
+        /// check out the branch even if it's used by another worktree
+        #[clap(short, long)]
+        force: bool,
+    },
+    /// List the worktrees, the main one first
+    List {
+        /// output in an easy-to-parse format for scripts
+        #[clap(long)]
+        porcelain: bool,
+    },
+    /// Remove a linked worktree & its storage
+    Remove {
+        /// the path or the name of the worktree
+        worktree: String,
+        /// remove the worktree with modified or untracked files, twice to remove a locked one
+        #[clap(short, long, action = clap::ArgAction::Count)]
+        force: u8,
+    },
+    /// Remove the storages of the linked worktrees whose directories are deleted, except the locked ones
+    Prune {
+        /// only report what would be removed
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+        /// report the removed storages
+        #[clap(short, long)]
+        verbose: bool,
+    },
+    /// Lock a linked worktree to prevent it from being pruned or removed, e.g. on a removable device
+    Lock {
+        worktree: String,
+        #[clap(long)]
+        reason: Option<String>,
+    },
+    /// Unlock a linked worktree
+    Unlock { worktree: String },
+}
+
+pub async fn execute(cmd: WorktreeCmds) {
+    if !util::check_repo_exist() {
+        return;
+    }
+    match cmd {
+        WorktreeCmds::Add {
+            path,
+            commit_ish,
+            new_branch,
+            detach,
+            lock,
+            force,
+        } => add(&path, commit_ish, new_branch, detach, lock, force).await,
+        WorktreeCmds::List { porcelain } => list(porcelain).await,
+        WorktreeCmds::Remove { worktree, force } => remove(&worktree, force).await,
+        WorktreeCmds::Prune { dry_run, verbose } => prune(dry_run, verbose).await,
+        WorktreeCmds::Lock { worktree, reason } => {
+            if let Some(worktree) = find_linked(&worktree).await {
+                match worktree.locked() {
+                    Some(reason) => eprintln!(
+                        "fatal: '{}' is already locked, reason: {}",
+                        worktree.path.display(),
+                        reason
+                    ),
+                    None => {
+                        let file = worktree.storage.unwrap().join("locked");
+                        fs::write(file, reason.unwrap_or_default()).unwrap();
+                    }
+                }
+            }
+        }
+        WorktreeCmds::Unlock { worktree } => {
+            if let Some(worktree) = find_linked(&worktree).await {
+                match worktree.locked() {
+                    Some(_) => fs::remove_file(worktree.storage.unwrap().join("locked")).unwrap(),
+                    None => eprintln!("fatal: '{}' is not locked", worktree.path.display()),
+                }
+            }
+        }
+    }
+}
+
+/// Run `f` in the working directory `dir` of another worktree, then back to the current directory
+async fn in_worktree<T>(dir: &Path, f: impl Future<Output = T>) -> T {
+    let cur_dir = util::cur_dir();
+    std::env::set_current_dir(dir).unwrap();
+    let result = f.await;
+    std::env::set_current_dir(cur_dir).unwrap();
+    result
+}
+
+/// The worktree of the path (relative to the current dir) or the name
+async fn find(worktree: &str) -> Option<Worktree> {
+    let abs = util::cur_dir().join(worktree);
+    let abs = abs.absolutize().unwrap();
+    worktree::list()
+        .await
+        .into_iter()
+        .find(|w| w.path.absolutize().unwrap() == abs || w.name() == Some(worktree))
+}
+
+/// The linked worktree of the path or the name, print the error if not found or it's the main worktree
+async fn find_linked(worktree: &str) -> Option<Worktree> {
+    match find(worktree).await {
+        None => {
+            eprintln!("fatal: '{}' is not a working tree", worktree);
+            None
+        }
+        Some(w) if w.storage.is_none() => {
+            eprintln!("fatal: '{}' is a main working tree", worktree);
+            None
+        }
+        found => found,
+    }
+}
+
+async fn add(
+    path: &str,
+    commit_ish: Option<String>,
+    new_branch: Option<String>,
+    detach: bool,
+    lock: bool,
+    force: bool,
+) {
+    let dir = util::cur_dir()
+        .join(path)
+        .absolutize()
+        .unwrap()
+        .to_path_buf();
+    if dir.exists() && !util::is_empty_dir(&dir) {
+        eprintln!("fatal: '{}' already exists", path);
+        return;
+    }
+    let Some(base_name) = dir.file_name().and_then(|name| name.to_str()) else {
+        eprintln!("fatal: invalid path '{}'", path);
+        return;
+    };
+
+    let commit = match &commit_ish {
+        Some(commit_ish) => match get_target_commit(commit_ish).await {
+            Ok(commit) => commit,
+            Err(e) => {
+                eprintln!("fatal: {}", e);
+                return;
+            }
+        },
+        None => match Head::current_commit().await {
+            Some(commit) => commit,
+            None => {
+                eprintln!("fatal: invalid reference: HEAD");
+                return;
+            }
+        },
+    };
+    // (HEAD of the new worktree, the branch to create)
+    let (head, create) = match (new_branch, &commit_ish) {
+        (Some(new_branch), _) => (Head::Branch(new_branch.clone()), Some(new_branch)),
+        (None, _) if detach => (Head::Detached(commit), None),
+        (None, Some(name)) if Branch::exists(name).await => (Head::Branch(name.clone()), None),
+        (None, Some(_)) => (Head::Detached(commit), None),
+        (None, None) if Branch::exists(base_name).await => {
+            (Head::Branch(base_name.to_string()), None)
+        }
+        (None, None) => (
+            Head::Branch(base_name.to_string()),
+            Some(base_name.to_string()),
+        ),
+    };
+    if let Some(name) = &create {
+        if !branch::is_valid_git_branch_name(name) {
+            eprintln!("fatal: invalid branch name: {}", name);
+            return;
+        }
+        if Branch::exists(name).await {
+            eprintln!("fatal: a branch named '{}' already exists", name);
+            return;
+        }
+    }
+    let commit = match &head {
+        Head::Branch(name) if create.is_none() => {
+            // including the current worktree
+            let used = worktree::list()
+                .await
+                .into_iter()
+                .find(|w| w.uses_branch(name));
+            if let (false, Some(w)) = (force, used) {
+                eprintln!(
+                    "fatal: '{}' is already used by worktree at '{}'",
+                    name,
+                    w.path.display()
+                );
+                return;
+            }
+            Branch::find_branch(name, None).await.unwrap().commit
+        }
+        _ => commit,
+    };
+
+    match (&head, &create) {
+        (Head::Branch(name), Some(_)) => println!("Preparing worktree (new branch '{}')", name),
+        (Head::Branch(name), None) => println!("Preparing worktree (checking out '{}')", name),
+        (Head::Detached(commit), _) => {
+            println!(
+                "Preparing worktree (detached HEAD {})",
+                &commit.to_string()[..7]
+            )
+        }
+    }
+    if let Some(name) = &create {
+        branch::create_branch(name.clone(), commit_ish).await;
+    }
+    if let Err(e) = create_storage(&dir, base_name, &head, lock) {
+        eprintln!("fatal: could not create worktree '{}': {}", path, e);
+        // don't leave the branch created for the worktree behind
+        if let Some(name) = create {
+            Branch::delete_branch(&name, None).await;
+            Reflog::clear(&reflog::branch_ref(&name)).await;
+        }
+        return;
+    }
+
+    in_worktree(&dir, async {
+        restore::execute(RestoreArgs {
+            worktree: true,
+            staged: true,
+            source: Some(commit.to_string()),
+            pathspec: vec![util::working_dir_string()],
+        })
+        .await;
+        hooks::post_checkout(None, commit).await;
+    })
+    .await;
+}
+
+/// Create the working directory `dir` with its `.libra` file, and its storage named after `name` (unique)
+fn create_storage(dir: &Path, name: &str, head: &Head, lock: bool) -> std::io::Result<()> {
+    let mut storage = path::worktrees().join(name);
+    let mut i = 1;
+    while storage.exists() {
+        storage = path::worktrees().join(format!("{}{}", name, i));
+        i += 1;
+    }
+    fs::create_dir_all(&storage)?;
+    fs::create_dir_all(dir)?;
+    let libra_file = dir.join(util::ROOT_DIR);
+    head.write_file(&storage.join("HEAD"))?;
+    if lock {
+        fs::write(storage.join("locked"), "")?;
+    }
+    fs::write(
+        storage.join("libradir"),
+        format!("{}\n", libra_file.display()),
+    )?;
+    fs::write(libra_file, format!("libradir: {}\n", storage.display()))
+}
+
+/// The commit of the HEAD, `None` if there is no commit
+async fn head_commit(head: &Head) -> Option<SHA1> {
+    match head {
+        Head::Detached(commit) => Some(*commit),
+        Head::Branch(name) => Branch::find_branch(name, None).await.map(|b| b.commit),
+    }
+}
+
+async fn list(porcelain: bool) {
+    let worktrees = worktree::list().await;
+    let width = worktrees
+        .iter()
+        .map(|w| w.path.display().to_string().len())
+        .max()
+        .unwrap_or_default();
+    for w in worktrees {
+        let commit = match &w.head {
+            Some(head) => head_commit(head).await.unwrap_or_default(),
+            None => SHA1::default(),
+        };
+        if porcelain {
+            println!("worktree {}", w.path.display());
+            println!("HEAD {}", commit);
+            match &w.head {
+                Some(Head::Branch(name)) => println!("branch refs/heads/{}", name),
+                _ => println!("detached"),
+            }
+            match w.locked() {
+                Some(reason) if reason.is_empty() => println!("locked"),
+                Some(reason) => println!("locked {}", reason),
+                None => {}
+            }
+            if let Some(reason) = w.prunable() {
+                println!("prunable {}", reason);
+            }
+            println!();
+            continue;
+        }
+        let head = match &w.head {
+            Some(Head::Branch(name)) => format!("[{}]", name),
+            _ => "(detached HEAD)".to_string(),
+        };
+        let mut line = format!(
+            "{:<width$} {} {}",
+            w.path.display(),
+            &commit.to_string()[..7],
+            head
+        );
+        if w.locked().is_some() {
+            line.push_str(" locked");
+        }
+        if w.prunable().is_some() {
+            line.push_str(" prunable");
+        }
+        println!("{}", line);
+    }
+}
+
+async fn remove(worktree: &str, force: u8) {
+    let Some(w) = find_linked(worktree).await else {
+        return;
+    };
+    if let Some(reason) = w.locked() {
+        if force < 2 {
+            eprintln!(
+                "fatal: cannot remove a locked working tree, lock reason: {}",
+                reason
+            );
+            eprintln!("use 'remove -f -f' to override or unlock first");
+            return;
+        }
+    }
+    if w.path.join(util::ROOT_DIR).is_file() {
+        if force == 0 {
+            let clean = in_worktree(&w.path, async {
+                status::is_clean().await && status::changes_to_be_staged().await.new.is_empty()
+            })
+            .await;
+            if !clean {
+                eprintln!(
+                    "fatal: '{}' contains modified or untracked files, use --force to delete it",
+                    worktree
+                );
+                return;
+            }
+        }
+        if let Err(e) = fs::remove_dir_all(&w.path) {
+            eprintln!("fatal: failed to delete '{}': {}", w.path.display(), e);
+            return;
+        }
+    }
+    Reflog::clear(&reflog::worktree_head_ref(w.name().unwrap())).await;
+    SparseCheckout::remove_worktree(w.name().unwrap()).await;
+    fs::remove_dir_all(w.storage.unwrap()).unwrap();
+}
+
+async fn prune(dry_run: bool, verbose: bool) {
+    for w in worktree::list().await {
+        let Some(reason) = w.prunable() else {
+            continue;
+        };
+        if w.locked().is_some() {
+            continue;
+        }
+        if verbose || dry_run {
+            println!("Removing worktrees/{}: {}", w.name().unwrap(), reason);
+        }
+        if !dry_run {
+            Reflog::clear(&reflog::worktree_head_ref(w.name().unwrap())).await;
+            SparseCheckout::remove_worktree(w.name().unwrap()).await;
+            fs::remove_dir_all(w.storage.unwrap()).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serial_test::serial;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::internal::config::Config;
+    use crate::utils::test;
+
+    /// The storages of the linked worktrees
+    fn storages() -> Vec<PathBuf> {
+        match fs::read_dir(path::worktrees()) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn add_cmd(path: &Path, commit_ish: Option<&str>) -> WorktreeCmds {
+        WorktreeCmds::Add {
+            path: path.to_str().unwrap().to_string(),
+            commit_ish: commit_ish.map(str::to_string),
+            new_branch: None,
+            detach: false,
+            lock: false,
+            force: false,
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_add_and_remove() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a"));
+        test::commit_all("init").await;
+        let main_storage = util::storage_path();
+        let dir = tempdir().unwrap();
+        let wt = dir.path().join("review");
+
+        // a new branch named after the path
+        execute(add_cmd(&wt, None)).await;
+        assert!(wt.join(util::ROOT_DIR).is_file());
+        assert_eq!(fs::read_to_string(wt.join("a.txt")).unwrap(), "a");
+        assert!(worktree::find_by_branch("review").await.is_some());
+        assert!(!worktree::check_branch_unused("review").await);
+        assert!(matches!(Head::current().await, Head::Branch(name) if name == "master"));
+
+        // the branch can't be checked out twice
+        let other = dir.path().join("other");
+        execute(add_cmd(&other, Some("review"))).await;
+        assert!(!other.exists());
+        execute(add_cmd(&other, Some("master"))).await;
+        assert!(!other.exists());
+
+        // own HEAD & index, shared objects & references
+        in_worktree(&wt, async {
+            assert_eq!(util::storage_path(), main_storage);
+            assert_eq!(util::working_dir(), wt);
+            assert_eq!(path::index(), main_storage.join("worktrees/review/index"));
+            assert!(matches!(Head::current().await, Head::Branch(name) if name == "review"));
+            assert!(worktree::find_by_branch("master").await.is_some());
+            assert!(worktree::find_by_branch("review").await.is_none());
+            test::ensure_file("b.txt", Some("b"));
+            test::commit_all("in the worktree").await;
+            assert_eq!(reflog::head_ref(), "worktrees/review/HEAD");
+            let entry = Reflog::find(&reflog::head_ref(), 0).await.unwrap();
+            assert_eq!(entry.message, "commit: in the worktree");
+            SparseCheckout::new(true, &["src".to_string()]).save().await;
+            assert!(SparseCheckout::load().await.is_some());
+        })
+        .await;
+        // the sparse set is per worktree too
+        assert_eq!(SparseCheckout::load().await, None);
+        // the HEAD of each worktree has its own reflog
+        let entry = Reflog::find(reflog::HEAD, 0).await.unwrap();
+        assert_eq!(entry.message, "commit (initial): init");
+        let review = Branch::find_branch("review", None).await.unwrap();
+        assert_ne!(Some(review.commit), Head::current_commit().await);
+        assert!(!util::working_dir().join("b.txt").exists());
+
+        // untracked files are kept unless forced
+        fs::write(wt.join("c.txt"), "c").unwrap();
+        execute(WorktreeCmds::Remove {
+            worktree: "review".to_string(),
+            force: 0,
+        })
+        .await;
+        assert!(wt.exists());
+        execute(WorktreeCmds::Remove {
+            worktree: wt.to_str().unwrap().to_string(),
+            force: 1,
+        })
+        .await;
+        assert!(!wt.exists());
+        assert!(storages().is_empty());
+        assert!(worktree::check_branch_unused("review").await);
+        assert!(Reflog::list("worktrees/review/HEAD").await.is_empty());
+        assert!(Config::get_all("sparse", Some("review"), "pattern")
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_lock_and_prune() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a"));
+        test::commit_all("init").await;
+        let dir = tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        execute(add_cmd(&a, Some("HEAD"))).await;
+        execute(add_cmd(&b, None)).await;
+        assert!(matches!(
+            worktree::load(&path::worktrees().join("a")).head,
+            Some(Head::Detached(_))
+        ));
+        execute(WorktreeCmds::Lock {
+            worktree: "a".to_string(),
+            reason: Some("on a usb drive".to_string()),
+        })
+        .await;
+        execute(WorktreeCmds::Remove {
+            worktree: "a".to_string(),
+            force: 1,
+        })
+        .await;
+        assert!(a.exists());
+
+        fs::remove_dir_all(&a).unwrap();
+        fs::remove_dir_all(&b).unwrap();
+        execute(WorktreeCmds::Prune {
+            dry_run: false,
+            verbose: false,
+        })
+        .await;
+        assert_eq!(storages(), vec![path::worktrees().join("a")]);
+
+        execute(WorktreeCmds::Unlock {
+            worktree: "a".to_string(),
+        })
+        .await;
+        execute(WorktreeCmds::Prune {
+            dry_run: false,
+            verbose: false,
+        })
+        .await;
+        assert!(storages().is_empty());
+    }
+}
@@ -0,0 +1,763 @@
+//! The commit-graph file at `objects/info/commit-graph`, same format as git:
+//! the parents, root tree, generation number & commit time of each commit in one table,
+//! so the history is walked without decompressing & parsing the commit objects.
+//! - The new commits of `commit` & `fetch` are written to the incremental layers in `objects/info/commit-graphs`
+//!   listed by `commit-graph-chain`, each on the files below it, so the whole file isn't rewritten each time.
+//!   Unlike git, the bottom of the chain is the `commit-graph` file. The small layers on top are merged into
+//!   a new one, and `gc` rewrites all in one file.
+//! - The commits not in the files, e.g. committed by an older version, are loaded from the objects.
+//! - It's not used in a shallow repository, whose grafted commits differ from the objects, like git.
+//!
+//! [gitformat-commit-graph](https://git-scm.com/docs/gitformat-commit-graph)
+
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::types::ObjectType;
+use once_cell::sync::Lazy;
+
+use crate::command::prune;
+use crate::internal::shallow;
+use crate::internal::tag::Tag;
+use crate::utils::chunk::{self, Chunks};
+use crate::utils::object_ext::CommitExt;
+use crate::utils::{path, util};
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const CHUNK_OID_FANOUT: u32 = 0x4f494446; // "OIDF"
+const CHUNK_OID_LOOKUP: u32 = 0x4f49444c; // "OIDL"
+const CHUNK_COMMIT_DATA: u32 = 0x43444154; // "CDAT"
+const CHUNK_EXTRA_EDGES: u32 = 0x45444745; // "EDGE"
+const CHUNK_BASE_GRAPHS: u32 = 0x42415345; // "BASE"
+const CHAIN: &str = "commit-graph-chain";
+const HEADER_SIZE: usize = 8;
+const COMMIT_DATA_WIDTH: usize = 36;
+const PARENT_NONE: u32 = 0x7000_0000;
+const EDGE_FLAG: u32 = 0x8000_0000;
+/// The generation is stored in 30 bits, larger ones are capped
+const GENERATION_MAX: u32 = 0x3fff_ffff;
+/// The commit time is stored in 34 bits
+const TIME_MASK: u64 = 0x3_ffff_ffff;
+
+/// A commit-graph, with the modified times of the file & the chain when it was read
+type CachedGraph = ((Option<SystemTime>, Option<SystemTime>), Arc<Stored>);
+
+/// The parsed commit-graph of each repository
+static GRAPH_CACHE: Lazy<Mutex<HashMap<PathBuf, CachedGraph>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphCommit {
+    pub tree: SHA1,
+    pub parents: Vec<SHA1>,
+    /// The topological level, 1 for a root commit, otherwise 1 + the max generation of its parents.
+    /// A commit can't reach another commit of a larger or equal generation.
+    pub generation: u32,
+    /// The committer time
+    pub timestamp: u64,
+}
+
+/// A file of the commit-graph: the `commit-graph` file, or a layer of the chain
+#[derive(Debug, Clone, PartialEq)]
+struct GraphFile {
+    checksum: SHA1,
+    /// The number of commits in the file
+    len: usize,
+    layer: bool,
+}
+
+/// The commits of the commit-graph files
+#[derive(Debug, Default)]
+struct Stored {
+    commits: HashMap<SHA1, GraphCommit>,
+    /// The commits by position: the sorted commits of each file, in the order of `files`
+    ids: Vec<SHA1>,
+    files: Vec<GraphFile>,
+}
+
+/// The files below a new file, its parents out of itself are in them
+#[derive(Clone, Copy, Default)]
+struct Base<'a> {
+    ids: &'a [SHA1],
+    files: &'a [GraphFile],
+}
+
+/// The commits of the commit-graph files, and those loaded from the objects on demand
+pub struct CommitGraph {
+    stored: Arc<Stored>,
+    loaded: HashMap<SHA1, GraphCommit>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("commit-graph: {}", msg))
+}
+
+impl CommitGraph {
+    /// Open the commit-graph of the repository, an empty one if it doesn't exist or can't be used
+    pub fn open() -> Self {
+        let stored = match Self::read_file() {
+            Some(stored) if !shallow::is_shallow_repo() => stored,
+            _ => Arc::default(),
+        };
+        CommitGraph {
+            stored,
+            loaded: HashMap::new(),
+        }
+    }
+
+    fn read_file() -> Option<Arc<Stored>> {
+        let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+        let (path, chain) = (path::commit_graph(), path::commit_graphs().join(CHAIN));
+        let stamp = (modified(&path), modified(&chain));
+        if stamp == (None, None) {
+            return None;
+        }
+        let mut cache = GRAPH_CACHE.lock().unwrap();
+        match cache.get(&path) {
+            Some((time, stored)) if *time == stamp => Some(stored.clone()),
+            _ => {
+                let stored = Arc::new(Stored::read_files(&path, &chain));
+                cache.insert(path, (stamp, stored.clone()));
+                Some(stored)
+            }
+        }
+    }
+
+    /// The number of commits in the commit-graph files
+    pub fn len(&self) -> usize {
+        self.stored.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stored.commits.is_empty()
+    }
+
+    pub fn contains(&self, commit_id: &SHA1) -> bool {
+        self.stored.commits.contains_key(commit_id)
+    }
+
+    /// Get the commit from the file, or load it & its ancestors not in the file from the objects
+    pub fn get(&mut self, commit_id: &SHA1) -> &GraphCommit {
+        if !self.contains(commit_id) && !self.loaded.contains_key(commit_id) {
+            self.load(*commit_id);
+        }
+        self.known(commit_id).unwrap()
+    }
+
+    fn known(&self, commit_id: &SHA1) -> Option<&GraphCommit> {
+        self.stored
+            .commits
+            .get(commit_id)
+            .or_else(|| self.loaded.get(commit_id))
+    }
+
+    /// Load the commit, the generation is known after all its parents are loaded
+    fn load(&mut self, commit_id: SHA1) {
+        let mut pending: HashMap<SHA1, Commit> = HashMap::new();
+        let mut stack = vec![commit_id];
+        while let Some(id) = stack.last().copied() {
+            if self.known(&id).is_some() {
+                stack.pop();
+                continue;
+            }
+            // the parents of shallow commits are cut off
+            let commit = pending.entry(id).or_insert_with(|| Commit::load(&id));
+            let missing: Vec<SHA1> = commit
+                .parent_commit_ids
+                .iter()
+                .filter(|p| self.known(p).is_none())
+                .copied()
+                .collect();
+            if !missing.is_empty() {
+                stack.extend(missing);
+                continue;
+            }
+            let commit = pending.remove(&id).unwrap();
+            let generation = commit
+                .parent_commit_ids
+                .iter()
+                .map(|p| self.known(p).unwrap().generation)
+                .max()
+                .map_or(1, |max| (max + 1).min(GENERATION_MAX));
+            self.loaded.insert(
+                id,
+                GraphCommit {
+                    tree: commit.tree_id,
+                    parents: commit.parent_commit_ids,
+                    generation,
+                    timestamp: commit.committer.timestamp as u64,
+                },
+            );
+            stack.pop();
+        }
+    }
+
+    /// The commits reachable from `tips`, including themselves, in breadth-first order
+    pub fn ancestors(&mut self, tips: &[SHA1]) -> Vec<SHA1> {
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<SHA1> = tips.iter().copied().collect();
+        let mut ancestors = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            if !visited.insert(id) {
+                continue;
+            }
+            queue.extend(self.get(&id).parents.clone());
+            ancestors.push(id);
+        }
+        ancestors
+    }
+
+    /// The best common ancestor of `lhs` & `rhs`, `None` for unrelated histories.
+    /// The commits are walked from the largest generation, so the first commit reached from both
+    /// is not an ancestor of any other common ancestor, and the older history is not walked.
+    pub fn merge_base(&mut self, lhs: &SHA1, rhs: &SHA1) -> Option<SHA1> {
+        const LEFT: u8 = 1;
+        const RIGHT: u8 = 2;
+        let mut flags: HashMap<SHA1, u8> = HashMap::from([(*lhs, LEFT)]);
+        *flags.entry(*rhs).or_default() |= RIGHT;
+        let mut queue = BinaryHeap::new();
+        for id in [lhs, rhs] {
+            let commit = self.get(id);
+            queue.push((commit.generation, commit.timestamp, *id));
+        }
+        while let Some((_, _, id)) = queue.pop() {
+            let flag = flags[&id];
+            if flag == LEFT | RIGHT {
+                return Some(id);
+            }
+            for parent in self.get(&id).parents.clone() {
+                let parent_flag = flags.entry(parent).or_default();
+                if *parent_flag | flag != *parent_flag {
+                    *parent_flag |= flag;
+                    let commit = self.get(&parent);
+                    queue.push((commit.generation, commit.timestamp, parent));
+                }
+            }
+        }
+        None
+    }
+
+    /// If `ancestor` is reachable from `commit`, the commits of smaller generations are not walked
+    pub fn is_ancestor(&mut self, ancestor: &SHA1, commit: &SHA1) -> bool {
+        let generation = self.get(ancestor).generation;
+        let mut visited = HashSet::new();
+        let mut stack = vec![*commit];
+        while let Some(id) = stack.pop() {
+            if id == *ancestor {
+                return true;
+            }
+            if !visited.insert(id) || self.get(&id).generation <= generation {
+                continue;
+            }
+            stack.extend(self.get(&id).parents.clone());
+        }
+        false
+    }
+
+    /// Sort the commits so that the parents come before their children,
+    /// otherwise the older first; the commits of a branch are kept together rather than intermixed.
+    pub fn topo_order(&mut self, commits: &[SHA1]) -> Vec<SHA1> {
+        let mut commits = commits.to_vec();
+        commits.sort_by_key(|id| self.get(id).timestamp);
+        let included: HashSet<SHA1> = commits.iter().copied().collect();
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        for commit in &commits {
+            // DFS without recursion, a commit is pushed after all its parents
+            let mut stack = vec![(*commit, false)];
+            while let Some((id, parents_done)) = stack.pop() {
+                if parents_done {
+                    order.push(id);
+                    continue;
+                }
+                if !visited.insert(id) {
+                    continue;
+                }
+                stack.push((id, true));
+                for parent in self.get(&id).parents.iter().rev() {
+                    if included.contains(parent) && !visited.contains(parent) {
+                        stack.push((*parent, false));
+                    }
+                }
+            }
+        }
+        order
+    }
+}
+
+/// `commit-graphs/graph-<checksum>.graph`, a layer of the chain
+fn layer_path(checksum: impl Display) -> PathBuf {
+    path::commit_graphs().join(format!("graph-{}.graph", checksum))
+}
+
+impl Base<'_> {
+    /// The position of a commit in the files, the commits of each file are sorted
+    fn position(&self, id: &SHA1) -> Option<u32> {
+        let mut start = 0;
+        for file in self.files {
+            if let Ok(i) = self.ids[start..start + file.len].binary_search(id) {
+                return Some((start + i) as u32);
+            }
+            start += file.len;
+        }
+        None
+    }
+
+    /// Encode `commits` as a file on the base files, the parents are in `commits` or the base files
+    fn encode(&self, commits: &HashMap<SHA1, GraphCommit>) -> Result<Vec<u8>, io::Error> {
+        let commits: BTreeMap<&SHA1, &GraphCommit> = commits.iter().collect();
+        let positions: HashMap<&SHA1, u32> = commits
+            .keys()
+            .enumerate()
+            .map(|(i, id)| (*id, (self.ids.len() + i) as u32))
+            .collect();
+        let position = |id: &SHA1| {
+            positions
+                .get(id)
+                .copied()
+                .or_else(|| self.position(id))
+                .ok_or_else(|| invalid(&format!("parent {} is not in the graph", id)))
+        };
+
+        let mut counts = [0u32; 256];
+        for id in commits.keys() {
+            counts[id.0[0] as usize] += 1;
+        }
+        let mut fanout = Vec::with_capacity(256 * 4);
+        let mut total = 0;
+        for count in counts {
+            total += count;
+            fanout.write_u32::<BigEndian>(total)?;
+        }
+        let mut lookup = Vec::with_capacity(commits.len() * 20);
+        let mut data = Vec::with_capacity(commits.len() * COMMIT_DATA_WIDTH);
+        // the parents after the first one of an octopus merge, the last one is marked
+        let mut edges = Vec::new();
+        for (id, commit) in &commits {
+            lookup.write_all(&id.0)?;
+            data.write_all(&commit.tree.0)?;
+            let parents = &commit.parents;
+            match parents.first() {
+                Some(parent) => data.write_u32::<BigEndian>(position(parent)?)?,
+                None => data.write_u32::<BigEndian>(PARENT_NONE)?,
+            }
+            match parents.len() {
+                0 | 1 => data.write_u32::<BigEndian>(PARENT_NONE)?,
+                2 => data.write_u32::<BigEndian>(position(&parents[1])?)?,
+                _ => {
+                    data.write_u32::<BigEndian>(EDGE_FLAG | (edges.len() / 4) as u32)?;
+                    for (i, parent) in parents[1..].iter().enumerate() {
+                        let last = if i == parents.len() - 2 { EDGE_FLAG } else { 0 };
+                        edges.write_u32::<BigEndian>(position(parent)? | last)?;
+                    }
+                }
+            }
+            let generation = commit.generation.min(GENERATION_MAX) as u64;
+            data.write_u64::<BigEndian>((generation << 34) | (commit.timestamp & TIME_MASK))?;
+        }
+        let mut chunks = vec![
+            (CHUNK_OID_FANOUT, fanout),
+            (CHUNK_OID_LOOKUP, lookup),
+            (CHUNK_COMMIT_DATA, data),
+        ];
+        if !edges.is_empty() {
+            chunks.push((CHUNK_EXTRA_EDGES, edges));
+        }
+        if !self.files.is_empty() {
+            let bases = self.files.iter().flat_map(|f| f.checksum.0).collect();
+            chunks.push((CHUNK_BASE_GRAPHS, bases));
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.write_all(SIGNATURE)?;
+        header.write_u8(1)?; // version
+        header.write_u8(1)?; // hash version, SHA-1
+        header.write_u8(chunks.len() as u8)?;
+        header.write_u8(self.files.len() as u8)?; // number of base commit-graph files
+        chunk::write(&header, chunks)
+    }
+}
+
+impl Stored {
+    /// Read the `commit-graph` file & the layers of the chain on it,
+    /// the files from an invalid one are ignored as the rest are on it
+    fn read_files(path: &Path, chain: &Path) -> Self {
+        let mut stored = Stored::default();
+        if path.exists() {
+            if let Err(e) = fs::read(path).and_then(|data| stored.read(&data, false)) {
+                tracing::warn!("ignore {}: {}", path.display(), e);
+            }
+        }
+        for checksum in fs::read_to_string(chain).unwrap_or_default().lines() {
+            let path = layer_path(checksum.trim());
+            if let Err(e) = fs::read(&path).and_then(|data| stored.read(&data, true)) {
+                tracing::warn!("ignore {}: {}", path.display(), e);
+                break;
+            }
+        }
+        stored
+    }
+
+    /// Decode a file on the files read, and add its commits
+    fn read(&mut self, data: &[u8], layer: bool) -> Result<(), io::Error> {
+        if data.len() < HEADER_SIZE + 20 || &data[..4] != SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(invalid("unsupported version"));
+        }
+        let chunks = Chunks::read(data, HEADER_SIZE, data[6] as usize).map_err(invalid)?;
+        let chunk = |id: u32, len: usize| chunks.require(id, len).map_err(invalid);
+        // a layer is only valid on the files it was written on
+        let bases: Vec<u8> = self.files.iter().flat_map(|f| f.checksum.0).collect();
+        if data[7] as usize != self.files.len() || chunks.get(CHUNK_BASE_GRAPHS) != bases {
+            return Err(invalid("the base files are changed"));
+        }
+
+        let fanout = chunk(CHUNK_OID_FANOUT, 256 * 4)?;
+        let num_commits = BigEndian::read_u32(&fanout[255 * 4..]) as usize;
+        let ids: Vec<SHA1> = chunk(CHUNK_OID_LOOKUP, num_commits * 20)?
+            .chunks(20)
+            .map(SHA1::from_bytes)
+            .collect();
+        let edges = chunks.get(CHUNK_EXTRA_EDGES);
+        let commit_id = |position: u32| {
+            let position = position as usize;
+            match position.checked_sub(self.ids.len()) {
+                None => Some(self.ids[position]),
+                Some(i) => ids.get(i).copied(),
+            }
+            .ok_or_else(|| invalid("bad parent position"))
+        };
+
+        let mut commits = HashMap::with_capacity(num_commits);
+        let data = chunk(CHUNK_COMMIT_DATA, num_commits * COMMIT_DATA_WIDTH)?;
+        for (id, entry) in ids.iter().zip(data.chunks(COMMIT_DATA_WIDTH)) {
+            let mut parents = Vec::new();
+            let (parent1, parent2) = (
+                BigEndian::read_u32(&entry[20..24]),
+                BigEndian::read_u32(&entry[24..28]),
+            );
+            if parent1 != PARENT_NONE {
+                parents.push(commit_id(parent1)?);
+            }
+            if parent2 & EDGE_FLAG != 0 {
+                // read the extra edges until the one marked as the last
+                let mut i = (parent2 & !EDGE_FLAG) as usize * 4;
+                loop {
+                    let edge = edges
+                        .get(i..i + 4)
+                        .map(BigEndian::read_u32)
+                        .ok_or_else(|| invalid("bad extra edge"))?;
+                    parents.push(commit_id(edge & !EDGE_FLAG)?);
+                    if edge & EDGE_FLAG != 0 {
+                        break;
+                    }
+                    i += 4;
+                }
+            } else if parent2 != PARENT_NONE {
+                parents.push(commit_id(parent2)?);
+            }
+            let generation_time = BigEndian::read_u64(&entry[28..36]);
+            commits.insert(
+                *id,
+                GraphCommit {
+                    tree: SHA1::from_bytes(&entry[..20]),
+                    parents,
+                    generation: (generation_time >> 34) as u32,
+                    timestamp: generation_time & TIME_MASK,
+                },
+            );
+        }
+        self.files.push(GraphFile {
+            checksum: chunks.checksum,
+            len: num_commits,
+            layer,
+        });
+        self.ids.extend(ids);
+        self.commits.extend(commits);
+        Ok(())
+    }
+}
+
+/// Write the commits reachable from `tips` & those in the commit-graph to one file, the layers are merged
+/// into it; with `rewrite`, the file only keeps the commits reachable from `tips`, e.g. to drop the commits
+/// pruned by `gc`. Return the number of commits in the file, nothing is written in a shallow repository.
+pub fn write(tips: &[SHA1], rewrite: bool) -> Result<usize, io::Error> {
+    if shallow::is_shallow_repo() {
+        return Ok(0);
+    }
+    let mut graph = CommitGraph::open();
+    for tip in tips {
+        graph.get(tip);
+    }
+    let layered = graph.stored.files.iter().any(|f| f.layer);
+    let commits: HashMap<SHA1, GraphCommit> = if rewrite {
+        graph
+            .ancestors(tips)
+            .into_iter()
+            .map(|id| (id, graph.get(&id).clone()))
+            .collect()
+    } else if graph.loaded.is_empty() && !layered && path::commit_graph().exists() {
+        return Ok(graph.len()); // up to date
+    } else {
+        let mut commits = graph.stored.commits.clone();
+        commits.extend(graph.loaded);
+        commits
+    };
+
+    util::write_file_atomic(&Base::default().encode(&commits)?, &path::commit_graph())?;
+    remove_layers(&graph.stored.files);
+    Ok(commits.len())
+}
+
+/// Write the commits reachable from `tips` & not in the commit-graph to a new layer of the chain,
+/// e.g. the new commit of `commit`. The layers on top, not more than twice as large as the new one,
+/// are merged into it like git, so that the chain is kept short; all are merged into the `commit-graph`
+/// file if it's not larger either. Return the number of commits in the commit-graph.
+pub fn write_layer(tips: &[SHA1]) -> Result<usize, io::Error> {
+    if shallow::is_shallow_repo() {
+        return Ok(0);
+    }
+    let mut graph = CommitGraph::open();
+    for tip in tips {
+        graph.get(tip);
+    }
+    if graph.loaded.is_empty() {
+        return Ok(graph.len());
+    }
+    let CommitGraph {
+        stored,
+        loaded: mut commits,
+    } = graph;
+    let mut files = stored.files.as_slice();
+    let mut base_len = stored.ids.len();
+    while let Some((top, rest)) = files.split_last() {
+        if top.len > commits.len() * 2 {
+            break;
+        }
+        base_len -= top.len;
+        for id in &stored.ids[base_len..base_len + top.len] {
+            commits.insert(*id, stored.commits[id].clone());
+        }
+        files = rest;
+    }
+    let count = base_len + commits.len();
+    let merged = &stored.files[files.len()..];
+    if files.is_empty() {
+        util::write_file_atomic(&Base::default().encode(&commits)?, &path::commit_graph())?;
+        remove_layers(merged);
+        return Ok(count);
+    }
+
+    let base = Base {
+        ids: &stored.ids[..base_len],
+        files,
+    };
+    let data = base.encode(&commits)?;
+    let checksum = SHA1::from_bytes(&data[data.len() - 20..]);
+    util::write_file_atomic(&data, &layer_path(checksum))?;
+    let chain: String = files
+        .iter()
+        .filter(|f| f.layer)
+        .map(|f| f.checksum)
+        .chain([checksum])
+        .map(|checksum| format!("{}\n", checksum))
+        .collect();
+    util::write_file_atomic(chain.as_bytes(), &path::commit_graphs().join(CHAIN))?;
+    for file in merged.iter().filter(|f| f.layer) {
+        let _ = fs::remove_file(layer_path(file.checksum));
+    }
+    Ok(count)
+}
+
+/// Remove the chain & its layers in `files`, after they are merged into the `commit-graph` file
+fn remove_layers(files: &[GraphFile]) {
+    let _ = fs::remove_file(path::commit_graphs().join(CHAIN));
+    for file in files.iter().filter(|f| f.layer) {
+        let _ = fs::remove_file(layer_path(file.checksum));
+    }
+}
+
+/// [write_layer] of the new commits, a failure is only a warning as the commit-graph is an optional cache
+pub fn update(tips: &[SHA1]) {
+    if let Err(e) = write_layer(tips) {
+        eprintln!("warning: failed to write commit-graph: {}", e);
+    }
+}
+
+/// Rewrite the commit-graph file with the commits reachable from HEAD, branches & tags
+pub async fn write_for_refs() -> Result<usize, io::Error> {
+    let storage = util::objects_storage();
+    let mut tips = Vec::new();
+    for tip in prune::ref_tips().await {
+        let commit = Tag::peel(&tip);
+        if !tips.contains(&commit) && storage.is_object_type(&commit, ObjectType::Commit) {
+            tips.push(commit);
+        }
+    }
+    write(&tips, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use mercury::internal::object::ObjectTrait;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::internal::head::Head;
+    use crate::utils::test;
+
+    /// A commit with `parents` at `timestamp`, saved to the objects
+    fn save_commit(name: &str, parents: Vec<SHA1>, timestamp: usize) -> SHA1 {
+        let mut commit = Commit::from_tree_id(SHA1::new(name.as_bytes()), parents, name);
+        commit.committer.timestamp = timestamp;
+        let commit = Commit::new(
+            commit.author,
+            commit.committer,
+            commit.tree_id,
+            commit.parent_commit_ids,
+            &commit.message,
+        );
+        let data = commit.to_data().unwrap();
+        util::objects_storage()
+            .put(&commit.id, &data, commit.get_type())
+            .unwrap();
+        commit.id
+    }
+
+    #[test]
+    fn test_commit_graph_round_trip() {
+        let ids: Vec<SHA1> = (0..5u8).map(|i| SHA1::new(&[i])).collect();
+        let commit = |parents: &[usize], generation| GraphCommit {
+            tree: SHA1::new(b"tree"),
+            parents: parents.iter().map(|&i| ids[i]).collect(),
+            generation,
+            timestamp: 0x2_0000_0000 + generation as u64,
+        };
+        let commits = HashMap::from([
+            (ids[0], commit(&[], 1)),
+            (ids[1], commit(&[0], 2)),
+            (ids[2], commit(&[0], 2)),
+            (ids[3], commit(&[1, 2], 3)),
+            (ids[4], commit(&[3, 1, 2], 4)), // octopus
+        ]);
+        let data = Base::default().encode(&commits).unwrap();
+        let mut stored = Stored::default();
+        stored.read(&data, false).unwrap();
+        assert_eq!(stored.commits, commits);
+
+        let mut corrupt = data.clone();
+        corrupt[HEADER_SIZE + 1] ^= 1;
+        assert!(Stored::default().read(&corrupt, false).is_err());
+    }
+
+    #[test]
+    fn test_topo_order() {
+        let (root, child, grandchild) = (SHA1::new(b"1"), SHA1::new(b"2"), SHA1::new(b"3"));
+        let commit = |parents: Vec<SHA1>, generation, timestamp| GraphCommit {
+            tree: SHA1::default(),
+            parents,
+            generation,
+            timestamp,
+        };
+        let mut graph = CommitGraph {
+            // parents must come first even if they are newer
+            stored: Arc::new(Stored {
+                commits: HashMap::from([
+                    (root, commit(vec![], 1, 3)),
+                    (child, commit(vec![root], 2, 2)),
+                    (grandchild, commit(vec![child], 3, 1)),
+                ]),
+                ..Default::default()
+            }),
+            loaded: HashMap::new(),
+        };
+        let order = graph.topo_order(&[grandchild, root, child]);
+        assert_eq!(order, vec![root, child, grandchild]);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_write_and_query() {
+        test::setup_with_new_libra().await;
+        // base - a1 - a2 - m
+        //     \- b1 -----/
+        let base = save_commit("base", vec![], 1);
+        let a1 = save_commit("a1", vec![base], 2);
+        let b1 = save_commit("b1", vec![base], 3);
+        let a2 = save_commit("a2", vec![a1], 4);
+        assert_eq!(write(&[a2], false).unwrap(), 3);
+        let m = save_commit("m", vec![a2, b1], 5);
+
+        // `m` & `b1` are loaded from the objects
+        let mut graph = CommitGraph::open();
+        assert_eq!(graph.len(), 3);
+        assert!(!graph.contains(&m));
+        assert_eq!(graph.get(&m).generation, 4);
+        assert_eq!(graph.merge_base(&a2, &b1), Some(base));
+        assert_eq!(graph.merge_base(&m, &b1), Some(b1));
+        assert_eq!(graph.merge_base(&a1, &m), Some(a1));
+        assert!(graph.is_ancestor(&b1, &m));
+        assert!(!graph.is_ancestor(&b1, &a2));
+        assert_eq!(graph.ancestors(&[m]).len(), 5);
+        assert_eq!(graph.topo_order(&[m, a2, b1]), vec![b1, a2, m]);
+
+        assert_eq!(write(&[m], false).unwrap(), 5);
+        assert!(CommitGraph::open().contains(&m));
+        // only the commits reachable from the tips are kept
+        assert_eq!(write(&[a2], true).unwrap(), 3);
+        let unrelated = save_commit("unrelated", vec![], 6);
+        assert_eq!(CommitGraph::open().merge_base(&a2, &unrelated), None);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_write_layer() {
+        test::setup_with_new_libra().await;
+        let layers = || {
+            CommitGraph::open()
+                .stored
+                .files
+                .iter()
+                .filter(|f| f.layer)
+                .count()
+        };
+        let mut commits = vec![save_commit("0", vec![], 1)];
+        for i in 1..4 {
+            commits.push(save_commit(&i.to_string(), vec![commits[i - 1]], i + 1));
+        }
+        // the small files are merged into the `commit-graph` file
+        for (i, commit) in commits[..3].iter().enumerate() {
+            assert_eq!(write_layer(&[*commit]).unwrap(), i + 1);
+        }
+        assert_eq!(layers(), 0);
+        // a layer on it
+        assert_eq!(write_layer(&[commits[3]]).unwrap(), 4);
+        assert_eq!(layers(), 1);
+        let mut graph = CommitGraph::open();
+        assert!(graph.contains(&commits[3]));
+        assert_eq!(graph.get(&commits[3]).parents, vec![commits[2]]);
+        assert_eq!(graph.get(&commits[3]).generation, 4);
+
+        // the commits of `commit` are added
+        test::ensure_file("a.txt", Some("a"));
+        test::commit_all("add a").await;
+        let head = Head::current_commit().await.unwrap();
+        assert!(CommitGraph::open().contains(&head));
+
+        // all in one file again
+        let tip = save_commit("tip", vec![commits[3]], 5);
+        assert_eq!(write(&[tip, head], false).unwrap(), 6);
+        assert_eq!(layers(), 0);
+        assert_eq!(fs::read_dir(path::commit_graphs()).unwrap().count(), 0);
+    }
+}
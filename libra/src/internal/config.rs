@@ -11,6 +11,10 @@ use crate::internal::model::config::Model;
 
 use super::model::config::ActiveModel;
 
+/// The identity used if `user.name` or `user.email` is not set
+const DEFAULT_USER_NAME: &str = "mega";
+const DEFAULT_USER_EMAIL: &str = "admin@mega.org";
+
 pub struct Config;
 
 pub struct RemoteConfig {
@@ -71,6 +75,16 @@ impl Config {
             .unwrap()
     }
 
+    /// The name & email of the user, `user.name` & `user.email`, for commits, tags & reflog entries
+    pub async fn user_identity() -> (String, String) {
+        let name = Self::get("user", None, "name").await;
+        let email = Self::get("user", None, "email").await;
+        (
+            name.unwrap_or_else(|| DEFAULT_USER_NAME.to_string()),
+            email.unwrap_or_else(|| DEFAULT_USER_EMAIL.to_string()),
+        )
+    }
+
     /// Get one configuration value
     pub async fn get(configuration: &str, name: Option<&str>, key: &str) -> Option<String> {
         let values = Self::query(configuration, name, key).await;
@@ -194,6 +208,7 @@ impl Config {
         let db = get_db_conn_instance().await;
         let remotes = config::Entity::find()
             .filter(config::Column::Configuration.eq("remote"))
+            .filter(config::Column::Key.eq("url"))
             .all(db)
             .await
             .unwrap();
@@ -224,6 +239,7 @@ impl Config {
         let remote = config::Entity::find()
             .filter(config::Column::Configuration.eq("remote"))
             .filter(config::Column::Name.eq(name))
+            .filter(config::Column::Key.eq("url"))
             .one(db)
             .await
             .unwrap();
@@ -7,8 +7,8 @@ use sea_orm::{
 use std::io;
 use std::io::Error as IOError;
 use std::io::ErrorKind;
-use std::path::Path;
-use tokio::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
 
 /// Establish a connection to the database.
 ///  - `db_path` is the path to the SQLite database file.
@@ -32,19 +32,33 @@ pub async fn establish_connection(db_path: &str) -> Result<DatabaseConnection, I
     })
 }
 
-static DB_CONN: OnceCell<DbConn> = OnceCell::const_new();
-/// Get global database connection instance (singleton)
+/// The connection of the database, with its path. Another repository in the process,
+/// e.g. a clone or a test, is connected again.
+static DB_CONN: Mutex<Option<(PathBuf, &'static DbConn)>> = Mutex::const_new(None);
+
+/// Get global database connection instance (singleton) of current repo
 pub async fn get_db_conn_instance() -> &'static DbConn {
-    DB_CONN
-        .get_or_init(|| async { get_db_conn().await.unwrap() })
-        .await
+    let db_path = path::database();
+    let mut instance = DB_CONN.lock().await;
+    match *instance {
+        Some((ref path, conn)) if *path == db_path => conn,
+        _ => {
+            // kept until the process exits, as it's usually the only repository
+            let conn: &'static DbConn = Box::leak(Box::new(get_db_conn(&db_path).await.unwrap()));
+            *instance = Some((db_path, conn));
+            conn
+        }
+    }
 }
 
-/// Create a connection to the database of current repo: `.libra/libra.db`
-async fn get_db_conn() -> io::Result<DatabaseConnection> {
-    let db_path = path::database(); // for longer lifetime
-    let db_path = db_path.to_str().unwrap();
-    establish_connection(db_path).await
+/// Create a connection to the database of current repo: `.libra/libra.db`,
+/// the tables added later are created if the database is older.
+async fn get_db_conn(db_path: &Path) -> io::Result<DatabaseConnection> {
+    let conn = establish_connection(db_path.to_str().unwrap()).await?;
+    migrate_database(&conn)
+        .await
+        .map_err(|err| IOError::other(format!("Failed to migrate database: {:?}", err)))?;
+    Ok(conn)
 }
 
 /// create table according to the Model
@@ -81,12 +95,25 @@ async fn setup_database_sql(conn: &DatabaseConnection) -> Result<(), Transaction
             const SETUP_SQL: &str = include_str!("../../sql/sqlite_20240331_init.sql");
             txn.execute(Statement::from_string(backend, SETUP_SQL))
                 .await?;
+            txn.execute(Statement::from_string(backend, MIGRATE_SQL))
+                .await?;
             Ok(())
         })
     })
     .await
 }
 
+/// The tables added after the first release, `IF NOT EXISTS` so it can be run on every connection
+const MIGRATE_SQL: &str = include_str!("../../sql/sqlite_20240720_reflog.sql");
+
+/// Create the tables missing from a database created by an older version
+async fn migrate_database(conn: &DatabaseConnection) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    conn.execute(Statement::from_string(backend, MIGRATE_SQL))
+        .await?;
+    Ok(())
+}
+
 /// Create a new SQLite database file at the specified path.
 /// **should only be called in init or test**
 /// - `db_path` is the path to the SQLite database file.
@@ -101,6 +128,8 @@ pub async fn create_database(db_path: &str) -> io::Result<DatabaseConnection> {
         ));
     }
 
+    // a database removed & created again at the same path, the connection to the old file is stale
+    DB_CONN.lock().await.take();
     std::fs::File::create(db_path).map_err(|err| {
         IOError::new(
             ErrorKind::Other,
@@ -176,6 +205,23 @@ mod tests {
         // fs::remove_file(db_path).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_migrate_database() {
+        // a database created before the reflog table was added
+        let test_db = TestDbPath::new("test_migrate_database.db").await;
+        let conn = establish_connection(&test_db.0).await.unwrap();
+        let backend = conn.get_database_backend();
+        conn.execute(Statement::from_string(backend, "DROP TABLE `reflog`"))
+            .await
+            .unwrap();
+        assert!(reflog::Entity::find().all(&conn).await.is_err());
+
+        migrate_database(&conn).await.unwrap();
+        assert!(reflog::Entity::find().all(&conn).await.unwrap().is_empty());
+        // run on every connection
+        migrate_database(&conn).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_insert_config() {
         // insert into config_entry & config_section, check foreign key constraint
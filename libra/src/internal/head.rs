@@ -1,4 +1,6 @@
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::{fs, io};
 
 use sea_orm::ActiveValue::Set;
 use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
@@ -8,6 +10,7 @@ use mercury::hash::SHA1;
 use crate::internal::branch::Branch;
 use crate::internal::db::get_db_conn_instance;
 use crate::internal::model::reference;
+use crate::utils::util;
 
 #[derive(Debug, Clone)]
 pub enum Head {
@@ -37,7 +40,32 @@ impl Head {
             .unwrap()
     }
 
+    /// The HEAD of the current worktree, in the file of a linked worktree or in the database.
+    /// - exits if the HEAD file of a linked worktree is broken, which is checked by
+    ///   [util::check_repo_exist] before running a command
     pub async fn current() -> Head {
+        if util::is_linked_worktree() {
+            return Self::read_worktree_head().unwrap_or_else(|e| {
+                eprintln!("fatal: {}", e);
+                std::process::exit(128);
+            });
+        }
+        Self::main_worktree().await
+    }
+
+    /// Read the HEAD file of the current linked worktree, an error if it's missing or invalid
+    pub fn read_worktree_head() -> io::Result<Head> {
+        let file = Self::worktree_head_file();
+        Self::read_file(&file).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid HEAD of the worktree: {}", file.display()),
+            )
+        })
+    }
+
+    /// The HEAD of the main worktree, which is in the database
+    pub async fn main_worktree() -> Head {
         let head = Self::query_local_head().await;
         match head.name {
             Some(name) => Head::Branch(name),
@@ -75,8 +103,38 @@ impl Head {
         }
     }
 
+    /// `HEAD` in the storage of the current worktree, only used by a linked worktree
+    fn worktree_head_file() -> PathBuf {
+        util::worktree_storage_path().join("HEAD")
+    }
+
+    /// Read the HEAD file of a linked worktree, in the format of git: `ref: refs/heads/<branch>` or the commit
+    pub fn read_file(file: &Path) -> Option<Head> {
+        let content = fs::read_to_string(file).ok()?;
+        let content = content.trim_end();
+        match content.strip_prefix("ref: refs/heads/") {
+            Some(branch) => Some(Head::Branch(branch.to_string())),
+            None => SHA1::from_str(content).ok().map(Head::Detached),
+        }
+    }
+
+    /// Write the HEAD file of a linked worktree
+    pub fn write_file(&self, file: &Path) -> io::Result<()> {
+        let content = match self {
+            Head::Branch(branch) => format!("ref: refs/heads/{}\n", branch),
+            Head::Detached(commit) => format!("{}\n", commit),
+        };
+        fs::write(file, content)
+    }
+
     // HEAD is unique, update if exists, insert if not
     pub async fn update(new_head: Self, remote: Option<&str>) {
+        if remote.is_none() && util::is_linked_worktree() {
+            new_head
+                .write_file(&Self::worktree_head_file())
+                .expect("fatal: failed to write HEAD");
+            return;
+        }
         let db_conn = get_db_conn_instance().await;
 
         let head = match remote {
@@ -1,27 +1,30 @@
-use super::ProtocolClient;
-use crate::command::ask_basic_auth;
+use super::{parse_references, ProtocolClient, UploadPackOptions};
+use crate::internal::credential::{self, Credential};
 use bytes::Bytes;
 use ceres::protocol::smart::{add_pkt_line_string, read_pkt_line};
 use ceres::protocol::ServiceType;
-use ceres::protocol::ServiceType::UploadPack;
 use futures_util::{StreamExt, TryStreamExt};
 use mercury::errors::GitError;
-use mercury::hash::SHA1;
-use reqwest::header::CONTENT_TYPE;
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::{Body, RequestBuilder, Response, StatusCode};
 use std::io::Error as IoError;
-use std::ops::Deref;
 use std::sync::Mutex;
 use tokio_util::bytes::BytesMut;
 use url::Url;
 
 /// A Git protocol client that communicates with a Git server over HTTPS.
 /// Only support `SmartProtocol` now, see [http-protocol](https://www.git-scm.com/docs/http-protocol) for protocol details.
+/// [protocol-v2](https://git-scm.com/docs/protocol-v2) is preferred for fetching if the server advertises it.
 pub struct HttpsClient {
     pub(crate) url: Url,
     pub(crate) client: reqwest::Client,
+    /// capabilities advertised by a protocol v2 server, `None` if v0 is used
+    pub(crate) v2_capabilities: Mutex<Option<Vec<String>>>,
 }
 
+/// The header to request protocol v2, ignored by servers that don't support it
+const GIT_PROTOCOL_V2: (&str, &str) = ("Git-Protocol", "version=2");
+
 impl ProtocolClient for HttpsClient {
     fn from_url(url: &Url) -> Self {
         // TODO check repo url
@@ -33,7 +36,11 @@ impl ProtocolClient for HttpsClient {
             url
         };
         let client = reqwest::Client::builder().http1_only().build().unwrap();
-        Self { url, client }
+        Self {
+            url,
+            client,
+            v2_capabilities: Mutex::new(None),
+        }
     }
 }
 
@@ -43,14 +50,21 @@ pub struct BasicAuth {
     pub(crate) username: String,
     pub(crate) password: String,
 }
-static AUTH: Mutex<Option<BasicAuth>> = Mutex::new(None);
+/// The credential in use, filled by the helpers or the user, see [credential]
+static AUTH: Mutex<Option<Credential>> = Mutex::new(None);
 impl BasicAuth {
-    /// set username & password manually
-    pub async fn set_auth(auth: BasicAuth) {
-        AUTH.lock().unwrap().replace(auth);
+    /// set username & password of the server of `url` manually
+    pub async fn set_auth(url: &Url, auth: BasicAuth) {
+        let credential = Credential {
+            username: Some(auth.username),
+            password: Some(auth.password),
+            ..Credential::for_url(url).await
+        };
+        AUTH.lock().unwrap().replace(credential);
     }
 
-    /// send request with basic auth, retry 3 times
+    /// send request with the credential of the server (basic auth or bearer token), retry 3 times.
+    /// The credential helpers are asked at the first request to a server, the user is asked if the server rejects it.
     pub async fn send<Fut>(request_builder: impl Fn() -> Fut) -> Result<Response, reqwest::Error>
     where
         Fut: std::future::Future<Output = RequestBuilder>,
@@ -58,26 +72,58 @@ impl BasicAuth {
         const MAX_TRY: usize = 3;
         let mut res;
         let mut try_cnt = 0;
+        let mut filled = false; // whether the credential is new, to be approved if it's accepted
         loop {
-            let mut request = request_builder().await; // RequestBuilder can't be cloned
-            if let Some(auth) = AUTH.lock().unwrap().deref() {
-                request = request.basic_auth(auth.username.clone(), Some(auth.password.clone()));
-            } // if no auth exists, try without auth (e.g. clone public)
-            res = request.send().await?;
+            // RequestBuilder can't be cloned
+            let (client, request) = request_builder().await.build_split();
+            let mut request = request?;
+            let target = Credential::for_url(request.url()).await;
+            let cached = AUTH.lock().unwrap().clone();
+            let auth = match cached.filter(|auth| auth.same_server(&target)) {
+                Some(auth) => auth,
+                None => {
+                    // if the helpers have nothing, try without auth (e.g. clone public)
+                    let auth = credential::fill(target.clone(), false).await;
+                    AUTH.lock().unwrap().replace(auth.clone());
+                    filled = true;
+                    auth
+                }
+            };
+            if let Some(mut value) = auth
+                .authorization()
+                .and_then(|value| HeaderValue::from_str(&value).ok())
+            {
+                value.set_sensitive(true);
+                request.headers_mut().insert(AUTHORIZATION, value);
+            }
+            res = client.execute(request).await?;
             if res.status() == StatusCode::FORBIDDEN {
                 // 403: no access, no need to retry
                 eprintln!("Authentication failed, forbidden");
                 break;
             } else if res.status() != StatusCode::UNAUTHORIZED {
+                // only a successful response proves the credential, not e.g. 404 or 500
+                if filled && res.status().is_success() {
+                    credential::approve(&auth).await;
+                }
                 break;
             }
             // 401 (Unauthorized): username or password is incorrect
+            if auth.is_complete() {
+                credential::reject(&auth).await;
+            }
             if try_cnt >= MAX_TRY {
                 eprintln!("Failed to authenticate after {} attempts", MAX_TRY);
                 break;
             }
             eprintln!("Authentication required, retrying...");
-            AUTH.lock().unwrap().replace(ask_basic_auth());
+            let auth = credential::fill(target, true).await;
+            if !auth.is_complete() {
+                eprintln!("Failed to authenticate, no credential available");
+                break;
+            }
+            AUTH.lock().unwrap().replace(auth);
+            filled = true;
             try_cnt += 1;
         }
         Ok(res)
@@ -99,20 +145,30 @@ impl HttpsClient {
     /// GET $GIT_URL/info/refs?service=git-upload-pack HTTP/1.0<br>
     /// Discover the references of the remote repository before fetching the objects.
     /// the first ref named HEAD as default ref.
+    /// For `git-upload-pack`, protocol v2 is requested: if the server answers with `version 2`,
+    /// the refs are listed by the `ls-refs` command, filtered by `ref_prefixes` (all refs if empty).
     /// ## Args
     /// - auth: (username, password)
     pub async fn discovery_reference(
         &self,
-        service: ServiceType,
+        service_type: ServiceType,
+        ref_prefixes: &[String],
     ) -> Result<Vec<DiscRef>, GitError> {
-        let service: &str = &service.to_string();
+        let service: &str = &service_type.to_string();
         let url = self
             .url
             .join(&format!("info/refs?service={}", service))
             .unwrap();
-        let res = BasicAuth::send(|| async { self.client.get(url.clone()) })
-            .await
-            .unwrap();
+        let res = BasicAuth::send(|| async {
+            let request = self.client.get(url.clone());
+            match service_type {
+                ServiceType::UploadPack => request.header(GIT_PROTOCOL_V2.0, GIT_PROTOCOL_V2.1),
+                // there is no protocol v2 for git-receive-pack
+                ServiceType::ReceivePack => request,
+            }
+        })
+        .await
+        .unwrap();
         tracing::debug!("{:?}", res);
 
         if res.status() == 401 {
@@ -142,83 +198,108 @@ impl HttpsClient {
 
         // the first five bytes of the response entity matches the regex ^[0-9a-f]{4}#.
         // verify the first pkt-line is # service=$servicename, and ignore LF
-        let (_, first_line) = read_pkt_line(&mut response_content);
-        if first_line[..].ne(format!("# service={}\n", service).as_bytes()) {
+        // a v2 server may omit it and start with `version 2` directly
+        let (_, mut first_line) = read_pkt_line(&mut response_content);
+        if first_line.starts_with(b"# service=") {
+            if first_line[..].ne(format!("# service={}\n", service).as_bytes()) {
+                return Err(GitError::NetworkError(format!(
+                    "Error Response format, didn't start with `# service={}`",
+                    service
+                )));
+            }
+            read_pkt_line(&mut response_content); // flush-pkt
+            let mut v2_content = response_content.clone();
+            (_, first_line) = read_pkt_line(&mut v2_content);
+            if first_line[..].ne(b"version 2\n") {
+                return Ok(parse_references(&mut response_content, service_type));
+            }
+            response_content = v2_content;
+        } else if first_line[..].ne(b"version 2\n") {
             return Err(GitError::NetworkError(format!(
                 "Error Response format, didn't start with `# service={}`",
                 service
             )));
         }
 
-        let mut ref_list = vec![];
-        let mut read_first_line = false;
-        loop {
-            let (bytes_take, pkt_line) = read_pkt_line(&mut response_content);
-            if bytes_take == 0 {
-                if response_content.is_empty() {
-                    break;
-                } else {
-                    continue;
-                }
-            }
-            let pkt_line = String::from_utf8(pkt_line.to_vec()).unwrap();
-            let (hash, mut refs) = pkt_line.split_at(40); // hex SHA1 string is 40 bytes
-            refs = refs.trim();
-            if !read_first_line {
-                if hash == SHA1::default().to_string() {
-                    break; // empty repo, return empty list // TODO: parse capability
-                }
-                let (head, caps) = refs.split_once('\0').unwrap();
-                if service == UploadPack.to_string() {
-                    // for git-upload-pack, the first line is HEAD
-                    assert_eq!(head, "HEAD");
-                }
-                // default ref named HEAD as the first ref. The stream MUST include capability declarations behind a NUL on the first ref.
-                ref_list.push(DiscoveredReference {
-                    _hash: hash.to_string(),
-                    _ref: head.to_string(),
-                });
-                let caps = caps.split(' ').collect::<Vec<&str>>();
-                tracing::debug!("capability declarations: {:?}", caps);
-                // tracing::warn!(
-                //     "temporary ignore capability declarations:[ {:?} ]",
-                //     refs[4..].to_string()
-                // );
-                read_first_line = true;
-            } else {
-                ref_list.push(DiscoveredReference {
-                    _hash: hash.to_string(),
-                    _ref: refs.to_string(),
-                });
-            }
+        let capabilities = parse_capabilities_v2(&mut response_content);
+        tracing::debug!("protocol v2 capabilities: {:?}", capabilities);
+        if !capabilities.iter().any(|cap| cap == "ls-refs") {
+            return Err(GitError::NetworkError(
+                "protocol v2 server doesn't support `ls-refs`".to_string(),
+            ));
         }
-        Ok(ref_list)
+        self.v2_capabilities.lock().unwrap().replace(capabilities);
+        self.ls_refs(ref_prefixes).await
+    }
+
+    /// POST $GIT_URL/git-upload-pack with `command=ls-refs` of protocol v2.
+    /// HEAD is always requested, and put first in the result.
+    async fn ls_refs(&self, ref_prefixes: &[String]) -> Result<Vec<DiscRef>, GitError> {
+        let body = generate_ls_refs_content(ref_prefixes);
+        tracing::debug!("ls-refs with body: {:?}", body);
+        let res = self
+            .post_command_v2(body)
+            .await
+            .map_err(|e| GitError::NetworkError(e.to_string()))?;
+        if res.status() != 200 {
+            return Err(GitError::NetworkError(format!(
+                "Error Response format, status code: {}",
+                res.status()
+            )));
+        }
+        let mut response_content = res
+            .bytes()
+            .await
+            .map_err(|e| GitError::NetworkError(e.to_string()))?;
+        Ok(parse_ls_refs(&mut response_content))
+    }
+
+    /// POST a command request of protocol v2 to `git-upload-pack`
+    async fn post_command_v2(&self, body: Bytes) -> Result<Response, reqwest::Error> {
+        let url = self.url.join("git-upload-pack").unwrap();
+        BasicAuth::send(|| async {
+            self.client
+                .post(url.clone())
+                .header(CONTENT_TYPE, "application/x-git-upload-pack-request")
+                .header(GIT_PROTOCOL_V2.0, GIT_PROTOCOL_V2.1)
+                .body(body.clone())
+        })
+        .await
     }
 
     /// POST $GIT_URL/git-upload-pack HTTP/1.0<br>
     /// Fetch the objects from the remote repository, which is specified by `have` and `want`.<br>
     /// `have` is the list of objects' hashes that the client already has, and `want` is the list of objects that the client wants.
     /// Obtain the `want` references from the `discovery_reference` method.<br>
-    /// If the returned stream is empty, it may be due to incorrect refs or an incorrect format.
+    /// If the returned stream is empty, it may be due to incorrect refs or an incorrect format.<br>
+    /// With protocol v2, the `fetch` command is used, and the response starts with the `packfile` section header.
     // TODO support some necessary options
     pub async fn fetch_objects(
         &self,
         have: &Vec<String>,
         want: &Vec<String>,
+        options: &UploadPackOptions,
     ) -> Result<impl StreamExt<Item = Result<Bytes, IoError>>, IoError> {
-        // POST $GIT_URL/git-upload-pack HTTP/1.0
-        let url = self.url.join("git-upload-pack").unwrap();
-        let body = generate_upload_pack_content(have, want).await;
-        tracing::debug!("fetch_objects with body: {:?}", body);
-
-        let res = BasicAuth::send(|| async {
-            self.client
-                .post(url.clone())
-                .header("Content-Type", "application/x-git-upload-pack-request")
-                .body(body.clone())
-        })
-        .await
-        .unwrap();
+        let v2 = self.v2_capabilities.lock().unwrap().is_some();
+        let res = if v2 {
+            let body = generate_fetch_content_v2(have, want, options);
+            tracing::debug!("fetch_objects (v2) with body: {:?}", body);
+            self.post_command_v2(body).await.unwrap()
+        } else {
+            // POST $GIT_URL/git-upload-pack HTTP/1.0
+            let url = self.url.join("git-upload-pack").unwrap();
+            let body = generate_upload_pack_content(have, want, options).await;
+            tracing::debug!("fetch_objects with body: {:?}", body);
+
+            BasicAuth::send(|| async {
+                self.client
+                    .post(url.clone())
+                    .header("Content-Type", "application/x-git-upload-pack-request")
+                    .body(body.clone())
+            })
+            .await
+            .unwrap()
+        };
         tracing::debug!("request: {:?}", res);
 
         if res.status() != 200 && res.status() != 304 {
@@ -249,11 +330,22 @@ impl HttpsClient {
     }
 }
 /// for fetching
-async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) -> Bytes {
+pub(crate) async fn generate_upload_pack_content(
+    have: &Vec<String>,
+    want: &Vec<String>,
+    options: &UploadPackOptions,
+) -> Bytes {
     let mut buf = BytesMut::new();
     let mut write_first_line = false;
 
-    let capability = ["side-band-64k", "ofs-delta", "multi_ack_detailed"].join(" ");
+    let mut capability = vec!["side-band-64k", "ofs-delta", "multi_ack_detailed"];
+    if options.depth.is_some() || !options.shallow.is_empty() {
+        capability.push("shallow");
+    }
+    if options.filter.is_some() {
+        capability.push("filter");
+    }
+    let capability = capability.join(" ");
     for w in want {
         if !write_first_line {
             add_pkt_line_string(
@@ -265,6 +357,7 @@ async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) ->
             add_pkt_line_string(&mut buf, format!("want {}\n", w).to_string());
         }
     }
+    add_upload_pack_options(&mut buf, options);
     buf.extend(b"0000"); // split pkt-lines with a flush-pkt
     for h in have {
         add_pkt_line_string(&mut buf, format!("have {}\n", h).to_string());
@@ -275,9 +368,109 @@ async fn generate_upload_pack_content(have: &Vec<String>, want: &Vec<String>) ->
     buf.freeze()
 }
 
+/// shallow-lines, depth-request & filter-request, the same in protocol v0 & v2
+fn add_upload_pack_options(buf: &mut BytesMut, options: &UploadPackOptions) {
+    for s in &options.shallow {
+        add_pkt_line_string(buf, format!("shallow {}\n", s));
+    }
+    if let Some(depth) = options.depth {
+        add_pkt_line_string(buf, format!("deepen {}\n", depth));
+    }
+    if let Some(filter) = &options.filter {
+        add_pkt_line_string(buf, format!("filter {}\n", filter));
+    }
+}
+
+/// Parse the capability advertisement of protocol v2 (after `version 2`), until a flush-pkt
+fn parse_capabilities_v2(content: &mut Bytes) -> Vec<String> {
+    let mut capabilities = vec![];
+    loop {
+        let (bytes_take, pkt_line) = read_pkt_line(content);
+        if bytes_take == 0 {
+            break;
+        }
+        let cap = String::from_utf8_lossy(&pkt_line);
+        capabilities.push(cap.trim_end().to_string());
+    }
+    capabilities
+}
+
+/// `command=ls-refs` request of protocol v2
+fn generate_ls_refs_content(ref_prefixes: &[String]) -> Bytes {
+    let mut buf = BytesMut::new();
+    add_pkt_line_string(&mut buf, "command=ls-refs\n".to_string());
+    add_pkt_line_string(&mut buf, "agent=libra/0.1.0\n".to_string());
+    buf.extend(b"0001"); // delim-pkt, the arguments follow
+    add_pkt_line_string(&mut buf, "symrefs\n".to_string());
+    if !ref_prefixes.is_empty() {
+        add_pkt_line_string(&mut buf, "ref-prefix HEAD\n".to_string());
+        for prefix in ref_prefixes {
+            add_pkt_line_string(&mut buf, format!("ref-prefix {}\n", prefix));
+        }
+    }
+    buf.extend(b"0000");
+    buf.freeze()
+}
+
+/// Parse the response of `ls-refs`: `<oid> <refname>[ <attribute>]*` lines until a flush-pkt.
+/// Attributes like `symref-target` are ignored, and unborn refs are skipped.
+fn parse_ls_refs(content: &mut Bytes) -> Vec<DiscRef> {
+    let mut ref_list = vec![];
+    loop {
+        let (bytes_take, pkt_line) = read_pkt_line(content);
+        if bytes_take == 0 {
+            break;
+        }
+        let pkt_line = String::from_utf8_lossy(&pkt_line);
+        let mut parts = pkt_line.trim_end().split(' ');
+        let (Some(hash), Some(name)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        if hash == "unborn" {
+            continue;
+        }
+        let reference = DiscRef {
+            _hash: hash.to_string(),
+            _ref: name.to_string(),
+        };
+        if name == "HEAD" {
+            ref_list.insert(0, reference);
+        } else {
+            ref_list.push(reference);
+        }
+    }
+    ref_list
+}
+
+/// `command=fetch` request of protocol v2, side-band-64k is implied for the `packfile` section
+fn generate_fetch_content_v2(
+    have: &[String],
+    want: &[String],
+    options: &UploadPackOptions,
+) -> Bytes {
+    let mut buf = BytesMut::new();
+    add_pkt_line_string(&mut buf, "command=fetch\n".to_string());
+    add_pkt_line_string(&mut buf, "agent=libra/0.1.0\n".to_string());
+    buf.extend(b"0001"); // delim-pkt, the arguments follow
+    add_pkt_line_string(&mut buf, "ofs-delta\n".to_string());
+    for w in want {
+        add_pkt_line_string(&mut buf, format!("want {}\n", w));
+    }
+    for h in have {
+        add_pkt_line_string(&mut buf, format!("have {}\n", h));
+    }
+    add_upload_pack_options(&mut buf, options);
+    // skip the negotiation, ask for the pack directly
+    add_pkt_line_string(&mut buf, "done\n".to_string());
+    buf.extend(b"0000");
+    buf.freeze()
+}
+
 #[cfg(test)]
 mod tests {
 
+    use ceres::protocol::ServiceType::UploadPack;
+
     use crate::utils::test::init_debug_logger;
     use crate::utils::test::init_logger;
     use tokio::io::AsyncReadExt;
@@ -292,7 +485,7 @@ mod tests {
         let test_repo = "https://github.com/web3infra-foundation/mega.git/";
 
         let client = HttpsClient::from_url(&Url::parse(test_repo).unwrap());
-        let refs = client.discovery_reference(UploadPack).await;
+        let refs = client.discovery_reference(UploadPack, &[]).await;
         if refs.is_err() {
             tracing::error!("{:?}", refs.err().unwrap());
             panic!();
@@ -309,7 +502,7 @@ mod tests {
 
         let test_repo = "https://github.com/web3infra-foundation/mega/";
         let client = HttpsClient::from_url(&Url::parse(test_repo).unwrap());
-        let refs = client.discovery_reference(UploadPack).await.unwrap();
+        let refs = client.discovery_reference(UploadPack, &[]).await.unwrap();
         let refs: Vec<DiscoveredReference> = refs
             .iter()
             .filter(|r| r._ref.starts_with("refs/heads"))
@@ -320,7 +513,10 @@ mod tests {
         let want = refs.iter().map(|r| r._hash.clone()).collect();
 
         let have = vec!["81a162e7b725bbad2adfe01879fd57e0119406b9".to_string()];
-        let mut result_stream = client.fetch_objects(&have, &want).await.unwrap();
+        let mut result_stream = client
+            .fetch_objects(&have, &want, &UploadPackOptions::default())
+            .await
+            .unwrap();
 
         let mut buffer = vec![];
         while let Some(item) = result_stream.next().await {
@@ -357,7 +553,7 @@ mod tests {
         let have = vec!["1c05d7f7dd70e38150bfd2d5fb8fb969e2eb9851".to_string()];
         // **want MUST change to one of the refs in the remote repo, such as `refs/heads/main` before running the test**
         let want = vec!["6b4e69962dbbc75e80d5263cc5c81571669db9bc".to_string()];
-        let body = generate_upload_pack_content(&have, &want).await;
+        let body = generate_upload_pack_content(&have, &want, &UploadPackOptions::default()).await;
         tracing::info!("upload-pack content: {:?}", body);
         let mut cmd = tokio::process::Command::new("/usr/bin/git-upload-pack");
         cmd.arg("..");
@@ -406,4 +602,64 @@ mod tests {
             panic!("no pack data found");
         }
     }
+
+    #[test]
+    fn test_parse_v2_responses() {
+        let mut content = BytesMut::new();
+        add_pkt_line_string(&mut content, "agent=mega/0.1.0\n".to_string());
+        add_pkt_line_string(&mut content, "ls-refs\n".to_string());
+        add_pkt_line_string(&mut content, "fetch\n".to_string());
+        content.extend(b"0000");
+        let caps = parse_capabilities_v2(&mut content.freeze());
+        assert_eq!(caps, vec!["agent=mega/0.1.0", "ls-refs", "fetch"]);
+
+        let head = "6b4e69962dbbc75e80d5263cc5c81571669db9bc";
+        let mut content = BytesMut::new();
+        add_pkt_line_string(&mut content, format!("{} refs/heads/main\n", head));
+        add_pkt_line_string(
+            &mut content,
+            format!("{} HEAD symref-target:refs/heads/main\n", head),
+        );
+        add_pkt_line_string(&mut content, "unborn refs/heads/dev\n".to_string());
+        content.extend(b"0000");
+        let refs = parse_ls_refs(&mut content.freeze());
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0]._ref, "HEAD");
+        assert_eq!(refs[0]._hash, head);
+        assert_eq!(refs[1]._ref, "refs/heads/main");
+    }
+
+    #[test]
+    fn test_generate_v2_requests() {
+        let body = generate_ls_refs_content(&["refs/heads/".to_string()]);
+        assert_eq!(
+            &body[..],
+            b"0014command=ls-refs\n0016agent=libra/0.1.0\n0001000csymrefs\n0014ref-prefix HEAD\n001bref-prefix refs/heads/\n0000"
+        );
+        assert!(generate_ls_refs_content(&[]).ends_with(b"0001000csymrefs\n0000"));
+
+        let want = vec!["6b4e69962dbbc75e80d5263cc5c81571669db9bc".to_string()];
+        let body = generate_fetch_content_v2(&[], &want, &UploadPackOptions::default());
+        assert!(body.starts_with(b"0012command=fetch\n"));
+        assert!(
+            body.ends_with(b"0032want 6b4e69962dbbc75e80d5263cc5c81571669db9bc\n0009done\n0000")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generate_shallow_request() {
+        let want = vec!["6b4e69962dbbc75e80d5263cc5c81571669db9bc".to_string()];
+        let options = UploadPackOptions {
+            depth: Some(1),
+            filter: Some("blob:none".to_string()),
+            shallow: vec![],
+        };
+        let body = generate_upload_pack_content(&vec![], &want, &options).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("multi_ack_detailed shallow filter agent"));
+        assert!(body.ends_with("000ddeepen 1\n0015filter blob:none\n00000009done\n"));
+
+        let body = generate_fetch_content_v2(&[], &want, &options);
+        assert!(body.ends_with(b"000ddeepen 1\n0015filter blob:none\n0009done\n0000"));
+    }
 }
@@ -0,0 +1,466 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use ceres::protocol::smart::add_pkt_line_string;
+use ceres::protocol::ServiceType;
+use futures_util::Stream;
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag;
+use mercury::internal::object::tree::{Tree, TreeItemMode};
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+use mercury::internal::pack::encode::PackEncoder;
+use mercury::internal::pack::entry::Entry;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use tokio::sync::mpsc;
+use url::Url;
+
+use super::https_client::DiscoveredReference;
+use super::ProtocolClient;
+use crate::internal::db;
+use crate::internal::model::reference;
+use crate::utils::client_storage::ClientStorage;
+use crate::utils::{path, util};
+
+/// max data size of a side-band-64k pkt-line: 65520 - 4 (length) - 1 (band)
+const MAX_BAND_DATA: usize = 65515;
+
+/// A client of a repository on the same disk, `libra clone /path/to/repo` or `file://` remotes.
+/// The source can be a libra repository (refs in `libra.db`) or a git repository (refs in files),
+/// objects are read directly from its loose objects & packs, then sent as a pack like the other clients.
+pub struct LocalClient {
+    /// the working directory or the storage directory (bare) of the source repository
+    path: PathBuf,
+    /// hard link the object files of the source instead of sending a pack
+    pub(crate) hardlinks: bool,
+}
+
+/// Where the source repository keeps its refs
+enum LocalRepo {
+    /// `libra.db` in the storage directory
+    Libra(PathBuf),
+    /// the `.git` directory
+    Git(PathBuf),
+}
+
+impl LocalRepo {
+    fn storage(&self) -> &Path {
+        match self {
+            LocalRepo::Libra(storage) | LocalRepo::Git(storage) => storage,
+        }
+    }
+}
+
+impl ProtocolClient for LocalClient {
+    fn from_url(url: &Url) -> Self {
+        let path = url
+            .to_file_path()
+            .unwrap_or_else(|_| PathBuf::from(url.path()));
+        Self {
+            path,
+            hardlinks: false,
+        }
+    }
+}
+
+impl LocalClient {
+    /// Read the refs of the source repository, the first ref is HEAD if it points to a commit.
+    /// `push` fails here with [Self::send_pack], before the pack is built.
+    pub async fn discovery_reference(
+        &self,
+        service: ServiceType,
+    ) -> Result<Vec<DiscoveredReference>, GitError> {
+        if service == ServiceType::ReceivePack {
+            return Err(self.push_unsupported());
+        }
+        let refs = match self.repo()? {
+            LocalRepo::Libra(storage) => libra_refs(&storage).await?,
+            LocalRepo::Git(git_dir) => git_refs(&git_dir)?,
+        };
+        Ok(refs
+            .into_iter()
+            .map(|(name, hash)| DiscoveredReference {
+                _hash: hash.to_string(),
+                _ref: name,
+            })
+            .collect())
+    }
+
+    /// Pack the objects reachable from `want` but not from `have`,
+    /// the response is the same as `git-upload-pack` with side-band-64k.
+    /// In hardlink mode, all object files are linked into the current repository & the pack is empty.
+    pub async fn fetch_objects(
+        &self,
+        have: &[String],
+        want: &[String],
+    ) -> Result<impl Stream<Item = Result<Bytes, IoError>>, IoError> {
+        let to_io_error = |e: GitError| IoError::other(e.to_string());
+        let objects = self.repo().map_err(to_io_error)?.storage().join("objects");
+        let entries = if self.hardlinks {
+            link_objects(&objects, &path::objects())?;
+            vec![]
+        } else {
+            let storage = ClientStorage::init(objects);
+            let parse = |hashes: &[String]| {
+                hashes
+                    .iter()
+                    .filter_map(|hash| SHA1::from_str(hash).ok())
+                    .filter(|hash| storage.exist(hash))
+                    .collect::<Vec<_>>()
+            };
+            let excluded =
+                reachable_objects(&storage, parse(have), &HashSet::new()).map_err(to_io_error)?;
+            let wanted =
+                reachable_objects(&storage, parse(want), &excluded).map_err(to_io_error)?;
+            wanted
+                .into_iter()
+                .map(|hash| load_entry(&storage, hash))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(to_io_error)?
+        };
+        let pack = encode_pack(entries).await;
+
+        let mut response = BytesMut::new();
+        add_pkt_line_string(&mut response, "NAK\n".to_string());
+        for chunk in pack.chunks(MAX_BAND_DATA) {
+            response.put(format!("{:04x}", chunk.len() + 5).as_bytes());
+            response.put_u8(1); // band 1: pack data
+            response.put(chunk);
+        }
+        response.put(&b"0000"[..]);
+        Ok(futures::stream::iter([Ok(response.freeze())]))
+    }
+
+    /// Pushing to a local repository is not supported, push to its server instead
+    pub async fn send_pack(&self, _data: Bytes) -> Result<Bytes, GitError> {
+        Err(self.push_unsupported())
+    }
+
+    fn push_unsupported(&self) -> GitError {
+        GitError::CustomError(format!(
+            "local push is unsupported, can't push to '{}'",
+            self.path.display()
+        ))
+    }
+
+    /// Find the refs & objects of the source, the working directory or the storage directory (bare)
+    fn repo(&self) -> Result<LocalRepo, GitError> {
+        let libra_dir = self.path.join(util::ROOT_DIR);
+        let git_dir = self.path.join(".git");
+        if libra_dir.join(util::DATABASE).exists() {
+            Ok(LocalRepo::Libra(libra_dir))
+        } else if self.path.join(util::DATABASE).exists() {
+            Ok(LocalRepo::Libra(self.path.clone()))
+        } else if git_dir.join("HEAD").exists() {
+            Ok(LocalRepo::Git(git_dir))
+        } else if self.path.join("HEAD").exists() && self.path.join("objects").is_dir() {
+            Ok(LocalRepo::Git(self.path.clone()))
+        } else {
+            Err(GitError::RepoNotFound)
+        }
+    }
+}
+
+/// HEAD, branches & tags in `libra.db` of another repository
+pub(crate) async fn libra_refs(storage: &Path) -> Result<Vec<(String, SHA1)>, GitError> {
+    let db_path = storage.join(util::DATABASE);
+    let db_conn = db::establish_connection(db_path.to_str().unwrap())
+        .await
+        .map_err(|e| GitError::CustomError(e.to_string()))?;
+    let local_refs = reference::Entity::find()
+        .filter(reference::Column::Remote.is_null())
+        .all(&db_conn)
+        .await
+        .map_err(|e| GitError::CustomError(e.to_string()))?;
+
+    let commit_of = |model: &reference::Model| {
+        model
+            .commit
+            .as_deref()
+            .and_then(|commit| SHA1::from_str(commit).ok())
+    };
+    let mut refs = vec![];
+    let mut head = None;
+    for model in &local_refs {
+        let name = model.name.clone().unwrap_or_default();
+        match model.kind {
+            reference::ConfigKind::Head => head = Some(model.clone()),
+            reference::ConfigKind::Branch => {
+                if let Some(commit) = commit_of(model) {
+                    refs.push((format!("refs/heads/{}", name), commit));
+                }
+            }
+            reference::ConfigKind::Tag => {
+                if let Some(object) = commit_of(model) {
+                    refs.push((format!("refs/tags/{}", name), object));
+                }
+            }
+        }
+    }
+    refs.sort();
+    // HEAD points to a branch (name) or a commit (detached)
+    let head_commit = head.and_then(|head| match head.name {
+        Some(branch) => refs
+            .iter()
+            .find(|(name, _)| *name == format!("refs/heads/{}", branch))
+            .map(|(_, commit)| *commit),
+        None => commit_of(&head),
+    });
+    if let Some(commit) = head_commit {
+        refs.insert(0, ("HEAD".to_string(), commit));
+    }
+    Ok(refs)
+}
+
+/// HEAD, branches & tags of a git repository, loose refs take precedence over `packed-refs`
+pub(crate) fn git_refs(git_dir: &Path) -> Result<Vec<(String, SHA1)>, GitError> {
+    let mut refs = std::collections::BTreeMap::new();
+    if let Ok(packed) = fs::read_to_string(git_dir.join("packed-refs")) {
+        for line in packed.lines() {
+            if line.starts_with('#') || line.starts_with('^') {
+                continue; // comment or peeled tag
+            }
+            if let Some((hash, name)) = line.split_once(' ') {
+                if let Ok(hash) = SHA1::from_str(hash) {
+                    refs.insert(name.to_string(), hash);
+                }
+            }
+        }
+    }
+    for dir in ["refs/heads", "refs/tags"] {
+        let mut queue = VecDeque::from([PathBuf::from(dir)]);
+        while let Some(dir) = queue.pop_front() {
+            let Ok(entries) = fs::read_dir(git_dir.join(&dir)) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = dir.join(entry.file_name());
+                if entry.path().is_dir() {
+                    queue.push_back(name);
+                } else if let Ok(hash) = SHA1::from_str(fs::read_to_string(entry.path())?.trim()) {
+                    // ref names use `/` on all platforms
+                    let name = name.to_string_lossy().replace('\\', "/");
+                    refs.insert(name, hash);
+                }
+            }
+        }
+    }
+
+    let head = fs::read_to_string(git_dir.join("HEAD"))?;
+    let head_commit = match head.trim().strip_prefix("ref: ") {
+        Some(branch) => refs.get(branch).copied(),
+        None => SHA1::from_str(head.trim()).ok(),
+    };
+    let mut result: Vec<(String, SHA1)> = head_commit
+        .map(|commit| ("HEAD".to_string(), commit))
+        .into_iter()
+        .collect();
+    result.extend(refs);
+    Ok(result)
+}
+
+/// Objects reachable from `roots` in `storage`, the traversal stops at `excluded`.
+/// Submodules (gitlinks) are skipped, their commits are not in the repository.
+fn reachable_objects(
+    storage: &ClientStorage,
+    roots: Vec<SHA1>,
+    excluded: &HashSet<SHA1>,
+) -> Result<HashSet<SHA1>, GitError> {
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from(roots);
+    while let Some(hash) = queue.pop_front() {
+        if excluded.contains(&hash) || !visited.insert(hash) {
+            continue;
+        }
+        match storage.get_object_type(&hash)? {
+            ObjectType::Commit => {
+                let commit = Commit::from_bytes(&storage.get(&hash)?, hash)?;
+                queue.push_back(commit.tree_id);
+                queue.extend(commit.parent_commit_ids);
+            }
+            ObjectType::Tree => {
+                let tree = Tree::from_bytes(&storage.get(&hash)?, hash)?;
+                queue.extend(
+                    tree.tree_items
+                        .into_iter()
+                        .filter(|item| item.mode != TreeItemMode::Commit)
+                        .map(|item| item.id),
+                );
+            }
+            ObjectType::Tag => {
+                let tag = Tag::from_bytes(&storage.get(&hash)?, hash)?;
+                queue.push_back(tag.object_hash);
+            }
+            _ => {}
+        }
+    }
+    Ok(visited)
+}
+
+fn load_entry(storage: &ClientStorage, hash: SHA1) -> Result<Entry, GitError> {
+    Ok(Entry {
+        obj_type: storage.get_object_type(&hash)?,
+        data: storage.get(&hash)?,
+        hash,
+    })
+}
+
+/// Encode `entries` to a pack, an empty pack is only the header & checksum
+async fn encode_pack(entries: Vec<Entry>) -> Vec<u8> {
+    if entries.is_empty() {
+        let mut pack = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes()); // version
+        pack.extend(0u32.to_be_bytes()); // number of objects
+        let checksum = SHA1::new(&pack);
+        pack.extend(checksum.0);
+        return pack;
+    }
+
+    let (entry_tx, entry_rx) = mpsc::channel(1_000_000);
+    let (stream_tx, mut stream_rx) = mpsc::channel(1_000_000);
+    let encoder = PackEncoder::new(entries.len(), 0, stream_tx);
+    encoder.encode_async(entry_rx).await.unwrap();
+    for entry in entries {
+        entry_tx.send(entry).await.unwrap();
+    }
+    drop(entry_tx);
+
+    let mut pack = Vec::new();
+    while let Some(chunk) = stream_rx.recv().await {
+        pack.extend(chunk);
+    }
+    pack
+}
+
+/// Hard link the loose objects & packs from `source` to `target` (both are `objects` directories),
+/// fall back to copying if they are on different file systems.
+fn link_objects(source: &Path, target: &Path) -> Result<(), IoError> {
+    let mut queue = VecDeque::from([PathBuf::new()]);
+    while let Some(dir) = queue.pop_front() {
+        fs::create_dir_all(target.join(&dir))?;
+        for entry in fs::read_dir(source.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                queue.push_back(relative);
+                continue;
+            }
+            let target = target.join(&relative);
+            if target.exists() {
+                continue;
+            }
+            if fs::hard_link(entry.path(), &target).is_err() {
+                fs::copy(entry.path(), &target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{add::AddArgs, commit::CommitArgs};
+    use crate::internal::head::Head;
+    use crate::utils::test;
+    use serial_test::serial;
+
+    #[test]
+    fn test_git_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let git_dir = dir.path();
+        let (main, tag, dev) = (
+            "6b4e69962dbbc75e80d5263cc5c81571669db9bc",
+            "1c05d7f7dd70e38150bfd2d5fb8fb969e2eb9851",
+            "81a162e7b725bbad2adfe01879fd57e0119406b9",
+        );
+        fs::create_dir_all(git_dir.join("refs/heads/feature")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git_dir.join("refs/heads/main"), format!("{}\n", main)).unwrap();
+        fs::write(git_dir.join("refs/heads/feature/dev"), dev).unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled\n{} refs/tags/v1\n^{}\n{} refs/heads/main\n",
+                tag, main, dev
+            ),
+        )
+        .unwrap();
+
+        let refs = git_refs(git_dir).unwrap();
+        let refs: Vec<(&str, String)> = refs
+            .iter()
+            .map(|(name, hash)| (name.as_str(), hash.to_string()))
+            .collect();
+        assert_eq!(
+            refs,
+            vec![
+                ("HEAD", main.to_string()),
+                ("refs/heads/feature/dev", dev.to_string()),
+                ("refs/heads/main", main.to_string()), // loose ref wins
+                ("refs/tags/v1", tag.to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_libra_refs_and_objects() {
+        test::setup_with_new_libra().await;
+        test::ensure_file("a.txt", Some("a"));
+        crate::command::add::execute(AddArgs {
+            pathspec: vec![],
+            all: true,
+            update: false,
+            verbose: false,
+            force: false,
+        })
+        .await;
+        crate::command::commit::execute(CommitArgs {
+            message: "init".to_string(),
+            allow_empty: false,
+            conventional: false,
+            gpg_sign: false,
+            no_verify: false,
+        })
+        .await;
+        let head = Head::current_commit().await.unwrap();
+
+        let url = Url::from_directory_path(util::working_dir()).unwrap();
+        let client = LocalClient::from_url(&url);
+        let refs = client
+            .discovery_reference(ServiceType::UploadPack)
+            .await
+            .unwrap();
+        assert_eq!(refs[0]._ref, "HEAD");
+        assert_eq!(refs[0]._hash, head.to_string());
+        assert!(refs.iter().any(|r| r._ref == "refs/heads/master"));
+
+        // commit, tree & blob
+        let storage = ClientStorage::init(path::objects());
+        let objects = reachable_objects(&storage, vec![head], &HashSet::new()).unwrap();
+        assert_eq!(objects.len(), 3);
+        let excluded = reachable_objects(&storage, vec![head], &objects).unwrap();
+        assert!(excluded.is_empty());
+
+        let err = client.discovery_reference(ServiceType::ReceivePack).await;
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("local push is unsupported"));
+    }
+
+    #[tokio::test]
+    async fn test_encode_empty_pack() {
+        let pack = encode_pack(vec![]).await;
+        assert_eq!(pack.len(), 32);
+        assert_eq!(&pack[..4], b"PACK");
+        assert_eq!(SHA1::new(&pack[..12]), SHA1::from_bytes(&pack[12..]));
+    }
+}
@@ -1,13 +1,253 @@
+use std::io::Error as IoError;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use ceres::protocol::smart::read_pkt_line;
+use ceres::protocol::ServiceType;
+use ceres::protocol::ServiceType::UploadPack;
+use futures_util::{Stream, StreamExt};
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use path_absolutize::Absolutize;
 use url::Url;
 
+use https_client::{DiscoveredReference, HttpsClient};
+use local_client::LocalClient;
+use ssh_client::SshClient;
+
 pub mod https_client;
 pub mod lfs_client;
+pub mod local_client;
+pub mod ssh_client;
 
-#[allow(dead_code)] // todo: unimplemented
 pub trait ProtocolClient {
     /// create client from url
     fn from_url(url: &Url) -> Self;
 }
 
+/// Shallow & partial clone arguments of a fetch request to `git-upload-pack`
+#[derive(Debug, Clone, Default)]
+pub struct UploadPackOptions {
+    /// `deepen <depth>`, limit the history to `depth` commits from the tips
+    pub depth: Option<usize>,
+    /// `filter <filter-spec>`, e.g. `blob:none`, omit objects from the pack
+    pub filter: Option<String>,
+    /// `shallow <oid>`, the shallow commits the client has
+    pub shallow: Vec<String>,
+}
+
+/// The stream of data sent by the remote, e.g. the response of `git-upload-pack`
+pub type FetchStream<'a> = Pin<Box<dyn Stream<Item = Result<Bytes, IoError>> + Send + 'a>>;
+
+/// Client of a remote repository, the transport is chosen by the url scheme:
+/// - `http://` & `https://`: [HttpsClient]
+/// - `ssh://` & scp-like `user@host:path`: [SshClient]
+/// - `file://` & local paths: [LocalClient]
+pub enum RemoteClient {
+    Https(HttpsClient),
+    Ssh(SshClient),
+    Local(LocalClient),
+}
+
+impl RemoteClient {
+    /// Create a client from a remote url, see [parse_remote_url] for the supported formats
+    pub fn from_url(url: &Url) -> Result<Self, GitError> {
+        match url.scheme() {
+            "http" | "https" => Ok(RemoteClient::Https(HttpsClient::from_url(url))),
+            "ssh" => Ok(RemoteClient::Ssh(SshClient::from_url(url))),
+            "file" => Ok(RemoteClient::Local(LocalClient::from_url(url))),
+            scheme => Err(GitError::NetworkError(format!(
+                "unsupported protocol '{}'",
+                scheme
+            ))),
+        }
+    }
+
+    /// Discover the references of the remote repository, the first ref is HEAD if it exists.
+    /// `ref_prefixes` limits the refs to list (all refs if empty), it is only a hint:
+    /// the filtering happens on the server with protocol v2, other transports return all refs.
+    pub async fn discovery_reference(
+        &self,
+        service: ServiceType,
+        ref_prefixes: &[String],
+    ) -> Result<Vec<DiscoveredReference>, GitError> {
+        match self {
+            RemoteClient::Https(client) => client.discovery_reference(service, ref_prefixes).await,
+            RemoteClient::Ssh(client) => client.discovery_reference(service).await,
+            RemoteClient::Local(client) => client.discovery_reference(service).await,
+        }
+    }
+
+    /// Request the objects, see [HttpsClient::fetch_objects].
+    /// `options` are ignored by a local repository, which is always cloned fully.
+    pub async fn fetch_objects(
+        &self,
+        have: &Vec<String>,
+        want: &Vec<String>,
+        options: &UploadPackOptions,
+    ) -> Result<FetchStream<'_>, IoError> {
+        match self {
+            RemoteClient::Https(client) => {
+                Ok(client.fetch_objects(have, want, options).await?.boxed())
+            }
+            RemoteClient::Ssh(client) => {
+                Ok(client.fetch_objects(have, want, options).await?.boxed())
+            }
+            RemoteClient::Local(client) => Ok(client.fetch_objects(have, want).await?.boxed()),
+        }
+    }
+
+    /// Send the ref update commands & pack data to `git-receive-pack`, return the report of the remote
+    pub async fn send_pack(&self, data: Bytes) -> Result<Bytes, GitError> {
+        match self {
+            RemoteClient::Https(client) => {
+                let res = client
+                    .send_pack(data)
+                    .await
+                    .map_err(|e| GitError::NetworkError(e.to_string()))?;
+                if res.status() != 200 {
+                    return Err(GitError::NetworkError(format!(
+                        "status code: {}",
+                        res.status()
+                    )));
+                }
+                res.bytes()
+                    .await
+                    .map_err(|e| GitError::NetworkError(e.to_string()))
+            }
+            RemoteClient::Ssh(client) => client.send_pack(data).await,
+            RemoteClient::Local(client) => client.send_pack(data).await,
+        }
+    }
+}
+
+/// Parse the location of a remote repository.
+/// Besides urls, the scp-like syntax `[user@]host:path` is accepted and converted to `ssh://[user@]host/path`,
+/// a path starting with `~` is kept relative to the home directory of the user.
+/// Other locations without a scheme are local paths, converted to absolute `file://` urls.
+pub fn parse_remote_url(url: &str) -> Result<Url, GitError> {
+    if url.contains("://") {
+        return Url::parse(url)
+            .map_err(|e| GitError::NetworkError(format!("invalid URL '{}': {}", url, e)));
+    }
+    if let Some((host, path)) = url.split_once(':') {
+        // `C:\repo` is a Windows path rather than the host `C`
+        if host.len() > 1 && !host.contains(['/', '\\']) {
+            let url = format!("ssh://{}/{}", host, path.trim_start_matches('/'));
+            return Url::parse(&url).map_err(|e| GitError::NetworkError(e.to_string()));
+        }
+    }
+    let path = std::path::Path::new(url).absolutize()?;
+    Url::from_directory_path(&path)
+        .map_err(|_| GitError::NetworkError(format!("invalid path '{}'", path.display())))
+}
+
+/// Parse the reference advertisement (after the `# service` line of http), until a flush-pkt.
+/// The capability declarations behind a NUL on the first ref are ignored.
+pub(crate) fn parse_references(
+    content: &mut Bytes,
+    service: ServiceType,
+) -> Vec<DiscoveredReference> {
+    let mut ref_list = vec![];
+    let mut read_first_line = false;
+    loop {
+        let (bytes_take, pkt_line) = read_pkt_line(content);
+        if bytes_take == 0 {
+            if content.is_empty() {
+                break;
+            } else {
+                continue;
+            }
+        }
+        let pkt_line = String::from_utf8(pkt_line.to_vec()).unwrap();
+        let (hash, mut refs) = pkt_line.split_at(40); // hex SHA1 string is 40 bytes
+        refs = refs.trim();
+        if !read_first_line {
+            if hash == SHA1::default().to_string() {
+                break; // empty repo, return empty list // TODO: parse capability
+            }
+            let (head, caps) = refs.split_once('\0').unwrap();
+            if service == UploadPack {
+                // for git-upload-pack, the first line is HEAD
+                assert_eq!(head, "HEAD");
+            }
+            // default ref named HEAD as the first ref. The stream MUST include capability declarations behind a NUL on the first ref.
+            ref_list.push(DiscoveredReference {
+                _hash: hash.to_string(),
+                _ref: head.to_string(),
+            });
+            let caps = caps.split(' ').collect::<Vec<&str>>();
+            tracing::debug!("capability declarations: {:?}", caps);
+            read_first_line = true;
+        } else {
+            ref_list.push(DiscoveredReference {
+                _hash: hash.to_string(),
+                _ref: refs.to_string(),
+            });
+        }
+    }
+    ref_list
+}
+
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_url() {
+        let url = parse_remote_url("git@github.com:web3infra-foundation/mega.git").unwrap();
+        assert_eq!(url.scheme(), "ssh");
+        assert_eq!(url.username(), "git");
+        assert_eq!(url.host_str(), Some("github.com"));
+        assert_eq!(url.path(), "/web3infra-foundation/mega.git");
+
+        let url = parse_remote_url("ssh://git@localhost:2222/project/mega.git").unwrap();
+        assert_eq!(url.port(), Some(2222));
+        assert_eq!(url.path(), "/project/mega.git");
+
+        let url = parse_remote_url("https://github.com/web3infra-foundation/mega.git").unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert!(!parse_remote_url("C:\\repo").is_ok_and(|url| url.scheme() == "ssh"));
+
+        let url = parse_remote_url("../repo").unwrap();
+        assert_eq!(url.scheme(), "file");
+        assert!(url.path().ends_with("/repo/"));
+        let url = parse_remote_url("file:///tmp/repo").unwrap();
+        assert_eq!(url.path(), "/tmp/repo");
+    }
+
+    #[test]
+    fn test_remote_client_scheme() {
+        let client = |url: &str| RemoteClient::from_url(&parse_remote_url(url).unwrap());
+        assert!(matches!(
+            client("https://github.com/a/b.git"),
+            Ok(RemoteClient::Https(_))
+        ));
+        assert!(matches!(
+            client("git@github.com:a/b.git"),
+            Ok(RemoteClient::Ssh(_))
+        ));
+        assert!(matches!(client("/tmp/repo"), Ok(RemoteClient::Local(_))));
+        assert!(client("ftp://github.com/a/b.git").is_err());
+    }
+
+    #[test]
+    fn test_parse_references() {
+        let head = "6b4e69962dbbc75e80d5263cc5c81571669db9bc";
+        let mut content = bytes::BytesMut::new();
+        ceres::protocol::smart::add_pkt_line_string(
+            &mut content,
+            format!("{} HEAD\0side-band-64k ofs-delta\n", head),
+        );
+        ceres::protocol::smart::add_pkt_line_string(
+            &mut content,
+            format!("{} refs/heads/main\n", head),
+        );
+        content.extend_from_slice(b"0000");
+        let refs = parse_references(&mut content.freeze(), UploadPack);
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[0]._ref, "HEAD");
+        assert_eq!(refs[1]._ref, "refs/heads/main");
+        assert_eq!(refs[1]._hash, head);
+    }
+}
@@ -0,0 +1,417 @@
+use std::io::{self, Error as IoError, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use ceres::protocol::ServiceType;
+use ceres::protocol::ServiceType::{ReceivePack, UploadPack};
+use futures_util::Stream;
+use mercury::errors::GitError;
+use russh::client::{self, Handle, Msg};
+use russh::keys::known_hosts::{check_known_hosts, learn_known_hosts};
+use russh::keys::{load_secret_key, HashAlg, PrivateKeyWithHashAlg, PublicKey};
+use russh::{Channel, ChannelMsg};
+use tokio::sync::Mutex;
+use url::Url;
+
+use super::https_client::{generate_upload_pack_content, DiscoveredReference};
+use super::{parse_references, ProtocolClient, UploadPackOptions};
+
+const DEFAULT_PORT: u16 = 22;
+const DEFAULT_USER: &str = "git";
+/// private keys tried in order after ssh-agent, same as the defaults of OpenSSH
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A Git protocol client that runs `git-upload-pack` / `git-receive-pack` on the server over SSH.
+/// The data is the same as [HttpsClient](super::https_client::HttpsClient) without the `# service` line,
+/// but the reference discovery and the request share one channel,
+/// so the channel opened by `discovery_reference` is kept for the next request.
+pub struct SshClient {
+    host: String,
+    port: u16,
+    user: String,
+    /// path of the repository on the server, e.g. `/project/mega.git` or `~/mega.git`
+    path: String,
+    connection: Mutex<Option<SshConnection>>,
+}
+
+/// An opened channel which has received the reference advertisement
+struct SshConnection {
+    service: ServiceType,
+    /// keep the session alive while using the channel
+    _session: Handle<SshHandler>,
+    channel: Channel<Msg>,
+}
+
+impl ProtocolClient for SshClient {
+    fn from_url(url: &Url) -> Self {
+        let path = url.path().trim_end_matches('/');
+        // `ssh://host/~/repo` & scp-like `host:~/repo` are relative to the home directory
+        let path = match path.strip_prefix("/~") {
+            Some(path) => format!("~{}", path),
+            None => path.to_string(),
+        };
+        let user = match url.username() {
+            "" => DEFAULT_USER.to_string(),
+            user => user.to_string(),
+        };
+        Self {
+            host: url.host_str().unwrap_or_default().to_string(),
+            port: url.port().unwrap_or(DEFAULT_PORT),
+            user,
+            path,
+            connection: Mutex::new(None),
+        }
+    }
+}
+
+/// Verify the host key with `~/.ssh/known_hosts` like the default `StrictHostKeyChecking=ask` of OpenSSH:
+/// an unknown host is added after the user confirms its fingerprint, refused if there is no terminal to ask
+struct SshHandler {
+    host: String,
+    port: u16,
+}
+
+impl client::Handler for SshHandler {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &PublicKey,
+    ) -> Result<bool, Self::Error> {
+        match check_known_hosts(&self.host, self.port, server_public_key) {
+            Ok(true) => Ok(true),
+            Ok(false) => {
+                if !confirm_host_key(&self.host, server_public_key) {
+                    eprintln!("fatal: host key verification failed for '{}'", self.host);
+                    return Ok(false);
+                }
+                eprintln!(
+                    "Warning: Permanently added '{}' to the list of known hosts.",
+                    self.host
+                );
+                if let Err(e) = learn_known_hosts(&self.host, self.port, server_public_key) {
+                    tracing::warn!("failed to save known host: {}", e);
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                eprintln!(
+                    "fatal: host key verification failed for '{}': {}",
+                    self.host, e
+                );
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Ask the user whether to trust the unknown key of `host`, `false` if stdin is not a terminal
+fn confirm_host_key(host: &str, key: &PublicKey) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprintln!("The authenticity of host '{}' can't be established.", host);
+    eprintln!(
+        "{} key fingerprint is {}.",
+        key.algorithm(),
+        key.fingerprint(HashAlg::Sha256)
+    );
+    eprint!("Are you sure you want to continue connecting (yes/no)? ");
+    loop {
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            return false;
+        }
+        match answer.trim() {
+            "yes" => return true,
+            "no" => return false,
+            _ => eprint!("Please type 'yes' or 'no': "),
+        }
+    }
+}
+
+/// Quote `arg` for the remote shell like git, in single quotes with `'` & `!` escaped
+fn sq_quote(arg: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in arg.chars() {
+        match c {
+            '\'' | '!' => {
+                quoted.push_str("'\\");
+                quoted.push(c);
+                quoted.push('\'');
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+impl SshClient {
+    /// Run `git-upload-pack` or `git-receive-pack` & read the reference advertisement,
+    /// the channel is kept for [Self::fetch_objects] or [Self::send_pack].
+    pub async fn discovery_reference(
+        &self,
+        service: ServiceType,
+    ) -> Result<Vec<DiscoveredReference>, GitError> {
+        let (connection, mut advertisement) = self.connect(service).await?;
+        self.connection.lock().await.replace(connection);
+        Ok(parse_references(&mut advertisement, service))
+    }
+
+    /// Send the `want` & `have` request to `git-upload-pack`, the response is the same as HTTPS.
+    pub async fn fetch_objects(
+        &self,
+        have: &Vec<String>,
+        want: &Vec<String>,
+        options: &UploadPackOptions,
+    ) -> Result<impl Stream<Item = Result<Bytes, IoError>>, IoError> {
+        let connection = self
+            .take_connection(UploadPack)
+            .await
+            .map_err(|e| IoError::other(e.to_string()))?;
+        let body = generate_upload_pack_content(have, want, options).await;
+        tracing::debug!("fetch_objects with body: {:?}", body);
+        connection
+            .channel
+            .data(&body[..])
+            .await
+            .map_err(|e| IoError::other(e.to_string()))?;
+
+        Ok(futures::stream::unfold(
+            Some(connection),
+            |connection| async move {
+                let mut connection = connection?;
+                match read_data(&mut connection.channel).await {
+                    Ok(Some(data)) => Some((Ok(data), Some(connection))),
+                    Ok(None) => None,
+                    Err(e) => Some((Err(IoError::other(e.to_string())), None)),
+                }
+            },
+        ))
+    }
+
+    /// Send the commands & pack to `git-receive-pack`, return the whole report.
+    pub async fn send_pack(&self, data: Bytes) -> Result<Bytes, GitError> {
+        let mut connection = self.take_connection(ReceivePack).await?;
+        let network_error = |e: russh::Error| GitError::NetworkError(e.to_string());
+        connection
+            .channel
+            .data(&data[..])
+            .await
+            .map_err(network_error)?;
+        // the server may wait for EOF to process the pack, e.g. mono
+        connection.channel.eof().await.map_err(network_error)?;
+
+        let mut report = BytesMut::new();
+        while let Some(data) = read_data(&mut connection.channel).await? {
+            report.extend_from_slice(&data);
+        }
+        Ok(report.freeze())
+    }
+
+    /// Use the channel of the last `discovery_reference`, or open a new one if the service is different
+    async fn take_connection(&self, service: ServiceType) -> Result<SshConnection, GitError> {
+        let connection = self.connection.lock().await.take();
+        match connection {
+            Some(connection) if connection.service == service => Ok(connection),
+            _ => Ok(self.connect(service).await?.0),
+        }
+    }
+
+    /// Connect, authenticate & run the service, return the channel & the reference advertisement
+    async fn connect(&self, service: ServiceType) -> Result<(SshConnection, Bytes), GitError> {
+        let network_error = |e: russh::Error| GitError::NetworkError(e.to_string());
+        let config = Arc::new(client::Config::default());
+        let handler = SshHandler {
+            host: self.host.clone(),
+            port: self.port,
+        };
+        let mut session = client::connect(config, (self.host.as_str(), self.port), handler)
+            .await
+            .map_err(|e| {
+                GitError::NetworkError(format!(
+                    "could not connect to {}:{}: {}",
+                    self.host, self.port, e
+                ))
+            })?;
+        if !self.authenticate(&mut session).await? {
+            return Err(GitError::UnAuthorized(format!(
+                "{}@{}: Permission denied (publickey)",
+                self.user, self.host
+            )));
+        }
+
+        let mut channel = session
+            .channel_open_session()
+            .await
+            .map_err(network_error)?;
+        let command = format!("{} {}", service, sq_quote(&self.path));
+        tracing::debug!("ssh exec: {}", command);
+        channel.exec(true, command).await.map_err(network_error)?;
+
+        // the advertisement ends with a flush-pkt, then the server waits for the request
+        let mut advertisement = BytesMut::new();
+        while !is_flushed(&advertisement) {
+            match read_data(&mut channel).await? {
+                Some(data) => advertisement.extend_from_slice(&data),
+                None => {
+                    return Err(GitError::NetworkError(format!(
+                        "the remote end hung up, could not read from '{}'",
+                        self.path
+                    )))
+                }
+            }
+        }
+        tracing::debug!("{:?}", advertisement);
+        let connection = SshConnection {
+            service,
+            _session: session,
+            channel,
+        };
+        Ok((connection, advertisement.freeze()))
+    }
+
+    /// Authenticate with the keys of ssh-agent, then the default private keys in `~/.ssh`
+    async fn authenticate(&self, session: &mut Handle<SshHandler>) -> Result<bool, GitError> {
+        let network_error = |e: russh::Error| GitError::NetworkError(e.to_string());
+        let hash_alg = session
+            .best_supported_rsa_hash()
+            .await
+            .map_err(network_error)?
+            .flatten();
+
+        #[cfg(unix)]
+        if let Ok(mut agent) = russh::keys::agent::client::AgentClient::connect_env().await {
+            let identities = agent.request_identities().await.unwrap_or_default();
+            for key in identities {
+                match session
+                    .authenticate_publickey_with(&self.user, key, hash_alg, &mut agent)
+                    .await
+                {
+                    Ok(result) if result.success() => return Ok(true),
+                    Ok(_) => continue,
+                    Err(e) => tracing::debug!("ssh-agent authentication failed: {:?}", e),
+                }
+            }
+        }
+
+        for path in default_key_paths() {
+            let key = match load_key(&path) {
+                Some(key) => key,
+                None => continue,
+            };
+            let key = PrivateKeyWithHashAlg::new(Arc::new(key), hash_alg);
+            let result = session
+                .authenticate_publickey(&self.user, key)
+                .await
+                .map_err(network_error)?;
+            if result.success() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Read the next data of stdout, messages of stderr are printed. `None` if the channel is closed.
+async fn read_data(channel: &mut Channel<Msg>) -> Result<Option<Bytes>, GitError> {
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { data } => return Ok(Some(Bytes::copy_from_slice(&data))),
+            ChannelMsg::ExtendedData { data, ext: 1 } => {
+                eprint!("remote: {}", String::from_utf8_lossy(&data));
+            }
+            ChannelMsg::ExitStatus { exit_status } if exit_status != 0 => {
+                return Err(GitError::NetworkError(format!(
+                    "the remote command exited with status {}",
+                    exit_status
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Check if `data` consists of complete pkt-lines & ends with a flush-pkt
+fn is_flushed(data: &[u8]) -> bool {
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        let len = std::str::from_utf8(&data[pos..pos + 4])
+            .ok()
+            .and_then(|len| usize::from_str_radix(len, 16).ok());
+        match len {
+            Some(0) => return pos + 4 == data.len(),
+            Some(len) if len >= 4 => pos += len,
+            _ => return false, // invalid length, wait for the server to hang up
+        }
+    }
+    false
+}
+
+fn default_key_paths() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    match home {
+        Some(home) => DEFAULT_KEYS
+            .iter()
+            .map(|name| PathBuf::from(&home).join(".ssh").join(name))
+            .filter(|path| path.exists())
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Load a private key, ask for the passphrase if it's encrypted
+fn load_key(path: &Path) -> Option<russh::keys::PrivateKey> {
+    match load_secret_key(path, None) {
+        Ok(key) => Some(key),
+        Err(russh::keys::Error::KeyIsEncrypted) => {
+            let prompt = format!("Enter passphrase for key '{}': ", path.display());
+            let passphrase = rpassword::prompt_password(prompt).ok()?;
+            load_secret_key(path, Some(&passphrase))
+                .inspect_err(|e| eprintln!("warning: could not load '{}': {}", path.display(), e))
+                .ok()
+        }
+        Err(e) => {
+            tracing::warn!("could not load '{}': {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_url() {
+        let client =
+            SshClient::from_url(&Url::parse("ssh://git@localhost:2222/project/mega.git/").unwrap());
+        assert_eq!(client.host, "localhost");
+        assert_eq!(client.port, 2222);
+        assert_eq!(client.user, "git");
+        assert_eq!(client.path, "/project/mega.git");
+
+        let client = SshClient::from_url(&Url::parse("ssh://admin@github.com/~/mega").unwrap());
+        assert_eq!(client.port, DEFAULT_PORT);
+        assert_eq!(client.user, "admin");
+        assert_eq!(client.path, "~/mega");
+    }
+
+    #[test]
+    fn test_sq_quote() {
+        assert_eq!(sq_quote("/project/mega.git"), "'/project/mega.git'");
+        assert_eq!(sq_quote("~/it's!.git"), "'~/it'\\''s'\\!'.git'");
+    }
+
+    #[test]
+    fn test_is_flushed() {
+        assert!(is_flushed(b"0000"));
+        assert!(is_flushed(b"0009hello0000"));
+        assert!(!is_flushed(b"0009hello"));
+        assert!(!is_flushed(b"0009hel"));
+        assert!(!is_flushed(b""));
+    }
+}
@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DbErr, EntityTrait, ModelTrait, QueryFilter, QueryOrder,
+};
+
+use mercury::hash::SHA1;
+
+use crate::internal::config::Config;
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::head::Head;
+use crate::internal::model::reflog;
+use crate::utils::util;
+
+pub const HEAD: &str = "HEAD";
+
+/// The reflog of HEAD of the current worktree, each worktree has its own HEAD:
+/// `HEAD` in the main worktree, `worktrees/<name>/HEAD` in a linked worktree like git
+pub fn head_ref() -> String {
+    if util::is_linked_worktree() {
+        let storage = util::worktree_storage_path();
+        worktree_head_ref(storage.file_name().unwrap().to_str().unwrap())
+    } else {
+        HEAD.to_string()
+    }
+}
+
+/// The reflog of HEAD of the linked worktree `name`
+pub fn worktree_head_ref(name: &str) -> String {
+    format!("worktrees/{}/HEAD", name)
+}
+
+/// The full reference name of a local branch, `refs/heads/<branch>`
+pub fn branch_ref(branch: &str) -> String {
+    format!("refs/heads/{}", branch)
+}
+
+/// Parse the reflog selector `<ref>@{n}` to `(<ref>, n)`, `<ref>` may be empty
+pub fn parse_selector(rev: &str) -> Option<(&str, usize)> {
+    let (name, rest) = rev.split_once("@{")?;
+    let n = rest.strip_suffix('}')?.parse().ok()?;
+    Some((name, n))
+}
+
+/// One update of a reference, like a line in `.git/logs/<ref>`
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    pub id: i64,
+    pub ref_name: String,
+    /// `SHA1::default()` (all zero) if the reference is created
+    pub old_oid: SHA1,
+    pub new_oid: SHA1,
+    pub committer: String,
+    pub timestamp: i64,
+    pub message: String,
+}
+
+impl From<reflog::Model> for ReflogEntry {
+    fn from(model: reflog::Model) -> Self {
+        ReflogEntry {
+            id: model.id,
+            ref_name: model.ref_name,
+            old_oid: SHA1::from_str(&model.old_oid).unwrap(),
+            new_oid: SHA1::from_str(&model.new_oid).unwrap(),
+            committer: model.committer,
+            timestamp: model.timestamp,
+            message: model.message,
+        }
+    }
+}
+
+pub struct Reflog;
+
+impl Reflog {
+    /// Record an update of `ref_name` from `old_oid` to `new_oid`
+    pub async fn append(
+        ref_name: &str,
+        old_oid: SHA1,
+        new_oid: SHA1,
+        message: &str,
+    ) -> Result<(), DbErr> {
+        let db_conn = get_db_conn_instance().await;
+        let (name, email) = Config::user_identity().await;
+        reflog::ActiveModel {
+            ref_name: Set(ref_name.to_owned()),
+            old_oid: Set(old_oid.to_string()),
+            new_oid: Set(new_oid.to_string()),
+            committer: Set(format!("{} <{}>", name, email)),
+            timestamp: Set(chrono::Utc::now().timestamp()),
+            message: Set(message.to_owned()),
+            ..Default::default()
+        }
+        .insert(db_conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Like [Reflog::append], but a failure is only a warning, as the reference is already updated
+    pub async fn record(ref_name: &str, old_oid: SHA1, new_oid: SHA1, message: &str) {
+        if let Err(e) = Self::append(ref_name, old_oid, new_oid, message).await {
+            eprintln!(
+                "warning: unable to append to the reflog of {}: {}",
+                ref_name, e
+            );
+        }
+    }
+
+    /// Record HEAD moving from `old` to `new`, the current branch is recorded too as it moves along.
+    /// Should be called after HEAD is updated.
+    pub async fn log_head_update(old: Option<SHA1>, new: SHA1, message: &str) {
+        let old = old.unwrap_or_default();
+        Self::record(&head_ref(), old, new, message).await;
+        if let Head::Branch(name) = Head::current().await {
+            Self::record(&branch_ref(&name), old, new, message).await;
+        }
+    }
+
+    /// Record HEAD switching from `from` to `to`, only HEAD is recorded because no branch moves
+    pub async fn log_checkout(from: &Head, old: Option<SHA1>, to: &Head, new: SHA1) {
+        let describe = |head: &Head| match head {
+            Head::Branch(name) => name.clone(),
+            Head::Detached(commit) => commit.to_string(),
+        };
+        let message = format!(
+            "checkout: moving from {} to {}",
+            describe(from),
+            describe(to)
+        );
+        Self::record(&head_ref(), old.unwrap_or_default(), new, &message).await;
+    }
+
+    /// List all entries of `ref_name`, the newest first, so `ref@{n}` is the n-th entry
+    pub async fn list(ref_name: &str) -> Vec<ReflogEntry> {
+        let db_conn = get_db_conn_instance().await;
+        reflog::Entity::find()
+            .filter(reflog::Column::RefName.eq(ref_name))
+            .order_by_desc(reflog::Column::Id)
+            .all(db_conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(ReflogEntry::from)
+            .collect()
+    }
+
+    /// List the entries of all references, e.g. to keep the objects they refer to from `prune`
+    pub async fn list_all() -> Vec<ReflogEntry> {
+        let db_conn = get_db_conn_instance().await;
+        reflog::Entity::find()
+            .all(db_conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(ReflogEntry::from)
+            .collect()
+    }
+
+    /// Get the entry `ref_name@{n}`
+    pub async fn find(ref_name: &str, n: usize) -> Option<ReflogEntry> {
+        Self::list(ref_name).await.into_iter().nth(n)
+    }
+
+    /// Delete an entry by its id
+    pub async fn delete(id: i64) {
+        let db_conn = get_db_conn_instance().await;
+        let entry = reflog::Entity::find_by_id(id).one(db_conn).await.unwrap();
+        if let Some(entry) = entry {
+            entry.delete(db_conn).await.unwrap();
+        }
+    }
+
+    /// Delete all entries of `ref_name`
+    pub async fn clear(ref_name: &str) {
+        let db_conn = get_db_conn_instance().await;
+        reflog::Entity::delete_many()
+            .filter(reflog::Column::RefName.eq(ref_name))
+            .exec(db_conn)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+//! Resolve revisions like git, see `gitrevisions(7)`:
+//! - `HEAD`, `@`, branch, remote branch, tag, full ref name (`refs/heads/<branch>` ...)
+//! - abbreviated hash, at least [MIN_ABBREV] hex characters
+//! - `<ref>@{n}`, `<branch>@{upstream}` (`@{u}`)
+//! - `<rev>~n`, `<rev>^n`, `<rev>^{<type>}`, `<rev>^{}`
+//! - ranges `A..B` & `A...B`, an omitted end means `HEAD`
+
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::types::ObjectType;
+
+use crate::internal::branch::Branch;
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::reflog::{self, Reflog};
+use crate::internal::tag::Tag;
+use crate::utils::object_ext::CommitExt;
+use crate::utils::util;
+
+/// The minimum length of an abbreviated hash
+pub const MIN_ABBREV: usize = 4;
+
+/// A single revision or a range of commits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevRange {
+    Single(SHA1),
+    /// `A..B`: commits reachable from `include` (B) but not from `exclude` (A)
+    Range {
+        exclude: SHA1,
+        include: SHA1,
+    },
+    /// `A...B`: commits reachable from either side but not from both
+    Symmetric(SHA1, SHA1),
+}
+
+/// Operators following the base revision
+#[derive(Debug, Clone, PartialEq)]
+enum Suffix {
+    /// `~n`, the n-th first-parent ancestor
+    Ancestor(usize),
+    /// `^n`, the n-th parent, `^0` is the commit itself
+    Parent(usize),
+    /// `^{<type>}`, empty type means peeling tags
+    Peel(String),
+}
+
+/// Split `rev` into the base revision & suffix operators, e.g. `HEAD~2^{tree}` -> `HEAD`, [~2, ^{tree}]
+fn split_rev(rev: &str) -> Result<(&str, Vec<Suffix>), String> {
+    let invalid = || format!("invalid revision '{}'", rev);
+    // `@{...}` is part of the base, the suffixes start after it
+    let start = match rev.rfind("@{") {
+        Some(i) => i + rev[i..].find('}').ok_or_else(invalid)? + 1,
+        None => 0,
+    };
+    let base_end = rev[start..]
+        .find(['~', '^'])
+        .map_or(rev.len(), |i| start + i);
+    let (base, mut rest) = rev.split_at(base_end);
+
+    let mut suffixes = vec![];
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[1..];
+        if op == '^' && rest.starts_with('{') {
+            let end = rest.find('}').ok_or_else(invalid)?;
+            suffixes.push(Suffix::Peel(rest[1..end].to_string()));
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n = match digits {
+            0 => 1, // `HEAD~` is `HEAD~1`
+            _ => rest[..digits].parse().map_err(|_| invalid())?,
+        };
+        rest = &rest[digits..];
+        suffixes.push(match op {
+            '~' => Suffix::Ancestor(n),
+            '^' => Suffix::Parent(n),
+            _ => return Err(invalid()),
+        });
+    }
+    Ok((base, suffixes))
+}
+
+/// Resolve `rev` to an object, tags are not peeled unless `^{}` or `^{<type>}` is given
+pub async fn resolve(rev: &str) -> Result<SHA1, String> {
+    let (base, suffixes) = split_rev(rev)?;
+    let mut object = resolve_base(base).await?;
+    for suffix in suffixes {
+        object = match suffix {
+            Suffix::Ancestor(n) => {
+                let mut commit = peel_to(object, ObjectType::Commit, rev)?;
+                for _ in 0..n {
+                    commit = *Commit::load(&commit)
+                        .parent_commit_ids
+                        .first()
+                        .ok_or_else(|| format!("'{}': no such ancestor", rev))?;
+                }
+                commit
+            }
+            Suffix::Parent(0) => peel_to(object, ObjectType::Commit, rev)?,
+            Suffix::Parent(n) => {
+                let commit = peel_to(object, ObjectType::Commit, rev)?;
+                *Commit::load(&commit)
+                    .parent_commit_ids
+                    .get(n - 1)
+                    .ok_or_else(|| format!("'{}': commit has no parent {}", rev, n))?
+            }
+            Suffix::Peel(kind) => match kind.as_str() {
+                "" => Tag::peel(&object),
+                "object" => object,
+                kind => match ObjectType::from_string(kind) {
+                    Ok(kind) => peel_to(object, kind, rev)?,
+                    Err(_) => return Err(format!("invalid object type '{}' in '{}'", kind, rev)),
+                },
+            },
+        };
+    }
+    Ok(object)
+}
+
+/// Resolve `rev` to a commit, tags are peeled
+pub async fn resolve_commit(rev: &str) -> Result<SHA1, String> {
+    let object = resolve(rev).await?;
+    peel_to(object, ObjectType::Commit, rev)
+}
+
+/// Resolve `rev` to a tree, commits & tags are peeled
+pub async fn resolve_tree(rev: &str) -> Result<SHA1, String> {
+    let object = resolve(rev).await?;
+    peel_to(object, ObjectType::Tree, rev)
+}
+
+/// Resolve a single revision or a range `A..B` / `A...B`, all ends are peeled to commits
+pub async fn resolve_range(spec: &str) -> Result<RevRange, String> {
+    let end = |rev: &str| match rev {
+        "" => "HEAD".to_string(),
+        rev => rev.to_string(),
+    };
+    if let Some((left, right)) = spec.split_once("...") {
+        let left = resolve_commit(&end(left)).await?;
+        let right = resolve_commit(&end(right)).await?;
+        return Ok(RevRange::Symmetric(left, right));
+    }
+    if let Some((exclude, include)) = spec.split_once("..") {
+        let exclude = resolve_commit(&end(exclude)).await?;
+        let include = resolve_commit(&end(include)).await?;
+        return Ok(RevRange::Range { exclude, include });
+    }
+    Ok(RevRange::Single(resolve_commit(spec).await?))
+}
+
+/// Resolve the revision without suffixes
+async fn resolve_base(name: &str) -> Result<SHA1, String> {
+    if name.is_empty() || name == "@" || name == reflog::HEAD {
+        return Head::current_commit()
+            .await
+            .ok_or_else(|| format!("ambiguous argument '{}': HEAD has no commits yet", name));
+    }
+    if let Some((branch, selector)) = name.split_once("@{") {
+        let selector = selector
+            .strip_suffix('}')
+            .ok_or_else(|| format!("invalid revision '{}'", name))?;
+        return match selector {
+            "upstream" | "u" => upstream(branch).await,
+            _ => reflog_entry(name).await,
+        };
+    }
+    if let Some(branch) = name.strip_prefix("refs/heads/") {
+        return Branch::find_branch(branch, None)
+            .await
+            .map(|branch| branch.commit)
+            .ok_or_else(|| format!("unknown revision '{}'", name));
+    }
+    if let Some(tag) = name.strip_prefix("refs/tags/") {
+        return Tag::find_tag(tag)
+            .await
+            .map(|tag| tag.object)
+            .ok_or_else(|| format!("unknown revision '{}'", name));
+    }
+
+    // branches first, then tags, then hashes
+    let short_name = name.strip_prefix("refs/remotes/").unwrap_or(name);
+    let branches = Branch::search_branch(short_name).await;
+    if branches.len() > 1 {
+        // TODO: git have a priority list of branches to use, continue with ambiguity, we didn't implement it yet
+        return Err(format!("ambiguous branch name '{}'", name));
+    }
+    if let Some(branch) = branches.first() {
+        return Ok(branch.commit);
+    }
+    if let Some(tag) = Tag::find_tag(name).await {
+        return Ok(tag.object);
+    }
+    if name.len() >= MIN_ABBREV && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        let objects = util::objects_storage().search(&name.to_lowercase());
+        match objects.len() {
+            0 => {}
+            1 => return Ok(objects[0]),
+            _ => return Err(format!("short SHA1 {} is ambiguous", name)),
+        }
+    }
+    Err(format!("unknown revision '{}'", name))
+}
+
+/// Resolve `<ref>@{n}` by the reflog, an empty `<ref>` means the current branch (or HEAD if detached)
+async fn reflog_entry(rev: &str) -> Result<SHA1, String> {
+    let (name, n) =
+        reflog::parse_selector(rev).ok_or_else(|| format!("invalid revision '{}'", rev))?;
+    let ref_name = match name {
+        "" => match Head::current().await {
+            Head::Branch(branch) => reflog::branch_ref(&branch),
+            Head::Detached(_) => reflog::head_ref(),
+        },
+        reflog::HEAD => reflog::head_ref(),
+        "stash" => "refs/stash".to_string(),
+        name if name.starts_with("refs/") => name.to_string(),
+        name => reflog::branch_ref(name),
+    };
+    match Reflog::find(&ref_name, n).await {
+        Some(entry) => Ok(entry.new_oid),
+        None => {
+            let len = Reflog::list(&ref_name).await.len();
+            Err(format!("log for '{}' only has {} entries", name, len))
+        }
+    }
+}
+
+/// The remote branch that `branch` tracks, an empty `branch` means the current branch
+async fn upstream(branch: &str) -> Result<SHA1, String> {
+    let branch = match branch {
+        "" | reflog::HEAD => match Head::current().await {
+            Head::Branch(name) => name,
+            Head::Detached(_) => return Err("HEAD does not point to a branch".to_string()),
+        },
+        name => name.to_string(),
+    };
+    let config = Config::branch_config(&branch)
+        .await
+        .ok_or_else(|| format!("no upstream configured for branch '{}'", branch))?;
+    Branch::find_branch(&config.merge, Some(&config.remote))
+        .await
+        .map(|branch| branch.commit)
+        .ok_or_else(|| {
+            format!(
+                "upstream branch '{}/{}' not found",
+                config.remote, config.merge
+            )
+        })
+}
+
+/// Peel `object` until it is of type `kind`: tags are followed, commits are peeled to trees
+fn peel_to(object: SHA1, kind: ObjectType, rev: &str) -> Result<SHA1, String> {
+    let storage = util::objects_storage();
+    let object_type = |object: &SHA1| {
+        storage
+            .get_object_type(object)
+            .map_err(|_| format!("object {} not found", object))
+    };
+    if object_type(&object)? == kind {
+        return Ok(object); // `^{tag}` is not peeled
+    }
+    let object = Tag::peel(&object);
+    match object_type(&object)? {
+        found if found == kind => Ok(object),
+        ObjectType::Commit if kind == ObjectType::Tree => Ok(Commit::load(&object).tree_id),
+        found => Err(format!(
+            "'{}': expected {} type, but the object dereferences to {} type",
+            rev, kind, found
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::commit;
+    use crate::utils::{path, test};
+    use mercury::internal::index::Index;
+    use serial_test::serial;
+
+    #[test]
+    fn test_split_rev() {
+        assert_eq!(split_rev("HEAD").unwrap(), ("HEAD", vec![]));
+        assert_eq!(
+            split_rev("HEAD~3").unwrap(),
+            ("HEAD", vec![Suffix::Ancestor(3)])
+        );
+        assert_eq!(
+            split_rev("main^2~").unwrap(),
+            ("main", vec![Suffix::Parent(2), Suffix::Ancestor(1)])
+        );
+        assert_eq!(
+            split_rev("v1.2^{tree}").unwrap(),
+            ("v1.2", vec![Suffix::Peel("tree".to_string())])
+        );
+        assert_eq!(
+            split_rev("master@{1}^^{}").unwrap(),
+            (
+                "master@{1}",
+                vec![Suffix::Parent(1), Suffix::Peel(String::new())]
+            )
+        );
+        assert_eq!(
+            split_rev("@{u}~").unwrap(),
+            ("@{u}", vec![Suffix::Ancestor(1)])
+        );
+        assert!(split_rev("HEAD^{tree").is_err());
+        assert!(split_rev("HEAD~2x").is_err());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_resolve() {
+        test::setup_with_new_libra().await;
+        let first = test::commit_file("a.txt", "first", "first").await;
+        let second = test::commit_file("b.txt", "second", "second").await;
+        let third = test::commit_file("c.txt", "third", "third").await;
+        // a merge commit with parents [third, first]
+        let index = Index::load(path::index()).unwrap();
+        let storage = util::objects_storage();
+        let merge =
+            commit::commit_index(&index, &storage, "merge", vec![third, first], None, false)
+                .await
+                .unwrap();
+        Tag::update_tag("v1", &second.to_string()).await;
+
+        assert_eq!(resolve_commit("HEAD").await.unwrap(), merge.id);
+        assert_eq!(resolve_commit("@").await.unwrap(), merge.id);
+        assert_eq!(resolve_commit("master").await.unwrap(), merge.id);
+        assert_eq!(resolve_commit("refs/heads/master").await.unwrap(), merge.id);
+        assert_eq!(resolve_commit("HEAD~1").await.unwrap(), third);
+        assert_eq!(resolve_commit("HEAD~3").await.unwrap(), first);
+        assert_eq!(resolve_commit("HEAD^").await.unwrap(), third);
+        assert_eq!(resolve_commit("HEAD^2").await.unwrap(), first);
+        assert_eq!(resolve_commit("HEAD^0").await.unwrap(), merge.id);
+        assert_eq!(resolve_commit("master^^").await.unwrap(), second);
+        assert_eq!(resolve_commit("v1").await.unwrap(), second);
+        assert_eq!(resolve_commit("v1~").await.unwrap(), first);
+        assert_eq!(
+            resolve("v1^{tree}").await.unwrap(),
+            Commit::load(&second).tree_id
+        );
+        assert_eq!(
+            resolve_commit(&first.to_string()[..7]).await.unwrap(),
+            first
+        );
+        assert_eq!(resolve_commit("HEAD@{1}").await.unwrap(), third);
+        assert_eq!(resolve_commit("master@{3}").await.unwrap(), first);
+        assert!(resolve_commit("HEAD~4").await.is_err());
+        assert!(resolve_commit("HEAD^3").await.is_err());
+        assert!(resolve_commit("HEAD^{tree}").await.is_err());
+        assert!(resolve_commit("@{u}").await.is_err()); // no upstream
+        assert!(resolve_commit("nothing").await.is_err());
+
+        assert_eq!(
+            resolve_range("HEAD~2..").await.unwrap(),
+            RevRange::Range {
+                exclude: second,
+                include: merge.id
+            }
+        );
+        assert_eq!(
+            resolve_range("v1...HEAD^").await.unwrap(),
+            RevRange::Symmetric(second, third)
+        );
+        assert_eq!(
+            resolve_range("HEAD^2").await.unwrap(),
+            RevRange::Single(first)
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Shallow clone support, the boundary commits are listed in `.libra/shallow` like `.git/shallow`.
+//! Their parents are missing from the repository, so they are treated as root commits when loaded.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use mercury::hash::SHA1;
+use mercury::internal::object::commit::Commit;
+use once_cell::sync::Lazy;
+
+use crate::utils::path;
+
+/// The shallow commits used by [graft], `.libra/shallow` is read once per repository of the process
+static GRAFTS: Lazy<Mutex<HashMap<PathBuf, BTreeSet<SHA1>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The shallow commits of the repository, empty if it is not a shallow clone
+pub fn read() -> BTreeSet<SHA1> {
+    match fs::read_to_string(path::shallow()) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| SHA1::from_str(line.trim()).ok())
+            .collect(),
+        Err(_) => BTreeSet::new(),
+    }
+}
+
+pub fn is_shallow_repo() -> bool {
+    path::shallow().exists()
+}
+
+/// Apply the shallow-update of a fetch, the file is removed when no shallow commit is left
+pub fn update(shallow: &[SHA1], unshallow: &[SHA1]) {
+    let mut commits = read();
+    commits.extend(shallow);
+    for commit in unshallow {
+        commits.remove(commit);
+    }
+    if commits.is_empty() {
+        let _ = fs::remove_file(path::shallow());
+    } else {
+        let content: String = commits.iter().map(|c| format!("{}\n", c)).collect();
+        fs::write(path::shallow(), content).unwrap();
+    }
+    GRAFTS.lock().unwrap().insert(path::shallow(), commits);
+}
+
+/// Drop the shallow commits read before, e.g. a new repository replaces a removed shallow one
+pub fn forget() {
+    GRAFTS.lock().unwrap().clear();
+}
+
+/// Cut off the parents of a shallow commit, like the grafts of git
+pub fn graft(commit: &mut Commit) {
+    let mut grafts = GRAFTS.lock().unwrap();
+    if grafts
+        .entry(path::shallow())
+        .or_insert_with(read)
+        .contains(&commit.id)
+    {
+        commit.parent_commit_ids.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_update_shallow() {
+        test::setup_with_new_libra().await;
+
+        let a = SHA1::from_str("6b4e69962dbbc75e80d5263cc5c81571669db9bc").unwrap();
+        let b = SHA1::from_str("81a162e7b725bbad2adfe01879fd57e0119406b9").unwrap();
+        assert!(!is_shallow_repo());
+
+        update(&[a, b], &[]);
+        assert!(is_shallow_repo());
+        assert_eq!(read(), BTreeSet::from([a, b]));
+
+        update(&[], &[a]);
+        assert_eq!(read(), BTreeSet::from([b]));
+
+        update(&[], &[b]);
+        assert!(!is_shallow_repo());
+    }
+}
@@ -0,0 +1,208 @@
+//! Sign & verify commits and tags with PGP, compatible with the signatures of `git` & `gpg`:
+//! - a commit carries the signature in the `gpgsig` header, the signed data is the commit without it
+//! - a tag carries the signature at the end of its message, the signed data is the tag before it
+//!
+//! The key is read from the armored key file at `user.signingkey` if it's configured,
+//! otherwise the key pair managed by vault is used, which is generated at the first signing.
+
+use std::fs;
+
+use common::utils::parse_commit_msg;
+use mercury::internal::object::commit::Commit;
+use mercury::internal::object::tag::Tag;
+use mercury::internal::object::ObjectTrait;
+use vault::pgp::{self, KeyType, SignedSecretKey};
+
+use crate::internal::config::Config;
+
+const SIGNATURE_BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const GPGSIG_HEADER: &str = "gpgsig ";
+
+/// Sign `data` with the configured key, return the armored detached signature
+pub async fn sign(data: &[u8]) -> Result<String, String> {
+    match Config::get("user", None, "signingkey").await {
+        Some(path) => {
+            let armored = fs::read_to_string(&path)
+                .map_err(|e| format!("could not read signing key '{}': {}", path, e))?;
+            let key = pgp::parse_sec_key(&armored).map_err(|e| e.to_string())?;
+            // try without passphrase first, the key file may be unencrypted
+            pgp::sign_detached(&key, None, data).or_else(|_| {
+                let prompt = format!("Enter passphrase for key {}: ", path);
+                let passwd = rpassword::prompt_password(prompt).map_err(|e| e.to_string())?;
+                pgp::sign_detached(&key, Some(passwd), data).map_err(|e| e.to_string())
+            })
+        }
+        None => {
+            let key = vault_sec_key().await;
+            pgp::sign_detached(&key, None, data).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Verify the armored detached signature of `data`, return the fingerprint of the key if it's good
+pub async fn verify(data: &[u8], signature: &str) -> Result<String, String> {
+    match Config::get("user", None, "signingkey").await {
+        Some(path) => {
+            let armored = fs::read_to_string(&path)
+                .map_err(|e| format!("could not read signing key '{}': {}", path, e))?;
+            // the secret key file used to sign is accepted as well
+            if armored.contains("PRIVATE KEY BLOCK") {
+                let key = pgp::parse_sec_key(&armored).map_err(|e| e.to_string())?;
+                pgp::verify_detached(&key, data, signature).map_err(|e| e.to_string())?;
+                Ok(pgp::fingerprint(&key))
+            } else {
+                let key = pgp::parse_pub_key(&armored).map_err(|e| e.to_string())?;
+                pgp::verify_detached(&key, data, signature).map_err(|e| e.to_string())?;
+                Ok(pgp::fingerprint(&key))
+            }
+        }
+        None => {
+            let key = pgp::load_pub_key()
+                .await
+                .ok_or("no public key found in vault, set `user.signingkey` to verify")?;
+            pgp::verify_detached(&key, data, signature).map_err(|e| e.to_string())?;
+            Ok(pgp::fingerprint(&key))
+        }
+    }
+}
+
+/// The secret key in vault, a new key pair is generated with the user info if there is none
+async fn vault_sec_key() -> SignedSecretKey {
+    if let Some(key) = pgp::load_sec_key().await {
+        return key;
+    }
+    let name = Config::get("user", None, "name").await;
+    let email = Config::get("user", None, "email").await;
+    let uid = match (name, email) {
+        (Some(name), Some(email)) => format!("{} <{}>", name, email),
+        (Some(name), None) => name,
+        (None, Some(email)) => format!("<{}>", email),
+        (None, None) => "libra".to_string(),
+    };
+    println!(
+        "PGP key not found in vault, generating a new one for '{}'...",
+        uid
+    );
+    let (pub_key, sec_key) =
+        pgp::gen_pgp_keypair(pgp::params(KeyType::Rsa(2048), None, &uid), None);
+    pgp::save_keys(pub_key, sec_key.clone()).await;
+    sec_key
+}
+
+/// Format an armored signature to the `gpgsig` header of a commit, the continuation lines start with a space
+pub fn to_gpgsig_header(signature: &str) -> String {
+    let lines: Vec<&str> = signature.trim_end().lines().collect();
+    format!("{}{}", GPGSIG_HEADER, lines.join("\n "))
+}
+
+/// Split a commit to the signed data & the armored signature, `None` if it's not signed
+pub fn split_commit_signature(commit: &Commit) -> Option<(Vec<u8>, String)> {
+    let (_, header) = parse_commit_msg(&commit.message);
+    let header = header?;
+    let signature = header
+        .strip_prefix(GPGSIG_HEADER)?
+        .lines()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !signature.starts_with(SIGNATURE_BEGIN) {
+        return None; // e.g. SSH signature, not supported
+    }
+    // the header is at the beginning of `message`, followed by the LF ending it
+    let rest = &commit.message[header.len()..];
+    let unsigned = Commit {
+        message: rest.strip_prefix('\n').unwrap_or(rest).to_string(),
+        ..commit.clone()
+    };
+    Some((unsigned.to_data().ok()?, signature + "\n"))
+}
+
+/// Split a tag object to the signed data & the armored signature, `None` if it's not signed
+pub fn split_tag_signature(tag: &Tag) -> Option<(Vec<u8>, String)> {
+    let begin = tag.message.find(SIGNATURE_BEGIN)?;
+    let unsigned = Tag {
+        message: tag.message[..begin].to_string(),
+        ..tag.clone()
+    };
+    Some((unsigned.to_data().ok()?, tag.message[begin..].to_string()))
+}
+
+/// Verify the signature of a commit, `None` if it's not signed
+pub async fn verify_commit(commit: &Commit) -> Option<Result<String, String>> {
+    let (data, signature) = split_commit_signature(commit)?;
+    Some(verify(&data, &signature).await)
+}
+
+/// Verify the signature of a tag object, `None` if it's not signed
+pub async fn verify_tag(tag: &Tag) -> Option<Result<String, String>> {
+    let (data, signature) = split_tag_signature(tag)?;
+    Some(verify(&data, &signature).await)
+}
+
+/// Describe the result of verification like `gpg`, for `verify-commit` & `log --show-signature`
+pub fn describe(result: &Result<String, String>) -> String {
+    match result {
+        Ok(fingerprint) => format!("gpg: Good signature, key fingerprint {}", fingerprint),
+        Err(e) => format!("gpg: BAD signature: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::utils::format_commit_msg;
+    use mercury::hash::SHA1;
+    use mercury::internal::object::signature::{Signature, SignatureType};
+    use mercury::internal::object::types::ObjectType;
+
+    const SIGNATURE: &str =
+        "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n=abcd\n-----END PGP SIGNATURE-----\n";
+
+    #[test]
+    fn test_split_commit_signature() {
+        let unsigned =
+            Commit::from_tree_id(SHA1::default(), vec![], &format_commit_msg("msg", None));
+        let header = to_gpgsig_header(SIGNATURE);
+        assert!(header.starts_with("gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEz"));
+
+        let signed = Commit::new(
+            unsigned.author.clone(),
+            unsigned.committer.clone(),
+            unsigned.tree_id,
+            vec![],
+            &format_commit_msg("msg", Some(&header)),
+        );
+        let (data, signature) = split_commit_signature(&signed).unwrap();
+        assert_eq!(data, unsigned.to_data().unwrap());
+        assert_eq!(signature, SIGNATURE);
+        assert!(split_commit_signature(&unsigned).is_none());
+    }
+
+    #[test]
+    fn test_split_tag_signature() {
+        let tagger = Signature::new(
+            SignatureType::Tagger,
+            "mega".into(),
+            "admin@mega.org".into(),
+        );
+        let message = format_commit_msg("v1.0\n", None);
+        let unsigned = Tag::new(
+            SHA1::default(),
+            ObjectType::Commit,
+            "v1.0".to_string(),
+            tagger,
+            &message,
+        );
+        let signed = Tag::new(
+            unsigned.object_hash,
+            unsigned.object_type,
+            unsigned.tag_name.clone(),
+            unsigned.tagger.clone(),
+            &(message + SIGNATURE),
+        );
+        let (data, signature) = split_tag_signature(&signed).unwrap();
+        assert_eq!(data, unsigned.to_data().unwrap());
+        assert_eq!(signature, SIGNATURE);
+        assert!(split_tag_signature(&unsigned).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use sea_orm::ActiveModelTrait;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder};
+
+use mercury::hash::SHA1;
+use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::ObjectTrait;
+
+use crate::internal::db::get_db_conn_instance;
+use crate::internal::model::reference;
+use crate::utils::util;
+
+/// A tag reference, `refs/tags/<name>`
+#[derive(Debug)]
+pub struct Tag {
+    pub name: String,
+    /// the tagged object for lightweight tag, or the tag object for annotated tag
+    pub object: SHA1,
+}
+
+async fn query_reference(tag_name: &str) -> Option<reference::Model> {
+    let db_conn = get_db_conn_instance().await;
+    reference::Entity::find()
+        .filter(reference::Column::Name.eq(tag_name))
+        .filter(reference::Column::Kind.eq(reference::ConfigKind::Tag))
+        .one(db_conn)
+        .await
+        .unwrap()
+}
+
+impl Tag {
+    /// list all tags, sorted by name
+    pub async fn list_tags() -> Vec<Self> {
+        let db_conn = get_db_conn_instance().await;
+        reference::Entity::find()
+            .filter(reference::Column::Kind.eq(reference::ConfigKind::Tag))
+            .order_by_asc(reference::Column::Name)
+            .all(db_conn)
+            .await
+            .unwrap()
+            .iter()
+            .map(|tag| Tag {
+                name: tag.name.as_ref().unwrap().clone(),
+                object: SHA1::from_str(tag.commit.as_ref().unwrap()).unwrap(),
+            })
+            .collect()
+    }
+
+    /// get the tag by name
+    pub async fn find_tag(tag_name: &str) -> Option<Self> {
+        query_reference(tag_name).await.map(|tag| Tag {
+            name: tag.name.unwrap(),
+            object: SHA1::from_str(tag.commit.as_ref().unwrap()).unwrap(),
+        })
+    }
+
+    /// create the tag or move it to `object`
+    pub async fn update_tag(tag_name: &str, object: &str) {
+        let db_conn = get_db_conn_instance().await;
+        match query_reference(tag_name).await {
+            Some(tag) => {
+                let mut tag: reference::ActiveModel = tag.into();
+                tag.commit = Set(Some(object.to_owned()));
+                tag.update(db_conn).await.unwrap();
+            }
+            None => {
+                reference::ActiveModel {
+                    name: Set(Some(tag_name.to_owned())),
+                    kind: Set(reference::ConfigKind::Tag),
+                    commit: Set(Some(object.to_owned())),
+                    remote: Set(None), // tag can't be remote
+                    ..Default::default()
+                }
+                .insert(db_conn)
+                .await
+                .unwrap();
+            }
+        }
+    }
+
+    pub async fn delete_tag(tag_name: &str) {
+        let db_conn = get_db_conn_instance().await;
+        let tag = query_reference(tag_name).await;
+        if let Some(tag) = tag {
+            tag.delete(db_conn).await.unwrap();
+        }
+    }
+
+    /// Follow annotated tags until a non-tag object, usually a commit
+    pub fn peel(object: &SHA1) -> SHA1 {
+        let storage = util::objects_storage();
+        let mut object = *object;
+        while storage.is_object_type(&object, ObjectType::Tag) {
+            let data = storage.get(&object).unwrap();
+            let tag = mercury::internal::object::tag::Tag::from_bytes(&data, object).unwrap();
+            object = tag.object_hash;
+        }
+        object
+    }
+
+    /// The commit the tag finally points to
+    pub fn commit(&self) -> SHA1 {
+        Self::peel(&self.object)
+    }
+}
@@ -0,0 +1,354 @@
+//! Reachability bitmaps of the multi-pack-index, at `objects/pack/multi-pack-index.bitmap`.
+//! The objects reachable from some commits, e.g. the tips of branches, are recorded as bitsets
+//! over the positions in [MultiPackIndex::objects], so the objects between two commits
+//! are computed by bit operations rather than walking every tree.
+//! - Unlike the `.bitmap` of git, the bitsets are stored in libra's own format, run-length encoded
+//!   like EWAH: the long runs of 0 or 1 words cost a marker, the other words are stored as is.
+//!   A bitset takes at most `objects / 8` bytes & a few markers in the file, and `objects / 8` bytes in memory.
+//! - A bitmap file is only valid for the multi-pack-index with the same checksum.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use mercury::errors::GitError;
+use mercury::hash::SHA1;
+use mercury::internal::object::types::ObjectType;
+use sha1::{Digest, Sha1};
+
+use crate::command::prune;
+use crate::internal::tag::Tag;
+use crate::utils::midx::MultiPackIndex;
+use crate::utils::{path, util};
+
+const SIGNATURE: &[u8; 4] = b"LBMP";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 36; // signature, version, midx checksum, number of objects & commits
+
+/// `objects/pack/multi-pack-index.bitmap`
+pub fn bitmap_path(objects: &Path) -> PathBuf {
+    objects.join("pack").join("multi-pack-index.bitmap")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    /// An empty bitmap of `len` bits
+    pub fn new(len: usize) -> Self {
+        Bitmap {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn set(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn or(&mut self, other: &Bitmap) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// The positions of the set bits, in ascending order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+/// The max number of fill words & literal words after a marker
+const RUN_MAX: usize = 0x7fff_ffff;
+const LITERALS_MAX: usize = u32::MAX as usize;
+
+/// Encode `words` as the sequence of: a marker of `fill bit (1) | fill words (31) | literal words (32)`,
+/// followed by the literal words; a fill word is all 0 or all 1 bits
+fn encode_words(words: &[u64], buf: &mut Vec<u8>) -> Result<(), io::Error> {
+    let is_fill = |word: u64| word == 0 || word == u64::MAX;
+    let mut i = 0;
+    while i < words.len() {
+        let fill = words[i];
+        let mut run = 0;
+        while run < RUN_MAX && i < words.len() && is_fill(words[i]) && words[i] == fill {
+            run += 1;
+            i += 1;
+        }
+        let start = i;
+        while i - start < LITERALS_MAX && i < words.len() && !is_fill(words[i]) {
+            i += 1;
+        }
+        let fill_bit = if run > 0 && fill == u64::MAX {
+            1 << 63
+        } else {
+            0
+        };
+        buf.write_u64::<BigEndian>(fill_bit | (run as u64) << 32 | (i - start) as u64)?;
+        for word in &words[start..i] {
+            buf.write_u64::<BigEndian>(*word)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decode `num_words` words encoded by [encode_words] from the start of `data`,
+/// return them & the number of bytes read
+fn decode_words(data: &[u8], num_words: usize) -> Result<(Vec<u64>, usize), io::Error> {
+    let mut words = Vec::with_capacity(num_words);
+    let mut read = 0;
+    while words.len() < num_words {
+        let marker = data
+            .get(read..read + 8)
+            .map(BigEndian::read_u64)
+            .ok_or_else(|| invalid("truncated bitmap"))?;
+        read += 8;
+        let fill = if marker >> 63 == 1 { u64::MAX } else { 0 };
+        let run = (marker >> 32) as usize & RUN_MAX;
+        let literals = marker as u32 as usize;
+        if words.len() + run + literals > num_words {
+            return Err(invalid("bitmap too long"));
+        }
+        words.resize(words.len() + run, fill);
+        let data = data
+            .get(read..read + literals * 8)
+            .ok_or_else(|| invalid("truncated bitmap"))?;
+        words.extend(data.chunks(8).map(BigEndian::read_u64));
+        read += literals * 8;
+    }
+    Ok((words, read))
+}
+
+/// The bitmaps of some commits, for the multi-pack-index of `midx_checksum`
+pub struct BitmapIndex {
+    pub midx_checksum: SHA1,
+    pub num_objects: usize,
+    pub commits: HashMap<SHA1, Bitmap>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bitmap: {}", msg))
+}
+
+impl BitmapIndex {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        let mut buf = Vec::new();
+        buf.write_all(SIGNATURE)?;
+        buf.write_u32::<BigEndian>(VERSION)?;
+        buf.write_all(&self.midx_checksum.0)?;
+        buf.write_u32::<BigEndian>(self.num_objects as u32)?;
+        buf.write_u32::<BigEndian>(self.commits.len() as u32)?;
+        // commit & its encoded words
+        for (commit, bitmap) in &self.commits {
+            buf.write_all(&commit.0)?;
+            encode_words(&bitmap.words, &mut buf)?;
+        }
+        let checksum: [u8; 20] = Sha1::digest(&buf).into();
+        buf.write_all(&checksum)?;
+        Ok(buf)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, io::Error> {
+        if data.len() < HEADER_SIZE + 20 || &data[..4] != SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        if BigEndian::read_u32(&data[4..8]) != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(content).as_slice() != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+        let midx_checksum = SHA1::from_bytes(&data[8..28]);
+        let num_objects = BigEndian::read_u32(&data[28..32]) as usize;
+        let num_commits = BigEndian::read_u32(&data[32..36]) as usize;
+        let num_words = num_objects.div_ceil(64);
+
+        let mut commits = HashMap::with_capacity(num_commits);
+        let mut entries = &content[HEADER_SIZE..];
+        for _ in 0..num_commits {
+            if entries.len() < 20 {
+                return Err(invalid("bad size"));
+            }
+            let (words, read) = decode_words(&entries[20..], num_words)?;
+            commits.insert(SHA1::from_bytes(&entries[..20]), Bitmap { words });
+            entries = &entries[20 + read..];
+        }
+        if !entries.is_empty() {
+            return Err(invalid("bad size"));
+        }
+        Ok(BitmapIndex {
+            midx_checksum,
+            num_objects,
+            commits,
+        })
+    }
+
+    /// Load the bitmaps of the multi-pack-index, `None` if they don't exist or are built for another one
+    pub fn load(midx: &MultiPackIndex) -> Option<Self> {
+        let data = fs::read(bitmap_path(&path::objects())).ok()?;
+        let index = Self::from_bytes(&data)
+            .map_err(|e| tracing::warn!("ignore bitmaps: {}", e))
+            .ok()?;
+        (index.midx_checksum == midx.checksum && index.num_objects == midx.objects.len())
+            .then_some(index)
+    }
+}
+
+/// The objects reachable from some objects, as bits of the objects in the multi-pack-index
+/// and the set of the other objects, e.g. the loose objects
+struct Reachable {
+    bits: Bitmap,
+    extra: HashSet<SHA1>,
+}
+
+impl Reachable {
+    /// Walk from `tips`, the commits in `bitmaps` are not walked but taken from their bitmaps,
+    /// the objects in `exclude` are not walked either, which are not needed to compute the difference
+    fn walk(
+        midx: &MultiPackIndex,
+        bitmaps: &HashMap<SHA1, Bitmap>,
+        tips: &[SHA1],
+        exclude: Option<&Reachable>,
+    ) -> Self {
+        let mut bits = Bitmap::new(midx.objects.len());
+        let mut extra = HashSet::new();
+        prune::walk_objects(tips.to_vec(), |obj_id| {
+            let position = midx.position(obj_id);
+            if exclude.is_some_and(|exclude| exclude.contains(position, obj_id)) {
+                return false;
+            }
+            match position {
+                Some(position) if bits.get(position) => return false,
+                Some(position) => bits.set(position),
+                None if !extra.insert(*obj_id) => return false,
+                None => {}
+            }
+            match bitmaps.get(obj_id) {
+                Some(bitmap) => {
+                    bits.or(bitmap);
+                    false
+                }
+                None => true,
+            }
+        });
+        Reachable { bits, extra }
+    }
+
+    fn contains(&self, position: Option<usize>, obj_id: &SHA1) -> bool {
+        match position {
+            Some(position) => self.bits.get(position),
+            None => self.extra.contains(obj_id),
+        }
+    }
+}
+
+/// Write the bitmaps of the commits pointed by HEAD, branches & tags, for the current multi-pack-index.
+/// The commits reaching objects not in the multi-pack-index are skipped, e.g. new commits not packed yet.
+/// Return the number of the commits with bitmaps.
+pub async fn write_bitmaps() -> Result<usize, GitError> {
+    let storage = util::objects_storage();
+    let midx = storage
+        .load_midx()
+        .ok_or_else(|| GitError::CustomError("multi-pack-index not found".to_string()))?;
+    let mut commits = Vec::new();
+    for tip in prune::ref_tips().await {
+        let commit = Tag::peel(&tip);
+        if !commits.contains(&commit) && storage.is_object_type(&commit, ObjectType::Commit) {
+            commits.push(commit);
+        }
+    }
+
+    let mut bitmaps = HashMap::new();
+    for commit in commits {
+        // the bitmaps computed before are reused, e.g. a branch behind another
+        let reachable = Reachable::walk(&midx, &bitmaps, &[commit], None);
+        if reachable.extra.is_empty() {
+            bitmaps.insert(commit, reachable.bits);
+        }
+    }
+    let count = bitmaps.len();
+    let index = BitmapIndex {
+        midx_checksum: midx.checksum,
+        num_objects: midx.objects.len(),
+        commits: bitmaps,
+    };
+    util::write_file_atomic(&index.to_bytes()?, &bitmap_path(&path::objects()))?;
+    Ok(count)
+}
+
+/// The objects reachable from `want` but not from `have`, computed with the bitmaps.
+/// `None` if there are no bitmaps for the current multi-pack-index.
+pub fn objects_between(want: &[SHA1], have: &[SHA1]) -> Option<Vec<SHA1>> {
+    let midx = util::objects_storage().load_midx()?;
+    let index = BitmapIndex::load(&midx)?;
+    let have = Reachable::walk(&midx, &index.commits, have, None);
+    let want = Reachable::walk(&midx, &index.commits, want, Some(&have));
+    let mut objects: Vec<SHA1> = want
+        .bits
+        .ones()
+        .filter(|&i| !have.bits.get(i))
+        .map(|i| midx.objects[i])
+        .collect();
+    objects.extend(want.extra); // not in have, or they are excluded from the walk
+    Some(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap() {
+        let mut a = Bitmap::new(130);
+        a.set(0);
+        a.set(129);
+        let mut b = Bitmap::new(130);
+        b.set(64);
+        b.or(&a);
+        assert!(b.get(0) && b.get(64) && b.get(129));
+        assert!(!b.get(1));
+        assert_eq!(b.ones().collect::<Vec<_>>(), vec![0, 64, 129]);
+    }
+
+    #[test]
+    fn test_bitmap_index_round_trip() {
+        let mut bitmap = Bitmap::new(70);
+        bitmap.set(69);
+        let index = BitmapIndex {
+            midx_checksum: SHA1::new(b"midx"),
+            num_objects: 70,
+            commits: HashMap::from([(SHA1::new(b"commit"), bitmap.clone())]),
+        };
+        let data = index.to_bytes().unwrap();
+        let index = BitmapIndex::from_bytes(&data).unwrap();
+        assert_eq!(index.midx_checksum, SHA1::new(b"midx"));
+        assert_eq!(index.commits[&SHA1::new(b"commit")], bitmap);
+        assert!(BitmapIndex::from_bytes(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_encode_words() {
+        let mut words = vec![0; 1000];
+        words.extend([u64::MAX; 500]);
+        words.extend([1, 2, u64::MAX, 3]);
+        words.extend([0; 10]);
+        let mut buf = Vec::new();
+        encode_words(&words, &mut buf).unwrap();
+        // 4 markers & 3 literal words
+        assert_eq!(buf.len(), 7 * 8);
+        buf.extend([0xff; 8]); // the data after it isn't read
+        assert_eq!(decode_words(&buf, words.len()).unwrap(), (words, 7 * 8));
+        assert!(decode_words(&buf[..6 * 8], 1514).is_err());
+    }
+}
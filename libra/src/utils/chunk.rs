@@ -0,0 +1,88 @@
+//! The chunk-based file format shared by the multi-pack-index & the commit-graph, same as git:
+//! a header, a table of contents of `(chunk id, offset)` terminated by a zero id with the end offset,
+//! the chunks in the order of the table, then the SHA-1 checksum of all above.
+//!
+//! [gitformat-chunk](https://git-scm.com/docs/gitformat-chunk)
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use mercury::hash::SHA1;
+use sha1::{Digest, Sha1};
+
+const LOOKUP_WIDTH: usize = 12;
+
+/// Write `header` (with the number of chunks in it), the table of contents, `chunks` & the checksum
+pub fn write(header: &[u8], chunks: Vec<(u32, Vec<u8>)>) -> Result<Vec<u8>, io::Error> {
+    let mut buf = header.to_vec();
+    let mut offset = (header.len() + LOOKUP_WIDTH * (chunks.len() + 1)) as u64;
+    for (id, data) in &chunks {
+        buf.write_u32::<BigEndian>(*id)?;
+        buf.write_u64::<BigEndian>(offset)?;
+        offset += data.len() as u64;
+    }
+    buf.write_u32::<BigEndian>(0)?;
+    buf.write_u64::<BigEndian>(offset)?;
+    for (_, data) in chunks {
+        buf.extend(data);
+    }
+    let checksum: [u8; 20] = Sha1::digest(&buf).into();
+    buf.write_all(&checksum)?;
+    Ok(buf)
+}
+
+/// The chunks of a file written by [write]
+pub struct Chunks<'a> {
+    chunks: BTreeMap<u32, &'a [u8]>,
+    pub checksum: SHA1,
+}
+
+impl<'a> Chunks<'a> {
+    /// Verify the checksum of `data` & read its table of contents after the header of `header_size`
+    pub fn read(
+        data: &'a [u8],
+        header_size: usize,
+        num_chunks: usize,
+    ) -> Result<Self, &'static str> {
+        if data.len() < header_size + 20 {
+            return Err("bad size");
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(content).as_slice() != checksum {
+            return Err("checksum mismatch");
+        }
+        if header_size + LOOKUP_WIDTH * (num_chunks + 1) > content.len() {
+            return Err("bad chunk lookup table");
+        }
+
+        let mut chunks = BTreeMap::new();
+        for i in 0..num_chunks {
+            let row = &data[header_size + LOOKUP_WIDTH * i..];
+            let next = &row[LOOKUP_WIDTH..];
+            let start = BigEndian::read_u64(&row[4..12]) as usize;
+            let end = BigEndian::read_u64(&next[4..12]) as usize;
+            if start > end || end > content.len() {
+                return Err("bad chunk offset");
+            }
+            chunks.insert(BigEndian::read_u32(&row[..4]), &data[start..end]);
+        }
+        Ok(Chunks {
+            chunks,
+            checksum: SHA1::from_bytes(checksum),
+        })
+    }
+
+    /// The optional chunk of `id`, empty if it doesn't exist
+    pub fn get(&self, id: u32) -> &'a [u8] {
+        self.chunks.get(&id).copied().unwrap_or_default()
+    }
+
+    /// The chunk of `id`, at least `len` bytes
+    pub fn require(&self, id: u32, len: usize) -> Result<&'a [u8], &'static str> {
+        match self.chunks.get(&id) {
+            Some(chunk) if chunk.len() >= len => Ok(&chunk[..len]),
+            _ => Err("missing or truncated chunk"),
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use std::{fs, io};
 
 use byteorder::{BigEndian, ReadBytesExt};
@@ -19,6 +20,9 @@ use mercury::utils::read_sha1;
 use once_cell::sync::Lazy;
 
 use crate::command;
+use crate::utils::midx::MultiPackIndex;
+use crate::utils::{bitmap, util};
+
 static PACK_OBJ_CACHE: Lazy<Mutex<LruCache<String, CacheObject>>> = Lazy::new(|| {
     // `lazy_static!` may affect IDE's code completion
     Mutex::new(LruCache::new(1024 * 1024 * 200))
@@ -48,7 +52,7 @@ impl ClientStorage {
     }
 
     /// join `base_path` and `obj_id` to get the full path of the object
-    fn get_obj_path(&self, obj_id: &SHA1) -> PathBuf {
+    pub fn get_obj_path(&self, obj_id: &SHA1) -> PathBuf {
         Path::new(&self.base_path).join(self.transform_path(obj_id))
     }
 
@@ -84,7 +88,7 @@ impl ClientStorage {
     }
 
     /// list all objects' hash in `objects`
-    fn list_objects_loose(&self) -> Vec<SHA1> {
+    pub fn list_objects_loose(&self) -> Vec<SHA1> {
         let mut objects = Vec::new();
         let paths = fs::read_dir(&self.base_path).unwrap();
         for path in paths {
@@ -107,7 +111,7 @@ impl ClientStorage {
     }
 
     /// List all objects' hash in PACKs
-    fn list_objects_pack(&self) -> HashSet<SHA1> {
+    pub fn list_objects_pack(&self) -> HashSet<SHA1> {
         let idxes = self.list_all_idx();
         let mut objs = HashSet::new();
         for idx in idxes {
@@ -195,6 +199,14 @@ impl ClientStorage {
         Ok(path.to_str().unwrap().to_string())
     }
 
+    /// Delete the loose object, and its directory if it becomes empty
+    pub fn remove_loose(&self, obj_id: &SHA1) -> Result<(), io::Error> {
+        let path = self.get_obj_path(obj_id);
+        fs::remove_file(&path)?;
+        let _ = fs::remove_dir(path.parent().unwrap()); // fails if not empty
+        Ok(())
+    }
+
     /// Check if the object with `obj_id` exists in `objects` or PACKs
     pub fn exist(&self, obj_id: &SHA1) -> bool {
         let path = self.get_obj_path(obj_id);
@@ -202,16 +214,116 @@ impl ClientStorage {
     }
 
     /// Check if the object with `obj_id` exists in `objects`
-    fn exist_loosely(&self, obj_id: &SHA1) -> bool {
+    pub fn exist_loosely(&self, obj_id: &SHA1) -> bool {
         let path = self.get_obj_path(obj_id);
         Path::exists(&path)
     }
 }
-const FANOUT: u64 = 256 * 4;
+/// The parsed .idx files, a .idx file never changes once written (named by the pack checksum)
+static IDX_CACHE: Lazy<Mutex<HashMap<PathBuf, Arc<PackIndex>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A multi-pack-index, with the modified time when it was read
+type CachedMidx = (SystemTime, Arc<MultiPackIndex>);
+
+/// The parsed multi-pack-index of each repository
+static MIDX_CACHE: Lazy<Mutex<HashMap<PathBuf, CachedMidx>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The objects & offsets of a .idx file in memory
+struct PackIndex {
+    fanout: [u32; 256],
+    hashes: Vec<SHA1>,
+    offsets: Vec<u64>,
+}
+
+impl PackIndex {
+    /// Parse .idx file of version 1 or 2
+    /// - version 1 has no header, version 2 starts with the magic number `\377tOc` & the version
+    fn read(idx_file: &Path) -> Result<Self, io::Error> {
+        let mut idx_file = io::BufReader::new(fs::File::open(idx_file)?);
+        let mut magic = [0; 4];
+        idx_file.read_exact(&mut magic)?;
+        let version = if magic == command::index_pack::IDX_V2_MAGIC {
+            let version = idx_file.read_u32::<BigEndian>()?;
+            if version != 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported index version {}", version),
+                ));
+            }
+            version
+        } else {
+            idx_file.seek(io::SeekFrom::Start(0))?;
+            1
+        };
+        let mut fanout: [u32; 256] = [0; 256]; // 256 * 4 bytes
+        for x in fanout.iter_mut() {
+            *x = idx_file.read_u32::<BigEndian>()?;
+        }
+        let total = fanout[255] as usize;
+        let mut hashes = Vec::with_capacity(total);
+        let mut offsets = Vec::with_capacity(total);
+
+        if version == 1 {
+            // 4-byte offset & hash of each object
+            for _ in 0..total {
+                offsets.push(idx_file.read_u32::<BigEndian>()? as u64);
+                hashes.push(read_sha1(&mut idx_file)?);
+            }
+            return Ok(PackIndex {
+                fanout,
+                hashes,
+                offsets,
+            });
+        }
+
+        // version 2: hashes, CRC32s, 4-byte offsets, 8-byte offsets
+        for _ in 0..total {
+            hashes.push(read_sha1(&mut idx_file)?);
+        }
+        idx_file.seek_relative(4 * total as i64)?; // CRC32 is not checked here
+        for _ in 0..total {
+            offsets.push(idx_file.read_u32::<BigEndian>()? as u64);
+        }
+        let num_large = offsets.iter().filter(|&&o| o & 0x8000_0000 != 0).count();
+        let mut large_offsets = Vec::with_capacity(num_large);
+        for _ in 0..num_large {
+            large_offsets.push(idx_file.read_u64::<BigEndian>()?);
+        }
+        for offset in offsets.iter_mut() {
+            if *offset & 0x8000_0000 != 0 {
+                let index = (*offset & 0x7fff_ffff) as usize;
+                *offset = *large_offsets.get(index).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "bad large offset index")
+                })?;
+            }
+        }
+        Ok(PackIndex {
+            fanout,
+            hashes,
+            offsets,
+        })
+    }
+
+    /// Binary search the object in the range of its first byte in the fan-out table
+    fn find(&self, obj_id: &SHA1) -> Option<u64> {
+        let first_byte = obj_id.0[0] as usize;
+        let start = if first_byte == 0 {
+            0
+        } else {
+            self.fanout[first_byte - 1] as usize
+        };
+        let end = self.fanout[first_byte] as usize;
+        let i = self.hashes[start..end].binary_search(obj_id).ok()?;
+        Some(self.offsets[start + i])
+    }
+}
+
 // TODO refactor to `PackReader`
 impl ClientStorage {
     /// List all .pack files in `pack` directory
-    fn list_all_packs(&self) -> Vec<PathBuf> {
+    pub fn list_all_packs(&self) -> Vec<PathBuf> {
         let pack_dir = self.base_path.join("pack");
         if !pack_dir.exists() {
             return Vec::new();
@@ -219,7 +331,8 @@ impl ClientStorage {
         let mut packs = Vec::new();
         for entry in fs::read_dir(pack_dir).unwrap() {
             let path = entry.unwrap().path();
-            if path.is_file() && path.extension().unwrap() == "pack" {
+            // there are other files, e.g. `multi-pack-index`
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "pack") {
                 packs.push(path);
             }
         }
@@ -234,7 +347,7 @@ impl ClientStorage {
         for pack in packs {
             let idx = pack.with_extension("idx");
             if !idx.exists() {
-                command::index_pack::build_index_v1(pack.to_str().unwrap(), idx.to_str().unwrap())
+                command::index_pack::build_index_v2(pack.to_str().unwrap(), idx.to_str().unwrap())
                     .unwrap();
             }
             idxs.push(idx);
@@ -242,84 +355,141 @@ impl ClientStorage {
         idxs
     }
 
+    /// List all objects' hash in a .pack file, by its .idx file
+    pub fn list_pack_objects(&self, pack_file: &Path) -> Result<Vec<SHA1>, io::Error> {
+        Self::list_idx_objects(&pack_file.with_extension("idx"))
+    }
+
+    /// Delete a .pack file and its .idx file
+    pub fn remove_pack(&self, pack_file: &Path) -> Result<(), io::Error> {
+        let _ = fs::remove_file(pack_file.with_extension("idx"));
+        fs::remove_file(pack_file)
+    }
+
     /// Get object from PACKs by hash, if not found, return None
     fn get_from_pack(&self, obj_id: &SHA1) -> Result<Option<(Vec<u8>, ObjectType)>, GitError> {
+        match self.find_in_packs(obj_id)? {
+            Some((pack_file, offset)) => {
+                let data = Self::read_pack_obj(&pack_file, offset)?;
+                Ok(Some((data.data_decompressed.clone(), data.object_type())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Find the pack file & offset of the object, by the multi-pack-index first,
+    /// then the .idx files not covered by it, e.g. the packs fetched after it's written
+    fn find_in_packs(&self, obj_id: &SHA1) -> Result<Option<(PathBuf, u64)>, GitError> {
         let idxes = self.list_all_idx(); // list or build
+        let pack_dir = self.base_path.join("pack");
+        let midx = self.midx_of(&idxes);
+        if let Some(midx) = &midx {
+            if let Some((idx, offset)) = midx.find(obj_id) {
+                return Ok(Some((pack_dir.join(idx).with_extension("pack"), offset)));
+            }
+        }
         for idx in idxes {
-            let res = Self::read_pack_by_idx(&idx, obj_id)?;
-            if let Some(data) = res {
-                return Ok(Some((data.data_decompressed.clone(), data.object_type())));
+            let name = idx.file_name().unwrap().to_str().unwrap();
+            if midx
+                .as_ref()
+                .is_some_and(|midx| midx.packs.iter().any(|p| p == name))
+            {
+                continue;
+            }
+            if let Some(offset) = Self::read_idx(&idx, obj_id)? {
+                return Ok(Some((idx.with_extension("pack"), offset)));
             }
         }
 
         Ok(None)
     }
 
-    fn read_idx_fanout(idx_file: &Path) -> Result<[u32; 256], io::Error> {
-        let mut idx_file = fs::File::open(idx_file)?;
-        // const FANOUT: usize = 256 * 4;
-        let mut fanout: [u32; 256] = [0; 256]; // 256 * 4 bytes
-        let mut buf = [0; 4];
-        fanout.iter_mut().for_each(|x| {
-            idx_file.read_exact(&mut buf).unwrap();
-            *x = u32::from_be_bytes(buf);
-        });
-        Ok(fanout)
+    /// Path of the multi-pack-index, `objects/pack/multi-pack-index`
+    fn midx_path(&self) -> PathBuf {
+        self.base_path.join("pack").join("multi-pack-index")
     }
 
-    /// List all objects hash in .idx file
-    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?; // TODO param change to `&mut File`, to auto seek
-        let mut idx_file = fs::File::open(idx_file)?;
-        idx_file.seek(io::SeekFrom::Start(FANOUT))?; // important!
-
-        let mut objs = Vec::new();
-        for _ in 0..fanout[255] {
-            let _offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
-
-            objs.push(hash);
-        }
-        Ok(objs)
+    /// Load the multi-pack-index if it exists and all its packs still exist
+    pub fn load_midx(&self) -> Option<Arc<MultiPackIndex>> {
+        self.midx_of(&self.list_all_idx())
     }
 
-    /// Read object `offset` from .idx file by `hash`
-    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, io::Error> {
-        let fanout: [u32; 256] = Self::read_idx_fanout(idx_file)?;
-        let mut idx_file = fs::File::open(idx_file)?;
-
-        let first_byte = obj_id.0[0];
-        let start = if first_byte == 0 {
-            0
-        } else {
-            fanout[first_byte as usize - 1] as usize
+    /// Load the multi-pack-index for the .idx files `idxes`, it's cached until modified
+    fn midx_of(&self, idxes: &[PathBuf]) -> Option<Arc<MultiPackIndex>> {
+        let path = self.midx_path();
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let mut cache = MIDX_CACHE.lock().unwrap();
+        let midx = match cache.get(&path) {
+            Some((time, midx)) if *time == modified => midx.clone(),
+            _ => {
+                let data = fs::read(&path).ok()?;
+                let midx = match MultiPackIndex::from_bytes(&data) {
+                    Ok(midx) => Arc::new(midx),
+                    Err(e) => {
+                        tracing::warn!("ignore {}: {}", path.display(), e);
+                        return None;
+                    }
+                };
+                cache.insert(path, (modified, midx.clone()));
+                midx
+            }
         };
-        let end = fanout[first_byte as usize] as usize;
+        // stale if a pack is removed, e.g. by `repack -d` of an older libra
+        let all_exist = midx.packs.iter().all(|pack| {
+            idxes
+                .iter()
+                .any(|idx| idx.file_name().unwrap() == pack.as_str())
+        });
+        all_exist.then_some(midx)
+    }
 
-        idx_file.seek(io::SeekFrom::Start(FANOUT + 24 * start as u64))?;
-        for _ in start..end {
-            let offset = idx_file.read_u32::<BigEndian>()?;
-            let hash = read_sha1(&mut idx_file)?;
+    /// Write the multi-pack-index of all packs, or remove it if there is no pack.
+    /// - The reachability bitmaps of the old multi-pack-index is removed as well.
+    pub fn write_midx(&self) -> Result<(), GitError> {
+        let _ = fs::remove_file(bitmap::bitmap_path(&self.base_path));
+        let path = self.midx_path();
+        let idxes = self.list_all_idx();
+        if idxes.is_empty() {
+            let _ = fs::remove_file(path);
+            return Ok(());
+        }
+        let mut packs = Vec::with_capacity(idxes.len());
+        for idx in idxes {
+            let index = Self::load_idx(&idx)?;
+            let entries = index
+                .hashes
+                .iter()
+                .copied()
+                .zip(index.offsets.iter().copied());
+            let name = idx.file_name().unwrap().to_str().unwrap().to_string();
+            packs.push((name, entries.collect()));
+        }
+        let data = MultiPackIndex::new(packs).to_bytes()?;
+        util::write_file_atomic(&data, &path)?;
+        Ok(())
+    }
 
-            if &hash == obj_id {
-                return Ok(Some(offset as u64));
-            }
+    /// Parse .idx file, or get it from the cache
+    fn load_idx(idx_file: &Path) -> Result<Arc<PackIndex>, io::Error> {
+        if let Some(index) = IDX_CACHE.lock().unwrap().get(idx_file) {
+            return Ok(index.clone());
         }
+        let index = Arc::new(PackIndex::read(idx_file)?);
+        IDX_CACHE
+            .lock()
+            .unwrap()
+            .insert(idx_file.to_path_buf(), index.clone());
+        Ok(index)
+    }
 
-        Ok(None)
+    /// List all objects hash in .idx file
+    fn list_idx_objects(idx_file: &Path) -> Result<Vec<SHA1>, io::Error> {
+        Ok(Self::load_idx(idx_file)?.hashes.clone())
     }
 
-    /// Get object from pack by .idx file
-    fn read_pack_by_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<CacheObject>, GitError> {
-        let pack_file = idx_file.with_extension("pack");
-        let res = Self::read_idx(idx_file, obj_id)?;
-        match res {
-            None => Ok(None),
-            Some(offset) => {
-                let res = Self::read_pack_obj(&pack_file, offset)?;
-                Ok(Some(res))
-            }
-        }
+    /// Read object `offset` from .idx file by `hash`
+    fn read_idx(idx_file: &Path, obj_id: &SHA1) -> Result<Option<u64>, io::Error> {
+        Ok(Self::load_idx(idx_file)?.find(obj_id))
     }
 
     /// Read object from pack file, with offset
@@ -377,7 +547,9 @@ mod tests {
     use std::fs;
     use std::path::PathBuf;
 
+    use crate::command;
     use crate::utils::{test, util};
+    use mercury::hash::SHA1;
 
     use super::ClientStorage;
 
@@ -467,4 +639,77 @@ mod tests {
         let decompressed_data = ClientStorage::decompress_zlib(&pack_content).unwrap();
         println!("{:?}", String::from_utf8(decompressed_data).unwrap());
     }
+
+    #[test]
+    fn test_read_idx_v1_v2() {
+        let packs = test::find_cargo_dir()
+            .parent()
+            .unwrap()
+            .join("tests/data/packs");
+        let tmp = tempfile::tempdir().unwrap();
+        let pack_dir = tmp.path().join("pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        for name in [
+            "pack-1d0e6c14760c956c173ede71cb28f33d921e232f",
+            "ref-delta-65d47638aa7cb7c39f1bd1d5011a415439b887a8",
+        ] {
+            // v2 .idx files created by git
+            for ext in ["pack", "idx"] {
+                let file = format!("{}.{}", name, ext);
+                fs::copy(packs.join(&file), pack_dir.join(&file)).unwrap();
+            }
+            let pack_file = pack_dir.join(format!("{}.pack", name));
+            let idx_v1 = tmp.path().join(format!("{}.idx", name));
+            command::index_pack::build_index_v1(
+                pack_file.to_str().unwrap(),
+                idx_v1.to_str().unwrap(),
+            )
+            .unwrap();
+
+            let idx_v2 = pack_file.with_extension("idx");
+            let objs = ClientStorage::list_idx_objects(&idx_v2).unwrap();
+            assert_eq!(objs, ClientStorage::list_idx_objects(&idx_v1).unwrap());
+            for obj in &objs {
+                let offset = ClientStorage::read_idx(&idx_v2, obj).unwrap();
+                assert!(offset.is_some());
+                assert_eq!(offset, ClientStorage::read_idx(&idx_v1, obj).unwrap());
+            }
+        }
+
+        let client_storage = ClientStorage::init(tmp.path().to_path_buf());
+        let objs = client_storage.list_objects_pack();
+        assert!(!objs.is_empty());
+        for obj in objs {
+            let data = client_storage.get(&obj).unwrap();
+            let obj_type = client_storage.get_object_type(&obj).unwrap();
+            // the hash of the rebuilt object must be the same
+            let header = format!("{} {}\0", obj_type, data.len());
+            assert_eq!(SHA1::new(&[header.as_bytes(), &data].concat()), obj);
+        }
+    }
+
+    #[test]
+    fn test_read_idx_v2_large_offset() {
+        let mut entries = vec![
+            (SHA1::new(b"small"), 12, 1),
+            (SHA1::new(b"large"), 0x1_2345_6789, 2),
+            (SHA1::new(b"boundary"), 0x8000_0000, 3),
+        ];
+        entries.sort_by_key(|(hash, _, _)| *hash);
+        let idx = command::index_pack::encode_index_v2(&entries, &SHA1::default()).unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let idx_file = tmp.path().join("large.idx");
+        fs::write(&idx_file, idx).unwrap();
+
+        let hashes: Vec<SHA1> = entries.iter().map(|(hash, _, _)| *hash).collect();
+        assert_eq!(ClientStorage::list_idx_objects(&idx_file).unwrap(), hashes);
+        for (hash, offset, _) in entries {
+            assert_eq!(
+                ClientStorage::read_idx(&idx_file, &hash).unwrap(),
+                Some(offset)
+            );
+        }
+        let missing = SHA1::new(b"missing");
+        assert_eq!(ClientStorage::read_idx(&idx_file, &missing).unwrap(), None);
+    }
 }
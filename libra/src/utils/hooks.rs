@@ -0,0 +1,129 @@
+//! Client-side hooks: the executables in `.libra/hooks` (or `core.hooksPath`) run by the commands, like git.
+//! - `pre-commit`, `prepare-commit-msg`, `commit-msg` & `pre-push` run before the operation, which is aborted
+//!   if the hook exits non-zero
+//! - `post-commit`, `post-checkout` & `post-merge` are notifications, their exit statuses are ignored
+//!
+//! The hooks run in the working directory, with the same arguments & stdin as git.
+//! [githooks](https://git-scm.com/docs/githooks)
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use mercury::hash::SHA1;
+
+use crate::internal::config::Config;
+use crate::utils::util;
+
+pub const PRE_COMMIT: &str = "pre-commit";
+pub const PREPARE_COMMIT_MSG: &str = "prepare-commit-msg";
+pub const COMMIT_MSG: &str = "commit-msg";
+pub const POST_COMMIT: &str = "post-commit";
+pub const PRE_PUSH: &str = "pre-push";
+pub const POST_CHECKOUT: &str = "post-checkout";
+pub const POST_MERGE: &str = "post-merge";
+
+/// The directory of the hooks, `core.hooksPath` (relative to the working directory) or `.libra/hooks`
+pub async fn hooks_dir() -> PathBuf {
+    match Config::get("core", None, "hooksPath").await {
+        Some(dir) => util::working_dir().join(dir),
+        None => util::storage_path().join("hooks"),
+    }
+}
+
+/// The executable of the hook, `None` if there is none
+async fn find(name: &str) -> Option<PathBuf> {
+    let hook = hooks_dir().await.join(name);
+    if !hook.is_file() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if hook.metadata().ok()?.permissions().mode() & 0o111 == 0 {
+            eprintln!(
+                "hint: The '{}' hook was ignored because it's not set as executable.",
+                hook.display()
+            );
+            return None;
+        }
+    }
+    Some(hook)
+}
+
+/// Run the hook `name` if it exists, with `args` & `stdin` (`/dev/null` if `None`)
+/// - `Err` if the hook can't be run or exits non-zero, `Ok` if there is no such hook
+pub async fn run(name: &str, args: &[&str], stdin: Option<&str>) -> Result<(), String> {
+    let Some(hook) = find(name).await else {
+        return Ok(());
+    };
+    let mut child = Command::new(&hook)
+        .args(args)
+        .current_dir(util::working_dir())
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .spawn()
+        .map_err(|e| format!("cannot run the '{}' hook: {}", name, e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // the hook may exit without reading its input
+        let _ = pipe.write_all(input.as_bytes());
+    }
+    let status = child
+        .wait()
+        .map_err(|e| format!("cannot run the '{}' hook: {}", name, e))?;
+    if !status.success() {
+        return Err(format!("the '{}' hook exited with {}", name, status));
+    }
+    Ok(())
+}
+
+/// Run `post-checkout` after HEAD is moved, the old HEAD is null (zeros) if there was no commit
+pub async fn post_checkout(old: Option<SHA1>, new: SHA1) {
+    let old = old.unwrap_or_default().to_string();
+    let new = new.to_string();
+    // `1` for a branch checkout, rather than the files
+    let _ = run(POST_CHECKOUT, &[&old, &new, "1"], None).await;
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_run() {
+        test::setup_with_new_libra().await;
+        assert_eq!(run(PRE_PUSH, &[], None).await, Ok(()));
+
+        test::install_hook(
+            PRE_PUSH,
+            "test \"$1\" = origin && read line && test \"$line\" = refs",
+        );
+        assert!(run(PRE_PUSH, &["origin"], Some("refs\n")).await.is_ok());
+        assert!(run(PRE_PUSH, &["other"], Some("refs\n")).await.is_err());
+        assert!(run(PRE_PUSH, &["origin"], None).await.is_err());
+
+        // not executable
+        let hook = util::storage_path().join("hooks").join(PRE_PUSH);
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(run(PRE_PUSH, &["other"], None).await.is_ok());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_hooks_path() {
+        test::setup_with_new_libra().await;
+        Config::insert("core", None, "hooksPath", "githooks").await;
+        assert_eq!(hooks_dir().await, util::working_dir().join("githooks"));
+        test::install_hook(PRE_COMMIT, "exit 1"); // in `.libra/hooks`, not used
+        assert!(run(PRE_COMMIT, &[], None).await.is_ok());
+    }
+}
@@ -0,0 +1,473 @@
+//! The ignore rules of the worktree, with the same semantics as gitignore:
+//! - `.gitignore` & `.libraignore` in any directory, for the paths under it
+//! - `.libra/info/exclude`, for the whole worktree but not shared by clones
+//! - the file of `core.excludesFile` in config, for all repositories
+//!
+//! The rules of a deeper directory take precedence over those of its parents, then `info/exclude`,
+//! then `core.excludesFile`; among them the last matching rule decides, so a later `!pattern` re-includes a path.
+//! A path under an ignored directory can't be re-included, as the directory is never walked.
+//!
+//! [gitignore](https://git-scm.com/docs/gitignore)
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::internal::config::Config;
+use crate::utils::util;
+
+/// The ignore files read in each directory, in the order of precedence from low to high
+pub const IGNORE_FILES: [&str; 2] = [".gitignore", ".libraignore"];
+
+/// A pattern of gitignore, also used for the paths of `.libra_attributes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    /// Without the `!`, the leading & trailing `/`
+    glob: String,
+    /// `!pattern`, the matched paths are re-included
+    pub negated: bool,
+    /// `pattern/`, only matches directories
+    dir_only: bool,
+    /// With a `/` at the beginning or middle, matched against the path relative to the directory of the
+    /// ignore file, rather than the file name at any level
+    anchored: bool,
+}
+
+impl Pattern {
+    /// Parse a line of an ignore file, `None` for a blank line or a comment
+    pub fn parse(line: &str) -> Option<Self> {
+        if line.starts_with('#') {
+            return None;
+        }
+        // trailing spaces are ignored unless they are escaped with `\`
+        let bytes = line.as_bytes();
+        let mut end = bytes.len();
+        while end > 0 && bytes[end - 1] == b' ' && !(end > 1 && bytes[end - 2] == b'\\') {
+            end -= 1;
+        }
+        let mut glob = &line[..end];
+        let negated = glob.starts_with('!');
+        if negated {
+            glob = &glob[1..];
+        }
+        let dir_only = glob.ends_with('/');
+        if dir_only {
+            glob = &glob[..glob.len() - 1];
+        }
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        if glob.is_empty() {
+            return None;
+        }
+        Some(Pattern {
+            glob: glob.to_string(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// If the pattern matches `path`, which is relative to the directory of the pattern with `/` separators
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let target = if self.anchored {
+            path
+        } else {
+            path.rsplit('/').next().unwrap_or(path)
+        };
+        wildmatch(self.glob.as_bytes(), target.as_bytes())
+    }
+}
+
+/// Match `text` with the glob `pattern` like the `wildmatch` of git with `WM_PATHNAME`:
+/// `*`, `?` & `[...]` don't match `/`, while `**` as a whole path component matches any directories.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    while p < pattern.len() {
+        match pattern[p] {
+            b'*' => {
+                let component_start = p == 0 || pattern[p - 1] == b'/';
+                if pattern.get(p + 1) == Some(&b'*') && component_start {
+                    let rest = &pattern[p + 2..];
+                    if rest.is_empty() {
+                        return true; // `/**` matches everything inside
+                    }
+                    if let Some(rest) = rest.strip_prefix(b"/") {
+                        // `**/` matches zero or more directories
+                        return wildmatch(rest, &text[t..])
+                            || (t..text.len())
+                                .any(|i| text[i] == b'/' && wildmatch(rest, &text[i + 1..]));
+                    }
+                }
+                // any characters but `/`, the consecutive `*` are the same as one
+                while pattern.get(p) == Some(&b'*') {
+                    p += 1;
+                }
+                let rest = &pattern[p..];
+                let mut i = t;
+                loop {
+                    if wildmatch(rest, &text[i..]) {
+                        return true;
+                    }
+                    if i == text.len() || text[i] == b'/' {
+                        return false;
+                    }
+                    i += 1;
+                }
+            }
+            b'?' => {
+                if t == text.len() || text[t] == b'/' {
+                    return false;
+                }
+                p += 1;
+            }
+            b'[' => {
+                if t == text.len() || text[t] == b'/' {
+                    return false;
+                }
+                match match_class(&pattern[p..], text[t]) {
+                    Some((true, len)) => p += len,
+                    Some((false, _)) => return false,
+                    // without the closing `]`, it's a literal `[`
+                    None if text[t] == b'[' => p += 1,
+                    None => return false,
+                }
+            }
+            c => {
+                // `\` escapes the next character
+                let c = match (c, pattern.get(p + 1)) {
+                    (b'\\', Some(&next)) => {
+                        p += 1;
+                        next
+                    }
+                    _ => c,
+                };
+                if t == text.len() || text[t] != c {
+                    return false;
+                }
+                p += 1;
+            }
+        }
+        t += 1;
+    }
+    t == text.len()
+}
+
+/// Match `c` with the bracket expression at the beginning of `pattern`, like `[a-z]` or `[!0-9]`,
+/// return if it matches & the length of the expression, `None` if it's not closed
+fn match_class(pattern: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let start = i;
+    // the escaped character at `i`, and the index after it
+    let escaped = |i: usize| match pattern.get(i) {
+        Some(b'\\') => pattern.get(i + 1).map(|&c| (c, i + 2)),
+        Some(&c) => Some((c, i + 1)),
+        None => None,
+    };
+    while i < pattern.len() {
+        // a `]` right after `[` or `[!` is a literal
+        if pattern[i] == b']' && i > start {
+            return Some((matched != negated, i + 1));
+        }
+        let (low, next) = escaped(i)?;
+        if pattern.get(next) == Some(&b'-') && pattern.get(next + 1).is_some_and(|&c| c != b']') {
+            let (high, next) = escaped(next + 1)?;
+            matched |= (low..=high).contains(&c);
+            i = next;
+        } else {
+            matched |= low == c;
+            i = next;
+        }
+    }
+    None
+}
+
+/// A pattern with where it's from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub pattern: Pattern,
+    /// The line as written, for `check-ignore -v`
+    pub text: String,
+    /// The ignore file
+    pub source: PathBuf,
+    /// The line number in the ignore file, from 1
+    pub line: usize,
+    /// The directory of the ignore file relative to the workdir, with `/` separators, empty for the root
+    /// and the files not in the worktree
+    base: String,
+}
+
+impl Rule {
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        let path = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(path) => path,
+                None => return false,
+            }
+        };
+        self.pattern.matches(path, is_dir)
+    }
+}
+
+/// Read the rules of an ignore file, empty if it doesn't exist
+fn read_rules(file: &Path, base: &str) -> Vec<Rule> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            Pattern::parse(line).map(|pattern| Rule {
+                pattern,
+                text: line.to_string(),
+                source: file.to_path_buf(),
+                line: i + 1,
+                base: base.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// A workdir path with `/` separators, empty for the workdir itself
+pub fn to_slash(path: &Path) -> String {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The ignore rules of the repository, the ignore files of each directory are read on demand
+pub struct IgnoreRules {
+    /// `core.excludesFile` & `info/exclude`, in the order of precedence from low to high
+    global: Vec<Rule>,
+    /// The rules of the ignore files in each directory, by the workdir path of the directory
+    dirs: HashMap<String, Vec<Rule>>,
+}
+
+impl IgnoreRules {
+    /// Load the rules of the current repository
+    pub async fn load() -> Self {
+        let excludes_file = Config::get("core", None, "excludesFile")
+            .await
+            .map(|file| match file.strip_prefix("~/") {
+                Some(file) => {
+                    PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(file)
+                }
+                None => PathBuf::from(file),
+            });
+        Self::new(excludes_file.as_deref())
+    }
+
+    fn new(excludes_file: Option<&Path>) -> Self {
+        let mut global = Vec::new();
+        if let Some(file) = excludes_file {
+            global.extend(read_rules(file, ""));
+        }
+        global.extend(read_rules(&util::storage_path().join("info/exclude"), ""));
+        IgnoreRules {
+            global,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// The rules of the ignore files in `dir`, a workdir path with `/` separators
+    fn dir_rules(&mut self, dir: &str) -> &[Rule] {
+        self.dirs.entry(dir.to_string()).or_insert_with(|| {
+            let abs = util::workdir_to_absolute(dir);
+            IGNORE_FILES
+                .iter()
+                .flat_map(|name| read_rules(&abs.join(name), dir))
+                .collect()
+        })
+    }
+
+    /// The last rule matching the workdir `path` itself, which may be negated, regardless of its parents
+    fn last_match(&mut self, path: &str, is_dir: bool) -> Option<Rule> {
+        // the ancestors of the path, from the root
+        let mut dirs = vec![String::new()];
+        for (i, c) in path.char_indices() {
+            if c == '/' {
+                dirs.push(path[..i].to_string());
+            }
+        }
+        for dir in &dirs {
+            self.dir_rules(dir);
+        }
+        self.global
+            .iter()
+            .chain(dirs.iter().flat_map(|dir| &self.dirs[dir]))
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .cloned()
+    }
+
+    /// The rule deciding if the workdir `path` is ignored: the rule excluding one of its parent directories,
+    /// otherwise the last rule matching it, which is negated if the path is re-included
+    pub fn matched(&mut self, path: &Path, is_dir: bool) -> Option<Rule> {
+        let path = to_slash(path);
+        if path.is_empty() {
+            return None; // the workdir itself
+        }
+        for (i, c) in path.char_indices() {
+            if c != '/' {
+                continue;
+            }
+            if let Some(rule) = self.last_match(&path[..i], true) {
+                if !rule.pattern.negated {
+                    return Some(rule);
+                }
+            }
+        }
+        self.last_match(&path, is_dir)
+    }
+
+    /// If the workdir `path` is ignored, including being in an ignored directory
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        self.matched(path, is_dir)
+            .is_some_and(|rule| !rule.pattern.negated)
+    }
+
+    /// List the files in `dir` & its sub-dirs which are not ignored, the ignored directories are not walked.
+    /// Like [util::list_files], `.libra` is skipped and a nested repository is listed as itself.
+    /// - input `dir`: absolute path or relative path to the current dir, not ignored itself
+    /// - output: to workdir path
+    pub fn list_files(&mut self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if !dir.is_dir() || dir.file_name().unwrap_or_default() == util::ROOT_DIR {
+            return Ok(files);
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.file_name().unwrap_or_default() == util::ROOT_DIR {
+                continue; // the `.libra` file of a linked worktree
+            }
+            let is_dir = path.is_dir();
+            let workdir_path = util::to_workdir_path(&path);
+            let ignored = self
+                .last_match(&to_slash(&workdir_path), is_dir)
+                .is_some_and(|rule| !rule.pattern.negated);
+            if ignored {
+                continue;
+            }
+            if is_dir && !util::is_nested_repo(&path) {
+                files.extend(self.list_files(&path)?);
+            } else {
+                files.push(workdir_path);
+            }
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    fn matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+        Pattern::parse(pattern).unwrap().matches(path, is_dir)
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(Pattern::parse("# comment"), None);
+        assert_eq!(Pattern::parse("   "), None);
+        assert_eq!(Pattern::parse("/"), None);
+        let pattern = Pattern::parse("!/build/  ").unwrap();
+        assert!(pattern.negated && pattern.dir_only && pattern.anchored);
+        assert_eq!(pattern.glob, "build");
+        assert_eq!(Pattern::parse("a\\ ").unwrap().glob, "a\\ ");
+        assert!(!Pattern::parse("*.log").unwrap().anchored);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(matches("*.log", "a.log", false));
+        assert!(matches("*.log", "dir/sub/a.log", false));
+        assert!(!matches("*.log", "a.log.txt", false));
+        assert!(matches("/a.log", "a.log", false));
+        assert!(!matches("/a.log", "dir/a.log", false));
+        assert!(matches("doc/*.txt", "doc/a.txt", false));
+        assert!(!matches("doc/*.txt", "doc/sub/a.txt", false));
+        assert!(matches("build/", "build", true));
+        assert!(!matches("build/", "build", false));
+        assert!(matches("**/foo", "foo", false));
+        assert!(matches("**/foo", "a/b/foo", false));
+        assert!(matches("a/**/b", "a/b", false));
+        assert!(matches("a/**/b", "a/x/y/b", false));
+        assert!(matches("a/**", "a/x/y", false));
+        assert!(!matches("a/**", "b/x", false));
+        assert!(matches("a?c", "abc", false));
+        assert!(!matches("a?c", "a/c", false));
+        assert!(matches("[a-c]x", "bx", false));
+        assert!(!matches("[!a-c]x", "bx", false));
+        assert!(matches("[]]", "]", false));
+        assert!(matches("\\#a", "#a", false));
+        assert!(matches("\\!a", "!a", false));
+        assert!(matches("a\\ ", "a ", false));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_ignore_rules() {
+        test::setup_with_new_libra().await;
+        test::ensure_file(".gitignore", Some("*.log\nbuild/\n!keep.log\n"));
+        test::ensure_file("sub/.libraignore", Some("!*.log\ntmp\n"));
+        test::ensure_file("sub/.gitignore", Some("*.log\n"));
+        fs::write(util::storage_path().join("info/exclude"), "secret\n").unwrap();
+        let global = tempfile::NamedTempFile::new().unwrap();
+        fs::write(global.path(), "*.bak\n!secret\n").unwrap();
+
+        let mut rules = IgnoreRules::new(Some(global.path()));
+        let mut ignored = |path: &str, is_dir| rules.is_ignored(Path::new(path), is_dir);
+        assert!(ignored("a.log", false));
+        assert!(!ignored("keep.log", false));
+        assert!(ignored("build", true));
+        assert!(ignored("build/out.txt", false));
+        assert!(!ignored("build", false)); // a file named `build`
+                                           // `.libraignore` takes precedence over `.gitignore` in the same directory
+        assert!(!ignored("sub/a.log", false));
+        assert!(ignored("sub/tmp", false));
+        assert!(!ignored("tmp", false));
+        // info/exclude takes precedence over core.excludesFile
+        assert!(ignored("secret", false));
+        assert!(ignored("a.bak", false));
+
+        let rule = rules.matched(Path::new("build/out.txt"), false).unwrap();
+        assert_eq!((rule.line, rule.text.as_str()), (2, "build/"));
+        let rule = rules.matched(Path::new("keep.log"), false).unwrap();
+        assert!(rule.pattern.negated);
+        assert_eq!(rule.source, util::working_dir().join(".gitignore"));
+
+        test::ensure_file("build/out.txt", None);
+        test::ensure_file("sub/a.log", None);
+        test::ensure_file("sub/b.txt", None);
+        test::ensure_file("c.log", None);
+        let mut files = rules.list_files(&util::working_dir()).unwrap();
+        files.sort();
+        let expected: Vec<PathBuf> = [".gitignore", "sub/.gitignore", "sub/.libraignore"]
+            .iter()
+            .chain(&["sub/a.log", "sub/b.txt"])
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(files, expected);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::utils::ignore::Pattern;
 use crate::utils::path_ext::PathExt;
 use crate::utils::{path, util};
 use lazy_static::lazy_static;
@@ -10,7 +11,6 @@ use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 use url::Url;
-use wax::Pattern;
 
 lazy_static! {
     static ref LFS_PATTERNS: Vec<String> = { // cache
@@ -27,7 +27,7 @@ lazy_static! {
 }
 
 /// Check if a file is LFS tracked
-/// - support Glob pattern matching (TODO: support .gitignore patterns)
+/// - the patterns are matched like .gitignore, except that negative patterns are not allowed
 /// - only check root attributes file now, should check all attributes files in sub-dirs
 /// - absolute path
 pub fn is_lfs_tracked<P>(path: P) -> bool
@@ -39,8 +39,11 @@ where
     }
 
     let path = util::to_workdir_path(path);
-    let glob = wax::any(LFS_PATTERNS.iter().map(|s| s.as_str()).collect::<Vec<_>>()).unwrap();
-    glob.is_match(path.to_str().unwrap())
+    let path = path.to_str().unwrap();
+    LFS_PATTERNS
+        .iter()
+        .filter_map(|pattern| Pattern::parse(pattern))
+        .any(|pattern| !pattern.negated && pattern.matches(path, false))
 }
 
 const LFS_VERSION: &str = "https://git-lfs.github.com/spec/v1";
@@ -0,0 +1,227 @@
+//! The multi-pack-index (MIDX) at `objects/pack/multi-pack-index`, same format as git:
+//! the objects of all packs sorted in one table, so an object is found by one binary search
+//! rather than by searching every .idx file.
+//!
+//! [gitformat-pack](https://git-scm.com/docs/gitformat-pack#_multi_pack_index_midx_files_have_the_following_format)
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use mercury::hash::SHA1;
+
+use crate::utils::chunk::{self, Chunks};
+
+const SIGNATURE: &[u8; 4] = b"MIDX";
+const CHUNK_PACK_NAMES: u32 = 0x504e414d; // "PNAM"
+const CHUNK_OID_FANOUT: u32 = 0x4f494446; // "OIDF"
+const CHUNK_OID_LOOKUP: u32 = 0x4f49444c; // "OIDL"
+const CHUNK_OBJECT_OFFSETS: u32 = 0x4f4f4646; // "OOFF"
+const CHUNK_LARGE_OFFSETS: u32 = 0x4c4f4646; // "LOFF"
+const HEADER_SIZE: usize = 12;
+
+pub struct MultiPackIndex {
+    /// The names of the .idx files, sorted
+    pub packs: Vec<String>,
+    fanout: [u32; 256],
+    /// All objects sorted by hash, the position of an object is its index here
+    pub objects: Vec<SHA1>,
+    /// `(pack index in packs, offset in the pack)` of each object
+    offsets: Vec<(u32, u64)>,
+    /// The checksum of the file, to check if a bitmap is built for it
+    pub checksum: SHA1,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("multi-pack-index: {}", msg),
+    )
+}
+
+impl MultiPackIndex {
+    /// Build from the objects & offsets of each pack, `packs` is `(idx file name, [(hash, offset)])`.
+    /// If an object is in more than one pack, the first pack in name order is taken.
+    pub fn new(mut packs: Vec<(String, Vec<(SHA1, u64)>)>) -> Self {
+        packs.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut objects = BTreeMap::new();
+        for (i, (_, entries)) in packs.iter().enumerate() {
+            for (hash, offset) in entries {
+                objects.entry(*hash).or_insert((i as u32, *offset));
+            }
+        }
+        let mut counts = [0; 256];
+        for hash in objects.keys() {
+            counts[hash.0[0] as usize] += 1;
+        }
+        let mut total = 0;
+        let fanout = counts.map(|count| {
+            total += count;
+            total
+        });
+        MultiPackIndex {
+            packs: packs.into_iter().map(|(name, _)| name).collect(),
+            fanout,
+            objects: objects.keys().copied().collect(),
+            offsets: objects.into_values().collect(),
+            checksum: SHA1::default(),
+        }
+    }
+
+    /// The position of the object in [MultiPackIndex::objects]
+    pub fn position(&self, obj_id: &SHA1) -> Option<usize> {
+        let first_byte = obj_id.0[0] as usize;
+        let start = if first_byte == 0 {
+            0
+        } else {
+            self.fanout[first_byte - 1] as usize
+        };
+        let end = self.fanout[first_byte] as usize;
+        self.objects[start..end]
+            .binary_search(obj_id)
+            .ok()
+            .map(|i| start + i)
+    }
+
+    /// Find the .idx file name & the offset of the object in its pack
+    pub fn find(&self, obj_id: &SHA1) -> Option<(&str, u64)> {
+        let (pack, offset) = self.offsets[self.position(obj_id)?];
+        Some((&self.packs[pack as usize], offset))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        // chunks in the order of the file
+        let mut pack_names = Vec::new();
+        for pack in &self.packs {
+            pack_names.write_all(pack.as_bytes())?;
+            pack_names.write_u8(0)?;
+        }
+        while pack_names.len() % 4 != 0 {
+            pack_names.write_u8(0)?; // aligned to 4 bytes
+        }
+        let mut fanout = Vec::with_capacity(256 * 4);
+        for count in self.fanout {
+            fanout.write_u32::<BigEndian>(count)?;
+        }
+        let mut lookup = Vec::with_capacity(self.objects.len() * 20);
+        for hash in &self.objects {
+            lookup.write_all(&hash.0)?;
+        }
+        // the MSB of an offset marks it as an index of the large offsets
+        let mut offsets = Vec::with_capacity(self.offsets.len() * 8);
+        let mut large_offsets = Vec::new();
+        for &(pack, offset) in &self.offsets {
+            offsets.write_u32::<BigEndian>(pack)?;
+            if offset < 0x8000_0000 {
+                offsets.write_u32::<BigEndian>(offset as u32)?;
+            } else {
+                offsets.write_u32::<BigEndian>(0x8000_0000 | (large_offsets.len() / 8) as u32)?;
+                large_offsets.write_u64::<BigEndian>(offset)?;
+            }
+        }
+        let mut chunks = vec![
+            (CHUNK_PACK_NAMES, pack_names),
+            (CHUNK_OID_FANOUT, fanout),
+            (CHUNK_OID_LOOKUP, lookup),
+            (CHUNK_OBJECT_OFFSETS, offsets),
+        ];
+        if !large_offsets.is_empty() {
+            chunks.push((CHUNK_LARGE_OFFSETS, large_offsets));
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.write_all(SIGNATURE)?;
+        header.write_u8(1)?; // version
+        header.write_u8(1)?; // object id version, SHA-1
+        header.write_u8(chunks.len() as u8)?;
+        header.write_u8(0)?; // number of base multi-pack-index files
+        header.write_u32::<BigEndian>(self.packs.len() as u32)?;
+        chunk::write(&header, chunks)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, io::Error> {
+        if data.len() < HEADER_SIZE + 20 || &data[..4] != SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(invalid("unsupported version"));
+        }
+        if data[7] != 0 {
+            return Err(invalid("incremental multi-pack-index is not supported"));
+        }
+        let num_packs = BigEndian::read_u32(&data[8..12]) as usize;
+        let chunks = Chunks::read(data, HEADER_SIZE, data[6] as usize).map_err(invalid)?;
+        let chunk = |id: u32, len: usize| chunks.require(id, len).map_err(invalid);
+
+        let pack_names = chunks.get(CHUNK_PACK_NAMES);
+        let packs: Vec<String> = pack_names
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect();
+        if packs.len() != num_packs {
+            return Err(invalid("bad pack names"));
+        }
+        let mut fanout = [0; 256];
+        BigEndian::read_u32_into(chunk(CHUNK_OID_FANOUT, 256 * 4)?, &mut fanout);
+        let num_objects = fanout[255] as usize;
+        let objects = chunk(CHUNK_OID_LOOKUP, num_objects * 20)?
+            .chunks(20)
+            .map(SHA1::from_bytes)
+            .collect();
+        let large_offsets = chunks.get(CHUNK_LARGE_OFFSETS);
+        let offsets = chunk(CHUNK_OBJECT_OFFSETS, num_objects * 8)?
+            .chunks(8)
+            .map(|entry| {
+                let pack = BigEndian::read_u32(&entry[..4]);
+                if pack as usize >= num_packs {
+                    return Err(invalid("bad pack id"));
+                }
+                let offset = BigEndian::read_u32(&entry[4..]);
+                if offset & 0x8000_0000 == 0 {
+                    return Ok((pack, offset as u64));
+                }
+                let i = (offset & 0x7fff_ffff) as usize * 8;
+                large_offsets
+                    .get(i..i + 8)
+                    .map(|large| (pack, BigEndian::read_u64(large)))
+                    .ok_or_else(|| invalid("bad large offset"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(MultiPackIndex {
+            packs,
+            fanout,
+            objects,
+            offsets,
+            checksum: chunks.checksum,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midx_round_trip() {
+        let (a, b, c) = (SHA1::new(b"a"), SHA1::new(b"b"), SHA1::new(b"c"));
+        let midx = MultiPackIndex::new(vec![
+            ("pack-2.idx".to_string(), vec![(c, 0x1_0000_0000), (a, 42)]),
+            ("pack-1.idx".to_string(), vec![(a, 12), (b, 100)]),
+        ]);
+        let data = midx.to_bytes().unwrap();
+        let midx = MultiPackIndex::from_bytes(&data).unwrap();
+        assert_eq!(midx.packs, vec!["pack-1.idx", "pack-2.idx"]);
+        assert_eq!(midx.objects.len(), 3);
+        assert_eq!(midx.find(&a), Some(("pack-1.idx", 12)));
+        assert_eq!(midx.find(&b), Some(("pack-1.idx", 100)));
+        assert_eq!(midx.find(&c), Some(("pack-2.idx", 0x1_0000_0000)));
+        assert_eq!(midx.find(&SHA1::new(b"d")), None);
+        assert_eq!(midx.checksum, SHA1::from_bytes(&data[data.len() - 20..]));
+
+        let mut corrupt = data.clone();
+        corrupt[HEADER_SIZE + 1] ^= 1;
+        assert!(MultiPackIndex::from_bytes(&corrupt).is_err());
+    }
+}
@@ -1,7 +1,15 @@
+pub(crate) mod bitmap;
+pub(crate) mod chunk;
 pub(crate) mod client_storage;
+pub(crate) mod hooks;
+pub(crate) mod ignore;
 pub mod lfs;
+pub(crate) mod midx;
 pub(crate) mod object_ext;
 pub(crate) mod path;
 pub(crate) mod path_ext;
+pub(crate) mod sparse;
+pub(crate) mod submodule;
 pub(crate) mod test;
 pub(crate) mod util;
+pub(crate) mod worktree;
@@ -1,18 +1,35 @@
-use colored::Colorize;
 use mercury::hash::SHA1;
 use mercury::internal::object::blob::Blob;
 use mercury::internal::object::commit::Commit;
 use mercury::internal::object::tree::{Tree, TreeItemMode};
 use mercury::internal::object::ObjectTrait;
+use std::collections::HashSet;
 use std::fs;
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 
+use crate::command::fetch;
+use crate::internal::shallow;
 use crate::utils::{lfs, util};
 
+/// Fetch the objects omitted by a partial clone in one request, rather than one by one,
+/// the ones already in the repository are skipped
+pub async fn fetch_missing_objects(objects: impl IntoIterator<Item = SHA1>) -> Result<(), String> {
+    let storage = util::objects_storage();
+    let missing = objects
+        .into_iter()
+        .filter(|hash| !storage.exist(hash))
+        .collect::<HashSet<_>>();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    fetch::fetch_promised_objects(&missing.into_iter().collect::<Vec<_>>()).await
+}
+
 pub trait TreeExt {
     fn load(hash: &SHA1) -> Tree;
     fn get_plain_items(&self) -> Vec<(PathBuf, SHA1)>;
+    fn get_plain_items_with_mode(&self) -> Vec<(PathBuf, SHA1, TreeItemMode)>;
 }
 
 pub trait CommitExt {
@@ -34,25 +51,32 @@ impl TreeExt for Tree {
     }
 
     /// Get all the items in the tree recursively (to workdir path)
+    /// - a submodule is a gitlink item, its hash is the commit checked out in it
     fn get_plain_items(&self) -> Vec<(PathBuf, SHA1)> {
+        self.get_plain_items_with_mode()
+            .into_iter()
+            .map(|(path, hash, _)| (path, hash))
+            .collect()
+    }
+
+    /// Like [TreeExt::get_plain_items], with the modes to tell the gitlinks (`TreeItemMode::Commit`) apart
+    fn get_plain_items_with_mode(&self) -> Vec<(PathBuf, SHA1, TreeItemMode)> {
         let mut items = Vec::new();
         for item in self.tree_items.iter() {
             if item.mode != TreeItemMode::Tree {
-                // Not Tree, maybe Blob, link, etc.
-                if item.mode == TreeItemMode::Commit {
-                    // submodule
-                    eprintln!("{}", "Warning: Submodule is not supported yet".red());
-                }
-                items.push((PathBuf::from(item.name.clone()), item.id));
+                // Not Tree, maybe Blob, link, submodule etc.
+                items.push((PathBuf::from(item.name.clone()), item.id, item.mode));
             } else {
                 let sub_tree = Tree::load(&item.id);
-                let sub_entries = sub_tree.get_plain_items();
+                let sub_entries = sub_tree.get_plain_items_with_mode();
 
                 items.append(
                     sub_entries
-                        .iter()
-                        .map(|(path, hash)| (PathBuf::from(item.name.clone()).join(path), *hash))
-                        .collect::<Vec<(PathBuf, SHA1)>>()
+                        .into_iter()
+                        .map(|(path, hash, mode)| {
+                            (PathBuf::from(item.name.clone()).join(path), hash, mode)
+                        })
+                        .collect::<Vec<_>>()
                         .as_mut(),
                 );
             }
@@ -62,16 +86,25 @@ impl TreeExt for Tree {
 }
 
 impl CommitExt for Commit {
+    /// The parents of a shallow commit are cut off, they are not in the repository
     fn load(hash: &SHA1) -> Commit {
         let storage = util::objects_storage();
         let commit_data = storage.get(hash).unwrap();
-        Commit::from_bytes(&commit_data, *hash).unwrap()
+        let mut commit = Commit::from_bytes(&commit_data, *hash).unwrap();
+        shallow::graft(&mut commit);
+        commit
     }
 }
 
 impl BlobExt for Blob {
+    /// A blob omitted by a partial clone is fetched from the promisor remote on demand
     fn load(hash: &SHA1) -> Blob {
         let storage = util::objects_storage();
+        if !storage.exist(hash) {
+            if let Err(e) = fetch::fetch_promised_objects_blocking(&[*hash]) {
+                eprintln!("fatal: failed to fetch missing object {}: {}", hash, e);
+            }
+        }
         let blob_data = storage.get(hash).unwrap();
         Blob::from_bytes(&blob_data, *hash).unwrap()
     }
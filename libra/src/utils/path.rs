@@ -1,8 +1,9 @@
 use crate::utils::util;
 use std::path::PathBuf;
 
+/// The index of the current worktree
 pub fn index() -> PathBuf {
-    util::storage_path().join("index")
+    util::worktree_storage_path().join("index")
 }
 
 pub fn objects() -> PathBuf {
@@ -16,3 +17,48 @@ pub fn database() -> PathBuf {
 pub fn attributes() -> PathBuf {
     util::working_dir().join(util::ATTRIBUTES)
 }
+
+/// `.libra/MERGE_HEAD`, the commit being merged when a merge stops with conflicts
+pub fn merge_head() -> PathBuf {
+    util::worktree_storage_path().join("MERGE_HEAD")
+}
+
+/// `.libra/MERGE_MSG`, the default message of the merge commit
+pub fn merge_msg() -> PathBuf {
+    util::worktree_storage_path().join("MERGE_MSG")
+}
+
+/// `.libra/COMMIT_EDITMSG`, the message of the commit in progress, edited by the hooks
+pub fn commit_editmsg() -> PathBuf {
+    util::worktree_storage_path().join("COMMIT_EDITMSG")
+}
+
+/// `.libra/rebase-merge`, the directory to keep the state of an in-progress rebase
+pub fn rebase_merge() -> PathBuf {
+    util::worktree_storage_path().join("rebase-merge")
+}
+
+/// `.libra/sequencer`, the directory to keep the state of an in-progress cherry-pick or revert
+pub fn sequencer() -> PathBuf {
+    util::worktree_storage_path().join("sequencer")
+}
+
+/// `.libra/shallow`, the commits of a shallow clone whose parents are not in the repository
+pub fn shallow() -> PathBuf {
+    util::storage_path().join("shallow")
+}
+
+/// `.libra/worktrees`, the storages of the linked worktrees, each in a directory named after the worktree
+pub fn worktrees() -> PathBuf {
+    util::storage_path().join("worktrees")
+}
+
+/// `.libra/objects/info/commit-graph`, the parents & generation numbers of the commits
+pub fn commit_graph() -> PathBuf {
+    objects().join("info").join("commit-graph")
+}
+
+/// `.libra/objects/info/commit-graphs`, the incremental layers on the commit-graph & their chain
+pub fn commit_graphs() -> PathBuf {
+    objects().join("info").join("commit-graphs")
+}
@@ -0,0 +1,236 @@
+//! The sparse checkout: only the files in the sparse set are materialised in the worktree.
+//! - cone mode (default): the set is directories, including all the files under them, the files directly
+//!   in their parent directories, and the files at the root
+//! - non-cone mode: gitignore-style patterns of the files to include, the last matching one decides
+//!
+//! The set is in the config of the database, kept per worktree: `sparse.enabled`, `sparse.cone` and
+//! `sparse.pattern` (multiple) for the main worktree, `sparse.<name>.*` for the linked worktree `<name>`.
+//! The tracked files out of it are marked skip-worktree in the index,
+//! so they are neither expected in the worktree nor reported as deleted.
+//!
+//! [git-sparse-checkout](https://git-scm.com/docs/git-sparse-checkout)
+
+use std::path::Path;
+
+use crate::internal::config::Config;
+use crate::utils::ignore::{self, Pattern};
+use crate::utils::util;
+
+/// The patterns of non-cone mode equivalent to an empty cone: only the files at the root
+pub const DEFAULT_PATTERNS: [&str; 2] = ["/*", "!/*/"];
+
+const SECTION: &str = "sparse";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseCheckout {
+    cone: bool,
+    /// Directories without the leading & trailing `/` in cone mode, otherwise gitignore-style patterns
+    patterns: Vec<String>,
+    rules: Vec<Pattern>,
+}
+
+impl SparseCheckout {
+    pub fn new(cone: bool, patterns: &[String]) -> Self {
+        let mut sparse = SparseCheckout {
+            cone,
+            patterns: Vec::new(),
+            rules: Vec::new(),
+        };
+        sparse.add(patterns);
+        sparse
+    }
+
+    /// The sparse checkout of the current worktree, `None` if it's disabled
+    pub async fn load() -> Option<Self> {
+        let worktree = worktree();
+        if Config::get(SECTION, worktree.as_deref(), "enabled")
+            .await
+            .as_deref()
+            != Some("true")
+        {
+            return None;
+        }
+        Self::load_saved().await
+    }
+
+    /// The set saved last time, even if the sparse checkout is disabled, `None` if there is none
+    pub async fn load_saved() -> Option<Self> {
+        let worktree = worktree();
+        let cone = Config::get(SECTION, worktree.as_deref(), "cone").await?;
+        let patterns = Config::get_all(SECTION, worktree.as_deref(), "pattern").await;
+        Some(Self::new(cone != "false", &patterns))
+    }
+
+    /// Enable the sparse checkout with this set
+    pub async fn save(&self) {
+        let worktree = worktree();
+        let worktree = worktree.as_deref();
+        set_config(worktree, "enabled", "true").await;
+        set_config(worktree, "cone", &self.cone.to_string()).await;
+        Config::remove_config(SECTION, worktree, "pattern", None, true).await;
+        for pattern in &self.patterns {
+            Config::insert(SECTION, worktree, "pattern", pattern).await;
+        }
+    }
+
+    /// Disable the sparse checkout, the set is kept for the next `init`
+    pub async fn disable() {
+        set_config(worktree().as_deref(), "enabled", "false").await;
+    }
+
+    /// Remove the set of the linked worktree `name`, when the worktree is removed
+    pub async fn remove_worktree(name: &str) {
+        for key in ["enabled", "cone", "pattern"] {
+            Config::remove_config(SECTION, Some(name), key, None, true).await;
+        }
+    }
+
+    pub fn is_cone(&self) -> bool {
+        self.cone
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Add directories (cone mode) or patterns to the set, the duplicates are skipped
+    pub fn add(&mut self, patterns: &[String]) {
+        for pattern in patterns {
+            let pattern = if self.cone {
+                pattern.trim().trim_start_matches("./").trim_matches('/')
+            } else {
+                pattern.trim()
+            };
+            if pattern.is_empty() || self.patterns.iter().any(|p| p == pattern) {
+                continue;
+            }
+            if !self.cone {
+                match Pattern::parse(pattern) {
+                    Some(rule) => self.rules.push(rule),
+                    None => continue, // comment
+                }
+            }
+            self.patterns.push(pattern.to_string());
+        }
+    }
+
+    /// If the file at `path` (to workdir) is in the sparse set
+    pub fn contains(&self, path: &Path) -> bool {
+        let path = ignore::to_slash(path);
+        let mut dirs = Vec::new(); // the parent directories, from the root
+        let mut end = 0;
+        while let Some(pos) = path[end..].find('/') {
+            end += pos;
+            dirs.push(&path[..end]);
+            end += 1;
+        }
+        if self.cone {
+            let Some(parent) = dirs.last() else {
+                return true; // at the root
+            };
+            // under a directory of the set, or directly in one of its parents
+            self.patterns
+                .iter()
+                .any(|dir| is_under(parent, dir) || is_under(dir, parent))
+        } else {
+            self.rules
+                .iter()
+                .rev()
+                .find(|rule| {
+                    rule.matches(&path, false) || dirs.iter().any(|d| rule.matches(d, true))
+                })
+                .is_some_and(|rule| !rule.negated)
+        }
+    }
+}
+
+/// The config subsection of the current worktree: the name of a linked worktree, `None` for the main one
+fn worktree() -> Option<String> {
+    util::is_linked_worktree().then(|| {
+        let storage = util::worktree_storage_path();
+        storage.file_name().unwrap().to_string_lossy().into_owned()
+    })
+}
+
+async fn set_config(worktree: Option<&str>, key: &str, value: &str) {
+    if Config::get(SECTION, worktree, key).await.is_some() {
+        Config::update(SECTION, worktree, key, value).await;
+    } else {
+        Config::insert(SECTION, worktree, key, value).await;
+    }
+}
+
+/// If `path` is `dir` or under it
+fn is_under(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Whether the file at `path` is materialised, all the files are if the sparse checkout is disabled
+pub fn is_included(sparse: Option<&SparseCheckout>, path: &Path) -> bool {
+    match sparse {
+        Some(sparse) => sparse.contains(path),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+    use crate::utils::test;
+
+    fn sparse(cone: bool, patterns: &[&str]) -> SparseCheckout {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        SparseCheckout::new(cone, &patterns)
+    }
+
+    #[test]
+    fn test_cone_contains() {
+        let sparse = sparse(true, &["/a/b/", "./c", "c"]);
+        assert_eq!(sparse.patterns(), ["a/b", "c"]);
+        let contains = |path: &str| sparse.contains(Path::new(path));
+        assert!(contains("README.md"));
+        assert!(contains("a/file"));
+        assert!(contains("a/b/file"));
+        assert!(contains("a/b/d/e/file"));
+        assert!(contains("c/file"));
+        assert!(!contains("a/x/file"));
+        assert!(!contains("ab/file"));
+        assert!(!contains("d/file"));
+    }
+
+    #[test]
+    fn test_non_cone_contains() {
+        let sparse = sparse(false, &["/*", "!/*/", "docs/", "!docs/draft/", "*.md"]);
+        let contains = |path: &str| sparse.contains(Path::new(path));
+        assert!(contains("Cargo.toml"));
+        assert!(!contains("src/main.rs"));
+        assert!(contains("docs/guide/index.html"));
+        assert!(!contains("docs/draft/index.html"));
+        assert!(contains("src/README.md"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_save_load() {
+        test::setup_with_new_libra().await;
+        assert_eq!(SparseCheckout::load().await, None);
+
+        let saved = sparse(true, &["a", "b/c"]);
+        saved.save().await;
+        assert_eq!(SparseCheckout::load().await, Some(saved));
+        let saved = sparse(false, &DEFAULT_PATTERNS);
+        saved.save().await;
+        assert_eq!(SparseCheckout::load().await, Some(saved.clone()));
+
+        SparseCheckout::disable().await;
+        assert_eq!(SparseCheckout::load().await, None);
+        assert_eq!(SparseCheckout::load_saved().await, Some(saved));
+        assert_eq!(
+            Config::get_all(SECTION, None, "pattern").await,
+            DEFAULT_PATTERNS
+        );
+    }
+}
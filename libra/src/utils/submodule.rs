@@ -0,0 +1,226 @@
+//! Submodules: the repositories nested in the working directory, recorded as gitlinks (mode `160000`)
+//! in the trees & the index, whose hashes are the commits checked out in them.
+//! - `.gitmodules` maps the paths of the submodules to their names & urls, in the git config format
+//! - a submodule is initialized once `submodule.<name>.url` is in the config
+//!
+//! [gitsubmodules](https://git-scm.com/docs/gitsubmodules)
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs, io};
+
+use mercury::hash::SHA1;
+
+use crate::internal::config::Config;
+use crate::internal::head::Head;
+use crate::internal::protocol::local_client;
+use crate::utils::util;
+
+pub const GITMODULES: &str = ".gitmodules";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submodule {
+    pub name: String,
+    /// to workdir
+    pub path: PathBuf,
+    pub url: String,
+    pub branch: Option<String>,
+}
+
+/// A `[submodule "<name>"]` section of `.gitmodules`: (name, path, url, branch)
+type Section = (String, Option<String>, Option<String>, Option<String>);
+
+/// Parse the content of `.gitmodules`, the submodules without `path` or `url` are skipped
+pub fn parse(content: &str) -> Vec<Submodule> {
+    // in the order of appearance, a section can be split
+    let mut sections: Vec<Section> = Vec::new();
+    let mut current: Option<usize> = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = header
+                .strip_prefix("submodule")
+                .map(str::trim)
+                .and_then(|name| name.strip_prefix('"')?.strip_suffix('"'))
+                .map(|name| match sections.iter().position(|s| s.0 == name) {
+                    Some(i) => i,
+                    None => {
+                        sections.push((name.to_string(), None, None, None));
+                        sections.len() - 1
+                    }
+                });
+            continue;
+        }
+        let (Some(i), Some((key, value))) = (current, line.split_once('=')) else {
+            continue;
+        };
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .to_string();
+        match key.trim().to_lowercase().as_str() {
+            "path" => sections[i].1 = Some(value),
+            "url" => sections[i].2 = Some(value),
+            "branch" => sections[i].3 = Some(value),
+            _ => {}
+        }
+    }
+    sections
+        .into_iter()
+        .filter_map(|(name, path, url, branch)| {
+            Some(Submodule {
+                name,
+                path: PathBuf::from(path?.trim_end_matches('/')),
+                url: url?,
+                branch,
+            })
+        })
+        .collect()
+}
+
+/// The submodules in `.gitmodules` of the working directory, empty if there is none
+pub fn load() -> Vec<Submodule> {
+    match fs::read_to_string(util::working_dir().join(GITMODULES)) {
+        Ok(content) => parse(&content),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Resolve a url relative to the remote of the super project (`./` or `../`), others are kept as is
+/// - `base`: the url of the remote, or the working directory if there is no remote
+pub fn resolve_url(url: &str, base: &str) -> Result<String, String> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return Ok(url.to_string());
+    }
+    let mut base = base.trim_end_matches('/').to_string();
+    let mut rest = url;
+    loop {
+        if let Some(r) = rest.strip_prefix("./") {
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("../") {
+            match base.rfind('/') {
+                Some(pos) => base.truncate(pos),
+                None => return Err(format!("cannot strip one component off url '{}'", base)),
+            }
+            rest = r;
+        } else {
+            break;
+        }
+    }
+    Ok(format!("{}/{}", base, rest))
+}
+
+/// The url the relative urls of the submodules are resolved against:
+/// the remote of the current branch, `origin`, or the working directory
+pub async fn base_url() -> String {
+    let remote = match Head::current().await {
+        Head::Branch(branch) => Config::get_remote(&branch).await,
+        Head::Detached(_) => None,
+    };
+    let remote = remote.unwrap_or_else(|| "origin".to_string());
+    match Config::get("remote", Some(&remote), "url").await {
+        Some(url) => url,
+        None => util::working_dir_string(),
+    }
+}
+
+/// The commit checked out in the repository at `dir` (libra or git), `None` if it's not a repository or has no commit
+pub async fn head_commit(dir: &Path) -> Option<SHA1> {
+    let libra_dir = dir.join(util::ROOT_DIR);
+    let refs = if libra_dir.join(util::DATABASE).exists() {
+        local_client::libra_refs(&libra_dir).await.ok()?
+    } else if dir.join(".git").join("HEAD").exists() {
+        local_client::git_refs(&dir.join(".git")).ok()?
+    } else {
+        return None;
+    };
+    refs.into_iter()
+        .find(|(name, _)| name == "HEAD")
+        .map(|(_, commit)| commit)
+}
+
+/// Run libra in `dir`, e.g. in a submodule.
+/// The database connection is global to the process, so another repository is operated by another process.
+pub fn run_libra(dir: &Path, args: &[&str]) -> io::Result<()> {
+    let status = Command::new(env::current_exe()?)
+        .args(args)
+        .current_dir(dir)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "`libra {}` failed in '{}'",
+            args.join(" "),
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = r#"
+# comment
+[submodule "lib/a"]
+	path = lib/a/
+	url = https://example.com/a.git
+[core]
+	path = ignored
+[submodule "b"]
+	url = "../b.git"
+	branch = dev
+[submodule "no-url"]
+	path = c
+[submodule "b"]
+	path = b
+"#;
+        let submodules = parse(content);
+        assert_eq!(
+            submodules,
+            vec![
+                Submodule {
+                    name: "lib/a".to_string(),
+                    path: PathBuf::from("lib/a"),
+                    url: "https://example.com/a.git".to_string(),
+                    branch: None,
+                },
+                Submodule {
+                    name: "b".to_string(),
+                    path: PathBuf::from("b"),
+                    url: "../b.git".to_string(),
+                    branch: Some("dev".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "https://example.com/org/repo.git/";
+        assert_eq!(
+            resolve_url("../lib.git", base).unwrap(),
+            "https://example.com/org/lib.git"
+        );
+        assert_eq!(
+            resolve_url("./lib", base).unwrap(),
+            "https://example.com/org/repo.git/lib"
+        );
+        assert_eq!(
+            resolve_url("../lib", "git@example.com:org/repo").unwrap(),
+            "git@example.com:org/lib"
+        );
+        assert_eq!(
+            resolve_url("https://example.com/lib.git", base).unwrap(),
+            "https://example.com/lib.git"
+        );
+        assert!(resolve_url("../../lib", "repo").is_err());
+    }
+}
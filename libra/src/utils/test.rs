@@ -8,7 +8,10 @@ use std::io::Write;
 use std::path::Path;
 use std::{env, fs, path::PathBuf};
 
+use mercury::hash::SHA1;
+
 use crate::command;
+use crate::internal::head::Head;
 use crate::utils::util;
 
 pub const TEST_DIR: &str = "libra_test_repo";
@@ -70,34 +73,18 @@ fn setup_env() {
 /// Sets up a clean environment for testing.
 ///
 /// This function first calls `setup_env()` to switch the current directory to the test directory.
-/// Then, it checks if the Libra root directory (`.libra`) exists in the current directory.
-/// If it does, the function removes the entire `.libra` directory.
+/// Then, it removes everything in the test directory: the Libra root directory (`.libra`), the files of
+/// a bare repository, and the files left in the worktree by the previous tests.
 pub fn setup_clean_testing_env() {
     // Switch the current directory to the test directory
     setup_env();
 
-    // Get the current directory
-    let cur_path = util::cur_dir();
-
-    // Append the Libra root directory to the current directory
-    let root_path = cur_path.join(util::ROOT_DIR);
-
-    // If the Libra root directory exists, remove it
-    if root_path.exists() {
-        fs::remove_dir_all(&root_path).unwrap();
-    }
-
-    // Define the directories that are present in a bare repository
-    let bare_repo_dirs = ["objects", "info", "description", "libra.db"];
-
-    // Remove the directories that are present in a bare repository if they exist
-    for dir in bare_repo_dirs.iter() {
-        let bare_repo_path = cur_path.join(dir);
-        if bare_repo_path.exists() && bare_repo_path.is_dir() {
-            fs::remove_dir_all(&bare_repo_path).unwrap();
-        } else if bare_repo_path.exists() && !bare_repo_path.is_dir() {
-            // Remove the file if it exists
-            fs::remove_file(&bare_repo_path).unwrap();
+    for entry in fs::read_dir(util::cur_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path).unwrap();
+        } else {
+            fs::remove_file(&path).unwrap();
         }
     }
 }
@@ -145,3 +132,54 @@ pub fn ensure_file(path: impl AsRef<Path>, content: Option<&str>) {
 pub fn reset_working_dir() {
     env::set_current_dir(env!("CARGO_MANIFEST_DIR")).unwrap();
 }
+
+/// install a shell script as the hook `name` in `.libra/hooks`
+#[cfg(unix)]
+pub fn install_hook(name: &str, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = util::storage_path().join("hooks");
+    fs::create_dir_all(&dir).unwrap();
+    let hook = dir.join(name);
+    fs::write(&hook, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// `add --all`
+pub async fn add_all() {
+    command::add::execute(command::add::AddArgs {
+        pathspec: vec![],
+        all: true,
+        update: false,
+        verbose: false,
+        force: false,
+    })
+    .await;
+}
+
+/// `add --all` & commit with `message`
+pub async fn commit_all(message: &str) {
+    add_all().await;
+    command::commit::execute(command::commit::CommitArgs {
+        message: message.to_string(),
+        allow_empty: false,
+        conventional: false,
+        gpg_sign: false,
+        no_verify: false,
+    })
+    .await;
+}
+
+/// Write `content` to the file `name`, then `add --all` & commit with `message`, return the new commit
+pub async fn commit_file(name: &str, content: &str, message: &str) -> SHA1 {
+    ensure_file(name, Some(content));
+    commit_all(message).await;
+    Head::current_commit().await.unwrap()
+}
+
+/// `switch <branch>`
+pub async fn switch_to(branch: &str) {
+    use clap::Parser;
+
+    command::switch::execute(command::switch::SwitchArgs::parse_from(["switch", branch])).await;
+}
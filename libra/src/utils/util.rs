@@ -1,12 +1,15 @@
 use indicatif::{ProgressBar, ProgressStyle};
-use mercury::hash::SHA1;
-use mercury::internal::object::types::ObjectType;
+use mercury::internal::object::signature::Signature;
+use once_cell::sync::Lazy;
 use path_absolutize::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::{env, fs, io};
 
+use crate::internal::head::Head;
+
 use crate::utils::client_storage::ClientStorage;
 use crate::utils::path;
 use crate::utils::path_ext::PathExt;
@@ -29,15 +32,13 @@ pub fn cur_dir() -> PathBuf {
     env::current_dir().unwrap()
 }
 
-/// Try to get the storage path of the repository, which is the path of the `.libra` directory
-/// - if the current directory is not a repository, return an error
-pub fn try_get_storage_path() -> Result<PathBuf, io::Error> {
+/// Find the working directory of the repository, the nearest directory containing `.libra` from the current dir
+/// - `.libra` is a directory in the main worktree, or a file pointing to the storage in a linked worktree
+fn try_get_working_dir() -> Result<PathBuf, io::Error> {
     let mut cur_dir = env::current_dir()?;
     loop {
-        let mut libra = cur_dir.clone();
-        libra.push(ROOT_DIR);
-        if libra.exists() {
-            return Ok(libra);
+        if cur_dir.join(ROOT_DIR).exists() {
+            return Ok(cur_dir);
         }
         if !cur_dir.pop() {
             return Err(io::Error::new(
@@ -48,17 +49,82 @@ pub fn try_get_storage_path() -> Result<PathBuf, io::Error> {
     }
 }
 
+/// Read the storage of a linked worktree from its `.libra` file: `libradir: <path>`
+pub fn read_libradir(file: &Path) -> Result<PathBuf, io::Error> {
+    let content = fs::read_to_string(file)?;
+    content
+        .trim_end()
+        .strip_prefix("libradir: ")
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid libradir file: {}", file.display()),
+            )
+        })
+}
+
+/// Try to get the storage path of the repository, which is the path of the `.libra` directory
+/// - shared by all the worktrees, the `.libra` of the main worktree
+/// - if the current directory is not a repository, return an error
+pub fn try_get_storage_path() -> Result<PathBuf, io::Error> {
+    let libra = try_get_working_dir()?.join(ROOT_DIR);
+    if !libra.is_file() {
+        return Ok(libra);
+    }
+    // linked worktree: `.libra/worktrees/<name>` in the main worktree
+    let dir = read_libradir(&libra)?;
+    dir.parent()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid libradir: {}", dir.display()),
+            )
+        })
+}
+
 /// Get the storage path of the repository, aka `.libra`
 /// - panics if the current directory is not a repository
 pub fn storage_path() -> PathBuf {
     try_get_storage_path().unwrap()
 }
-/// Check if libra repo exists
+
+/// Get the storage of the current worktree, for its index, HEAD & the states of the operations in progress:
+/// `.libra` in the main worktree, `.libra/worktrees/<name>` in a linked worktree
+/// - panics if the current directory is not a repository
+pub fn worktree_storage_path() -> PathBuf {
+    let libra = try_get_working_dir().unwrap().join(ROOT_DIR);
+    if libra.is_file() {
+        read_libradir(&libra).unwrap()
+    } else {
+        libra
+    }
+}
+
+/// Check if the current worktree is a linked one, created by `worktree add`
+/// - cached by the current directory, as it's checked whenever HEAD is read or updated
+pub fn is_linked_worktree() -> bool {
+    static LINKED: Lazy<Mutex<HashMap<PathBuf, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+    let mut linked = LINKED.lock().unwrap();
+    *linked
+        .entry(cur_dir())
+        .or_insert_with(|| worktree_storage_path() != storage_path())
+}
+
+/// Check if libra repo exists, and the HEAD of a linked worktree is readable
 pub fn check_repo_exist() -> bool {
     if try_get_storage_path().is_err() {
         eprintln!("fatal: not a libra repository (or any of the parent directories): .libra");
         return false;
     }
+    if is_linked_worktree() {
+        if let Err(e) = Head::read_worktree_head() {
+            eprintln!("fatal: {}", e);
+            return false;
+        }
+    }
     true
 }
 
@@ -70,9 +136,7 @@ pub fn objects_storage() -> ClientStorage {
 /// Get the working directory of the repository
 /// - panics if the current directory is not a repository
 pub fn working_dir() -> PathBuf {
-    let mut storage_path = storage_path();
-    storage_path.pop();
-    storage_path
+    try_get_working_dir().unwrap()
 }
 
 /// Get the working directory of the repository as a string, panics if the path is not valid utf-8
@@ -211,7 +275,14 @@ pub fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let path = entry.path();
-            if path.is_dir() {
+            if path.file_name().unwrap_or_default() == ROOT_DIR {
+                // the `.libra` file of a linked worktree
+                continue;
+            }
+            if is_nested_repo(&path) {
+                // a submodule is listed as itself, its files belong to its own repository
+                files.push(to_workdir_path(&path));
+            } else if path.is_dir() {
                 files.extend(list_files(&path)?);
             } else {
                 files.push(to_workdir_path(&path));
@@ -221,6 +292,12 @@ pub fn list_files(path: &Path) -> io::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
+/// Check if `dir` is the working directory of another repository (libra or git) in the working dir, e.g. a submodule or a linked worktree
+pub fn is_nested_repo(dir: &Path) -> bool {
+    (dir.join(ROOT_DIR).exists() || dir.join(".git").exists())
+        && to_workdir_path(dir) != Path::new(".")
+}
+
 /// list all files in the working dir(include sub_dir)
 /// - output: to workdir path
 pub fn list_workdir_files() -> io::Result<Vec<PathBuf>> {
@@ -232,7 +309,7 @@ pub fn list_workdir_files() -> io::Result<Vec<PathBuf>> {
 pub fn integrate_pathspec(paths: &Vec<PathBuf>) -> HashSet<PathBuf> {
     let mut workdir_paths = HashSet::new();
     for path in paths {
-        if path.is_dir() {
+        if path.is_dir() && !is_nested_repo(path) {
             let files = list_files(path).unwrap(); // to workdir
             workdir_paths.extend(files);
         } else {
@@ -252,6 +329,14 @@ pub fn write_file(content: &[u8], file: &PathBuf) -> io::Result<()> {
     file.write_all(content)
 }
 
+/// write content to a temp file next to `file` then rename it, so the readers never see a partial file
+/// - create parent directory if not exist
+pub fn write_file_atomic(content: &[u8], file: &Path) -> io::Result<()> {
+    let tmp = file.with_extension("tmp");
+    write_file(content, &tmp)?;
+    fs::rename(tmp, file)
+}
+
 /// Removing the empty directories in cascade until meet the root of workdir or the current dir
 pub fn clear_empty_dir(dir: &Path) {
     let mut dir = if dir.is_dir() {
@@ -260,9 +345,9 @@ pub fn clear_empty_dir(dir: &Path) {
         dir.parent().unwrap().to_path_buf()
     };
 
-    let repo = storage_path();
-    // CAN NOT remove .libra & current dir
-    while !is_sub_path(&repo, &dir) && !is_cur_dir(&dir) {
+    let workdir = working_dir();
+    // CAN NOT remove the working dir (with .libra) & current dir
+    while !is_sub_path(&workdir, &dir) && !is_cur_dir(&dir) {
         if is_empty_dir(&dir) {
             fs::remove_dir(&dir).unwrap();
         } else {
@@ -290,37 +375,42 @@ pub fn path_to_string(path: &Path) -> String {
     path.to_string_lossy().to_string()
 }
 
-/// extend hash, panic if not valid or ambiguous
-pub fn get_commit_base(commit_base: &str) -> Result<SHA1, String> {
-    let storage = objects_storage();
-
-    let commits = storage.search(commit_base);
-    if commits.is_empty() {
-        return Err(format!("fatal: invalid reference: {}", commit_base));
-    } else if commits.len() > 1 {
-        return Err(format!("fatal: ambiguous argument: {}", commit_base));
-    }
-    if !storage.is_object_type(&commits[0], ObjectType::Commit) {
-        Err(format!(
-            "fatal: reference is not a commit: {}, is {}",
-            commit_base,
-            storage.get_object_type(&commits[0]).unwrap()
-        ))
-    } else {
-        Ok(commits[0])
-    }
+/// `2024-04-01 12:00:00 +0800` in the timezone of the signature
+pub fn format_signature_date(signature: &Signature) -> String {
+    let tz = &signature.timezone;
+    let offset = match (tz.get(..1), tz.get(1..3), tz.get(3..5)) {
+        (Some(sign), Some(hours), Some(minutes)) => {
+            let seconds =
+                hours.parse::<i32>().unwrap_or(0) * 3600 + minutes.parse::<i32>().unwrap_or(0) * 60;
+            if sign == "-" {
+                -seconds
+            } else {
+                seconds
+            }
+        }
+        _ => 0,
+    };
+    let offset = chrono::FixedOffset::east_opt(offset).unwrap();
+    let date = chrono::DateTime::from_timestamp(signature.timestamp as i64, 0)
+        .unwrap()
+        .with_timezone(&offset);
+    format!("{} {}", date.format("%Y-%m-%d %H:%M:%S"), tz)
 }
 
 /// Get the repository name from the url
 /// - e.g. `https://github.com/web3infra-foundation/mega.git/` -> mega
 /// - e.g. `https://github.com/web3infra-foundation/mega.git` -> mega
+/// - e.g. `git@github.com:mega.git` -> mega
+/// - e.g. `file:///path/to/mega/` -> mega
 pub fn get_repo_name_from_url(mut url: &str) -> Option<&str> {
     if url.ends_with('/') {
         url = &url[..url.len() - 1];
     }
-    let repo_start = url.rfind('/')? + 1;
-    let repo_end = url.rfind('.')?;
-    Some(&url[repo_start..repo_end])
+    let name = &url[url.rfind(['/', ':'])? + 1..];
+    match name.rfind('.') {
+        Some(end) if end > 0 => Some(&name[..end]),
+        _ => Some(name), // a local path may have no extension
+    }
 }
 
 /// Find the appropriate unit and value for Bytes.
@@ -372,6 +462,18 @@ mod test {
         assert_eq!(to_relative(".", "src"), PathBuf::from(".."));
     }
 
+    #[test]
+    fn test_get_repo_name_from_url() {
+        let name = get_repo_name_from_url;
+        assert_eq!(
+            name("https://github.com/web3infra-foundation/mega.git/"),
+            Some("mega")
+        );
+        assert_eq!(name("git@github.com:mega.git"), Some("mega"));
+        assert_eq!(name("file:///path/to/mega/"), Some("mega"));
+        assert_eq!(name("mega"), None);
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_to_workdir_path() {
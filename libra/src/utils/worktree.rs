@@ -0,0 +1,132 @@
+//! Worktrees: more working directories of a repository, each with its own HEAD & index,
+//! sharing the objects, references & config of the main worktree, like git.
+//! - the `.libra` of a linked worktree is a file pointing to its storage: `libradir: <main>/.libra/worktrees/<name>`
+//! - the storage keeps `HEAD`, `index`, the states of the operations in progress, `libradir` (the path of the
+//!   `.libra` file, to find the worktree) & `locked` (the reason, if locked)
+//! - a branch can be checked out in only one worktree
+//!
+//! [git-worktree](https://git-scm.com/docs/git-worktree)
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::internal::head::Head;
+use crate::utils::{path, util};
+
+#[derive(Debug, Clone)]
+pub struct Worktree {
+    /// the working directory
+    pub path: PathBuf,
+    /// `.libra/worktrees/<name>`, `None` for the main worktree
+    pub storage: Option<PathBuf>,
+    /// `None` if the HEAD file of a linked worktree is broken
+    pub head: Option<Head>,
+}
+
+impl Worktree {
+    /// The name of a linked worktree, which is the name of its storage
+    pub fn name(&self) -> Option<&str> {
+        self.storage.as_ref()?.file_name()?.to_str()
+    }
+
+    /// The reason of the lock, `Some("")` if locked without a reason
+    pub fn locked(&self) -> Option<String> {
+        fs::read_to_string(self.storage.as_ref()?.join("locked")).ok()
+    }
+
+    /// Why the storage of a linked worktree can be pruned, `None` if the worktree is still there
+    pub fn prunable(&self) -> Option<String> {
+        let storage = self.storage.as_ref()?;
+        if !storage.join("libradir").is_file() {
+            return Some("libradir file does not exist".to_string());
+        }
+        if !self.path.join(util::ROOT_DIR).is_file() {
+            return Some("libradir file points to non-existent location".to_string());
+        }
+        None
+    }
+
+    /// If `branch` is checked out in the worktree, which is not pruned yet
+    pub fn uses_branch(&self, branch: &str) -> bool {
+        matches!(&self.head, Some(Head::Branch(name)) if name == branch)
+            && self.prunable().is_none()
+    }
+
+    /// If it's the worktree of the current directory
+    pub fn is_current(&self) -> bool {
+        match self.name() {
+            Some(name) => {
+                util::is_linked_worktree()
+                    && util::worktree_storage_path().file_name() == Some(OsStr::new(name))
+            }
+            None => !util::is_linked_worktree(),
+        }
+    }
+}
+
+/// The storages of the linked worktrees, `.libra/worktrees/*`
+fn linked_storages() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(path::worktrees()) else {
+        return Vec::new();
+    };
+    let mut storages: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    storages.sort();
+    storages
+}
+
+/// The linked worktree of `storage`
+pub fn load(storage: &Path) -> Worktree {
+    // `<worktree>/.libra`
+    let path = fs::read_to_string(storage.join("libradir"))
+        .ok()
+        .and_then(|file| {
+            PathBuf::from(file.trim_end())
+                .parent()
+                .map(Path::to_path_buf)
+        })
+        .unwrap_or_default();
+    Worktree {
+        path,
+        storage: Some(storage.to_path_buf()),
+        head: Head::read_file(&storage.join("HEAD")),
+    }
+}
+
+/// All the worktrees, the main one first
+pub async fn list() -> Vec<Worktree> {
+    let main = Worktree {
+        path: util::storage_path().parent().unwrap().to_path_buf(),
+        storage: None,
+        head: Some(Head::main_worktree().await),
+    };
+    let mut worktrees = vec![main];
+    worktrees.extend(linked_storages().iter().map(|storage| load(storage)));
+    worktrees
+}
+
+/// The worktree other than the current one which has `branch` checked out
+pub async fn find_by_branch(branch: &str) -> Option<Worktree> {
+    list()
+        .await
+        .into_iter()
+        .find(|worktree| !worktree.is_current() && worktree.uses_branch(branch))
+}
+
+/// Check if `branch` can be checked out in the current worktree, print the error if not
+pub async fn check_branch_unused(branch: &str) -> bool {
+    match find_by_branch(branch).await {
+        Some(worktree) => {
+            eprintln!(
+                "fatal: '{}' is already used by worktree at '{}'",
+                branch,
+                worktree.path.display()
+            );
+            false
+        }
+        None => true,
+    }
+}
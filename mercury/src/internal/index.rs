@@ -52,15 +52,21 @@ impl Display for Time {
     }
 }
 
-/// 16 bits
+/// 16 bits, followed by 16 bits extended flags if `extended` (v3)
 #[derive(Debug)]
 pub struct Flags {
     pub assume_valid: bool,
     pub extended: bool,   // must be 0 in v2
     pub stage: u8,        // 2-bit during merge
     pub name_length: u16, // 12-bit
+    /// extended: the file is not in the worktree, e.g. out of the sparse checkout
+    pub skip_worktree: bool,
+    /// the other extended flags, e.g. intent-to-add, kept as is
+    other_extended: u16,
 }
 
+const SKIP_WORKTREE: u16 = 0x4000;
+
 impl From<u16> for Flags {
     fn from(flags: u16) -> Self {
         Flags {
@@ -68,6 +74,8 @@ impl From<u16> for Flags {
             extended: flags & 0x4000 != 0,
             stage: ((flags & 0x3000) >> 12) as u8,
             name_length: flags & 0xFFF,
+            skip_worktree: false,
+            other_extended: 0,
         }
     }
 }
@@ -79,7 +87,7 @@ impl TryInto<u16> for &Flags {
         if self.assume_valid {
             flags |= 0x8000; // 16
         }
-        if self.extended {
+        if self.is_extended() {
             flags |= 0x4000; // 15
         }
         flags |= (self.stage as u16) << 12; // 13-14
@@ -98,8 +106,25 @@ impl Flags {
             extended: false,
             stage: 0,
             name_length: name_len,
+            skip_worktree: false,
+            other_extended: 0,
         }
     }
+
+    /// Whether the extended flags are written, which needs index v3
+    pub fn is_extended(&self) -> bool {
+        self.extended_flags() != 0
+    }
+
+    fn extended_flags(&self) -> u16 {
+        let skip_worktree = if self.skip_worktree { SKIP_WORKTREE } else { 0 };
+        self.other_extended | skip_worktree
+    }
+
+    fn set_extended_flags(&mut self, flags: u16) {
+        self.skip_worktree = flags & SKIP_WORKTREE != 0;
+        self.other_extended = flags & !SKIP_WORKTREE;
+    }
 }
 
 pub struct IndexEntry {
@@ -196,6 +221,18 @@ impl IndexEntry {
             name,
         }
     }
+
+    /// A gitlink entry of a submodule, `commit` is checked out in it
+    pub fn new_from_gitlink(name: String, commit: SHA1) -> Self {
+        let mut entry = IndexEntry::new_from_blob(name, commit, 0);
+        entry.mode = 0o160000;
+        entry
+    }
+
+    /// If the entry is a submodule (gitlink), its hash is a commit rather than a blob
+    pub fn is_gitlink(&self) -> bool {
+        self.mode & 0o170000 == 0o160000
+    }
 }
 
 /// see [index-format](https://git-scm.com/docs/index-format)
@@ -215,8 +252,8 @@ impl Index {
         }
 
         let version = file.read_u32::<BigEndian>()?;
-        // only support v2 now
-        if version != 2 {
+        // only support v2 & v3 (extended flags) now
+        if version != 2 && version != 3 {
             return Err(GitError::InvalidIndexHeader(version.to_string()));
         }
 
@@ -256,19 +293,23 @@ impl Index {
                 flags: Flags::from(file.read_u16::<BigEndian>()?),
                 name: String::new(),
             };
+            if entry.flags.extended {
+                let extended = file.read_u16::<BigEndian>()?;
+                entry.flags.set_extended_flags(extended);
+            }
             let name_len = entry.flags.name_length as usize;
             let mut name = vec![0; name_len];
             file.read_exact(&mut name)?;
             // The exact encoding is undefined, but the '.' and '/' characters are encoded in 7-bit ASCII
             entry.name = String::from_utf8(name)?; // TODO check the encoding
-            index
-                .entries
-                .insert((entry.name.clone(), entry.flags.stage), entry);
 
             // 1-8 nul bytes as necessary to pad the entry to a multiple of eight bytes
             // while keeping the name NUL-terminated. // so at least 1 byte nul
-            let padding = 8 - ((22 + name_len) % 8); // 22 = sha1 + flags, others are 40 % 8 == 0
+            let padding = Index::padding(entry.flags.extended, name_len);
             utils::read_bytes(file, padding)?;
+            index
+                .entries
+                .insert((entry.name.clone(), entry.flags.stage), entry);
         }
 
         // Extensions
@@ -299,13 +340,23 @@ impl Index {
         Ok(index)
     }
 
+    /// 1-8 nul bytes to pad the entry to a multiple of eight bytes
+    /// while keeping the name NUL-terminated, so at least 1 byte nul
+    fn padding(extended: bool, name_len: usize) -> usize {
+        // 22 = sha1 + flags (+ 2 extended flags), others are 40 % 8 == 0
+        let fixed = if extended { 24 } else { 22 };
+        8 - ((fixed + name_len) % 8)
+    }
+
     pub fn to_file(&self, path: impl AsRef<Path>) -> Result<(), GitError> {
         let mut file = File::create(path)?;
         let mut hash = Sha1::new();
 
+        // v3 is needed only if there are extended flags, like git
+        let extended = self.entries.values().any(|entry| entry.flags.is_extended());
         let mut header = Vec::new();
         header.write_all(b"DIRC")?;
-        header.write_u32::<BigEndian>(2u32)?; // version 2
+        header.write_u32::<BigEndian>(if extended { 3 } else { 2 })?;
         header.write_u32::<BigEndian>(self.entries.len() as u32)?;
         file.write_all(&header)?;
         hash.update(&header);
@@ -324,8 +375,11 @@ impl Index {
             entry_bytes.write_u32::<BigEndian>(entry.size)?;
             entry_bytes.write_all(&entry.hash.0)?;
             entry_bytes.write_u16::<BigEndian>((&entry.flags).try_into().unwrap())?;
+            if entry.flags.is_extended() {
+                entry_bytes.write_u16::<BigEndian>(entry.flags.extended_flags())?;
+            }
             entry_bytes.write_all(entry.name.as_bytes())?;
-            let padding = 8 - ((22 + entry.name.len()) % 8);
+            let padding = Index::padding(entry.flags.is_extended(), entry.name.len());
             entry_bytes.write_all(&vec![0; padding])?;
 
             file.write_all(&entry_bytes)?;
@@ -372,6 +426,23 @@ impl Index {
         self.entries.contains_key(&(name.to_string(), stage))
     }
 
+    /// Whether the entry of `name` is marked skip-worktree, i.e. it's not expected in the worktree
+    pub fn is_skip_worktree(&self, name: &str) -> bool {
+        self.get(name, 0)
+            .is_some_and(|entry| entry.flags.skip_worktree)
+    }
+
+    /// Set the skip-worktree bit of the entry of `name`, `false` if it's not tracked
+    pub fn set_skip_worktree(&mut self, name: &str, skip: bool) -> bool {
+        match self.entries.get_mut(&(name.to_string(), 0)) {
+            Some(entry) => {
+                entry.flags.skip_worktree = skip;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn get_hash(&self, file: &str, stage: u8) -> Option<SHA1> {
         self.get(file, stage).map(|entry| entry.hash)
     }
@@ -421,6 +492,30 @@ impl Index {
             .collect()
     }
 
+    /// Get all files that have unmerged entries (stage 1, 2 or 3), without duplicates
+    pub fn unmerged_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .filter(|((_, stage), _)| *stage != 0)
+            .map(|((name, _), _)| PathBuf::from(name))
+            .collect();
+        files.dedup(); // BTreeMap is sorted by name first, so duplicates are adjacent
+        files
+    }
+
+    /// Is there any unmerged entry (stage 1, 2 or 3) in the index
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.keys().any(|(_, stage)| *stage != 0)
+    }
+
+    /// Remove the unmerged entries (stage 1, 2 and 3) of `name`, aka mark it as resolved
+    pub fn remove_unmerged(&mut self, name: &str) {
+        for stage in 1..=3 {
+            self.remove(name, stage);
+        }
+    }
+
     /// Judge if the file(s) of `dir` is in the index
     /// - false if `dir` is a file
     pub fn contains_dir_file(&self, dir: &str) -> bool {
@@ -493,6 +588,76 @@ mod tests {
         assert_eq!(index.size(), new_index.size());
     }
 
+    #[test]
+    fn test_skip_worktree() {
+        let mut index = Index::new();
+        let hash = SHA1::from_bytes(&[0; 20]);
+        for name in ["a.txt", "dir/b.txt", "dir/c.txt"] {
+            index.add(IndexEntry::new_from_blob(name.to_string(), hash, 0));
+        }
+        assert!(index.set_skip_worktree("dir/b.txt", true));
+        assert!(!index.set_skip_worktree("none.txt", true));
+
+        let file = "/tmp/index-skip-worktree";
+        index.to_file(file).unwrap();
+        let mut version = [0; 8];
+        File::open(file).unwrap().read_exact(&mut version).unwrap();
+        assert_eq!(version[4..], [0, 0, 0, 3]); // extended flags need v3
+        let mut index = Index::from_file(file).unwrap();
+        assert_eq!(index.size(), 3);
+        assert!(index.is_skip_worktree("dir/b.txt"));
+        assert!(!index.is_skip_worktree("dir/c.txt"));
+
+        index.set_skip_worktree("dir/b.txt", false);
+        index.to_file(file).unwrap();
+        let mut index = Index::from_file(file).unwrap();
+        assert!(!index.is_skip_worktree("dir/b.txt"));
+
+        // the other extended flags (intent-to-add) are kept when skip-worktree changes
+        let entry = index.entries.get_mut(&("a.txt".to_string(), 0)).unwrap();
+        entry.flags.set_extended_flags(0x2000);
+        index.set_skip_worktree("a.txt", true);
+        index.to_file(file).unwrap();
+        let index = Index::from_file(file).unwrap();
+        assert_eq!(
+            index.get("a.txt", 0).unwrap().flags.extended_flags(),
+            0x6000
+        );
+    }
+
+    #[test]
+    fn test_gitlink_entry() {
+        let mut index = Index::new();
+        let hash = SHA1::from_bytes(&[1; 20]);
+        index.add(IndexEntry::new_from_gitlink("sub".to_string(), hash));
+        index.add(IndexEntry::new_from_blob("a.txt".to_string(), hash, 0));
+
+        let file = "/tmp/index-gitlink";
+        index.to_file(file).unwrap();
+        let index = Index::from_file(file).unwrap();
+        assert!(index.get("sub", 0).unwrap().is_gitlink());
+        assert!(!index.get("a.txt", 0).unwrap().is_gitlink());
+        assert_eq!(index.get_hash("sub", 0), Some(hash));
+    }
+
+    #[test]
+    fn test_unmerged_entries() {
+        let mut index = Index::new();
+        let hash = SHA1::from_bytes(&[0; 20]);
+        for stage in 1..=3 {
+            let mut entry = IndexEntry::new_from_blob("a.txt".to_string(), hash, 0);
+            entry.flags.stage = stage;
+            index.add(entry);
+        }
+        index.add(IndexEntry::new_from_blob("b.txt".to_string(), hash, 0));
+        assert!(index.has_conflicts());
+        assert_eq!(index.unmerged_files(), vec![PathBuf::from("a.txt")]);
+
+        index.remove_unmerged("a.txt");
+        assert!(!index.has_conflicts());
+        assert_eq!(index.size(), 1);
+    }
+
     #[test]
     fn test_index_entry_create() {
         let file = Path::new("Cargo.toml"); // use as a normal file
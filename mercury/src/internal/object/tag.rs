@@ -75,6 +75,27 @@ impl Display for Tag {
 }
 
 impl Tag {
+    /// Create an annotated tag object, the id is calculated from the data.
+    /// - `message`: like commit, should start with a blank line (`\n`)
+    pub fn new(
+        object_hash: SHA1,
+        object_type: ObjectType,
+        tag_name: String,
+        tagger: Signature,
+        message: &str,
+    ) -> Tag {
+        let mut tag = Tag {
+            id: SHA1::default(),
+            object_hash,
+            object_type,
+            tag_name,
+            tagger,
+            message: message.to_string(),
+        };
+        tag.id = SHA1::from_type_and_data(ObjectType::Tag, &tag.to_data().unwrap());
+        tag
+    }
+
     // pub fn new_from_meta(meta: Meta) -> Result<Tag, GitError> {
     //     Ok(Tag::new_from_data(meta.data))
     // }
@@ -181,3 +202,40 @@ impl ObjectTrait for Tag {
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::str::FromStr;
+
+    use crate::hash::SHA1;
+    use crate::internal::object::signature::{Signature, SignatureType};
+    use crate::internal::object::tag::Tag;
+    use crate::internal::object::types::ObjectType;
+    use crate::internal::object::ObjectTrait;
+
+    #[test]
+    fn test_tag_new_and_from_bytes() {
+        let tagger = Signature::new(
+            SignatureType::Tagger,
+            "mega".to_string(),
+            "admin@mega.org".to_string(),
+        );
+        let tag = Tag::new(
+            SHA1::from_str("8ab686eafeb1f44702738c8b0f24f2567c36da6d").unwrap(),
+            ObjectType::Commit,
+            "v1.0.0".to_string(),
+            tagger,
+            "\nrelease v1.0.0\n",
+        );
+        let data = tag.to_data().unwrap();
+        assert_eq!(tag.id, SHA1::from_type_and_data(ObjectType::Tag, &data));
+
+        let parsed = Tag::from_bytes(&data, tag.id).unwrap();
+        assert_eq!(parsed.object_hash, tag.object_hash);
+        assert_eq!(parsed.object_type, ObjectType::Commit);
+        assert_eq!(parsed.tag_name, "v1.0.0");
+        assert_eq!(parsed.tagger, tag.tagger);
+        assert_eq!(parsed.message, "\nrelease v1.0.0\n");
+    }
+}
@@ -12,7 +12,7 @@ use jupiter::context::Context;
 use tokio::io::AsyncReadExt;
 use tokio_stream::StreamExt;
 
-use ceres::protocol::{smart, ServiceType, SmartProtocol};
+use ceres::protocol::{smart, ProtocolVersion, ServiceType, SmartProtocol};
 use common::errors::ProtocolError;
 use common::model::InfoRefsParams;
 
@@ -41,48 +41,51 @@ pub async fn git_info_refs(
     Ok(response)
 }
 
+/// Accept `Basic <username:token>` or `Bearer <token>` with an access token of the user
 async fn http_auth(header: &HeaderMap<HeaderValue>, context: &Context) -> bool {
-    for (k, v) in header {
-        if k == http::header::AUTHORIZATION {
-            let decoded = general_purpose::STANDARD
-                .decode(
-                    v.to_str()
-                        .unwrap()
-                        .strip_prefix("Basic ")
-                        .unwrap()
-                        .as_bytes(),
-                )
-                .unwrap();
-            let credentials = String::from_utf8(decoded).unwrap_or_default();
-            let mut parts = credentials.splitn(2, ':');
-            let username = parts.next().unwrap_or("");
-            let token = parts.next().unwrap_or("");
-            tracing::debug!("{}, {}", username, token);
-            let auth_config = context.config.authentication.clone();
-            if auth_config.enable_test_user
-                && username == auth_config.test_user_name
-                && token == auth_config.test_user_token
-            {
-                return true;
-            }
-            match context
-                .user_stg()
-                .find_user_by_name(username)
-                .await
-                .unwrap()
-            {
-                Some(user) => {
-                    return context
-                        .user_stg()
-                        .check_token(user.id, token)
-                        .await
-                        .unwrap();
-                }
-                None => return false,
-            }
+    let Some(value) = header
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let auth_config = context.config.authentication.clone();
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        if auth_config.enable_test_user && token == auth_config.test_user_token {
+            return true;
         }
+        return matches!(context.user_stg().find_token(token).await, Ok(Some(_)));
+    }
+    let Some(encoded) = value.strip_prefix("Basic ") else {
+        return false;
+    };
+    let decoded = general_purpose::STANDARD
+        .decode(encoded.as_bytes())
+        .unwrap_or_default();
+    let credentials = String::from_utf8(decoded).unwrap_or_default();
+    let mut parts = credentials.splitn(2, ':');
+    let username = parts.next().unwrap_or("");
+    let token = parts.next().unwrap_or("");
+    tracing::debug!("{}, {}", username, token);
+    if auth_config.enable_test_user
+        && username == auth_config.test_user_name
+        && token == auth_config.test_user_token
+    {
+        return true;
+    }
+    match context
+        .user_stg()
+        .find_user_by_name(username)
+        .await
+        .unwrap()
+    {
+        Some(user) => context
+            .user_stg()
+            .check_token(user.id, token)
+            .await
+            .unwrap(),
+        None => false,
     }
-    false
 }
 
 fn auth_failed() -> Result<Response<Body>, ProtocolError> {
@@ -116,6 +119,9 @@ fn auth_failed() -> Result<Response<Body>, ProtocolError> {
 ///
 /// A new task is spawned to send the remaining `send_pack_data` using the `send_pack` function.
 ///
+/// With protocol v2, the request is a command (`ls-refs`, `fetch` or `object-info`) handled by
+/// `git_upload_pack_v2`, only the `fetch` command is followed by pack data.
+///
 /// Finally, the constructed response with the response body is returned.
 pub async fn git_upload_pack(
     req: Request<Body>,
@@ -131,31 +137,41 @@ pub async fn git_upload_pack(
         .await
         .unwrap();
     tracing::debug!("Receive bytes: <-------- {:?}", upload_request);
-    let (mut send_pack_data, protocol_buf) = pack_protocol
-        .git_upload_pack(&mut upload_request.freeze())
-        .await?;
+    let (send_pack_data, protocol_buf) = if pack_protocol.version == ProtocolVersion::V2 {
+        pack_protocol
+            .git_upload_pack_v2(&mut upload_request.freeze())
+            .await?
+    } else {
+        let (send_pack_data, protocol_buf) = pack_protocol
+            .git_upload_pack(&mut upload_request.freeze())
+            .await?;
+        (Some(send_pack_data), protocol_buf)
+    };
 
     let body_stream = async_stream::stream! {
         tracing::info!("send ack/nak message buf: --------> {:?}", &protocol_buf);
         yield Ok::<_, Infallible>(Bytes::copy_from_slice(&protocol_buf));
-        // send packdata with sideband64k
-        while let Some(chunk) = send_pack_data.next().await {
-            let mut reader = chunk.as_slice();
-            loop {
-                let mut temp = BytesMut::new();
-                temp.reserve(65500);
-                let length = reader.read_buf(&mut temp).await.unwrap();
-                if length == 0 {
-                    break;
+        // the response of v2 commands other than fetch is complete
+        if let Some(mut send_pack_data) = send_pack_data {
+            // send packdata with sideband64k
+            while let Some(chunk) = send_pack_data.next().await {
+                let mut reader = chunk.as_slice();
+                loop {
+                    let mut temp = BytesMut::new();
+                    temp.reserve(65500);
+                    let length = reader.read_buf(&mut temp).await.unwrap();
+                    if length == 0 {
+                        break;
+                    }
+                    let bytes_out = pack_protocol.build_side_band_format(temp, length);
+                    // tracing::info!("send pack file: length: {:?}", bytes_out.len());
+                    yield Ok::<_, Infallible>(bytes_out.freeze());
                 }
-                let bytes_out = pack_protocol.build_side_band_format(temp, length);
-                // tracing::info!("send pack file: length: {:?}", bytes_out.len());
-                yield Ok::<_, Infallible>(bytes_out.freeze());
             }
+            let bytes_out = Bytes::from_static(smart::PKT_LINE_END_MARKER);
+            tracing::info!("send back pkt-flush line '0000', actually: {:?}", bytes_out);
+            yield Ok::<_, Infallible>(bytes_out);
         }
-        let bytes_out = Bytes::from_static(smart::PKT_LINE_END_MARKER);
-        tracing::info!("send back pkt-flush line '0000', actually: {:?}", bytes_out);
-        yield Ok::<_, Infallible>(bytes_out);
     };
     let response = add_default_header(
         String::from("application/x-git-upload-pack-result"),
@@ -14,7 +14,7 @@ use tokio::io::AsyncReadExt;
 use ceres::lfs::lfs_structs::Link;
 use ceres::protocol::smart::{self};
 use ceres::protocol::ServiceType;
-use ceres::protocol::{SmartProtocol, TransportProtocol};
+use ceres::protocol::{ProtocolVersion, SmartProtocol, TransportProtocol};
 use jupiter::context::Context;
 use tokio::sync::Mutex;
 
@@ -29,6 +29,7 @@ pub struct SshServer {
     pub context: Context,
     pub smart_protocol: Option<SmartProtocol>,
     pub data_combined: BytesMut,
+    pub protocol_version: ProtocolVersion,
 }
 
 impl server::Server for SshServer {
@@ -90,6 +91,7 @@ impl server::Handler for SshServer {
         match command[0] {
             "git-upload-pack" | "git-receive-pack" => {
                 smart_protocol.service_type = Some(ServiceType::from_str(command[0]).unwrap());
+                smart_protocol.version = self.protocol_version;
                 // TODO handler ProtocolError
                 let res = smart_protocol.git_info_refs().await.unwrap();
                 self.smart_protocol = Some(smart_protocol);
@@ -124,6 +126,27 @@ impl server::Handler for SshServer {
         Ok(())
     }
 
+    /// The client requests protocol v2 by the `GIT_PROTOCOL` environment variable before `exec_request`,
+    /// other variables are ignored.
+    async fn env_request(
+        &mut self,
+        channel: ChannelId,
+        variable_name: &str,
+        variable_value: &str,
+        _: &mut Session,
+    ) -> Result<(), Self::Error> {
+        tracing::info!(
+            "env_request, channel:{:?}, {}={}",
+            channel,
+            variable_name,
+            variable_value
+        );
+        if variable_name == "GIT_PROTOCOL" {
+            self.protocol_version = ProtocolVersion::from_git_protocol(variable_value);
+        }
+        Ok(())
+    }
+
     async fn auth_publickey(
         &mut self,
         user: &str,
@@ -165,7 +188,7 @@ impl server::Handler for SshServer {
         let service_type = smart_protocol.service_type.unwrap();
         match service_type {
             ServiceType::UploadPack => {
-                self.handle_upload_pack(channel, data, session).await;
+                self.handle_upload_pack(channel, data, session).await?;
             }
             ServiceType::ReceivePack => {
                 self.data_combined.extend_from_slice(data);
@@ -186,30 +209,52 @@ impl server::Handler for SshServer {
             };
         }
 
-        {
-            let mut clients = self.clients.lock().await;
-            clients.remove(&(self.id, channel));
-        }
-        session.exit_status_request(channel, 0000)?;
-        session.close(channel)?;
-        Ok(())
+        self.close_channel(channel, 0, session).await
     }
 }
 
 impl SshServer {
-    async fn handle_upload_pack(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) {
+    async fn handle_upload_pack(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), anyhow::Error> {
         let smart_protocol = self.smart_protocol.as_mut().unwrap();
 
-        let (mut send_pack_data, buf) = smart_protocol
-            .git_upload_pack(&mut Bytes::copy_from_slice(data))
-            .await
-            .unwrap();
+        let mut upload_request = Bytes::copy_from_slice(data);
+        let result = if smart_protocol.version == ProtocolVersion::V2 {
+            // a flush-pkt alone instead of a command, the client ends the session
+            if data == smart::PKT_LINE_END_MARKER {
+                return self.close_channel(channel, 0, session).await;
+            }
+            smart_protocol.git_upload_pack_v2(&mut upload_request).await
+        } else {
+            smart_protocol
+                .git_upload_pack(&mut upload_request)
+                .await
+                .map(|(send_pack_data, buf)| (Some(send_pack_data), buf))
+        };
+        let (send_pack_data, buf) = match result {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!("upload pack failed: {}", e);
+                let mut buf = BytesMut::new();
+                smart::add_pkt_line_string(&mut buf, format!("ERR {}\n", e));
+                session.data(channel, buf.to_vec().into()).unwrap();
+                return self.close_channel(channel, 128, session).await;
+            }
+        };
 
         tracing::info!("buf is {:?}", buf);
         session
             .data(channel, String::from_utf8(buf.to_vec()).unwrap().into())
             .unwrap();
 
+        // the response of v2 commands other than fetch is complete
+        let Some(mut send_pack_data) = send_pack_data else {
+            return Ok(());
+        };
         while let Some(chunk) = send_pack_data.next().await {
             let mut reader = chunk.as_slice();
             loop {
@@ -226,6 +271,23 @@ impl SshServer {
         session
             .data(channel, smart::PKT_LINE_END_MARKER.to_vec().into())
             .unwrap();
+        Ok(())
+    }
+
+    /// Exit with `exit_status` and close the channel of the client
+    async fn close_channel(
+        &mut self,
+        channel: ChannelId,
+        exit_status: u32,
+        session: &mut Session,
+    ) -> Result<(), anyhow::Error> {
+        {
+            let mut clients = self.clients.lock().await;
+            clients.remove(&(self.id, channel));
+        }
+        session.exit_status_request(channel, exit_status)?;
+        session.close(channel)?;
+        Ok(())
     }
 
     async fn handle_receive_pack(&mut self, channel: ChannelId, session: &mut Session) {
@@ -6,7 +6,7 @@ use anyhow::Result;
 use async_session::MemoryStore;
 use axum::body::Body;
 use axum::extract::{Query, State};
-use axum::http::{self, Request, Uri};
+use axum::http::{self, HeaderMap, Request, Uri};
 use axum::response::Response;
 use axum::routing::get;
 use axum::Router;
@@ -19,7 +19,7 @@ use tower_http::cors::{Any, CorsLayer};
 use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::trace::TraceLayer;
 
-use ceres::protocol::{ServiceType, SmartProtocol, TransportProtocol};
+use ceres::protocol::{ProtocolVersion, ServiceType, SmartProtocol, TransportProtocol};
 use common::errors::ProtocolError;
 use common::model::{CommonOptions, InfoRefsParams};
 use jupiter::context::Context;
@@ -177,14 +177,16 @@ lazy_static! {
 pub async fn get_method_router(
     state: State<AppState>,
     Query(params): Query<InfoRefsParams>,
+    headers: HeaderMap,
     uri: Uri,
 ) -> Result<Response<Body>, ProtocolError> {
     if INFO_REFS_REGEX.is_match(uri.path()) {
-        let pack_protocol = SmartProtocol::new(
+        let mut pack_protocol = SmartProtocol::new(
             remove_git_suffix(uri, "/info/refs"),
             state.context.clone(),
             TransportProtocol::Http,
         );
+        pack_protocol.version = protocol_version(&headers);
         crate::git_protocol::http::git_info_refs(params, pack_protocol).await
     } else {
         Err(ProtocolError::NotFound(
@@ -205,6 +207,7 @@ pub async fn post_method_router(
             TransportProtocol::Http,
         );
         pack_protocol.service_type = Some(ServiceType::UploadPack);
+        pack_protocol.version = protocol_version(req.headers());
         crate::git_protocol::http::git_upload_pack(req, pack_protocol).await
     } else if REGEX_GIT_RECEIVE_PACK.is_match(uri.path()) {
         let mut pack_protocol = SmartProtocol::new(
@@ -221,5 +224,14 @@ pub async fn post_method_router(
     }
 }
 
+/// The protocol version requested by the `Git-Protocol` header, v0 if absent
+fn protocol_version(headers: &HeaderMap) -> ProtocolVersion {
+    headers
+        .get("Git-Protocol")
+        .and_then(|value| value.to_str().ok())
+        .map(ProtocolVersion::from_git_protocol)
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {}
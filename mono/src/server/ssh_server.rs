@@ -12,6 +12,7 @@ use russh::{
     Preferred,
 };
 
+use ceres::protocol::ProtocolVersion;
 use common::model::CommonOptions;
 use jupiter::context::Context;
 use tokio::sync::Mutex;
@@ -61,6 +62,7 @@ pub async fn start_server(context: Context, command: &SshOptions) {
         context,
         smart_protocol: None,
         data_combined: BytesMut::new(),
+        protocol_version: ProtocolVersion::default(),
     };
     let server_url = format!("{}:{}", host, ssh_port);
     let addr = SocketAddr::from_str(&server_url).unwrap();
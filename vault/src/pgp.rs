@@ -1,11 +1,15 @@
 use crate::vault::{delete_secret, read_secret, write_secret};
+use chrono::SubsecRound;
 use pgp::composed::Deserializable;
+use pgp::composed::StandaloneSignature;
 /// This module provides functions for generating, loading, saving, and deleting PGP key pairs.
 ///
 /// It uses the `pgp` crate for key generation and management, and stores the keys in a vault
 /// using asynchronous operations.
 pub use pgp::composed::{SignedPublicKey, SignedSecretKey};
-use pgp::types::SecretKeyTrait;
+use pgp::packet::{SignatureConfig, SignatureType, Subpacket, SubpacketData};
+pub use pgp::types::PublicKeyTrait;
+use pgp::types::{KeyVersion, SecretKeyTrait};
 pub use pgp::KeyType;
 use pgp::SecretKeyParams;
 use pgp::{SecretKeyParamsBuilder, SubkeyParamsBuilder};
@@ -127,6 +131,77 @@ pub async fn delete_keys() {
     });
 }
 
+/// Parses an armored secret key, e.g. the content of a key file exported by `gpg --export-secret-keys --armor`.
+pub fn parse_sec_key(armored: &str) -> Result<SignedSecretKey, pgp::errors::Error> {
+    let (key, _headers) = SignedSecretKey::from_string(armored)?;
+    key.verify()?;
+    Ok(key)
+}
+
+/// Parses an armored public key, e.g. the content of a key file exported by `gpg --export --armor`.
+pub fn parse_pub_key(armored: &str) -> Result<SignedPublicKey, pgp::errors::Error> {
+    let (key, _headers) = SignedPublicKey::from_string(armored)?;
+    key.verify()?;
+    Ok(key)
+}
+
+/// Returns the fingerprint of a key as an uppercase hex string, like `gpg --fingerprint`.
+pub fn fingerprint(key: &impl PublicKeyTrait) -> String {
+    hex::encode_upper(key.fingerprint().as_bytes())
+}
+
+/// Creates an armored detached signature of `data`, like `gpg --detach-sign --armor`.
+///
+/// # Arguments
+///
+/// *   `sec_key`: The secret key to sign with, its primary key must be able to sign.
+/// *   `passwd`: The passphrase of the secret key, `None` if the key is not encrypted.
+/// *   `data`: The signed data, e.g. a commit object without its `gpgsig` header.
+///
+/// # Returns
+///
+/// The armored signature, starting with `-----BEGIN PGP SIGNATURE-----`.
+pub fn sign_detached(
+    sec_key: &SignedSecretKey,
+    passwd: Option<String>,
+    data: &[u8],
+) -> Result<String, pgp::errors::Error> {
+    let (typ, alg, hash_alg) = (
+        SignatureType::Binary,
+        sec_key.algorithm(),
+        sec_key.hash_alg(),
+    );
+    // the signature version must match the key version
+    let mut config = match sec_key.version() {
+        KeyVersion::V6 => SignatureConfig::v6(secp256k1::rand::rngs::OsRng, typ, alg, hash_alg)?,
+        _ => SignatureConfig::v4(typ, alg, hash_alg),
+    };
+    config.hashed_subpackets = vec![
+        Subpacket::regular(SubpacketData::IssuerFingerprint(sec_key.fingerprint())),
+        Subpacket::regular(SubpacketData::SignatureCreationTime(
+            chrono::Utc::now().trunc_subsecs(0),
+        )),
+    ];
+    let signature = config.sign(sec_key, || passwd.unwrap_or_default(), data)?;
+    StandaloneSignature::new(signature).to_armored_string(None.into())
+}
+
+/// Verifies an armored detached signature of `data`, created by [sign_detached] or `gpg`.
+///
+/// # Arguments
+///
+/// *   `key`: The public key of the signer, a secret key is accepted as well.
+/// *   `data`: The signed data.
+/// *   `signature`: The armored signature.
+pub fn verify_detached(
+    key: &impl PublicKeyTrait,
+    data: &[u8],
+    signature: &str,
+) -> Result<(), pgp::errors::Error> {
+    let (signature, _headers) = StandaloneSignature::from_string(signature)?;
+    signature.verify(key, data)
+}
+
 /// Creates a set of parameters for generating a PGP secret key.
 ///
 /// This function simplifies the creation of `SecretKeyParams` by pre-configuring several options
@@ -187,6 +262,25 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_sign_verify_detached() {
+        let params = params(KeyType::Rsa(2048), None, "test");
+        let (pk, sk) = gen_pgp_keypair(params, None);
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\nmessage";
+
+        let signature = sign_detached(&sk, None, data).unwrap();
+        assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----"));
+        assert!(verify_detached(&pk, data, &signature).is_ok());
+        assert!(verify_detached(&sk, data, &signature).is_ok());
+        assert!(verify_detached(&pk, b"tampered", &signature).is_err());
+
+        let armored = pk.to_armored_string(None.into()).unwrap();
+        assert_eq!(
+            fingerprint(&parse_pub_key(&armored).unwrap()),
+            fingerprint(&pk)
+        );
+    }
+
     #[test]
     fn test_gen_pgp_keypair() {
         const PASSWD: &str = "hello";